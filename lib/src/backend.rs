@@ -18,6 +18,7 @@ use std::io::Read;
 use std::result::Result;
 use std::vec::Vec;
 
+use itertools::Itertools;
 use thiserror::Error;
 
 use crate::content_hash::ContentHash;
@@ -171,6 +172,35 @@ content_hash! {
     }
 }
 
+impl Conflict {
+    /// Tries to resolve the conflict without looking at the contents of the
+    /// terms. Removed and added terms with the same value cancel out (i.e. a
+    /// side left the value unchanged). If the remaining added terms all have the
+    /// same value, and no side removed the path while another side modified it,
+    /// that value is the resolution (i.e. all sides made the same change).
+    ///
+    /// Returns `Some(None)` if the conflict resolves to the path being absent,
+    /// and `None` if the conflict can't be resolved this way.
+    pub fn try_resolve_trivially(&self) -> Option<Option<TreeValue>> {
+        let mut removes = self.removes.iter().map(|term| &term.value).collect_vec();
+        let mut adds = vec![];
+        for term in &self.adds {
+            if let Some(index) = removes.iter().position(|value| **value == term.value) {
+                removes.remove(index);
+            } else {
+                adds.push(&term.value);
+            }
+        }
+        if adds.is_empty() {
+            Some(None)
+        } else if adds.len() > removes.len() && adds.iter().all_equal() {
+            Some(Some(adds[0].clone()))
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum BackendError {
     #[error(
@@ -405,5 +435,12 @@ pub trait Backend: Send + Sync + Debug {
 
     fn read_commit(&self, id: &CommitId) -> BackendResult<Commit>;
 
+    /// Reads several commits at once, returning them in the order of `ids`.
+    /// Backends can override this if reading in bulk is faster than reading
+    /// the commits one by one.
+    fn read_commits(&self, ids: &[CommitId]) -> BackendResult<Vec<Commit>> {
+        ids.iter().map(|id| self.read_commit(id)).collect()
+    }
+
     fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId>;
 }
@@ -0,0 +1,150 @@
+// Copyright 2023 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attribution of file lines to the commits that introduced them.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::Range;
+
+use crate::backend::{BackendError, BackendResult, CommitId, ObjectId, TreeValue};
+use crate::commit::Commit;
+use crate::diff::{find_line_ranges, Diff, DiffHunk};
+use crate::repo::Repo;
+use crate::repo_path::RepoPath;
+
+/// Range of 0-based line numbers in a file.
+pub type LineRange = Range<usize>;
+
+/// Attributes each line of the file at `path` in `commit` to the commit that
+/// introduced it.
+///
+/// Returns consecutive line ranges in file order, each paired with the
+/// introducing commit. Lines of a merge commit are attributed through its
+/// parents in order, so a line unchanged from the first parent is never
+/// attributed through a later one. The result is empty if `path` isn't a
+/// regular file in the commit.
+pub fn blame(
+    repo: &dyn Repo,
+    commit: &Commit,
+    path: &RepoPath,
+) -> BackendResult<Vec<(CommitId, LineRange)>> {
+    let commit_id = commit.id();
+    let content = match read_file_at(repo, commit_id, path)? {
+        Some(content) => content,
+        None => return Ok(vec![]),
+    };
+    let num_lines = find_line_ranges(&content).len();
+    let mut line_owners: Vec<Option<CommitId>> = vec![None; num_lines];
+
+    // Maps commit to pairs of (line in that commit, line in the original file),
+    // for lines that haven't been attributed yet.
+    let mut pending: HashMap<CommitId, Vec<(usize, usize)>> = HashMap::new();
+    pending.insert(commit_id.clone(), (0..num_lines).map(|i| (i, i)).collect());
+    // The walk yields children before their parents, so all lines passed down
+    // to a commit have been collected by the time it's visited.
+    for entry in repo.index().walk_revs(std::slice::from_ref(commit_id), &[]) {
+        if pending.is_empty() {
+            break;
+        }
+        let current_id = entry.commit_id();
+        let mut lines = match pending.remove(&current_id) {
+            Some(lines) => lines,
+            None => continue,
+        };
+        let current_commit = repo.store().get_commit(&current_id)?;
+        let content = read_file_at(repo, &current_id, path)?.unwrap();
+        for parent_id in current_commit.parent_ids() {
+            if lines.is_empty() {
+                break;
+            }
+            let parent_content = match read_file_at(repo, parent_id, path)? {
+                Some(content) => content,
+                None => continue,
+            };
+            let line_map = unchanged_line_map(&content, &parent_content);
+            let (moved, remaining): (Vec<_>, Vec<_>) = lines
+                .into_iter()
+                .partition(|(line, _)| line_map.contains_key(line));
+            if !moved.is_empty() {
+                pending.entry(parent_id.clone()).or_default().extend(
+                    moved
+                        .into_iter()
+                        .map(|(line, orig)| (line_map[&line], orig)),
+                );
+            }
+            lines = remaining;
+        }
+        for (_, orig) in lines {
+            line_owners[orig] = Some(current_id.clone());
+        }
+    }
+
+    let mut result: Vec<(CommitId, LineRange)> = vec![];
+    for (line, owner) in line_owners.into_iter().enumerate() {
+        let owner = owner.expect("all lines should have been attributed");
+        match result.last_mut() {
+            Some((last_owner, range)) if *last_owner == owner => range.end = line + 1,
+            _ => result.push((owner, line..line + 1)),
+        }
+    }
+    Ok(result)
+}
+
+fn read_file_at(
+    repo: &dyn Repo,
+    commit_id: &CommitId,
+    path: &RepoPath,
+) -> BackendResult<Option<Vec<u8>>> {
+    let commit = repo.store().get_commit(commit_id)?;
+    let id = match commit.tree().path_value(path) {
+        Some(TreeValue::File { id, .. }) => id,
+        _ => return Ok(None),
+    };
+    let mut content = vec![];
+    repo.store()
+        .read_file(path, &id)?
+        .read_to_end(&mut content)
+        .map_err(|err| BackendError::ReadObject {
+            object_type: id.object_type(),
+            hash: id.hex(),
+            source: Box::new(err),
+        })?;
+    Ok(Some(content))
+}
+
+/// Maps lines in `content` to the corresponding lines in `parent_content` if
+/// they are unchanged.
+fn unchanged_line_map(content: &[u8], parent_content: &[u8]) -> HashMap<usize, usize> {
+    let mut line_map = HashMap::new();
+    let mut line = 0;
+    let mut parent_line = 0;
+    let diff = Diff::for_tokenizer(&[content, parent_content], &find_line_ranges);
+    for hunk in diff.hunks() {
+        match hunk {
+            DiffHunk::Matching(text) => {
+                for _ in find_line_ranges(text) {
+                    line_map.insert(line, parent_line);
+                    line += 1;
+                    parent_line += 1;
+                }
+            }
+            DiffHunk::Different(texts) => {
+                line += find_line_ranges(texts[0]).len();
+                parent_line += find_line_ranges(texts[1]).len();
+            }
+        }
+    }
+    line_map
+}
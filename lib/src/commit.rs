@@ -17,8 +17,10 @@ use std::fmt::{Debug, Error, Formatter};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
+use itertools::Itertools;
+
 use crate::backend;
-use crate::backend::{ChangeId, CommitId, Signature, TreeId};
+use crate::backend::{BackendResult, ChangeId, CommitId, Signature, TreeId};
 use crate::repo_path::RepoPath;
 use crate::store::Store;
 use crate::tree::Tree;
@@ -87,6 +89,8 @@ impl Commit {
             .collect()
     }
 
+    /// The commits this commit was rewritten from, if any. Following these
+    /// gives the evolution of a change.
     pub fn predecessor_ids(&self) -> &[CommitId] {
         &self.data.predecessors
     }
@@ -129,6 +133,20 @@ impl Commit {
         &self.data.committer
     }
 
+    /// Returns the paths that are in conflict in this commit's tree, sorted.
+    pub fn conflicted_paths(&self) -> BackendResult<Vec<RepoPath>> {
+        let tree = self
+            .store
+            .get_tree(&RepoPath::root(), &self.data.root_tree)?;
+        let mut paths = tree
+            .conflicts()
+            .into_iter()
+            .map(|(path, _conflict_id)| path)
+            .collect_vec();
+        paths.sort();
+        Ok(paths)
+    }
+
     /// A commit is discardable if it has one parent, no change from its
     /// parent, and an empty description.
     pub fn is_discardable(&self) -> bool {
@@ -14,6 +14,8 @@
 
 use std::sync::Arc;
 
+use itertools::Itertools;
+
 use crate::backend::{self, BackendResult, ChangeId, CommitId, Signature, TreeId};
 use crate::commit::Commit;
 use crate::repo::{MutableRepo, Repo};
@@ -79,12 +81,24 @@ impl CommitBuilder<'_> {
         }
     }
 
+    /// Sets the parents of the commit. The parents must be distinct, and the
+    /// root commit can only be a parent if it's the only one. The tree is
+    /// left unchanged, so call `set_tree()` too if the changes in the commit
+    /// should be kept relative to the new parents.
     pub fn set_parents(mut self, parents: Vec<CommitId>) -> Self {
         assert!(!parents.is_empty());
+        assert!(parents.iter().all_unique(), "duplicate parents");
+        assert!(
+            parents.len() == 1 || !parents.contains(self.mut_repo.store().root_commit_id()),
+            "the root commit cannot be merged with other parents"
+        );
         self.commit.parents = parents;
         self
     }
 
+    /// Overrides the predecessors, e.g. to record all the commits that were
+    /// squashed into this one. Rewritten commits default to having the commit
+    /// they were rewritten from as their only predecessor.
     pub fn set_predecessors(mut self, predecessors: Vec<CommitId>) -> Self {
         self.commit.predecessors = predecessors;
         self
@@ -29,6 +29,26 @@ const CONFLICT_END_LINE: &[u8] = b">>>>>>>\n";
 const CONFLICT_DIFF_LINE: &[u8] = b"%%%%%%%\n";
 const CONFLICT_MINUS_LINE: &[u8] = b"-------\n";
 const CONFLICT_PLUS_LINE: &[u8] = b"+++++++\n";
+const CONFLICT_BASE_LINE: &[u8] = b"|||||||\n";
+const CONFLICT_SEPARATOR_LINE: &[u8] = b"=======\n";
+
+/// How conflicts in file contents are rendered as conflict markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterializeStyle {
+    /// Each removed term is shown as a diff against an added term (`%%%%%%%`),
+    /// and any remaining terms as snapshots (`-------` and `+++++++`).
+    Diff,
+    /// Git's "diff3" style, with snapshots of the two sides separated by the
+    /// base (`|||||||` and `=======`). Conflicts with more than two sides are
+    /// rendered in the `Diff` style.
+    Diff3,
+}
+
+impl Default for MaterializeStyle {
+    fn default() -> Self {
+        MaterializeStyle::Diff
+    }
+}
 
 fn describe_conflict_term(term: &ConflictTerm) -> String {
     match &term.value {
@@ -128,10 +148,77 @@ fn write_diff_hunks(hunks: &[DiffHunk], file: &mut dyn Write) -> std::io::Result
     Ok(())
 }
 
+/// Writes the conflict marker, followed by the label if there is one.
+fn write_conflict_marker(
+    output: &mut dyn Write,
+    marker: &[u8],
+    label: Option<String>,
+) -> std::io::Result<()> {
+    match label {
+        None => output.write_all(marker),
+        Some(label) => {
+            // Insert the label before the marker's newline
+            output.write_all(&marker[..marker.len() - 1])?;
+            writeln!(output, " {label}")
+        }
+    }
+}
+
+/// Returns which of the `markers` the line is, ignoring the label after the
+/// marker if it's one that `materialize_merge_result()` writes. Other text
+/// after a marker means that the line is content, such as a diff line removing
+/// or adding a line that starts with `------` or `++++++`.
+fn parse_conflict_marker<'a>(line: &[u8], markers: &[&'a [u8]]) -> Option<&'a [u8]> {
+    markers.iter().copied().find(|marker| {
+        line == *marker
+            || line
+                .strip_prefix(&marker[..marker.len() - 1])
+                .and_then(|rest| rest.strip_prefix(b" "))
+                .and_then(|rest| rest.strip_suffix(b"\n"))
+                .map_or(false, |label| is_conflict_marker_label(marker, label))
+    })
+}
+
+fn is_conflict_marker_label(marker: &[u8], label: &[u8]) -> bool {
+    let is_index = |text: &[u8]| !text.is_empty() && text.iter().all(u8::is_ascii_digit);
+    match marker {
+        CONFLICT_MINUS_LINE => label
+            .strip_prefix(b"Contents of base #")
+            .map_or(false, is_index),
+        CONFLICT_PLUS_LINE => label
+            .strip_prefix(b"Contents of side #")
+            .map_or(false, is_index),
+        CONFLICT_DIFF_LINE => label
+            .strip_prefix(b"Changes from base #")
+            .and_then(|rest| {
+                let (base, side) = rest.split_at(rest.iter().position(|b| *b == b' ')?);
+                Some(is_index(base) && is_index(side.strip_prefix(b" to side #")?))
+            })
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn write_diff3_conflict_hunk(
+    base: &[u8],
+    left: &[u8],
+    right: &[u8],
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
+    output.write_all(CONFLICT_START_LINE)?;
+    output.write_all(left)?;
+    output.write_all(CONFLICT_BASE_LINE)?;
+    output.write_all(base)?;
+    output.write_all(CONFLICT_SEPARATOR_LINE)?;
+    output.write_all(right)?;
+    output.write_all(CONFLICT_END_LINE)
+}
+
 pub fn materialize_conflict(
     store: &Store,
     path: &RepoPath,
     conflict: &Conflict,
+    style: MaterializeStyle,
     output: &mut dyn Write,
 ) -> std::io::Result<()> {
     match extract_file_conflict_as_single_hunk(store, path, conflict) {
@@ -140,7 +227,7 @@ pub fn materialize_conflict(
             // describe the conflict.
             describe_conflict(conflict, output)
         }
-        Some(content) => materialize_merge_result(&content, output),
+        Some(content) => materialize_merge_result(&content, style, output),
     }
 }
 
@@ -177,6 +264,7 @@ pub fn extract_file_conflict_as_single_hunk(
 
 pub fn materialize_merge_result(
     single_hunk: &ConflictHunk,
+    style: MaterializeStyle,
     output: &mut dyn Write,
 ) -> std::io::Result<()> {
     let removed_slices = single_hunk.removes.iter().map(Vec::as_slice).collect_vec();
@@ -193,15 +281,28 @@ pub fn materialize_merge_result(
                         output.write_all(&content)?;
                     }
                     MergeHunk::Conflict(ConflictHunk { removes, adds }) => {
+                        if style == MaterializeStyle::Diff3 && removes.len() == 1 && adds.len() == 2
+                        {
+                            write_diff3_conflict_hunk(&removes[0], &adds[0], &adds[1], output)?;
+                            continue;
+                        }
+                        // Conflicts with more than two sides are hard to read without
+                        // knowing which terms the sections come from, so label the markers.
+                        let labeled = adds.len() > 2;
                         output.write_all(CONFLICT_START_LINE)?;
                         let mut add_index = 0;
-                        for left in &removes {
+                        for (remove_index, left) in removes.iter().enumerate() {
                             let right1 = if let Some(right1) = adds.get(add_index) {
                                 right1
                             } else {
                                 // If we have no more positive terms, emit the remaining negative
                                 // terms as snapshots.
-                                output.write_all(CONFLICT_MINUS_LINE)?;
+                                write_conflict_marker(
+                                    output,
+                                    CONFLICT_MINUS_LINE,
+                                    labeled
+                                        .then(|| format!("Contents of base #{}", remove_index + 1)),
+                                )?;
                                 output.write_all(left)?;
                                 continue;
                             };
@@ -219,23 +320,53 @@ pub fn materialize_merge_result(
                                     // If the next positive term is a better match, emit
                                     // the current positive term as a snapshot and the next
                                     // positive term as a diff.
-                                    output.write_all(CONFLICT_PLUS_LINE)?;
+                                    write_conflict_marker(
+                                        output,
+                                        CONFLICT_PLUS_LINE,
+                                        labeled.then(|| {
+                                            format!("Contents of side #{}", add_index + 1)
+                                        }),
+                                    )?;
                                     output.write_all(right1)?;
-                                    output.write_all(CONFLICT_DIFF_LINE)?;
+                                    write_conflict_marker(
+                                        output,
+                                        CONFLICT_DIFF_LINE,
+                                        labeled.then(|| {
+                                            format!(
+                                                "Changes from base #{} to side #{}",
+                                                remove_index + 1,
+                                                add_index + 2
+                                            )
+                                        }),
+                                    )?;
                                     write_diff_hunks(&diff2, output)?;
                                     add_index += 2;
                                     continue;
                                 }
                             }
 
-                            output.write_all(CONFLICT_DIFF_LINE)?;
+                            write_conflict_marker(
+                                output,
+                                CONFLICT_DIFF_LINE,
+                                labeled.then(|| {
+                                    format!(
+                                        "Changes from base #{} to side #{}",
+                                        remove_index + 1,
+                                        add_index + 1
+                                    )
+                                }),
+                            )?;
                             write_diff_hunks(&diff1, output)?;
                             add_index += 1;
                         }
 
                         //  Emit the remaining positive terms as snapshots.
-                        for slice in &adds[add_index..] {
-                            output.write_all(CONFLICT_PLUS_LINE)?;
+                        for (index, slice) in adds.iter().enumerate().skip(add_index) {
+                            write_conflict_marker(
+                                output,
+                                CONFLICT_PLUS_LINE,
+                                labeled.then(|| format!("Contents of side #{}", index + 1)),
+                            )?;
                             output.write_all(slice)?;
                         }
                         output.write_all(CONFLICT_END_LINE)?;
@@ -263,7 +394,7 @@ pub fn conflict_to_materialized_value(
     conflict: &Conflict,
 ) -> TreeValue {
     let mut buf = vec![];
-    materialize_conflict(store, path, conflict, &mut buf).unwrap();
+    materialize_conflict(store, path, conflict, MaterializeStyle::Diff, &mut buf).unwrap();
     let file_id = store.write_file(path, &mut Cursor::new(&buf)).unwrap();
     TreeValue::File {
         id: file_id,
@@ -274,7 +405,8 @@ pub fn conflict_to_materialized_value(
 /// Parses conflict markers from a slice. Returns None if there were no valid
 /// conflict markers. The caller has to provide the expected number of removed
 /// and added inputs to the conflicts. Conflict markers that are otherwise valid
-/// will be considered invalid if they don't have the expected arity.
+/// will be considered invalid if they don't have the expected arity. Conflicts
+/// in either `MaterializeStyle` are recognized.
 // TODO: "parse" is not usually the opposite of "materialize", so maybe we
 // should rename them to "serialize" and "deserialize"?
 pub fn parse_conflict(input: &[u8], num_removes: usize, num_adds: usize) -> Option<Vec<MergeHunk>> {
@@ -320,6 +452,21 @@ pub fn parse_conflict(input: &[u8], num_removes: usize, num_adds: usize) -> Opti
 }
 
 fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
+    // Conflicts in the `Diff` style always start with one of its markers, while
+    // the diff3 style starts with the content of the first side.
+    let is_diff3 = input
+        .split_inclusive(|b| *b == b'\n')
+        .next()
+        .map_or(false, |line| {
+            parse_conflict_marker(
+                line,
+                &[CONFLICT_DIFF_LINE, CONFLICT_MINUS_LINE, CONFLICT_PLUS_LINE],
+            )
+            .is_none()
+        });
+    if is_diff3 {
+        return parse_diff3_conflict_hunk(input);
+    }
     enum State {
         Diff,
         Minus,
@@ -330,19 +477,22 @@ fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
     let mut removes = vec![];
     let mut adds = vec![];
     for line in input.split_inclusive(|b| *b == b'\n') {
-        match line {
-            CONFLICT_DIFF_LINE => {
+        match parse_conflict_marker(
+            line,
+            &[CONFLICT_DIFF_LINE, CONFLICT_MINUS_LINE, CONFLICT_PLUS_LINE],
+        ) {
+            Some(CONFLICT_DIFF_LINE) => {
                 state = State::Diff;
                 removes.push(vec![]);
                 adds.push(vec![]);
                 continue;
             }
-            CONFLICT_MINUS_LINE => {
+            Some(CONFLICT_MINUS_LINE) => {
                 state = State::Minus;
                 removes.push(vec![]);
                 continue;
             }
-            CONFLICT_PLUS_LINE => {
+            Some(CONFLICT_PLUS_LINE) => {
                 state = State::Plus;
                 adds.push(vec![]);
                 continue;
@@ -379,6 +529,29 @@ fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
     MergeHunk::Conflict(ConflictHunk { removes, adds })
 }
 
+fn parse_diff3_conflict_hunk(input: &[u8]) -> MergeHunk {
+    let mut parts = vec![vec![]];
+    let mut expected_markers = [CONFLICT_BASE_LINE, CONFLICT_SEPARATOR_LINE].iter();
+    let mut next_marker = expected_markers.next();
+    for line in input.split_inclusive(|b| *b == b'\n') {
+        if Some(&line) == next_marker {
+            parts.push(vec![]);
+            next_marker = expected_markers.next();
+        } else {
+            parts.last_mut().unwrap().extend_from_slice(line);
+        }
+    }
+    if next_marker.is_some() {
+        // Doesn't look like a conflict
+        return MergeHunk::Resolved(vec![]);
+    }
+    let [left, base, right]: [Vec<u8>; 3] = parts.try_into().unwrap();
+    MergeHunk::Conflict(ConflictHunk {
+        removes: vec![base],
+        adds: vec![left, right],
+    })
+}
+
 /// Returns `None` if there are no conflict markers in `content`.
 pub fn update_conflict_from_content(
     store: &Store,
@@ -394,7 +567,14 @@ pub fn update_conflict_from_content(
     // conflicts (for example) are not converted to regular files in the working
     // copy.
     let mut old_content = Vec::with_capacity(content.len());
-    materialize_conflict(store, path, &conflict, &mut old_content).unwrap();
+    materialize_conflict(
+        store,
+        path,
+        &conflict,
+        MaterializeStyle::Diff,
+        &mut old_content,
+    )
+    .unwrap();
     if content == old_content {
         return Ok(Some(conflict_id.clone()));
     }
@@ -195,8 +195,14 @@ impl IndexStore for DefaultIndexStore {
                     // we just reindex.
                     // TODO: Move this message to a callback or something.
                     println!("The index was corrupt (maybe the format has changed). Reindexing...");
-                    std::fs::remove_dir_all(self.dir.join("operations")).unwrap();
-                    std::fs::create_dir(self.dir.join("operations")).unwrap();
+                    self.reinit();
+                    self.index_at_operation(store, op).unwrap()
+                }
+                Err(IndexLoadError::UnsupportedVersion { .. }) => {
+                    // The index was written by a newer version of jj. We can't read it, but
+                    // we can rebuild it in the format we know.
+                    println!("The index was written in a newer format. Reindexing...");
+                    self.reinit();
                     self.index_at_operation(store, op).unwrap()
                 }
                 result => result.unwrap(),
@@ -385,11 +391,27 @@ impl CommitLookupEntry<'_> {
 pub enum IndexLoadError {
     #[error("Index file '{0}' is corrupt.")]
     IndexCorrupt(String),
+    #[error("Index file '{name}' has unsupported format version {version}.")]
+    UnsupportedVersion { name: String, version: u32 },
     #[error("I/O error while loading index file: {0}")]
     IoError(#[from] io::Error),
 }
 
+/// Marks index files that start with a format version. Files written before
+/// the format was versioned start with the length of the parent file name
+/// instead, which is always 0 or 128, so they can't be mistaken for this.
+const INDEX_FILE_MAGIC: &[u8; 4] = b"JJIX";
+/// Version of the index file format written by this version of jj. Files with
+/// older versions (including unversioned files, which are treated as version
+/// 0) can be read, and files with newer versions are rejected so they can be
+/// rebuilt.
+const INDEX_FILE_FORMAT_VERSION: u32 = 1;
+
 // File format:
+// [u8; 4]: magic bytes ("JJIX")
+// u32: format version
+// u32: length of the parent file name (0 if there's no parent file)
+// <parent file name length number of bytes>: parent file name
 // u32: number of entries
 // u32: number of parent overflow entries
 // for each entry, in some topological order with parents first:
@@ -401,7 +423,6 @@ pub enum IndexLoadError {
 // for each entry, sorted by commit id:
 //   <hash length number of bytes>: commit id
 //    u32: position in the entry table above
-// TODO: add a version number
 // TODO: replace the table by a trie so we don't have to repeat the full commit
 //       ids
 // TODO: add a fanout table like git's commit graph has?
@@ -546,6 +567,10 @@ impl MutableIndexImpl {
 
         let mut buf = vec![];
 
+        buf.write_all(INDEX_FILE_MAGIC).unwrap();
+        buf.write_u32::<LittleEndian>(INDEX_FILE_FORMAT_VERSION)
+            .unwrap();
+
         if let Some(parent_file) = &self.parent_file {
             buf.write_u32::<LittleEndian>(parent_file.name.len() as u32)
                 .unwrap();
@@ -674,6 +699,9 @@ impl MutableIndexImpl {
             IndexLoadError::IndexCorrupt(err) => {
                 panic!("Just-created index file is corrupt: {err}")
             }
+            IndexLoadError::UnsupportedVersion { version, .. } => {
+                panic!("Just-created index file has unsupported version {version}")
+            }
             IndexLoadError::IoError(err) => err,
         })
     }
@@ -712,6 +740,10 @@ impl Index for MutableIndexImpl {
         CompositeIndex(self).entry_by_pos(pos)
     }
 
+    fn parent_positions(&self, pos: IndexPosition) -> Vec<IndexPosition> {
+        CompositeIndex(self).entry_by_pos(pos).parent_positions()
+    }
+
     fn has_id(&self, commit_id: &CommitId) -> bool {
         CompositeIndex(self).has_id(commit_id)
     }
@@ -732,6 +764,10 @@ impl Index for MutableIndexImpl {
         CompositeIndex(self).heads(candidates)
     }
 
+    fn all_heads(&self) -> Vec<CommitId> {
+        CompositeIndex(self).all_heads()
+    }
+
     fn topo_order(&self, input: &mut dyn Iterator<Item = &CommitId>) -> Vec<IndexEntry> {
         CompositeIndex(self).topo_order(input)
     }
@@ -1080,6 +1116,21 @@ impl<'a> CompositeIndex<'a> {
             .collect()
     }
 
+    fn all_heads(&self) -> Vec<CommitId> {
+        let num_commits = self.num_commits();
+        let mut is_head = vec![true; num_commits as usize];
+        for pos in 0..num_commits {
+            for parent_pos in self.entry_by_pos(IndexPosition(pos)).parent_positions() {
+                is_head[parent_pos.0 as usize] = false;
+            }
+        }
+        (0..num_commits)
+            .rev()
+            .filter(|pos| is_head[*pos as usize])
+            .map(|pos| self.entry_by_pos(IndexPosition(pos)).commit_id())
+            .collect()
+    }
+
     fn heads_pos(
         &self,
         mut candidate_positions: BTreeSet<IndexPosition>,
@@ -1654,7 +1705,19 @@ impl ReadonlyIndexImpl {
         commit_id_length: usize,
         change_id_length: usize,
     ) -> Result<Arc<ReadonlyIndexImpl>, IndexLoadError> {
-        let parent_filename_len = file.read_u32::<LittleEndian>()?;
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let parent_filename_len = if &header == INDEX_FILE_MAGIC {
+            let version = file.read_u32::<LittleEndian>()?;
+            if version > INDEX_FILE_FORMAT_VERSION {
+                return Err(IndexLoadError::UnsupportedVersion { name, version });
+            }
+            file.read_u32::<LittleEndian>()?
+        } else {
+            // Unversioned files have the same layout as version 1 without the
+            // magic and version.
+            u32::from_le_bytes(header)
+        };
         let num_parent_commits;
         let maybe_parent_file;
         if parent_filename_len > 0 {
@@ -1794,6 +1857,10 @@ impl Index for ReadonlyIndexImpl {
         CompositeIndex(self).entry_by_pos(pos)
     }
 
+    fn parent_positions(&self, pos: IndexPosition) -> Vec<IndexPosition> {
+        CompositeIndex(self).entry_by_pos(pos).parent_positions()
+    }
+
     fn has_id(&self, commit_id: &CommitId) -> bool {
         CompositeIndex(self).has_id(commit_id)
     }
@@ -1814,6 +1881,10 @@ impl Index for ReadonlyIndexImpl {
         CompositeIndex(self).heads(candidates)
     }
 
+    fn all_heads(&self) -> Vec<CommitId> {
+        CompositeIndex(self).all_heads()
+    }
+
     fn topo_order(&self, input: &mut dyn Iterator<Item = &CommitId>) -> Vec<IndexEntry> {
         CompositeIndex(self).topo_order(input)
     }
@@ -1830,6 +1901,7 @@ impl Index for ReadonlyIndexImpl {
 
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use test_case::test_case;
 
     use super::*;
@@ -1842,6 +1914,50 @@ mod tests {
         move || iter.next().unwrap()
     }
 
+    #[test]
+    fn index_file_format_version() {
+        let temp_dir = testutils::new_temp_dir();
+        let mut new_change_id = change_id_generator();
+        let mut index = MutableIndexImpl::full(3, 16);
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("111111");
+        index.add_commit_data(id_0.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_1.clone(), new_change_id(), &[id_0]);
+        let data = index.serialize();
+        assert_eq!(&data[0..4], INDEX_FILE_MAGIC);
+
+        let load = |data: &[u8]| {
+            ReadonlyIndexImpl::load_from(
+                &mut Cursor::new(data),
+                temp_dir.path().to_owned(),
+                "index".to_string(),
+                3,
+                16,
+            )
+        };
+
+        // The current version can be read
+        let loaded = load(&data).unwrap();
+        let index = CompositeIndex(loaded.as_ref());
+        assert_eq!(index.num_commits(), 2);
+        assert_eq!(index.commit_id_to_pos(&id_1), Some(IndexPosition(1)));
+
+        // An unversioned file can be read
+        let loaded = load(&data[8..]).unwrap();
+        let index = CompositeIndex(loaded.as_ref());
+        assert_eq!(index.num_commits(), 2);
+        assert_eq!(index.commit_id_to_pos(&id_1), Some(IndexPosition(1)));
+
+        // A newer version is rejected
+        let mut newer_data = data.clone();
+        newer_data[4..8].copy_from_slice(&(INDEX_FILE_FORMAT_VERSION + 1).to_le_bytes());
+        assert_matches!(
+            load(&newer_data),
+            Err(IndexLoadError::UnsupportedVersion { version, .. })
+                if version == INDEX_FILE_FORMAT_VERSION + 1
+        );
+    }
+
     #[test_case(false; "memory")]
     #[test_case(true; "file")]
     fn index_empty(on_disk: bool) {
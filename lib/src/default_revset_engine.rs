@@ -13,20 +13,25 @@
 // limitations under the License.
 
 use std::cmp::{Ordering, Reverse};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
 use std::iter::Peekable;
 
 use itertools::Itertools;
 
-use crate::backend::{BackendError, CommitId, ObjectId};
+use crate::backend::{BackendError, CommitId, ObjectId, TreeValue};
 use crate::commit::Commit;
+use crate::conflicts::{self, MaterializeStyle};
 use crate::default_index_store::IndexEntry;
 use crate::default_revset_graph_iterator::RevsetGraphIterator;
+use crate::diff::{find_line_ranges, Diff, DiffHunk};
 use crate::hex_util::to_forward_hex;
 use crate::index::{HexPrefix, PrefixResolution};
-use crate::matchers::{EverythingMatcher, Matcher, PrefixMatcher};
-use crate::op_store::WorkspaceId;
+use crate::matchers::{EverythingMatcher, FilesMatcher, Matcher, PrefixMatcher};
+use crate::op_store::{BranchTarget, RefTarget, WorkspaceId};
+use crate::operation::{self, OpIdResolutionError, Operation};
 use crate::repo::Repo;
+use crate::repo_path::RepoPath;
 use crate::revset::{
     Revset, RevsetError, RevsetExpression, RevsetFilterPredicate, RevsetGraphEdge,
     RevsetIteratorExt, RevsetWorkspaceContext, GENERATION_RANGE_FULL,
@@ -43,24 +48,51 @@ fn resolve_git_ref(repo: &dyn Repo, symbol: &str) -> Option<Vec<CommitId>> {
     None
 }
 
-fn resolve_branch(repo: &dyn Repo, symbol: &str) -> Option<Vec<CommitId>> {
-    if let Some(branch_target) = repo.view().branches().get(symbol) {
-        return Some(
+fn resolve_branch(repo: &dyn Repo, symbol: &str) -> Result<Option<Vec<CommitId>>, RevsetError> {
+    let case_insensitive = repo.base_repo().settings().case_insensitive_branches();
+    if let Some(branch_target) = find_branch(repo, symbol, case_insensitive)? {
+        return Ok(Some(
             branch_target
                 .local_target
                 .as_ref()
                 .map(|target| target.adds())
                 .unwrap_or_default(),
-        );
+        ));
     }
     if let Some((name, remote_name)) = symbol.split_once('@') {
-        if let Some(branch_target) = repo.view().branches().get(name) {
+        if let Some(branch_target) = find_branch(repo, name, case_insensitive)? {
             if let Some(target) = branch_target.remote_targets.get(remote_name) {
-                return Some(target.adds());
+                return Ok(Some(target.adds()));
             }
         }
     }
-    None
+    Ok(None)
+}
+
+/// Looks up the branch by exact name. If `case_insensitive` and there's no
+/// exact match, falls back to the branch whose name differs only in case, which
+/// must be unique.
+fn find_branch<'a>(
+    repo: &'a dyn Repo,
+    name: &str,
+    case_insensitive: bool,
+) -> Result<Option<&'a BranchTarget>, RevsetError> {
+    let branches = repo.view().branches();
+    if let Some(branch_target) = branches.get(name) {
+        return Ok(Some(branch_target));
+    }
+    if !case_insensitive {
+        return Ok(None);
+    }
+    let folded_name = name.to_lowercase();
+    let mut matches = branches
+        .iter()
+        .filter(|(branch_name, _)| branch_name.to_lowercase() == folded_name);
+    match (matches.next(), matches.next()) {
+        (None, _) => Ok(None),
+        (Some((_, branch_target)), None) => Ok(Some(branch_target)),
+        (Some(_), Some(_)) => Err(RevsetError::AmbiguousBranchName(name.to_owned())),
+    }
 }
 
 fn resolve_full_commit_id(
@@ -145,7 +177,7 @@ pub fn resolve_symbol(
         }
 
         // Try to resolve as a branch
-        if let Some(ids) = resolve_branch(repo, symbol) {
+        if let Some(ids) = resolve_branch(repo, symbol)? {
             return Ok(ids);
         }
 
@@ -174,6 +206,25 @@ pub fn resolve_symbol(
     }
 }
 
+/// Resolves a full operation id or a unique prefix of the id of an ancestor of
+/// the repo's operation. Returns the repo's operation if `op_str` is `None`.
+fn resolve_operation(repo: &dyn Repo, op_str: Option<&str>) -> Result<Operation, RevsetError> {
+    let current_op = repo.base_repo().operation();
+    let op_str = match op_str {
+        Some(op_str) => op_str,
+        None => return Ok(current_op.clone()),
+    };
+    operation::resolve_op_id_prefix(&current_op.op_store(), [current_op.id().clone()], op_str)
+        .map_err(|err| match err {
+            OpIdResolutionError::InvalidIdPrefix(op_str)
+            | OpIdResolutionError::NoSuchOperation(op_str) => RevsetError::NoSuchOperation(op_str),
+            OpIdResolutionError::AmbiguousIdPrefix(op_str) => {
+                RevsetError::AmbiguousOperationIdPrefix(op_str)
+            }
+            OpIdResolutionError::OpStoreError(err) => RevsetError::OpStoreError(err),
+        })
+}
+
 trait ToPredicateFn<'index> {
     /// Creates function that tests if the given entry is included in the set.
     ///
@@ -558,6 +609,10 @@ fn evaluate_impl<'index>(
             )
         }
         RevsetExpression::Commits(commit_ids) => Ok(revset_for_commit_ids(repo, commit_ids)),
+        RevsetExpression::Root => Ok(revset_for_commit_ids(
+            repo,
+            &[repo.store().root_commit_id().clone()],
+        )),
         RevsetExpression::Symbol(symbol) => {
             let commit_ids = resolve_symbol(repo, symbol, workspace_ctx.map(|c| c.workspace_id))?;
             evaluate_impl(repo, &RevsetExpression::Commits(commit_ids), workspace_ctx)
@@ -579,6 +634,91 @@ fn evaluate_impl<'index>(
             };
             evaluate_impl(repo, &range_expression, workspace_ctx)
         }
+        RevsetExpression::Descendants { roots, generation } => {
+            let root_set = evaluate_impl(repo, roots, workspace_ctx)?;
+            let candidate_set = evaluate_impl(repo, &roots.descendants(), workspace_ctx)?;
+            let root_positions: HashSet<_> =
+                root_set.iter().map(|entry| entry.position()).collect();
+            // Generations at which each candidate can be reached from the roots.
+            // Generations past the end of the range are dropped, and if the range
+            // is unbounded, the ones past its start are clamped to the start.
+            let next_generation = |parent_generation: u32| {
+                let next = parent_generation + 1;
+                if generation.end == u32::MAX {
+                    Some(next.min(generation.start))
+                } else {
+                    Some(next).filter(|next| *next < generation.end)
+                }
+            };
+            let mut generations: HashMap<_, Vec<u32>> = HashMap::new();
+            let mut result = vec![];
+            let candidates = candidate_set.iter().collect_vec();
+            for candidate in candidates.into_iter().rev() {
+                let mut candidate_generations = candidate
+                    .parent_positions()
+                    .iter()
+                    .filter_map(|parent_pos| generations.get(parent_pos))
+                    .flatten()
+                    .filter_map(|parent_generation| next_generation(*parent_generation))
+                    .collect_vec();
+                if root_positions.contains(&candidate.position()) {
+                    candidate_generations.push(0);
+                }
+                if candidate_generations.is_empty() {
+                    continue;
+                }
+                candidate_generations.sort_unstable();
+                candidate_generations.dedup();
+                let candidate_pos = candidate.position();
+                if candidate_generations.iter().any(|g| generation.contains(g)) {
+                    result.push(candidate);
+                }
+                generations.insert(candidate_pos, candidate_generations);
+            }
+            result.reverse();
+            Ok(RevsetImpl::new(Box::new(EagerRevset {
+                index_entries: result,
+            })))
+        }
+        RevsetExpression::FirstAncestors(heads) => {
+            let head_set = evaluate_impl(repo, heads, workspace_ctx)?;
+            let mut visited = HashSet::new();
+            let mut index_entries = vec![];
+            for head in head_set.iter() {
+                let mut next_entry = Some(head);
+                while let Some(entry) = next_entry {
+                    if !visited.insert(entry.position()) {
+                        break;
+                    }
+                    next_entry = entry.parents().into_iter().next();
+                    index_entries.push(entry);
+                }
+            }
+            index_entries.sort_by_key(|entry| Reverse(entry.position()));
+            Ok(RevsetImpl::new(Box::new(EagerRevset { index_entries })))
+        }
+        RevsetExpression::FirstDescendants(roots) => {
+            let root_set = evaluate_impl(repo, roots, workspace_ctx)?;
+            let candidate_set = evaluate_impl(repo, &roots.descendants(), workspace_ctx)?;
+            let mut reachable: HashSet<_> = root_set.iter().map(|entry| entry.position()).collect();
+            let mut result = vec![];
+            let candidates = candidate_set.iter().collect_vec();
+            for candidate in candidates.into_iter().rev() {
+                if reachable.contains(&candidate.position())
+                    || candidate
+                        .parent_positions()
+                        .first()
+                        .map_or(false, |parent_pos| reachable.contains(parent_pos))
+                {
+                    reachable.insert(candidate.position());
+                    result.push(candidate);
+                }
+            }
+            result.reverse();
+            Ok(RevsetImpl::new(Box::new(EagerRevset {
+                index_entries: result,
+            })))
+        }
         RevsetExpression::Range {
             roots,
             heads,
@@ -618,10 +758,53 @@ fn evaluate_impl<'index>(
                 index_entries: result,
             })))
         }
-        RevsetExpression::VisibleHeads => Ok(revset_for_commit_ids(
-            repo,
-            &repo.view().heads().iter().cloned().collect_vec(),
-        )),
+        RevsetExpression::Reachable { sources, domain } => {
+            let domain_entries = evaluate_impl(repo, domain, workspace_ctx)?
+                .iter()
+                .collect_vec();
+            let source_set = evaluate_impl(repo, sources, workspace_ctx)?;
+            let source_positions: HashSet<_> =
+                source_set.iter().map(|entry| entry.position()).collect();
+            let domain_positions: HashSet<_> = domain_entries
+                .iter()
+                .map(|entry| entry.position())
+                .collect();
+            // Edges are followed in both directions, but only within the domain.
+            let mut neighbors: HashMap<_, Vec<_>> = HashMap::new();
+            for entry in &domain_entries {
+                for parent_pos in entry.parent_positions() {
+                    if domain_positions.contains(&parent_pos) {
+                        neighbors
+                            .entry(entry.position())
+                            .or_default()
+                            .push(parent_pos);
+                        neighbors
+                            .entry(parent_pos)
+                            .or_default()
+                            .push(entry.position());
+                    }
+                }
+            }
+            let mut reachable: HashSet<_> = domain_positions
+                .intersection(&source_positions)
+                .copied()
+                .collect();
+            let mut work = reachable.iter().copied().collect_vec();
+            while let Some(pos) = work.pop() {
+                for &neighbor in neighbors.get(&pos).into_iter().flatten() {
+                    if reachable.insert(neighbor) {
+                        work.push(neighbor);
+                    }
+                }
+            }
+            let index_entries = domain_entries
+                .into_iter()
+                .filter(|entry| reachable.contains(&entry.position()))
+                .collect();
+            Ok(RevsetImpl::new(Box::new(EagerRevset { index_entries })))
+        }
+        RevsetExpression::VisibleHeads => Ok(revset_for_commit_ids(repo, repo.visible_heads())),
+        RevsetExpression::AllHeads => Ok(revset_for_commit_ids(repo, &repo.index().all_heads())),
         RevsetExpression::Heads(candidates) => {
             let candidate_set = evaluate_impl(repo, candidates, workspace_ctx)?;
             let candidate_ids = candidate_set.iter().commit_ids().collect_vec();
@@ -646,10 +829,7 @@ fn evaluate_impl<'index>(
             }
             Ok(RevsetImpl::new(Box::new(EagerRevset { index_entries })))
         }
-        RevsetExpression::PublicHeads => Ok(revset_for_commit_ids(
-            repo,
-            &repo.view().public_heads().iter().cloned().collect_vec(),
-        )),
+        RevsetExpression::PublicHeads => Ok(revset_for_commit_ids(repo, repo.public_heads())),
         RevsetExpression::Branches(needle) => {
             let mut commit_ids = vec![];
             for (branch_name, branch_target) in repo.view().branches() {
@@ -663,12 +843,12 @@ fn evaluate_impl<'index>(
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
         RevsetExpression::RemoteBranches {
-            branch_needle,
+            branch_pattern,
             remote_needle,
         } => {
             let mut commit_ids = vec![];
             for (branch_name, branch_target) in repo.view().branches() {
-                if !branch_name.contains(branch_needle) {
+                if !branch_pattern.matches(branch_name) {
                     continue;
                 }
                 for (remote_name, remote_target) in branch_target.remote_targets.iter() {
@@ -679,6 +859,18 @@ fn evaluate_impl<'index>(
             }
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
+        RevsetExpression::BranchesAhead => Ok(revset_for_commit_ids(
+            repo,
+            &local_branch_targets_by_sync_state(repo, |local_id, remote_id| {
+                repo.index().is_ancestor(remote_id, local_id)
+            }),
+        )),
+        RevsetExpression::BranchesBehind => Ok(revset_for_commit_ids(
+            repo,
+            &local_branch_targets_by_sync_state(repo, |local_id, remote_id| {
+                repo.index().is_ancestor(local_id, remote_id)
+            }),
+        )),
         RevsetExpression::Tags => {
             let mut commit_ids = vec![];
             for ref_target in repo.view().tags().values() {
@@ -700,9 +892,73 @@ fn evaluate_impl<'index>(
             }
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
+        RevsetExpression::Evolog(candidates) => {
+            let candidate_set = evaluate_impl(repo, candidates, workspace_ctx)?;
+            let mut visited: HashSet<_> = candidate_set.iter().commit_ids().collect();
+            let mut work = visited.iter().cloned().collect_vec();
+            while let Some(commit_id) = work.pop() {
+                let commit = repo
+                    .store()
+                    .get_commit(&commit_id)
+                    .map_err(RevsetError::StoreError)?;
+                for predecessor_id in commit.predecessor_ids() {
+                    // Predecessors may have been discarded, e.g. by a git gc
+                    if repo.index().has_id(predecessor_id) && visited.insert(predecessor_id.clone())
+                    {
+                        work.push(predecessor_id.clone());
+                    }
+                }
+            }
+            Ok(revset_for_commit_ids(
+                repo,
+                &visited.into_iter().collect_vec(),
+            ))
+        }
+        RevsetExpression::Obsolete => {
+            let index = repo.index();
+            let mut obsolete_ids = HashSet::new();
+            for entry in index.walk_revs(&index.all_heads(), &[]) {
+                let commit = repo
+                    .store()
+                    .get_commit(&entry.commit_id())
+                    .map_err(RevsetError::StoreError)?;
+                obsolete_ids.extend(
+                    commit
+                        .predecessor_ids()
+                        .iter()
+                        .filter(|id| index.has_id(id))
+                        .cloned(),
+                );
+            }
+            Ok(revset_for_commit_ids(
+                repo,
+                &obsolete_ids.into_iter().collect_vec(),
+            ))
+        }
+        RevsetExpression::OpAffected(op_str) => {
+            let op = resolve_operation(repo, op_str.as_deref())?;
+            // Commits written by an operation have been indexed by the time it's
+            // committed, but views of other operations may point to commits
+            // missing from this repo's index.
+            let indexed_heads = |view: &operation::View| {
+                view.heads()
+                    .iter()
+                    .filter(|id| repo.index().has_id(id))
+                    .cloned()
+                    .collect_vec()
+            };
+            let parent_heads = op
+                .parents()
+                .iter()
+                .flat_map(|parent| indexed_heads(&parent.view()))
+                .collect_vec();
+            let expression = RevsetExpression::commits(parent_heads)
+                .range(&RevsetExpression::commits(indexed_heads(&op.view())));
+            evaluate_impl(repo, &expression, workspace_ctx)
+        }
         RevsetExpression::Filter(predicate) => Ok(RevsetImpl::new(Box::new(FilterRevset {
             candidates: evaluate_impl(repo, &RevsetExpression::All, workspace_ctx)?,
-            predicate: build_predicate_fn(repo, predicate),
+            predicate: build_predicate_fn(repo, predicate, workspace_ctx)?,
         }))),
         RevsetExpression::AsFilter(candidates) => evaluate_impl(repo, candidates, workspace_ctx),
         RevsetExpression::Present(candidates) => {
@@ -711,7 +967,16 @@ fn evaluate_impl<'index>(
                 Err(RevsetError::NoSuchRevision(_)) => {
                     Ok(RevsetImpl::new(Box::new(EagerRevset::empty())))
                 }
-                r @ Err(RevsetError::AmbiguousIdPrefix(_) | RevsetError::StoreError(_)) => r,
+                r @ Err(
+                    RevsetError::AmbiguousIdPrefix(_)
+                    | RevsetError::AmbiguousBranchName(_)
+                    | RevsetError::StoreError(_)
+                    | RevsetError::FsPathParseError(_)
+                    | RevsetError::FsPathWithoutWorkspace
+                    | RevsetError::NoSuchOperation(_)
+                    | RevsetError::AmbiguousOperationIdPrefix(_)
+                    | RevsetError::OpStoreError(_),
+                ) => r,
             }
         }
         RevsetExpression::NotIn(complement) => {
@@ -729,7 +994,7 @@ fn evaluate_impl<'index>(
                 RevsetExpression::Filter(predicate) => {
                     Ok(RevsetImpl::new(Box::new(FilterRevset {
                         candidates: evaluate_impl(repo, expression1, workspace_ctx)?,
-                        predicate: build_predicate_fn(repo, predicate),
+                        predicate: build_predicate_fn(repo, predicate, workspace_ctx)?,
                     })))
                 }
                 RevsetExpression::AsFilter(expression2) => {
@@ -755,9 +1020,9 @@ fn evaluate_impl<'index>(
     }
 }
 
-fn revset_for_commit_ids<'index>(
+fn revset_for_commit_ids<'index, 'a>(
     repo: &'index dyn Repo,
-    commit_ids: &[CommitId],
+    commit_ids: impl IntoIterator<Item = &'a CommitId>,
 ) -> RevsetImpl<'index> {
     let index = repo.index();
     let mut index_entries = vec![];
@@ -790,42 +1055,70 @@ impl<'index> ToPredicateFn<'index> for PurePredicateFn<'index> {
     }
 }
 
+/// Collects the targets of local branches that differ from at least one of their
+/// remote targets and are related to it as determined by `is_in_state`.
+/// Conflicted local or remote targets are skipped.
+fn local_branch_targets_by_sync_state(
+    repo: &dyn Repo,
+    is_in_state: impl Fn(&CommitId, &CommitId) -> bool,
+) -> Vec<CommitId> {
+    let mut commit_ids = vec![];
+    for branch_target in repo.view().branches().values() {
+        let local_id = match &branch_target.local_target {
+            Some(RefTarget::Normal(id)) => id,
+            _ => continue,
+        };
+        let matches =
+            branch_target
+                .remote_targets
+                .values()
+                .any(|remote_target| match remote_target {
+                    RefTarget::Normal(remote_id) => {
+                        remote_id != local_id && is_in_state(local_id, remote_id)
+                    }
+                    RefTarget::Conflict { .. } => false,
+                });
+        if matches {
+            commit_ids.push(local_id.clone());
+        }
+    }
+    commit_ids
+}
+
 fn build_predicate_fn<'index>(
     repo: &'index dyn Repo,
     predicate: &RevsetFilterPredicate,
-) -> PurePredicateFn<'index> {
-    match predicate {
+    workspace_ctx: Option<&RevsetWorkspaceContext>,
+) -> Result<PurePredicateFn<'index>, RevsetError> {
+    let predicate_fn: PurePredicateFn = match predicate {
         RevsetFilterPredicate::ParentCount(parent_count_range) => {
             let parent_count_range = parent_count_range.clone();
             Box::new(move |entry| parent_count_range.contains(&entry.num_parents()))
         }
-        RevsetFilterPredicate::Description(needle) => {
-            let needle = needle.clone();
+        RevsetFilterPredicate::Description(pattern) => {
+            let pattern = pattern.clone();
             Box::new(move |entry| {
-                repo.store()
-                    .get_commit(&entry.commit_id())
-                    .unwrap()
-                    .description()
-                    .contains(needle.as_str())
+                pattern.matches(
+                    repo.store()
+                        .get_commit(&entry.commit_id())
+                        .unwrap()
+                        .description(),
+                )
             })
         }
-        RevsetFilterPredicate::Author(needle) => {
-            let needle = needle.clone();
-            // TODO: Make these functions that take a needle to search for accept some
-            // syntax for specifying whether it's a regex and whether it's
-            // case-sensitive.
+        RevsetFilterPredicate::Author(pattern) => {
+            let pattern = pattern.clone();
             Box::new(move |entry| {
                 let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
-                commit.author().name.contains(needle.as_str())
-                    || commit.author().email.contains(needle.as_str())
+                pattern.matches(&commit.author().name) || pattern.matches(&commit.author().email)
             })
         }
-        RevsetFilterPredicate::Committer(needle) => {
-            let needle = needle.clone();
+        RevsetFilterPredicate::Committer(pattern) => {
+            let pattern = pattern.clone();
             Box::new(move |entry| {
                 let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
-                commit.committer().name.contains(needle.as_str())
-                    || commit.committer().email.contains(needle.as_str())
+                pattern.matches(&commit.committer().name)
+                    || pattern.matches(&commit.committer().email)
             })
         }
         RevsetFilterPredicate::File(paths) => {
@@ -837,17 +1130,148 @@ fn build_predicate_fn<'index>(
             };
             Box::new(move |entry| has_diff_from_parent(repo, entry, matcher.as_ref()))
         }
-    }
+        RevsetFilterPredicate::UnresolvedFile(patterns) => {
+            let paths = resolve_file_patterns(patterns, workspace_ctx)?;
+            let matcher = PrefixMatcher::new(&paths);
+            Box::new(move |entry| has_diff_from_parent(repo, entry, &matcher))
+        }
+        RevsetFilterPredicate::ExactFile(paths) => {
+            let matcher = FilesMatcher::new(paths);
+            Box::new(move |entry| has_diff_from_parent(repo, entry, &matcher))
+        }
+        RevsetFilterPredicate::UnresolvedExactFile(patterns) => {
+            let paths = resolve_file_patterns(patterns, workspace_ctx)?;
+            let matcher = FilesMatcher::new(&paths);
+            Box::new(move |entry| has_diff_from_parent(repo, entry, &matcher))
+        }
+        RevsetFilterPredicate::FileContent(file_id) => {
+            let file_id = file_id.clone();
+            Box::new(move |entry| {
+                let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
+                commit
+                    .tree()
+                    .entries()
+                    .any(|(_, value)| matches!(value, TreeValue::File { id, .. } if id == file_id))
+            })
+        }
+        RevsetFilterPredicate::Churn(line_count_range) => {
+            let line_count_range = line_count_range.clone();
+            Box::new(move |entry| line_count_range.contains(&count_changed_lines(repo, entry)))
+        }
+        RevsetFilterPredicate::HasConflict => Box::new(move |entry| {
+            let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
+            commit.tree().has_conflict()
+        }),
+        RevsetFilterPredicate::Divergent => {
+            let divergent_commit_ids: HashSet<CommitId> = repo
+                .divergent_change_ids()
+                .into_values()
+                .flatten()
+                .collect();
+            Box::new(move |entry| divergent_commit_ids.contains(&entry.commit_id()))
+        }
+    };
+    Ok(predicate_fn)
+}
+
+fn resolve_file_patterns(
+    patterns: &[String],
+    workspace_ctx: Option<&RevsetWorkspaceContext>,
+) -> Result<Vec<RepoPath>, RevsetError> {
+    let ctx = workspace_ctx.ok_or(RevsetError::FsPathWithoutWorkspace)?;
+    patterns
+        .iter()
+        .map(|pattern| RepoPath::parse_fs_path(ctx.cwd, ctx.workspace_root, pattern))
+        .try_collect()
+        .map_err(RevsetError::FsPathParseError)
 }
 
 fn has_diff_from_parent(repo: &dyn Repo, entry: &IndexEntry<'_>, matcher: &dyn Matcher) -> bool {
     let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
     let parents = commit.parents();
+    let from_tree_id = rewrite::merge_commit_tree_id(repo, &parents);
+    if &from_tree_id == commit.tree_id() {
+        return false;
+    }
     let from_tree = rewrite::merge_commit_trees(repo, &parents);
     let to_tree = commit.tree();
     from_tree.diff(&to_tree, matcher).next().is_some()
 }
 
+/// Counts the lines added or removed by the commit compared to its first
+/// parent, going through the paths in its diff summary. Merges are compared
+/// to their first parent rather than to the merged parents, so they count the
+/// lines merged in from the other parents. Symlinks count as their target,
+/// conflicts as their materialized contents, and submodules as their commit
+/// id. This reads the contents of all changed files, so it's expensive.
+fn count_changed_lines(repo: &dyn Repo, entry: &IndexEntry<'_>) -> u32 {
+    let store = repo.store();
+    let commit = store.get_commit(&entry.commit_id()).unwrap();
+    let from_tree = match commit.parent_ids().first() {
+        Some(parent_id) => store.get_commit(parent_id).unwrap().tree(),
+        None => store
+            .get_tree(&RepoPath::root(), store.empty_tree_id())
+            .unwrap(),
+    };
+    if from_tree.id() == commit.tree_id() {
+        return 0;
+    }
+    let to_tree = commit.tree();
+    let read_content = |path: &RepoPath, value: Option<TreeValue>| -> Vec<u8> {
+        let mut content = vec![];
+        match value {
+            None | Some(TreeValue::Tree(_)) => {}
+            Some(TreeValue::File { id, .. }) => {
+                store
+                    .read_file(path, &id)
+                    .unwrap()
+                    .read_to_end(&mut content)
+                    .unwrap();
+            }
+            Some(TreeValue::Symlink(id)) => {
+                content = store.read_symlink(path, &id).unwrap().into_bytes();
+                content.push(b'\n');
+            }
+            Some(TreeValue::GitSubmodule(id)) => {
+                content = format!("{}\n", id.hex()).into_bytes();
+            }
+            Some(TreeValue::Conflict(id)) => {
+                let conflict = store.read_conflict(path, &id).unwrap();
+                conflicts::materialize_conflict(
+                    store,
+                    path,
+                    &conflict,
+                    MaterializeStyle::default(),
+                    &mut content,
+                )
+                .unwrap();
+            }
+        }
+        content
+    };
+    let summary = from_tree.diff_summary(&to_tree, &EverythingMatcher);
+    let mut count = 0;
+    for path in &summary.added {
+        count += find_line_ranges(&read_content(path, to_tree.path_value(path))).len();
+    }
+    for path in &summary.removed {
+        count += find_line_ranges(&read_content(path, from_tree.path_value(path))).len();
+    }
+    for path in &summary.modified {
+        let left = read_content(path, from_tree.path_value(path));
+        let right = read_content(path, to_tree.path_value(path));
+        let line_diff = Diff::for_tokenizer(&[&left, &right], &find_line_ranges);
+        for hunk in line_diff.hunks() {
+            if let DiffHunk::Different(contents) = hunk {
+                for content in contents {
+                    count += find_line_ranges(content).len();
+                }
+            }
+        }
+    }
+    count.try_into().unwrap_or(u32::MAX)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -626,6 +626,218 @@ pub fn diff<'a>(left: &'a [u8], right: &'a [u8]) -> Vec<DiffHunk<'a>> {
         .collect_vec()
 }
 
+/// A hunk of a word-level diff, as computed by `diff_words()`. The ranges are
+/// byte ranges into the left and right inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WordDiffHunk {
+    /// The inputs have the same content in the ranges.
+    Matching {
+        left: Range<usize>,
+        right: Range<usize>,
+    },
+    /// The inputs differ in the ranges. One of the ranges may be empty if
+    /// content was only added or only removed.
+    Different {
+        left: Range<usize>,
+        right: Range<usize>,
+    },
+}
+
+/// Diffs two slices of bytes word by word, without diffing lines first. This
+/// is meant for highlighting the changes within a pair of changed lines.
+///
+/// Words are diffed first, and the changed regions are then refined to match
+/// unchanged whitespace and punctuation. The returned hunks cover both inputs
+/// in order and alternate between matching and different ones.
+pub fn diff_words(left: &[u8], right: &[u8]) -> Vec<WordDiffHunk> {
+    let mut diff = Diff::for_tokenizer(&[left, right], &find_word_ranges);
+    diff.refine_changed_regions(&find_nonword_ranges);
+    let mut left_pos = 0;
+    let mut right_pos = 0;
+    diff.hunks()
+        .map(|hunk| match hunk {
+            DiffHunk::Matching(content) => {
+                let left_range = left_pos..left_pos + content.len();
+                let right_range = right_pos..right_pos + content.len();
+                left_pos = left_range.end;
+                right_pos = right_range.end;
+                WordDiffHunk::Matching {
+                    left: left_range,
+                    right: right_range,
+                }
+            }
+            DiffHunk::Different(contents) => {
+                let left_range = left_pos..left_pos + contents[0].len();
+                let right_range = right_pos..right_pos + contents[1].len();
+                left_pos = left_range.end;
+                right_pos = right_range.end;
+                WordDiffHunk::Different {
+                    left: left_range,
+                    right: right_range,
+                }
+            }
+        })
+        .collect()
+}
+
+/// The type of a line in a `UnifiedDiffHunk`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffLineType {
+    Context,
+    Removed,
+    Added,
+}
+
+/// A hunk of a line-based diff with surrounding context lines, as shown in the
+/// unified diff format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnifiedDiffHunk<'content> {
+    /// 1-based line numbers of the hunk in the left input.
+    pub left_line_range: Range<usize>,
+    /// 1-based line numbers of the hunk in the right input.
+    pub right_line_range: Range<usize>,
+    /// The lines of the hunk, including their line terminators.
+    pub lines: Vec<(DiffLineType, &'content [u8])>,
+}
+
+/// Diffs two inputs line by line, and groups the changed lines into hunks with
+/// up to `num_context_lines` unchanged lines before and after each change.
+/// Changes separated by at most twice that many unchanged lines end up in the
+/// same hunk.
+pub fn unified_diff_hunks<'content>(
+    left_content: &'content [u8],
+    right_content: &'content [u8],
+    num_context_lines: usize,
+) -> Vec<UnifiedDiffHunk<'content>> {
+    let mut hunks = vec![];
+    let mut current_hunk = UnifiedDiffHunk {
+        left_line_range: 1..1,
+        right_line_range: 1..1,
+        lines: vec![],
+    };
+    let mut show_context_after = false;
+    let diff = Diff::for_tokenizer(&[left_content, right_content], &find_line_ranges);
+    for hunk in diff.hunks() {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                let lines = content.split_inclusive(|b| *b == b'\n').collect_vec();
+                // Number of context lines to print after the previous non-matching hunk.
+                let num_after_lines = lines.len().min(if show_context_after {
+                    num_context_lines
+                } else {
+                    0
+                });
+                current_hunk.left_line_range.end += num_after_lines;
+                current_hunk.right_line_range.end += num_after_lines;
+                for line in lines.iter().take(num_after_lines) {
+                    current_hunk.lines.push((DiffLineType::Context, line));
+                }
+                let num_skip_lines = lines
+                    .len()
+                    .saturating_sub(num_after_lines)
+                    .saturating_sub(num_context_lines);
+                if num_skip_lines > 0 {
+                    let left_start = current_hunk.left_line_range.end + num_skip_lines;
+                    let right_start = current_hunk.right_line_range.end + num_skip_lines;
+                    if !current_hunk.lines.is_empty() {
+                        hunks.push(current_hunk);
+                    }
+                    current_hunk = UnifiedDiffHunk {
+                        left_line_range: left_start..left_start,
+                        right_line_range: right_start..right_start,
+                        lines: vec![],
+                    };
+                }
+                let num_before_lines = lines.len() - num_after_lines - num_skip_lines;
+                current_hunk.left_line_range.end += num_before_lines;
+                current_hunk.right_line_range.end += num_before_lines;
+                for line in lines.iter().skip(num_after_lines + num_skip_lines) {
+                    current_hunk.lines.push((DiffLineType::Context, line));
+                }
+            }
+            DiffHunk::Different(content) => {
+                show_context_after = true;
+                let left_lines = content[0].split_inclusive(|b| *b == b'\n').collect_vec();
+                let right_lines = content[1].split_inclusive(|b| *b == b'\n').collect_vec();
+                if !left_lines.is_empty() {
+                    current_hunk.left_line_range.end += left_lines.len();
+                    for line in left_lines {
+                        current_hunk.lines.push((DiffLineType::Removed, line));
+                    }
+                }
+                if !right_lines.is_empty() {
+                    current_hunk.right_line_range.end += right_lines.len();
+                    for line in right_lines {
+                        current_hunk.lines.push((DiffLineType::Added, line));
+                    }
+                }
+            }
+        }
+    }
+    if !current_hunk
+        .lines
+        .iter()
+        .all(|(diff_type, _line)| *diff_type == DiffLineType::Context)
+    {
+        hunks.push(current_hunk);
+    }
+    hunks
+}
+
+/// Number of bytes at the start of the content that `is_probably_binary()`
+/// inspects.
+const BINARY_DETECTION_LENGTH: usize = 8000;
+
+/// Guesses whether the content is binary (as opposed to text), by looking at
+/// its first few thousand bytes. Content containing a NUL byte is considered
+/// binary, as is content where more than 10% of the bytes are control
+/// characters other than the usual whitespace and escape characters. Non-ASCII
+/// bytes are not counted, so UTF-8 text is never considered binary.
+pub fn is_probably_binary(content: &[u8]) -> bool {
+    let content = &content[..min(content.len(), BINARY_DETECTION_LENGTH)];
+    if content.contains(&b'\0') {
+        return true;
+    }
+    let num_non_text_bytes = content
+        .iter()
+        .filter(|&&b| {
+            (b < b' ' && !matches!(b, b'\t' | b'\n' | b'\r' | b'\x0c' | b'\x1b')) || b == b'\x7f'
+        })
+        .count();
+    num_non_text_bytes * 10 > content.len()
+}
+
+/// The result of `unified_diff_content()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffContent<'content> {
+    /// The inputs differ and at least one of them is binary, so no line-based
+    /// diff was computed.
+    Binary,
+    /// The line-based diff of the inputs. Empty if the inputs are equal.
+    Text(Vec<UnifiedDiffHunk<'content>>),
+}
+
+/// Like `unified_diff_hunks()`, but returns `DiffContent::Binary` instead of
+/// the hunks if the inputs differ and either of them is binary according to
+/// `is_probably_binary()`.
+pub fn unified_diff_content<'content>(
+    left_content: &'content [u8],
+    right_content: &'content [u8],
+    num_context_lines: usize,
+) -> DiffContent<'content> {
+    if left_content != right_content
+        && (is_probably_binary(left_content) || is_probably_binary(right_content))
+    {
+        DiffContent::Binary
+    } else {
+        DiffContent::Text(unified_diff_hunks(
+            left_content,
+            right_content,
+            num_context_lines,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1168,4 +1380,242 @@ int main(int argc, char **argv)
             ]
         );
     }
+
+    #[test]
+    fn test_diff_words_empty() {
+        assert_eq!(diff_words(b"", b""), vec![]);
+        assert_eq!(
+            diff_words(b"", b"foo"),
+            vec![WordDiffHunk::Different {
+                left: 0..0,
+                right: 0..3
+            }]
+        );
+        assert_eq!(
+            diff_words(b"foo bar", b"foo bar"),
+            vec![WordDiffHunk::Matching {
+                left: 0..7,
+                right: 0..7
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_words_changed_word() {
+        let left = b"let foo = bar(baz);";
+        let right = b"let foo = qux(baz);";
+        assert_eq!(
+            diff_words(left, right),
+            vec![
+                WordDiffHunk::Matching {
+                    left: 0..10,
+                    right: 0..10
+                },
+                WordDiffHunk::Different {
+                    left: 10..13,
+                    right: 10..13
+                },
+                WordDiffHunk::Matching {
+                    left: 13..19,
+                    right: 13..19
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_words_whitespace_only() {
+        // Added and removed whitespace between unchanged words
+        assert_eq!(
+            diff_words(b"foo bar", b"foo  bar"),
+            vec![
+                WordDiffHunk::Matching {
+                    left: 0..4,
+                    right: 0..4
+                },
+                WordDiffHunk::Different {
+                    left: 4..4,
+                    right: 4..5
+                },
+                WordDiffHunk::Matching {
+                    left: 4..7,
+                    right: 5..8
+                },
+            ]
+        );
+        assert_eq!(
+            diff_words(b"foo\tbar\n", b"foo bar\n"),
+            vec![
+                WordDiffHunk::Matching {
+                    left: 0..3,
+                    right: 0..3
+                },
+                WordDiffHunk::Different {
+                    left: 3..4,
+                    right: 3..4
+                },
+                WordDiffHunk::Matching {
+                    left: 4..8,
+                    right: 4..8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_words_token_boundaries() {
+        // Words are compared as a whole, not by common prefix
+        assert_eq!(
+            diff_words(b"foo_bar", b"foo_baz"),
+            vec![WordDiffHunk::Different {
+                left: 0..7,
+                right: 0..7
+            }]
+        );
+        // Punctuation splits words
+        assert_eq!(
+            diff_words(b"foo.bar", b"foo.baz"),
+            vec![
+                WordDiffHunk::Matching {
+                    left: 0..4,
+                    right: 0..4
+                },
+                WordDiffHunk::Different {
+                    left: 4..7,
+                    right: 4..7
+                },
+            ]
+        );
+        // Words at the start and the end of the input
+        assert_eq!(
+            diff_words(b"foo bar", b"bar baz"),
+            vec![
+                WordDiffHunk::Different {
+                    left: 0..4,
+                    right: 0..0
+                },
+                WordDiffHunk::Matching {
+                    left: 4..7,
+                    right: 0..3
+                },
+                WordDiffHunk::Different {
+                    left: 7..7,
+                    right: 3..7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_hunks_no_context() {
+        let left = b"a\nb\nc\nd\ne\n";
+        let right = b"a\nB\nc\nd\nE\n";
+        assert_eq!(
+            unified_diff_hunks(left, right, 0),
+            vec![
+                UnifiedDiffHunk {
+                    left_line_range: 2..3,
+                    right_line_range: 2..3,
+                    lines: vec![
+                        (DiffLineType::Removed, b"b\n".as_slice()),
+                        (DiffLineType::Added, b"B\n".as_slice()),
+                    ],
+                },
+                UnifiedDiffHunk {
+                    left_line_range: 5..6,
+                    right_line_range: 5..6,
+                    lines: vec![
+                        (DiffLineType::Removed, b"e\n".as_slice()),
+                        (DiffLineType::Added, b"E\n".as_slice()),
+                    ],
+                },
+            ]
+        );
+        assert_eq!(unified_diff_hunks(left, left, 0), vec![]);
+    }
+
+    #[test]
+    fn test_unified_diff_hunks_context() {
+        let left = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let right = b"1\nx\n3\n4\n5\n6\n7\ny\n9\n";
+        // Changes separated by more than twice the context are separate hunks
+        let hunks = unified_diff_hunks(left, right, 1);
+        assert_eq!(
+            hunks
+                .iter()
+                .map(|hunk| (hunk.left_line_range.clone(), hunk.right_line_range.clone()))
+                .collect_vec(),
+            vec![(1..4, 1..4), (7..10, 7..10)]
+        );
+        assert_eq!(
+            hunks[0].lines,
+            vec![
+                (DiffLineType::Context, b"1\n".as_slice()),
+                (DiffLineType::Removed, b"2\n".as_slice()),
+                (DiffLineType::Added, b"x\n".as_slice()),
+                (DiffLineType::Context, b"3\n".as_slice()),
+            ]
+        );
+        // A large context merges everything into one hunk covering all lines
+        let hunks = unified_diff_hunks(left, right, 100);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].left_line_range, 1..10);
+        assert_eq!(hunks[0].right_line_range, 1..10);
+        assert_eq!(
+            hunks[0]
+                .lines
+                .iter()
+                .filter(|(line_type, _)| *line_type == DiffLineType::Context)
+                .count(),
+            7
+        );
+    }
+
+    #[test]
+    fn test_is_probably_binary() {
+        assert!(!is_probably_binary(b""));
+        assert!(!is_probably_binary(b"foo\nbar\n"));
+        assert!(!is_probably_binary(b"tab\tcr\r\nform feed\x0c\x1b[0m\n"));
+        assert!(is_probably_binary(b"foo\0bar\n"));
+        assert!(is_probably_binary(b"\0"));
+        // A NUL byte beyond the inspected prefix is not detected
+        let mut content = vec![b'a'; BINARY_DETECTION_LENGTH];
+        content.push(b'\0');
+        assert!(!is_probably_binary(&content));
+    }
+
+    #[test]
+    fn test_is_probably_binary_threshold() {
+        // Non-ASCII UTF-8 bytes don't count as binary
+        assert!(!is_probably_binary(
+            "日本語のテキスト\n".repeat(100).as_bytes()
+        ));
+        // Exactly 10% control characters is still text
+        let mut content = "abcdefghi\x01".repeat(10).into_bytes();
+        assert!(std::str::from_utf8(&content).is_ok());
+        assert!(!is_probably_binary(&content));
+        // One more makes it binary
+        content[0] = b'\x02';
+        assert!(is_probably_binary(&content));
+    }
+
+    #[test]
+    fn test_unified_diff_content() {
+        assert_eq!(
+            unified_diff_content(b"a\n", b"a\n", 3),
+            DiffContent::Text(vec![])
+        );
+        assert_eq!(
+            unified_diff_content(b"a\n", b"b\n", 3),
+            DiffContent::Text(unified_diff_hunks(b"a\n", b"b\n", 3))
+        );
+        assert_eq!(unified_diff_content(b"a\0", b"b\n", 3), DiffContent::Binary);
+        assert_eq!(unified_diff_content(b"a\n", b"b\0", 3), DiffContent::Binary);
+        assert_eq!(unified_diff_content(b"", b"\0", 3), DiffContent::Binary);
+        // Equal binary content has no diff
+        assert_eq!(
+            unified_diff_content(b"a\0", b"a\0", 3),
+            DiffContent::Text(vec![])
+        );
+    }
 }
@@ -92,6 +92,72 @@ impl GitBackend {
         let extra_metadata_store = TableStore::load(store_path.join("extra"), HASH_LENGTH);
         GitBackend::new(repo, extra_metadata_store)
     }
+
+    fn read_extra_metadata(&self) -> BackendResult<Arc<ReadonlyTable>> {
+        let mut locked_head = self.cached_extra_metadata.lock().unwrap();
+        match locked_head.as_ref() {
+            Some(head) => Ok(head.clone()),
+            None => self.extra_metadata_store.get_head().map(|x| {
+                *locked_head = Some(x.clone());
+                x
+            }),
+        }
+        .map_err(|err| BackendError::Other(format!("Failed to read non-git metadata: {err}")))
+    }
+
+    /// Reads a non-root commit given the locked repo and the extra metadata.
+    fn read_commit_from_repo(
+        &self,
+        locked_repo: &git2::Repository,
+        table: &ReadonlyTable,
+        id: &CommitId,
+    ) -> BackendResult<Commit> {
+        let git_commit_id = validate_git_object_id(id)?;
+        let commit = locked_repo
+            .find_commit(git_commit_id)
+            .map_err(|err| map_not_found_err(err, id))?;
+        // We reverse the bits of the commit id to create the change id. We don't want
+        // to use the first bytes unmodified because then it would be ambiguous
+        // if a given hash prefix refers to the commit id or the change id. It
+        // would have been enough to pick the last 16 bytes instead of the
+        // leading 16 bytes to address that. We also reverse the bits to make it less
+        // likely that users depend on any relationship between the two ids.
+        let change_id = ChangeId::new(
+            id.as_bytes()[4..HASH_LENGTH]
+                .iter()
+                .rev()
+                .map(|b| b.reverse_bits())
+                .collect(),
+        );
+        let mut parents = commit
+            .parent_ids()
+            .map(|oid| CommitId::from_bytes(oid.as_bytes()))
+            .collect_vec();
+        if parents.is_empty() {
+            parents.push(self.root_commit_id.clone());
+        };
+        let tree_id = TreeId::from_bytes(commit.tree_id().as_bytes());
+        let description = commit.message().unwrap_or("<no message>").to_owned();
+        let author = signature_from_git(commit.author());
+        let committer = signature_from_git(commit.committer());
+
+        let mut commit = Commit {
+            parents,
+            predecessors: vec![],
+            root_tree: tree_id,
+            change_id,
+            description,
+            author,
+            committer,
+        };
+
+        let maybe_extras = table.get_value(git_commit_id.as_bytes());
+        if let Some(extras) = maybe_extras {
+            deserialize_extras(&mut commit, extras);
+        }
+
+        Ok(commit)
+    }
 }
 
 fn signature_from_git(signature: git2::Signature) -> Signature {
@@ -399,64 +465,27 @@ impl Backend for GitBackend {
                 self.empty_tree_id.clone(),
             ));
         }
-        let git_commit_id = validate_git_object_id(id)?;
-
         let locked_repo = self.repo.lock().unwrap();
-        let commit = locked_repo
-            .find_commit(git_commit_id)
-            .map_err(|err| map_not_found_err(err, id))?;
-        // We reverse the bits of the commit id to create the change id. We don't want
-        // to use the first bytes unmodified because then it would be ambiguous
-        // if a given hash prefix refers to the commit id or the change id. It
-        // would have been enough to pick the last 16 bytes instead of the
-        // leading 16 bytes to address that. We also reverse the bits to make it less
-        // likely that users depend on any relationship between the two ids.
-        let change_id = ChangeId::new(
-            id.as_bytes()[4..HASH_LENGTH]
-                .iter()
-                .rev()
-                .map(|b| b.reverse_bits())
-                .collect(),
-        );
-        let mut parents = commit
-            .parent_ids()
-            .map(|oid| CommitId::from_bytes(oid.as_bytes()))
-            .collect_vec();
-        if parents.is_empty() {
-            parents.push(self.root_commit_id.clone());
-        };
-        let tree_id = TreeId::from_bytes(commit.tree_id().as_bytes());
-        let description = commit.message().unwrap_or("<no message>").to_owned();
-        let author = signature_from_git(commit.author());
-        let committer = signature_from_git(commit.committer());
-
-        let mut commit = Commit {
-            parents,
-            predecessors: vec![],
-            root_tree: tree_id,
-            change_id,
-            description,
-            author,
-            committer,
-        };
-
-        let table = {
-            let mut locked_head = self.cached_extra_metadata.lock().unwrap();
-            match locked_head.as_ref() {
-                Some(head) => Ok(head.clone()),
-                None => self.extra_metadata_store.get_head().map(|x| {
-                    *locked_head = Some(x.clone());
-                    x
-                }),
-            }
-        }
-        .map_err(|err| BackendError::Other(format!("Failed to read non-git metadata: {err}")))?;
-        let maybe_extras = table.get_value(git_commit_id.as_bytes());
-        if let Some(extras) = maybe_extras {
-            deserialize_extras(&mut commit, extras);
-        }
+        let table = self.read_extra_metadata()?;
+        self.read_commit_from_repo(&locked_repo, &table, id)
+    }
 
-        Ok(commit)
+    fn read_commits(&self, ids: &[CommitId]) -> BackendResult<Vec<Commit>> {
+        // Lock the repo and look up the extra metadata only once for all commits
+        let locked_repo = self.repo.lock().unwrap();
+        let table = self.read_extra_metadata()?;
+        ids.iter()
+            .map(|id| {
+                if *id == self.root_commit_id {
+                    Ok(make_root_commit(
+                        self.root_change_id().clone(),
+                        self.empty_tree_id.clone(),
+                    ))
+                } else {
+                    self.read_commit_from_repo(&locked_repo, &table, id)
+                }
+            })
+            .collect()
     }
 
     fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId> {
@@ -853,6 +882,29 @@ mod tests {
         assert_ne!(store.write_commit(&commit2).unwrap(), commit_id1);
     }
 
+    #[test]
+    fn id_lengths() {
+        let temp_dir = testutils::new_temp_dir();
+        let store = GitBackend::init_internal(temp_dir.path());
+        assert_eq!(store.commit_id_length(), 20);
+        assert_eq!(store.change_id_length(), 16);
+
+        // Commit ids are git's SHA-1 hashes, so they're 40 hex digits
+        let commit = Commit {
+            parents: vec![store.root_commit_id().clone()],
+            predecessors: vec![],
+            root_tree: store.empty_tree_id().clone(),
+            change_id: ChangeId::new(vec![]),
+            description: "initial".to_string(),
+            author: create_signature(),
+            committer: create_signature(),
+        };
+        let commit_id = store.write_commit(&commit).unwrap();
+        assert_eq!(commit_id.as_bytes().len(), store.commit_id_length());
+        assert_eq!(commit_id.hex().len(), 40);
+        assert_eq!(store.root_commit_id().as_bytes().len(), 20);
+    }
+
     fn git_id(commit_id: &CommitId) -> Oid {
         Oid::from_bytes(commit_id.as_bytes()).unwrap()
     }
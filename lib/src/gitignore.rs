@@ -20,8 +20,11 @@ use std::sync::Arc;
 use itertools::Itertools;
 use regex::{escape as regex_escape, Regex};
 
+/// A single pattern from an ignore file.
 #[derive(Debug)]
-struct GitIgnoreLine {
+pub struct GitIgnoreLine {
+    prefix: String,
+    pattern: String,
     is_negative: bool,
     regex: Regex,
 }
@@ -53,6 +56,7 @@ impl GitIgnoreLine {
         }
 
         let input = GitIgnoreLine::remove_trailing_space(input);
+        let pattern = input.to_owned();
         // Remove leading "!" before checking for empty to match git's implementation
         // (i.e. just "!" matching nothing, not everything).
         let (is_negative, input) = match input.strip_prefix('!') {
@@ -137,7 +141,28 @@ impl GitIgnoreLine {
         }
         let regex = Regex::new(&regex).unwrap();
 
-        Some(GitIgnoreLine { is_negative, regex })
+        Some(GitIgnoreLine {
+            prefix: prefix.to_owned(),
+            pattern,
+            is_negative,
+            regex,
+        })
+    }
+
+    /// The directory of the ignore file the pattern came from, relative to the
+    /// workspace root. It's empty or ends with a "/".
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The pattern as written in the ignore file, including any leading "!".
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether the pattern is negated, i.e. un-ignores the paths it matches.
+    pub fn is_negative(&self) -> bool {
+        self.is_negative
     }
 
     fn matches(&self, path: &str) -> bool {
@@ -200,13 +225,22 @@ impl GitIgnoreFile {
     }
 
     pub fn matches_file(&self, path: &str) -> bool {
+        matches!(self.matched_pattern(path, false), Some(line) if !line.is_negative)
+    }
+
+    /// Returns the pattern deciding whether `path` is ignored, which is the
+    /// last matching one. The path is ignored if the pattern isn't negated.
+    /// Returns `None` if no pattern matches.
+    pub fn matched_pattern(&self, path: &str, is_dir: bool) -> Option<&GitIgnoreLine> {
+        let dir_path;
+        let path = if is_dir && !path.ends_with('/') {
+            dir_path = format!("{path}/");
+            &dir_path
+        } else {
+            path
+        };
         // Later lines take precedence, so check them in reverse
-        for line in self.all_lines_reversed() {
-            if line.matches(path) {
-                return !line.is_negative;
-            }
-        }
-        false
+        self.all_lines_reversed().find(|line| line.matches(path))
     }
 
     pub fn matches_all_files_in(&self, dir: &str) -> bool {
@@ -451,6 +485,68 @@ mod tests {
         assert!(!file3.matches_file("foo/bar/qux"));
     }
 
+    #[test]
+    fn test_gitignore_matched_pattern() {
+        let file1 = GitIgnoreFile::empty().chain("", b"*.log\n!keep.log\n");
+        let file2 = file1.chain("dir/", b"keep.log\n");
+        fn matched_pattern<'a>(
+            file: &'a GitIgnoreFile,
+            path: &str,
+            is_dir: bool,
+        ) -> Option<(&'a str, &'a str, bool)> {
+            file.matched_pattern(path, is_dir)
+                .map(|line| (line.prefix(), line.pattern(), line.is_negative()))
+        }
+        assert_eq!(
+            matched_pattern(&file1, "debug.log", false),
+            Some(("", "*.log", false))
+        );
+        assert_eq!(
+            matched_pattern(&file1, "keep.log", false),
+            Some(("", "!keep.log", true))
+        );
+        assert!(!file1.matches_file("keep.log"));
+        assert_eq!(matched_pattern(&file1, "keep.txt", false), None);
+        // A pattern in a nested ignore file overrides the parent's negation
+        assert_eq!(
+            matched_pattern(&file2, "dir/keep.log", false),
+            Some(("dir/", "keep.log", false))
+        );
+        assert_eq!(
+            matched_pattern(&file2, "other/keep.log", false),
+            Some(("", "!keep.log", true))
+        );
+        assert_eq!(
+            matched_pattern(&file2, "dir/debug.log", false),
+            Some(("", "*.log", false))
+        );
+    }
+
+    #[test]
+    fn test_gitignore_matched_pattern_dir() {
+        let file = GitIgnoreFile::empty().chain("", b"logs/\n");
+        assert_eq!(
+            file.matched_pattern("logs", false)
+                .map(|line| line.pattern()),
+            None
+        );
+        assert_eq!(
+            file.matched_pattern("logs", true)
+                .map(|line| line.pattern()),
+            Some("logs/")
+        );
+        assert_eq!(
+            file.matched_pattern("logs/", true)
+                .map(|line| line.pattern()),
+            Some("logs/")
+        );
+        assert_eq!(
+            file.matched_pattern("logs/debug", false)
+                .map(|line| line.pattern()),
+            Some("logs/")
+        );
+    }
+
     #[test]
     fn test_gitignore_match_dir() {
         assert!(matches_all_files_in(b"foo\n", "foo/"));
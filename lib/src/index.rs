@@ -60,6 +60,9 @@ pub trait Index {
 
     fn entry_by_pos(&self, pos: IndexPosition) -> IndexEntry;
 
+    /// Positions of the parents of the commit at `pos`, in parent order.
+    fn parent_positions(&self, pos: IndexPosition) -> Vec<IndexPosition>;
+
     fn has_id(&self, commit_id: &CommitId) -> bool;
 
     fn is_ancestor(&self, ancestor_id: &CommitId, descendant_id: &CommitId) -> bool;
@@ -70,6 +73,9 @@ pub trait Index {
 
     fn heads(&self, candidates: &mut dyn Iterator<Item = &CommitId>) -> Vec<CommitId>;
 
+    /// Heads of all commits in the index, including hidden ones.
+    fn all_heads(&self) -> Vec<CommitId>;
+
     /// Parents before children
     fn topo_order(&self, input: &mut dyn Iterator<Item = &CommitId>) -> Vec<IndexEntry>;
 
@@ -177,6 +183,16 @@ pub enum PrefixResolution<T> {
     AmbiguousMatch,
 }
 
+impl<T> PrefixResolution<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> PrefixResolution<U> {
+        match self {
+            PrefixResolution::NoMatch => PrefixResolution::NoMatch,
+            PrefixResolution::SingleMatch(x) => PrefixResolution::SingleMatch(f(x)),
+            PrefixResolution::AmbiguousMatch => PrefixResolution::AmbiguousMatch,
+        }
+    }
+}
+
 impl<T: Clone> PrefixResolution<T> {
     pub fn plus(&self, other: &PrefixResolution<T>) -> PrefixResolution<T> {
         match (self, other) {
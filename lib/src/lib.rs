@@ -18,6 +18,7 @@
 mod content_hash;
 
 pub mod backend;
+pub mod blame;
 pub mod commit;
 pub mod commit_builder;
 pub mod conflicts;
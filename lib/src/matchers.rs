@@ -256,6 +256,59 @@ impl Matcher for IntersectionMatcher<'_> {
     }
 }
 
+/// Matches paths that are matched by either of the input matchers.
+pub struct UnionMatcher<'input> {
+    input1: &'input dyn Matcher,
+    input2: &'input dyn Matcher,
+}
+
+impl<'input> UnionMatcher<'input> {
+    pub fn new(input1: &'input dyn Matcher, input2: &'input dyn Matcher) -> Self {
+        Self { input1, input2 }
+    }
+}
+
+impl Matcher for UnionMatcher<'_> {
+    fn matches(&self, file: &RepoPath) -> bool {
+        self.input1.matches(file) || self.input2.matches(file)
+    }
+
+    fn visit(&self, dir: &RepoPath) -> Visit {
+        match self.input1.visit(dir) {
+            Visit::AllRecursively => Visit::AllRecursively,
+            Visit::Nothing => self.input2.visit(dir),
+            Visit::Specific {
+                dirs: dirs1,
+                files: files1,
+            } => match self.input2.visit(dir) {
+                Visit::AllRecursively => Visit::AllRecursively,
+                Visit::Nothing => Visit::Specific {
+                    dirs: dirs1,
+                    files: files1,
+                },
+                Visit::Specific {
+                    dirs: dirs2,
+                    files: files2,
+                } => {
+                    let dirs = match (dirs1, dirs2) {
+                        (VisitDirs::All, _) | (_, VisitDirs::All) => VisitDirs::All,
+                        (VisitDirs::Set(dirs1), VisitDirs::Set(dirs2)) => {
+                            VisitDirs::Set(dirs1.union(&dirs2).cloned().collect())
+                        }
+                    };
+                    let files = match (files1, files2) {
+                        (VisitFiles::All, _) | (_, VisitFiles::All) => VisitFiles::All,
+                        (VisitFiles::Set(files1), VisitFiles::Set(files2)) => {
+                            VisitFiles::Set(files1.union(&files2).cloned().collect())
+                        }
+                    };
+                    Visit::Specific { dirs, files }
+                }
+            },
+        }
+    }
+}
+
 /// Keeps track of which subdirectories and files of each directory need to be
 /// visited.
 #[derive(PartialEq, Eq, Debug)]
@@ -712,4 +765,98 @@ mod tests {
             Visit::AllRecursively
         );
     }
+
+    #[test]
+    fn test_unionmatcher_concatenate_roots() {
+        let m1 = PrefixMatcher::new(&[
+            RepoPath::from_internal_string("foo"),
+            RepoPath::from_internal_string("bar"),
+        ]);
+        let m2 = PrefixMatcher::new(&[
+            RepoPath::from_internal_string("bar"),
+            RepoPath::from_internal_string("baz"),
+        ]);
+        let m = UnionMatcher::new(&m1, &m2);
+
+        assert!(m.matches(&RepoPath::from_internal_string("foo")));
+        assert!(m.matches(&RepoPath::from_internal_string("foo/bar")));
+        assert!(m.matches(&RepoPath::from_internal_string("bar")));
+        assert!(m.matches(&RepoPath::from_internal_string("bar/foo")));
+        assert!(m.matches(&RepoPath::from_internal_string("baz")));
+        assert!(m.matches(&RepoPath::from_internal_string("baz/foo")));
+        assert!(!m.matches(&RepoPath::from_internal_string("qux")));
+        assert!(!m.matches(&RepoPath::from_internal_string("qux/foo")));
+
+        assert_eq!(
+            m.visit(&RepoPath::root()),
+            Visit::sets(
+                hashset! {
+                    RepoPathComponent::from("foo"),
+                    RepoPathComponent::from("bar"),
+                    RepoPathComponent::from("baz"),
+                },
+                hashset! {
+                    RepoPathComponent::from("foo"),
+                    RepoPathComponent::from("bar"),
+                    RepoPathComponent::from("baz"),
+                },
+            )
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("bar")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("baz/foo")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("qux")),
+            Visit::Nothing
+        );
+    }
+
+    #[test]
+    fn test_unionmatcher_nested_dirs() {
+        // One input visits a whole subdirectory while the other prunes
+        // everything but a single file in a sibling directory
+        let m1 = PrefixMatcher::new(&[RepoPath::from_internal_string("foo/bar")]);
+        let m2 = FilesMatcher::new(&[RepoPath::from_internal_string("foo/baz/file")]);
+        let m = UnionMatcher::new(&m1, &m2);
+
+        assert!(!m.matches(&RepoPath::from_internal_string("foo")));
+        assert!(m.matches(&RepoPath::from_internal_string("foo/bar")));
+        assert!(m.matches(&RepoPath::from_internal_string("foo/bar/file")));
+        assert!(m.matches(&RepoPath::from_internal_string("foo/baz/file")));
+        assert!(!m.matches(&RepoPath::from_internal_string("foo/baz/other")));
+        assert!(!m.matches(&RepoPath::from_internal_string("foo/qux")));
+
+        assert_eq!(
+            m.visit(&RepoPath::root()),
+            Visit::sets(hashset! {RepoPathComponent::from("foo")}, hashset! {})
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo")),
+            Visit::sets(
+                hashset! {RepoPathComponent::from("bar"), RepoPathComponent::from("baz")},
+                hashset! {RepoPathComponent::from("bar")}
+            )
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo/bar")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo/baz")),
+            Visit::sets(hashset! {}, hashset! {RepoPathComponent::from("file")})
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo/qux")),
+            Visit::Nothing
+        );
+    }
 }
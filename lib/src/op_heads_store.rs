@@ -121,7 +121,15 @@ pub fn resolve_op_heads<E>(
         return Ok(op_heads.pop().unwrap());
     }
 
-    op_heads.sort_by_key(|op| op.store_operation().metadata.end_time.timestamp.clone());
+    // Merge the heads in a deterministic order regardless of the order the
+    // OpHeadsStore returned them in. Ties on the timestamp (e.g. with a fixed
+    // `debug.operation-timestamp`) are broken by the operation id.
+    op_heads.sort_by_key(|op| {
+        (
+            op.store_operation().metadata.end_time.timestamp.clone(),
+            op.id().clone(),
+        )
+    });
     match resolver(op_heads) {
         Ok(new_op) => {
             lock.promote_new_op(&new_op);
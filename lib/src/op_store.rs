@@ -14,6 +14,7 @@
 
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Error, Formatter};
+use std::time::SystemTime;
 
 use thiserror::Error;
 
@@ -261,4 +262,33 @@ pub trait OpStore: Send + Sync + Debug {
     fn read_operation(&self, id: &OperationId) -> OpStoreResult<Operation>;
 
     fn write_operation(&self, contents: &Operation) -> OpStoreResult<OperationId>;
+
+    // The methods below are only needed for garbage collection, so stores that
+    // don't support it can leave them unimplemented.
+
+    /// Returns the ids of the views in the store that were last written before
+    /// `written_before`, in no particular order.
+    fn view_ids(&self, _written_before: SystemTime) -> OpStoreResult<Vec<ViewId>> {
+        Err(gc_unsupported_error(self.name()))
+    }
+
+    /// Returns the ids of the operations in the store that were last written
+    /// before `written_before`, in no particular order.
+    fn operation_ids(&self, _written_before: SystemTime) -> OpStoreResult<Vec<OperationId>> {
+        Err(gc_unsupported_error(self.name()))
+    }
+
+    fn remove_view(&self, _id: &ViewId) -> OpStoreResult<()> {
+        Err(gc_unsupported_error(self.name()))
+    }
+
+    fn remove_operation(&self, _id: &OperationId) -> OpStoreResult<()> {
+        Err(gc_unsupported_error(self.name()))
+    }
+}
+
+fn gc_unsupported_error(store_name: &str) -> OpStoreError {
+    OpStoreError::Other(format!(
+        "Operation store '{store_name}' doesn't support garbage collection"
+    ))
 }
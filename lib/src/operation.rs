@@ -13,14 +13,19 @@
 // limitations under the License.
 
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Debug, Error, Formatter};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
+use itertools::Itertools;
+use thiserror::Error;
+
 use crate::backend::CommitId;
-use crate::op_store;
-use crate::op_store::{OpStore, OperationId, ViewId};
+use crate::op_store::{
+    BranchTarget, OpStore, OpStoreError, OperationId, RefTarget, ViewId, WorkspaceId,
+};
+use crate::{dag_walk, op_store};
 
 #[derive(Clone)]
 pub struct Operation {
@@ -99,6 +104,178 @@ impl Operation {
     pub fn store_operation(&self) -> &op_store::Operation {
         &self.data
     }
+
+    /// Returns the ids of this operation and all its ancestors.
+    pub fn reachable_operations(&self) -> HashSet<OperationId> {
+        dag_walk::bfs(
+            vec![self.clone()],
+            Box::new(|op: &Operation| op.id().clone()),
+            Box::new(|op: &Operation| op.parents()),
+        )
+        .map(|op| op.id)
+        .collect()
+    }
+
+    /// Summarizes the changes this operation made to the view compared to its
+    /// first parent's view, or to an empty view if it has no parents.
+    pub fn affected_summary(&self) -> OperationSummary {
+        let parent_view = match self.parents().first() {
+            Some(parent) => parent.view().take_store_view(),
+            None => op_store::View::default(),
+        };
+        let diff = diff_views(&parent_view, self.view().store_view());
+        OperationSummary {
+            added_heads: diff.added_heads,
+            removed_heads: diff.removed_heads,
+            changed_branches: diff.changed_branches.into_keys().collect(),
+            changed_tags: diff.changed_tags.into_keys().collect(),
+        }
+    }
+
+    /// Compares this operation's view to `other`'s view, which is typically
+    /// that of a parent operation. The result describes the changes going from
+    /// `other`'s view to this one.
+    pub fn view_diff(&self, other: &Operation) -> OperationViewDiff {
+        diff_views(other.view().store_view(), self.view().store_view())
+    }
+}
+
+/// Error from resolving an operation id or a prefix of one
+#[derive(Debug, Error)]
+pub enum OpIdResolutionError {
+    #[error("Operation ID \"{0}\" is not a valid hexadecimal prefix")]
+    InvalidIdPrefix(String),
+    #[error("No operation ID matching \"{0}\"")]
+    NoSuchOperation(String),
+    #[error("Operation ID prefix \"{0}\" is ambiguous")]
+    AmbiguousIdPrefix(String),
+    #[error("Failed to read operation: {0}")]
+    OpStoreError(#[from] OpStoreError),
+}
+
+/// Resolves a full operation id, or a prefix of the id of `head_op_ids` or one
+/// of their ancestors. A full id is looked up directly, so it doesn't have to
+/// be reachable from the heads.
+pub fn resolve_op_id_prefix(
+    op_store: &Arc<dyn OpStore>,
+    head_op_ids: impl IntoIterator<Item = OperationId>,
+    op_str: &str,
+) -> Result<Operation, OpIdResolutionError> {
+    if op_str.is_empty() || !op_str.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(OpIdResolutionError::InvalidIdPrefix(op_str.to_owned()));
+    }
+    if let Ok(binary_op_id) = hex::decode(op_str) {
+        let op_id = OperationId::new(binary_op_id);
+        match op_store.read_operation(&op_id) {
+            Ok(data) => return Ok(Operation::new(op_store.clone(), op_id, data)),
+            Err(OpStoreError::NotFound) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let mut visited = HashSet::new();
+    let mut work = head_op_ids.into_iter().collect_vec();
+    let mut matches = vec![];
+    while let Some(op_id) = work.pop() {
+        if !visited.insert(op_id.clone()) {
+            continue;
+        }
+        let data = op_store.read_operation(&op_id)?;
+        work.extend(data.parents.iter().cloned());
+        if op_id.hex().starts_with(op_str) {
+            matches.push(Operation::new(op_store.clone(), op_id, data));
+        }
+    }
+    match matches.len() {
+        0 => Err(OpIdResolutionError::NoSuchOperation(op_str.to_owned())),
+        1 => Ok(matches.pop().unwrap()),
+        _ => Err(OpIdResolutionError::AmbiguousIdPrefix(op_str.to_owned())),
+    }
+}
+
+/// Changes an operation made to the view, as computed by
+/// `Operation::affected_summary()`. All lists are sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationSummary {
+    /// Heads that were added by the operation.
+    pub added_heads: Vec<CommitId>,
+    /// Heads that were removed (e.g. rewritten or abandoned) by the operation.
+    pub removed_heads: Vec<CommitId>,
+    /// Branches that were created, deleted, or moved locally or on a remote.
+    pub changed_branches: Vec<String>,
+    /// Tags that were created, deleted, or moved.
+    pub changed_tags: Vec<String>,
+}
+
+impl OperationSummary {
+    pub fn is_empty(&self) -> bool {
+        self == &OperationSummary::default()
+    }
+}
+
+/// Differences between two views, as computed by `Operation::view_diff()`.
+///
+/// The maps contain only the entries that changed, each with its `(old, new)`
+/// value. A `None` value means that the entry is absent from that view.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OperationViewDiff {
+    /// Heads present in the new view but not in the old one, sorted.
+    pub added_heads: Vec<CommitId>,
+    /// Heads present in the old view but not in the new one, sorted.
+    pub removed_heads: Vec<CommitId>,
+    /// Branches whose local or remote targets changed.
+    pub changed_branches: BTreeMap<String, (Option<BranchTarget>, Option<BranchTarget>)>,
+    /// Tags that were created, deleted, or moved.
+    pub changed_tags: BTreeMap<String, (Option<RefTarget>, Option<RefTarget>)>,
+    /// Working-copy commits that were added, removed, or moved per workspace.
+    pub changed_wc_commits: BTreeMap<WorkspaceId, (Option<CommitId>, Option<CommitId>)>,
+}
+
+impl OperationViewDiff {
+    pub fn is_empty(&self) -> bool {
+        self == &OperationViewDiff::default()
+    }
+}
+
+fn diff_views(old: &op_store::View, new: &op_store::View) -> OperationViewDiff {
+    let added_heads = new
+        .head_ids
+        .difference(&old.head_ids)
+        .cloned()
+        .sorted()
+        .collect();
+    let removed_heads = old
+        .head_ids
+        .difference(&new.head_ids)
+        .cloned()
+        .sorted()
+        .collect();
+    let old_wc_commit_ids: BTreeMap<_, _> = old.wc_commit_ids.clone().into_iter().collect();
+    let new_wc_commit_ids: BTreeMap<_, _> = new.wc_commit_ids.clone().into_iter().collect();
+    OperationViewDiff {
+        added_heads,
+        removed_heads,
+        changed_branches: diff_maps(&old.branches, &new.branches),
+        changed_tags: diff_maps(&old.tags, &new.tags),
+        changed_wc_commits: diff_maps(&old_wc_commit_ids, &new_wc_commit_ids),
+    }
+}
+
+/// Returns the entries that differ between `old` and `new`, each with its
+/// `(old, new)` value.
+pub(crate) fn diff_maps<K: Ord + Clone, V: PartialEq + Clone>(
+    old: &BTreeMap<K, V>,
+    new: &BTreeMap<K, V>,
+) -> BTreeMap<K, (Option<V>, Option<V>)> {
+    old.keys()
+        .merge(new.keys())
+        .dedup()
+        .filter_map(|key| {
+            let old_value = old.get(key);
+            let new_value = new.get(key);
+            (old_value != new_value)
+                .then(|| (key.clone(), (old_value.cloned(), new_value.cloned())))
+        })
+        .collect()
 }
 
 #[derive(Clone)]
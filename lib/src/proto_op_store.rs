@@ -16,7 +16,8 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
 use std::io::{ErrorKind, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use itertools::Itertools;
 use prost::Message;
@@ -99,6 +100,44 @@ impl ProtoOpStore {
         persist_content_addressed_temp_file(temp_file, self.operation_path(&id))?;
         Ok(id)
     }
+
+    pub fn view_ids(&self, written_before: SystemTime) -> OpStoreResult<Vec<ViewId>> {
+        let ids = list_hex_file_names(&self.path.join("views"), written_before)?;
+        Ok(ids.into_iter().map(ViewId::new).collect())
+    }
+
+    pub fn operation_ids(&self, written_before: SystemTime) -> OpStoreResult<Vec<OperationId>> {
+        let ids = list_hex_file_names(&self.path.join("operations"), written_before)?;
+        Ok(ids.into_iter().map(OperationId::new).collect())
+    }
+
+    pub fn remove_view(&self, id: &ViewId) -> OpStoreResult<()> {
+        fs::remove_file(self.view_path(id)).map_err(not_found_to_store_error)
+    }
+
+    pub fn remove_operation(&self, id: &OperationId) -> OpStoreResult<()> {
+        fs::remove_file(self.operation_path(id)).map_err(not_found_to_store_error)
+    }
+}
+
+/// Decodes the names of the files in `dir` that were last modified before
+/// `written_before`, skipping any that aren't valid hex (such as leftover
+/// temporary files).
+fn list_hex_file_names(dir: &Path, written_before: SystemTime) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut ids = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| hex::decode(name).ok())
+        {
+            if entry.metadata()?.modified()? < written_before {
+                ids.push(id);
+            }
+        }
+    }
+    Ok(ids)
 }
 
 fn not_found_to_store_error(err: std::io::Error) -> OpStoreError {
@@ -12,11 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{hash_set, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 use std::{fs, io};
 
 use itertools::Itertools;
@@ -27,21 +28,22 @@ use self::dirty_cell::DirtyCell;
 use crate::backend::{Backend, BackendError, BackendResult, ChangeId, CommitId, ObjectId, TreeId};
 use crate::commit::Commit;
 use crate::commit_builder::CommitBuilder;
-use crate::dag_walk::topo_order_reverse;
+use crate::dag_walk::{self, topo_order_reverse};
 use crate::default_index_store::{DefaultIndexStore, IndexEntry, IndexPosition};
 use crate::git_backend::GitBackend;
 use crate::index::{HexPrefix, Index, IndexStore, MutableIndex, PrefixResolution, ReadonlyIndex};
 use crate::local_backend::LocalBackend;
 use crate::op_heads_store::{self, OpHeadResolutionError, OpHeadsStore};
-use crate::op_store::{BranchTarget, OpStore, OperationId, RefTarget, WorkspaceId};
+use crate::op_store::{BranchTarget, OpStore, OpStoreResult, OperationId, RefTarget, WorkspaceId};
 use crate::operation::Operation;
 use crate::refs::merge_ref_targets;
-use crate::rewrite::DescendantRebaser;
+use crate::rewrite::{merge_commit_trees, DescendantRebaser};
 use crate::settings::{RepoSettings, UserSettings};
 use crate::simple_op_heads_store::SimpleOpHeadsStore;
 use crate::simple_op_store::SimpleOpStore;
 use crate::store::Store;
 use crate::transaction::Transaction;
+use crate::tree::{merge_trees, TreeMergeError};
 use crate::view::{RefName, View};
 use crate::{backend, op_store};
 
@@ -56,6 +58,16 @@ pub trait Repo {
 
     fn view(&self) -> &View;
 
+    /// Iterates over the visible heads without collecting them.
+    fn visible_heads(&self) -> hash_set::Iter<'_, CommitId> {
+        self.view().heads().iter()
+    }
+
+    /// Iterates over the public heads without collecting them.
+    fn public_heads(&self) -> hash_set::Iter<'_, CommitId> {
+        self.view().public_heads().iter()
+    }
+
     fn resolve_change_id(&self, change_id: &ChangeId) -> Option<Vec<IndexEntry>> {
         // Replace this if we added more efficient lookup method.
         let prefix = HexPrefix::from_bytes(change_id.as_bytes());
@@ -68,7 +80,177 @@ pub trait Repo {
 
     fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<IndexEntry>>;
 
+    /// Resolves `prefix` to the ids of the indexed commits starting with it.
+    ///
+    /// Unlike `resolve_change_id_prefix()`, hidden commits are included, so a
+    /// prefix shared with a hidden commit is ambiguous. A match consists of a
+    /// single commit id.
+    fn resolve_commit_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<CommitId>> {
+        self.index()
+            .resolve_prefix(prefix)
+            .map(|commit_id| vec![commit_id])
+    }
+
+    /// Returns the distinct change ids of visible commits matching `prefix`,
+    /// in sorted order. Useful for explaining why `resolve_change_id_prefix()`
+    /// returned `AmbiguousMatch`.
+    fn change_ids_matching_prefix(&self, prefix: &HexPrefix) -> Vec<ChangeId>;
+
     fn shortest_unique_change_id_prefix_len(&self, target_id_bytes: &ChangeId) -> usize;
+
+    /// Returns the change ids shared by more than one visible commit, mapped
+    /// to the ids of these commits in sorted order.
+    fn divergent_change_ids(&self) -> HashMap<ChangeId, Vec<CommitId>>;
+
+    /// Returns the position of the given commit among the visible commits
+    /// sharing its change id, and the total number of such commits.
+    ///
+    /// Commits are ordered by index position, so older commits come first.
+    /// Returns `None` if the commit isn't visible.
+    fn change_id_siblings(&self, commit_id: &CommitId) -> Option<(usize, usize)> {
+        let entry = self.index().entry_by_id(commit_id)?;
+        let mut positions = self
+            .resolve_change_id(&entry.change_id())?
+            .iter()
+            .map(|entry| entry.position())
+            .collect_vec();
+        positions.sort_unstable();
+        let index = positions.iter().position(|&pos| pos == entry.position())?;
+        Some((index, positions.len()))
+    }
+
+    /// Finds the closest ancestor (including the commit itself) pointed to by
+    /// a tag or a local branch, and returns the ref name along with the
+    /// generation distance from the commit.
+    ///
+    /// If multiple refs point to the closest ancestor, tags are preferred over
+    /// branches, and names are compared alphabetically.
+    fn nearest_ref_ancestor(&self, commit_id: &CommitId) -> Option<(RefName, u32)> {
+        let view = self.view();
+        let mut ref_names_by_id: HashMap<CommitId, RefName> = HashMap::new();
+        let tag_refs = view
+            .tags()
+            .iter()
+            .map(|(name, target)| (RefName::Tag(name.clone()), target));
+        let branch_refs = view.branches().iter().filter_map(|(name, target)| {
+            let local_target = target.local_target.as_ref()?;
+            Some((RefName::LocalBranch(name.clone()), local_target))
+        });
+        for (ref_name, target) in tag_refs.chain(branch_refs) {
+            for id in target.adds() {
+                ref_names_by_id
+                    .entry(id)
+                    .or_insert_with(|| ref_name.clone());
+            }
+        }
+
+        let generation = self.index().entry_by_id(commit_id)?.generation_number();
+        // Ancestors aren't visited in generation order, so the whole history has
+        // to be walked to find the closest one.
+        self.index()
+            .walk_revs(std::slice::from_ref(commit_id), &[])
+            .filter_map(|entry| {
+                let ref_name = ref_names_by_id.get(&entry.commit_id())?;
+                Some((ref_name, generation - entry.generation_number()))
+            })
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(ref_name, distance)| (ref_name.clone(), distance))
+    }
+
+    /// Returns the names of the local branches pointing to the commit or to
+    /// any of its descendants, sorted by name. A conflicted branch is included
+    /// if any of its added targets contains the commit.
+    fn branches_containing(&self, commit_id: &CommitId) -> Vec<String> {
+        let index = self.index();
+        self.view()
+            .branches()
+            .iter()
+            .filter(|(_, target)| {
+                target.local_target.as_ref().map_or(false, |local_target| {
+                    local_target
+                        .adds()
+                        .iter()
+                        .any(|id| index.is_ancestor(commit_id, id))
+                })
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Guesses the commit the repo's main line of development ("trunk") is
+    /// at, e.g. to use as a default base.
+    ///
+    /// A local branch named "main", "master", or "trunk" is preferred, in that
+    /// order, unless it's conflicted. Otherwise, the public head with the
+    /// highest generation number is picked, and ties are broken by commit id.
+    fn guess_trunk(&self) -> Option<CommitId> {
+        let view = self.view();
+        let index = self.index();
+        for name in ["main", "master", "trunk"] {
+            if let Some(RefTarget::Normal(id)) = view.get_local_branch(name) {
+                if index.has_id(&id) {
+                    return Some(id);
+                }
+            }
+        }
+        view.public_heads()
+            .iter()
+            .filter_map(|id| {
+                let entry = index.entry_by_id(id)?;
+                Some((entry.generation_number(), id))
+            })
+            .max()
+            .map(|(_, id)| id.clone())
+    }
+
+    /// Returns the parents of the commit in the order they're recorded, unlike
+    /// the `parents()` revset which yields a set. Returns an empty list if the
+    /// commit isn't indexed.
+    fn ordered_parents(&self, commit_id: &CommitId) -> Vec<CommitId> {
+        self.index()
+            .entry_by_id(commit_id)
+            .map(|entry| {
+                entry
+                    .parents()
+                    .iter()
+                    .map(|parent| parent.commit_id())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the indexed commits that aren't reachable from any head or ref
+    /// in the view, such as abandoned or rewritten commits. These are
+    /// candidates for garbage collection. Newer commits come first.
+    fn unreachable_commits(&self) -> Vec<CommitId> {
+        let view = self.view();
+        let ref_targets = view
+            .branches()
+            .values()
+            .flat_map(|target| {
+                target
+                    .local_target
+                    .iter()
+                    .chain(target.remote_targets.values())
+            })
+            .chain(view.tags().values())
+            .chain(view.git_refs().values())
+            .chain(view.git_head());
+        let mut reachable_ids = view.heads().iter().cloned().collect_vec();
+        reachable_ids.extend(view.public_heads().iter().cloned());
+        reachable_ids.extend(view.wc_commit_ids().values().cloned());
+        for target in ref_targets {
+            reachable_ids.extend(target.adds());
+            reachable_ids.extend(target.removes());
+        }
+        let index = self.index();
+        // Refs may point to commits that have never been indexed
+        reachable_ids.retain(|id| index.has_id(id));
+        index
+            .walk_revs(&index.all_heads(), &reachable_ids)
+            .map(|entry| entry.commit_id())
+            .collect()
+    }
 }
 
 pub struct ReadonlyRepo {
@@ -125,8 +307,9 @@ impl ReadonlyRepo {
         let backend = backend_factory(&store_path);
         let backend_path = store_path.join("type");
         fs::write(&backend_path, backend.name()).context(&backend_path)?;
-        let store = Store::new(backend);
         let repo_settings = user_settings.with_repo(&repo_path).unwrap();
+        let store =
+            Store::with_commit_cache_capacity(backend, repo_settings.commit_cache_capacity());
 
         let op_store_path = repo_path.join("op_store");
         fs::create_dir(&op_store_path).context(&op_store_path)?;
@@ -285,12 +468,21 @@ impl Repo for Arc<ReadonlyRepo> {
     fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<IndexEntry>> {
         let index = self.index();
         self.change_id_index()
-            .resolve_prefix_with(prefix, |&pos| index.entry_by_pos(pos))
+            .resolve_prefix_iter(prefix)
+            .map(|positions| positions.map(|&pos| index.entry_by_pos(pos)).collect())
+    }
+
+    fn change_ids_matching_prefix(&self, prefix: &HexPrefix) -> Vec<ChangeId> {
+        self.change_id_index().resolve_prefix_keys(prefix)
     }
 
     fn shortest_unique_change_id_prefix_len(&self, target_id: &ChangeId) -> usize {
         self.change_id_index().shortest_unique_prefix_len(target_id)
     }
+
+    fn divergent_change_ids(&self) -> HashMap<ChangeId, Vec<CommitId>> {
+        divergent_change_ids(self.index(), self.change_id_index())
+    }
 }
 
 type BackendFactory = Box<dyn Fn(&Path) -> Box<dyn Backend>>;
@@ -516,8 +708,11 @@ impl RepoLoader {
         repo_path: &Path,
         store_factories: &StoreFactories,
     ) -> Result<Self, StoreLoadError> {
-        let store = Store::new(store_factories.load_backend(&repo_path.join("store"))?);
         let repo_settings = user_settings.with_repo(repo_path).unwrap();
+        let store = Store::with_commit_cache_capacity(
+            store_factories.load_backend(&repo_path.join("store"))?,
+            repo_settings.commit_cache_capacity(),
+        );
         let op_store = Arc::from(store_factories.load_op_store(&repo_path.join("op_store"))?);
         let op_heads_store =
             Arc::from(store_factories.load_op_heads_store(&repo_path.join("op_heads"))?);
@@ -591,6 +786,50 @@ impl RepoLoader {
         Arc::new(repo)
     }
 
+    /// Removes the operations and views that aren't reachable from the
+    /// current op heads from the op store. Returns the number of removed
+    /// operations.
+    ///
+    /// Operations and views written at or after `keep_newer` are kept even if
+    /// they're unreachable. A transaction writes its operation and view before
+    /// it publishes the operation as an op head, so another process's
+    /// unpublished operation looks unreachable until then. Pass a time far
+    /// enough in the past that no transaction is still running (like `git gc
+    /// --prune=<date>`).
+    pub fn gc_unreachable_operations(&self, keep_newer: SystemTime) -> OpStoreResult<usize> {
+        // Keep other processes from promoting new op heads while we're walking.
+        let _lock = self.op_heads_store.lock();
+        let mut op_heads = vec![];
+        for op_id in self.op_heads_store.get_op_heads() {
+            let data = self.op_store.read_operation(&op_id)?;
+            op_heads.push(Operation::new(self.op_store.clone(), op_id, data));
+        }
+        let mut reachable_op_ids = HashSet::new();
+        let mut reachable_view_ids = HashSet::new();
+        for op in dag_walk::bfs(
+            op_heads,
+            Box::new(|op: &Operation| op.id().clone()),
+            Box::new(|op: &Operation| op.parents()),
+        ) {
+            reachable_view_ids.insert(op.store_operation().view_id.clone());
+            reachable_op_ids.insert(op.id().clone());
+        }
+
+        let mut num_removed = 0;
+        for op_id in self.op_store.operation_ids(keep_newer)? {
+            if !reachable_op_ids.contains(&op_id) {
+                self.op_store.remove_operation(&op_id)?;
+                num_removed += 1;
+            }
+        }
+        for view_id in self.op_store.view_ids(keep_newer)? {
+            if !reachable_view_ids.contains(&view_id) {
+                self.op_store.remove_view(&view_id)?;
+            }
+        }
+        Ok(num_removed)
+    }
+
     fn _resolve_op_heads(
         &self,
         op_heads: Vec<Operation>,
@@ -627,6 +866,17 @@ pub struct MutableRepo {
     base_repo: Arc<ReadonlyRepo>,
     index: Box<dyn MutableIndex>,
     view: DirtyCell<View>,
+    /// Change ids of the visible commits, built on demand. Must be reset by
+    /// `invalidate_change_id_index()` whenever the visible heads change.
+    change_id_index: OnceCell<ChangeIdIndex>,
+    rewritten_commits: HashMap<CommitId, HashSet<CommitId>>,
+    abandoned_commits: HashSet<CommitId>,
+}
+
+/// Snapshot of a `MutableRepo`'s state, created by `MutableRepo::savepoint()`.
+#[derive(Clone, Debug)]
+pub struct Savepoint {
+    view: View,
     rewritten_commits: HashMap<CommitId, HashSet<CommitId>>,
     abandoned_commits: HashSet<CommitId>,
 }
@@ -643,6 +893,7 @@ impl MutableRepo {
             base_repo,
             index: mut_index,
             view: DirtyCell::with_clean(mut_view),
+            change_id_index: OnceCell::new(),
             rewritten_commits: Default::default(),
             abandoned_commits: Default::default(),
         }
@@ -652,12 +903,53 @@ impl MutableRepo {
         self.view.get_mut()
     }
 
+    fn change_id_index(&self) -> &ChangeIdIndex {
+        self.change_id_index.get_or_init(|| {
+            let heads = self.visible_heads().cloned().collect_vec();
+            let walk = self.index().walk_revs(&heads, &[]);
+            IdIndex::from_vec(
+                walk.map(|entry| (entry.change_id(), entry.position()))
+                    .collect(),
+            )
+        })
+    }
+
+    fn invalidate_change_id_index(&mut self) {
+        self.change_id_index.take();
+    }
+
     pub fn has_changes(&self) -> bool {
         !(self.abandoned_commits.is_empty()
             && self.rewritten_commits.is_empty()
             && self.view() == &self.base_repo.view)
     }
 
+    /// Captures the current view and the recorded rewrites and abandoned
+    /// commits so they can later be restored with `restore_savepoint()`.
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            view: self.view().clone(),
+            rewritten_commits: self.rewritten_commits.clone(),
+            abandoned_commits: self.abandoned_commits.clone(),
+        }
+    }
+
+    /// Reverts the view and the recorded rewrites and abandoned commits to the
+    /// state captured by `savepoint`. Commits added to the index since then
+    /// stay indexed, but they're no longer reachable from the view unless
+    /// they were before.
+    pub fn restore_savepoint(&mut self, savepoint: Savepoint) {
+        let Savepoint {
+            view,
+            rewritten_commits,
+            abandoned_commits,
+        } = savepoint;
+        self.view = DirtyCell::with_clean(view);
+        self.invalidate_change_id_index();
+        self.rewritten_commits = rewritten_commits;
+        self.abandoned_commits = abandoned_commits;
+    }
+
     pub fn consume(self) -> (Box<dyn MutableIndex>, View) {
         self.view.ensure_clean(|v| self.enforce_view_invariants(v));
         (self.index, self.view.into_inner())
@@ -680,12 +972,92 @@ impl MutableRepo {
         CommitBuilder::for_rewrite_from(self, settings, predecessor)
     }
 
+    /// Rewrites `commit` to have `new_parents` as its parents and records the
+    /// rewrite so descendants can be rebased by `rebase_descendants()`.
+    ///
+    /// With `ReparentTree::Keep`, the commit's tree is kept as is, so the
+    /// changes it introduces relative to its parents may differ. With
+    /// `ReparentTree::Merge`, the changes are merged onto the new parents like
+    /// `rewrite::rebase_commit()` does.
+    pub fn reparent(
+        &mut self,
+        settings: &UserSettings,
+        commit: &Commit,
+        new_parents: Vec<CommitId>,
+        tree: ReparentTree,
+    ) -> Result<Commit, ReparentError> {
+        if commit.id() == self.store().root_commit_id() {
+            return Err(ReparentError::RewriteRootCommit);
+        }
+        let new_tree_id = match tree {
+            ReparentTree::Keep => commit.tree_id().clone(),
+            ReparentTree::Merge => {
+                let new_parent_commits: Vec<_> = new_parents
+                    .iter()
+                    .map(|id| self.store().get_commit(id))
+                    .try_collect()?;
+                let old_base_tree = merge_commit_trees(self, &commit.parents());
+                let new_base_tree = merge_commit_trees(self, &new_parent_commits);
+                merge_trees(&new_base_tree, &old_base_tree, &commit.tree())?
+            }
+        };
+        let new_commit = self
+            .rewrite_commit(settings, commit)
+            .set_parents(new_parents)
+            .set_tree(new_tree_id)
+            .write()?;
+        Ok(new_commit)
+    }
+
+    /// Writes the commit data as is and adds the commit as a head. Unlike
+    /// `new_commit()`, no fields are filled in from the settings, so importers
+    /// can preserve the signatures, timestamps, and change id exactly.
     pub fn write_commit(&mut self, commit: backend::Commit) -> BackendResult<Commit> {
         let commit = self.store().write_commit(commit)?;
         self.add_head(&commit);
         Ok(commit)
     }
 
+    /// Like `write_commit()`, but for importing many commits at once. The
+    /// commits should be in topological order (parents first) so each one can
+    /// be indexed as it's written. The heads are updated once at the end
+    /// instead of after every commit.
+    pub fn write_commits(
+        &mut self,
+        commits: impl IntoIterator<Item = backend::Commit>,
+    ) -> BackendResult<Vec<Commit>> {
+        let mut written_commits = vec![];
+        let mut new_head_ids = HashSet::new();
+        for commit in commits {
+            let commit = self.store().write_commit(commit)?;
+            if commit
+                .parent_ids()
+                .iter()
+                .all(|parent_id| self.index().has_id(parent_id))
+            {
+                self.index.add_commit(&commit);
+            } else {
+                // Not in topological order, so let add_head() find and index
+                // the missing ancestors.
+                self.add_head(&commit);
+            }
+            for parent_id in commit.parent_ids() {
+                new_head_ids.remove(parent_id);
+            }
+            new_head_ids.insert(commit.id().clone());
+            written_commits.push(commit);
+        }
+        if !new_head_ids.is_empty() {
+            let view = self.view.get_mut();
+            for head_id in &new_head_ids {
+                view.add_head(head_id);
+            }
+            self.view.mark_dirty();
+            self.invalidate_change_id_index();
+        }
+        Ok(written_commits)
+    }
+
     /// Record a commit as having been rewritten in this transaction. This
     /// record is used by `rebase_descendants()`.
     ///
@@ -747,6 +1119,20 @@ impl MutableRepo {
         Ok(rebaser.rebased().len())
     }
 
+    /// Abandons the commit and rebases its descendants onto its parents.
+    ///
+    /// Workspaces editing the abandoned commit get a new working-copy commit on
+    /// top of its parents. Rewrites recorded earlier in the transaction are
+    /// applied as well. Returns the number of rebased commits.
+    pub fn abandon_commit(
+        &mut self,
+        settings: &UserSettings,
+        commit_id: CommitId,
+    ) -> Result<usize, BackendError> {
+        self.record_abandoned_commit(commit_id);
+        self.rebase_descendants(settings)
+    }
+
     pub fn set_wc_commit(
         &mut self,
         workspace_id: WorkspaceId,
@@ -822,8 +1208,7 @@ impl MutableRepo {
     pub fn add_head(&mut self, head: &Commit) {
         let current_heads = self.view.get_mut().heads();
         // Use incremental update for common case of adding a single commit on top a
-        // current head. TODO: Also use incremental update when adding a single
-        // commit on top a non-head.
+        // current head.
         if head
             .parent_ids()
             .iter()
@@ -834,6 +1219,18 @@ impl MutableRepo {
             for parent_id in head.parent_ids() {
                 self.view.get_mut().remove_head(parent_id);
             }
+        } else if head
+            .parent_ids()
+            .iter()
+            .all(|parent_id| self.index.as_index().has_id(parent_id))
+        {
+            // The parents are already indexed but some of them aren't heads (e.g.
+            // when adding a commit on top of an interior or hidden commit), so
+            // the index can still be updated incrementally. The view's heads need
+            // to be recomputed since the new head may descend from another head.
+            self.index.add_commit(head);
+            self.view.get_mut().add_head(head.id());
+            self.view.mark_dirty();
         } else {
             let missing_commits = topo_order_reverse(
                 vec![head.clone()],
@@ -852,21 +1249,25 @@ impl MutableRepo {
             self.view.get_mut().add_head(head.id());
             self.view.mark_dirty();
         }
+        self.invalidate_change_id_index();
     }
 
     pub fn remove_head(&mut self, head: &CommitId) {
         self.view_mut().remove_head(head);
         self.view.mark_dirty();
+        self.invalidate_change_id_index();
     }
 
     pub fn add_public_head(&mut self, head: &Commit) {
         self.view_mut().add_public_head(head.id());
         self.view.mark_dirty();
+        self.invalidate_change_id_index();
     }
 
     pub fn remove_public_head(&mut self, head: &CommitId) {
         self.view_mut().remove_public_head(head);
         self.view.mark_dirty();
+        self.invalidate_change_id_index();
     }
 
     pub fn get_branch(&self, name: &str) -> Option<BranchTarget> {
@@ -906,6 +1307,13 @@ impl MutableRepo {
         self.view_mut().remove_remote_branch(name, remote_name);
     }
 
+    /// Moves the local and remote targets of the branch `old` to `new`.
+    /// Returns false without changing anything if `old` doesn't exist or
+    /// `new` already exists.
+    pub fn rename_branch(&mut self, old: &str, new: &str) -> bool {
+        self.view_mut().rename_branch(old, new)
+    }
+
     pub fn rename_remote(&mut self, old: &str, new: &str) {
         self.view_mut().rename_remote(old, new);
     }
@@ -945,6 +1353,7 @@ impl MutableRepo {
     pub fn set_view(&mut self, data: op_store::View) {
         self.view_mut().set_view(data);
         self.view.mark_dirty();
+        self.invalidate_change_id_index();
     }
 
     pub fn merge(&mut self, base_repo: &ReadonlyRepo, other_repo: &ReadonlyRepo) {
@@ -958,6 +1367,7 @@ impl MutableRepo {
         self.view.ensure_clean(|v| self.enforce_view_invariants(v));
         self.merge_view(&base_repo.view, &other_repo.view);
         self.view.mark_dirty();
+        self.invalidate_change_id_index();
     }
 
     fn merge_view(&mut self, base: &View, other: &View) {
@@ -997,7 +1407,7 @@ impl MutableRepo {
         }
 
         let base_heads = base.heads().iter().cloned().collect_vec();
-        let own_heads = self.view().heads().iter().cloned().collect_vec();
+        let own_heads = self.visible_heads().cloned().collect_vec();
         let other_heads = other.heads().iter().cloned().collect_vec();
         self.record_rewrites(&base_heads, &own_heads);
         self.record_rewrites(&base_heads, &other_heads);
@@ -1159,7 +1569,7 @@ impl Repo for MutableRepo {
         // TODO: Create a persistent lookup from change id to (visible?) commit ids.
         let mut found_change_id = None;
         let mut found_entries = vec![];
-        let heads = self.view().heads().iter().cloned().collect_vec();
+        let heads = self.visible_heads().cloned().collect_vec();
         for entry in self.index().walk_revs(&heads, &[]) {
             let change_id = entry.change_id();
             if prefix.matches(&change_id) {
@@ -1177,8 +1587,23 @@ impl Repo for MutableRepo {
         PrefixResolution::SingleMatch(found_entries)
     }
 
+    fn change_ids_matching_prefix(&self, prefix: &HexPrefix) -> Vec<ChangeId> {
+        let heads = self.visible_heads().cloned().collect_vec();
+        self.index()
+            .walk_revs(&heads, &[])
+            .map(|entry| entry.change_id())
+            .filter(|change_id| prefix.matches(change_id))
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
     fn shortest_unique_change_id_prefix_len(&self, target_id: &ChangeId) -> usize {
-        target_id.as_bytes().len() * 2 // TODO
+        self.change_id_index().shortest_unique_prefix_len(target_id)
+    }
+
+    fn divergent_change_ids(&self) -> HashMap<ChangeId, Vec<CommitId>> {
+        divergent_change_ids(self.index(), self.change_id_index())
     }
 }
 
@@ -1187,6 +1612,26 @@ impl Repo for MutableRepo {
 #[error("Cannot rewrite the root commit")]
 pub struct RewriteRootCommit;
 
+/// How `MutableRepo::reparent()` computes the tree of the rewritten commit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReparentTree {
+    /// Keep the commit's tree as is
+    Keep,
+    /// Merge the commit's changes onto the new parents
+    Merge,
+}
+
+/// Error from attempts to reparent a commit
+#[derive(Debug, Error)]
+pub enum ReparentError {
+    #[error("Cannot rewrite the root commit")]
+    RewriteRootCommit,
+    #[error("Failed to merge trees: {0}")]
+    TreeMerge(#[from] TreeMergeError),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+}
+
 /// Error from attempts to edit a commit
 #[derive(Debug, Error)]
 pub enum EditCommitError {
@@ -1280,6 +1725,24 @@ mod dirty_cell {
 
 type ChangeIdIndex = IdIndex<ChangeId, IndexPosition>;
 
+fn divergent_change_ids(
+    index: &dyn Index,
+    change_id_index: &ChangeIdIndex,
+) -> HashMap<ChangeId, Vec<CommitId>> {
+    change_id_index
+        .duplicate_entries()
+        .into_iter()
+        .map(|(change_id, positions)| {
+            let commit_ids = positions
+                .into_iter()
+                .map(|&pos| index.entry_by_pos(pos).commit_id())
+                .sorted()
+                .collect();
+            (change_id.clone(), commit_ids)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct IdIndex<K, V>(Vec<(K, V)>);
 
@@ -1299,36 +1762,80 @@ where
     pub fn resolve_prefix_with<U>(
         &self,
         prefix: &HexPrefix,
-        mut value_mapper: impl FnMut(&V) -> U,
+        value_mapper: impl FnMut(&V) -> U,
     ) -> PrefixResolution<Vec<U>> {
-        let mut range = self.resolve_prefix_range(prefix).peekable();
-        if let Some((first_key, _)) = range.peek().copied() {
-            let maybe_entries: Option<Vec<_>> = range
-                .map(|(k, v)| (k == first_key).then(|| value_mapper(v)))
-                .collect();
-            if let Some(entries) = maybe_entries {
-                PrefixResolution::SingleMatch(entries)
-            } else {
-                PrefixResolution::AmbiguousMatch
+        self.resolve_prefix_iter(prefix)
+            .map(|values| values.map(value_mapper).collect())
+    }
+
+    /// Looks up entries with the given prefix, and collects references to the
+    /// values if matched entries have unambiguous keys.
+    pub fn resolve_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<&V>> {
+        self.resolve_prefix_iter(prefix)
+            .map(|values| values.collect())
+    }
+
+    /// Looks up entries with the given prefix, and iterates over the values
+    /// if matched entries have unambiguous keys.
+    ///
+    /// Since entries are sorted by key, the matched values are borrowed from
+    /// a contiguous range of the index without allocation.
+    pub fn resolve_prefix_iter(
+        &self,
+        prefix: &HexPrefix,
+    ) -> PrefixResolution<impl ExactSizeIterator<Item = &V>> {
+        let entries = self.resolve_prefix_slice(prefix);
+        match (entries.first(), entries.last()) {
+            (Some((first_key, _)), Some((last_key, _))) if first_key == last_key => {
+                PrefixResolution::SingleMatch(entries.iter().map(|(_, v)| v))
             }
-        } else {
-            PrefixResolution::NoMatch
+            (Some(_), Some(_)) => PrefixResolution::AmbiguousMatch,
+            _ => PrefixResolution::NoMatch,
         }
     }
 
+    /// Returns the keys associated with more than one value, in sorted order,
+    /// along with their values.
+    pub fn duplicate_entries(&self) -> Vec<(&K, Vec<&V>)> {
+        let mut duplicates = vec![];
+        let mut rest = &self.0[..];
+        while let Some((key, _)) = rest.first() {
+            let len = rest.partition_point(|(k, _)| k == key);
+            if len > 1 {
+                duplicates.push((key, rest[..len].iter().map(|(_, v)| v).collect()));
+            }
+            rest = &rest[len..];
+        }
+        duplicates
+    }
+
+    /// Looks up the distinct keys with the given prefix, in sorted order.
+    pub fn resolve_prefix_keys(&self, prefix: &HexPrefix) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.resolve_prefix_range(prefix)
+            .map(|(k, _)| k)
+            .dedup()
+            .cloned()
+            .collect()
+    }
+
     /// Iterates over entries with the given prefix.
-    pub fn resolve_prefix_range<'a: 'b, 'b>(
-        &'a self,
-        prefix: &'b HexPrefix,
-    ) -> impl Iterator<Item = (&'a K, &'a V)> + 'b {
-        let min_bytes = prefix.min_prefix_bytes();
-        let pos = self.0.partition_point(|(k, _)| k.as_bytes() < min_bytes);
-        self.0[pos..]
+    pub fn resolve_prefix_range(&self, prefix: &HexPrefix) -> impl Iterator<Item = (&K, &V)> {
+        self.resolve_prefix_slice(prefix)
             .iter()
-            .take_while(|(k, _)| prefix.matches(k))
             .map(|(k, v)| (k, v))
     }
 
+    /// Returns the contiguous range of entries with the given prefix.
+    fn resolve_prefix_slice(&self, prefix: &HexPrefix) -> &[(K, V)] {
+        let min_bytes = prefix.min_prefix_bytes();
+        let start = self.0.partition_point(|(k, _)| k.as_bytes() < min_bytes);
+        let len = self.0[start..].partition_point(|(k, _)| prefix.matches(k));
+        &self.0[start..start + len]
+    }
+
     /// This function returns the shortest length of a prefix of `key` that
     /// disambiguates it from every other key in the index.
     ///
@@ -1412,6 +1919,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_id_index_resolve_prefix_borrowed() {
+        let id_index = IdIndex::from_vec(vec![
+            (ChangeId::from_hex("0aab"), 4),
+            (ChangeId::from_hex("0000"), 0),
+            (ChangeId::from_hex("0099"), 1),
+            (ChangeId::from_hex("0099"), 2),
+            (ChangeId::from_hex("0aaa"), 3),
+        ]);
+        let resolve = |prefix: &str| {
+            id_index
+                .resolve_prefix(&HexPrefix::new(prefix).unwrap())
+                .map(|mut values| {
+                    values.sort(); // order of values might not be preserved by IdIndex
+                    values
+                })
+        };
+        assert_eq!(resolve("0"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve("0000"), PrefixResolution::SingleMatch(vec![&0]));
+        assert_eq!(resolve("009"), PrefixResolution::SingleMatch(vec![&1, &2]));
+        assert_eq!(resolve("0099"), PrefixResolution::SingleMatch(vec![&1, &2]));
+        assert_eq!(resolve("0aa"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve("0aaa"), PrefixResolution::SingleMatch(vec![&3]));
+        assert_eq!(resolve("0001"), PrefixResolution::NoMatch);
+        assert_eq!(resolve("f"), PrefixResolution::NoMatch);
+
+        let empty_index: IdIndex<ChangeId, i32> = IdIndex::from_vec(vec![]);
+        assert_eq!(
+            empty_index.resolve_prefix(&HexPrefix::new("0").unwrap()),
+            PrefixResolution::NoMatch,
+        );
+    }
+
+    #[test]
+    fn test_id_index_resolve_prefix_iter_many_duplicates() {
+        // Many values for a few keys, placed next to a key that shares a long
+        // prefix with them.
+        let mut entries = vec![];
+        for i in 0..1000 {
+            entries.push((ChangeId::from_hex("abcdef00"), i));
+            entries.push((ChangeId::from_hex("abcdef01"), i + 1000));
+        }
+        entries.push((ChangeId::from_hex("abcdef0100"), 2000));
+        let id_index = IdIndex::from_vec(entries);
+        let resolve_len = |prefix: &str| {
+            id_index
+                .resolve_prefix_iter(&HexPrefix::new(prefix).unwrap())
+                .map(|values| values.len())
+        };
+        assert_eq!(resolve_len("abcdef"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve_len("abcdef0"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve_len("abcdef00"), PrefixResolution::SingleMatch(1000));
+        assert_eq!(resolve_len("abcdef01"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve_len("abcdef010"), PrefixResolution::SingleMatch(1));
+        assert_eq!(resolve_len("abcdef02"), PrefixResolution::NoMatch);
+
+        let resolution = id_index.resolve_prefix_iter(&HexPrefix::new("abcdef00").unwrap());
+        match resolution {
+            PrefixResolution::SingleMatch(values) => {
+                assert!(values.copied().sorted().eq(0..1000));
+            }
+            _ => panic!("expected single match"),
+        }
+    }
+
+    #[test]
+    fn test_id_index_duplicate_entries() {
+        let id_index = IdIndex::from_vec(vec![
+            (ChangeId::from_hex("0aab"), 4),
+            (ChangeId::from_hex("0000"), 0),
+            (ChangeId::from_hex("0099"), 1),
+            (ChangeId::from_hex("0aab"), 5),
+            (ChangeId::from_hex("0099"), 2),
+            (ChangeId::from_hex("0aaa"), 3),
+            (ChangeId::from_hex("0099"), 6),
+        ]);
+        let duplicates = id_index
+            .duplicate_entries()
+            .into_iter()
+            .map(|(key, values)| {
+                (
+                    key.hex(),
+                    values.into_iter().copied().sorted().collect_vec(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            duplicates,
+            vec![
+                ("0099".to_owned(), vec![1, 2, 6]),
+                ("0aab".to_owned(), vec![4, 5]),
+            ]
+        );
+
+        let id_index = IdIndex::from_vec(vec![
+            (ChangeId::from_hex("0000"), 0),
+            (ChangeId::from_hex("0001"), 1),
+        ]);
+        assert!(id_index.duplicate_entries().is_empty());
+        let id_index: IdIndex<ChangeId, i32> = IdIndex::from_vec(vec![]);
+        assert!(id_index.duplicate_entries().is_empty());
+    }
+
+    #[test]
+    fn test_id_index_resolve_prefix_keys() {
+        let id_index = IdIndex::from_vec(vec![
+            (ChangeId::from_hex("0aab"), 4),
+            (ChangeId::from_hex("0000"), 0),
+            (ChangeId::from_hex("0099"), 1),
+            (ChangeId::from_hex("0099"), 2),
+            (ChangeId::from_hex("0aaa"), 3),
+        ]);
+        let keys = |prefix: &str| id_index.resolve_prefix_keys(&HexPrefix::new(prefix).unwrap());
+        assert_eq!(
+            keys("0"),
+            vec![
+                ChangeId::from_hex("0000"),
+                ChangeId::from_hex("0099"),
+                ChangeId::from_hex("0aaa"),
+                ChangeId::from_hex("0aab"),
+            ]
+        );
+        assert_eq!(
+            keys("0aa"),
+            vec![ChangeId::from_hex("0aaa"), ChangeId::from_hex("0aab")]
+        );
+        // Duplicated keys are reported once
+        assert_eq!(keys("009"), vec![ChangeId::from_hex("0099")]);
+        assert_eq!(keys("f"), vec![]);
+    }
+
     #[test]
     fn test_id_index_shortest_unique_prefix_len() {
         // No crash if empty
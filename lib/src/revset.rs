@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ops::Range;
 use std::path::Path;
 use std::rc::Rc;
@@ -25,12 +25,14 @@ use pest::iterators::{Pair, Pairs};
 use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
 use pest_derive::Parser;
+use regex::Regex;
 use thiserror::Error;
 
-use crate::backend::{BackendError, BackendResult, CommitId};
+use crate::backend::{BackendError, BackendResult, CommitId, FileId, ObjectId};
 use crate::commit::Commit;
 use crate::default_index_store::{IndexEntry, IndexPosition};
-use crate::op_store::WorkspaceId;
+use crate::default_revset_engine::resolve_symbol;
+use crate::op_store::{OpStoreError, WorkspaceId};
 use crate::repo::Repo;
 use crate::repo_path::{FsPathParseError, RepoPath};
 use crate::store::Store;
@@ -41,8 +43,20 @@ pub enum RevsetError {
     NoSuchRevision(String),
     #[error("Commit or change id prefix \"{0}\" is ambiguous")]
     AmbiguousIdPrefix(String),
+    #[error("Branch name \"{0}\" matches multiple branches ignoring case")]
+    AmbiguousBranchName(String),
     #[error("Unexpected error from store: {0}")]
     StoreError(#[source] BackendError),
+    #[error("Invalid file pattern: {0}")]
+    FsPathParseError(#[source] FsPathParseError),
+    #[error("Cannot resolve file pattern without workspace")]
+    FsPathWithoutWorkspace,
+    #[error("Operation \"{0}\" doesn't exist")]
+    NoSuchOperation(String),
+    #[error("Operation id prefix \"{0}\" is ambiguous")]
+    AmbiguousOperationIdPrefix(String),
+    #[error("Unexpected error from operation store: {0}")]
+    OpStoreError(#[source] OpStoreError),
 }
 
 #[derive(Parser)]
@@ -80,8 +94,12 @@ pub enum RevsetParseErrorKind {
     FsPathParseError(#[source] FsPathParseError),
     #[error("Cannot resolve file pattern without workspace")]
     FsPathWithoutWorkspace,
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
     #[error("Redefinition of function parameter")]
     RedefinedFunctionParameter,
+    #[error("Function parameter without default follows parameter with default")]
+    NonDefaultFunctionParameter,
     #[error(r#"Alias "{0}" cannot be expanded"#)]
     BadAliasExpansion(String),
     #[error(r#"Alias "{0}" expanded recursively"#)]
@@ -179,24 +197,95 @@ impl error::Error for RevsetParseError {
 pub const GENERATION_RANGE_FULL: Range<u32> = 0..u32::MAX;
 pub const GENERATION_RANGE_EMPTY: Range<u32> = 0..0;
 
+/// Pattern to match strings such as commit descriptions against.
+#[derive(Clone, Debug)]
+pub enum StringPattern {
+    /// Matches strings containing the needle.
+    Substring(String),
+    /// Matches strings containing the needle, ignoring case. The needle is
+    /// lowercase.
+    SubstringCaseInsensitive(String),
+    /// Matches strings equal to the needle.
+    Exact(String),
+    /// Matches strings in which the regular expression finds a match.
+    Regex(Regex),
+}
+
+impl StringPattern {
+    pub fn matches(&self, haystack: &str) -> bool {
+        match self {
+            StringPattern::Substring(needle) => haystack.contains(needle.as_str()),
+            StringPattern::SubstringCaseInsensitive(needle) => {
+                haystack.to_lowercase().contains(needle.as_str())
+            }
+            StringPattern::Exact(needle) => haystack == needle,
+            StringPattern::Regex(regex) => regex.is_match(haystack),
+        }
+    }
+}
+
+// `Regex` doesn't implement `Eq`, so compare the source patterns instead.
+impl PartialEq for StringPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StringPattern::Substring(needle1), StringPattern::Substring(needle2)) => {
+                needle1 == needle2
+            }
+            (
+                StringPattern::SubstringCaseInsensitive(needle1),
+                StringPattern::SubstringCaseInsensitive(needle2),
+            ) => needle1 == needle2,
+            (StringPattern::Exact(needle1), StringPattern::Exact(needle2)) => needle1 == needle2,
+            (StringPattern::Regex(regex1), StringPattern::Regex(regex2)) => {
+                regex1.as_str() == regex2.as_str()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for StringPattern {}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum RevsetFilterPredicate {
     /// Commits with number of parents in the range.
     ParentCount(Range<u32>),
-    /// Commits with description containing the needle.
-    Description(String),
-    /// Commits with author's name or email containing the needle.
-    Author(String),
-    /// Commits with committer's name or email containing the needle.
-    Committer(String),
+    /// Commits with description matching the pattern.
+    Description(StringPattern),
+    /// Commits with author's name or email matching the pattern.
+    Author(StringPattern),
+    /// Commits with committer's name or email matching the pattern.
+    Committer(StringPattern),
     /// Commits modifying the paths specified by the pattern.
     File(Option<Vec<RepoPath>>), // TODO: embed matcher expression?
+    /// Commits modifying the paths specified by the file patterns, which will
+    /// be resolved against the workspace at evaluation time.
+    UnresolvedFile(Vec<String>),
+    /// Commits modifying the given file paths. Unlike `File`, files under a
+    /// directory of the same name don't match.
+    ExactFile(Vec<RepoPath>),
+    /// Like `ExactFile`, but with file patterns to be resolved against the
+    /// workspace at evaluation time.
+    UnresolvedExactFile(Vec<String>),
+    /// Commits whose tree contains a file with the given content id.
+    FileContent(FileId),
+    /// Commits with number of lines added or removed in the range.
+    Churn(Range<u32>),
+    /// Commits with conflicts in their tree.
+    HasConflict,
+    /// Visible commits sharing their change id with other visible commits.
+    Divergent,
 }
 
+/// Mapping from symbol names to the commit ids they resolved to.
+pub type ResolvedSymbols = HashMap<String, Vec<CommitId>>;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RevsetExpression {
     None,
     All,
+    /// The root commit.
+    Root,
     Commits(Vec<CommitId>),
     Symbol(String),
     Children(Rc<RevsetExpression>),
@@ -204,6 +293,18 @@ pub enum RevsetExpression {
         heads: Rc<RevsetExpression>,
         generation: Range<u32>,
     },
+    /// Descendants of "roots" at the generations in the range, "roots" being
+    /// at generation 0. Unbounded descendants are represented as `DagRange`.
+    Descendants {
+        roots: Rc<RevsetExpression>,
+        generation: Range<u32>,
+    },
+    /// Ancestors of "heads" reachable by following only first-parent edges,
+    /// including "heads".
+    FirstAncestors(Rc<RevsetExpression>),
+    /// Descendants of "roots" that reach one of "roots" by following only
+    /// first-parent edges, including "roots".
+    FirstDescendants(Rc<RevsetExpression>),
     // Commits that are ancestors of "heads" but not ancestors of "roots"
     Range {
         roots: Rc<RevsetExpression>,
@@ -215,18 +316,38 @@ pub enum RevsetExpression {
         roots: Rc<RevsetExpression>,
         heads: Rc<RevsetExpression>,
     },
+    /// Commits in "domain" that are connected to "sources" within "domain",
+    /// following parent and child edges alike.
+    Reachable {
+        sources: Rc<RevsetExpression>,
+        domain: Rc<RevsetExpression>,
+    },
     Heads(Rc<RevsetExpression>),
     Roots(Rc<RevsetExpression>),
     VisibleHeads,
+    /// Heads of all commits in the index, including hidden ones.
+    AllHeads,
     PublicHeads,
     Branches(String),
     RemoteBranches {
-        branch_needle: String,
+        branch_pattern: StringPattern,
         remote_needle: String,
     },
+    /// Local branch targets that are descendants of their remote targets.
+    BranchesAhead,
+    /// Local branch targets that are ancestors of their remote targets.
+    BranchesBehind,
     Tags,
     GitRefs,
     GitHead,
+    /// Commits and their transitive predecessors.
+    Evolog(Rc<RevsetExpression>),
+    /// Indexed commits (including hidden ones) that are predecessors of other
+    /// indexed commits, i.e. that have been rewritten.
+    Obsolete,
+    /// Commits added by the operation with the given id (or id prefix), or by
+    /// the current operation if `None`.
+    OpAffected(Option<String>),
     Filter(RevsetFilterPredicate),
     /// Marker for subtree that should be intersected as filter.
     AsFilter(Rc<RevsetExpression>),
@@ -246,6 +367,10 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::All)
     }
 
+    pub fn root() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Root)
+    }
+
     pub fn symbol(value: String) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Symbol(value))
     }
@@ -262,6 +387,10 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::VisibleHeads)
     }
 
+    pub fn all_heads() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::AllHeads)
+    }
+
     pub fn public_heads() -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::PublicHeads)
     }
@@ -270,13 +399,24 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::Branches(needle))
     }
 
-    pub fn remote_branches(branch_needle: String, remote_needle: String) -> Rc<RevsetExpression> {
+    pub fn remote_branches(
+        branch_pattern: StringPattern,
+        remote_needle: String,
+    ) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::RemoteBranches {
-            branch_needle,
+            branch_pattern,
             remote_needle,
         })
     }
 
+    pub fn branches_ahead() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::BranchesAhead)
+    }
+
+    pub fn branches_behind() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::BranchesBehind)
+    }
+
     pub fn tags() -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Tags)
     }
@@ -289,6 +429,19 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::GitHead)
     }
 
+    pub fn obsolete() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Obsolete)
+    }
+
+    /// Commits in `self` and the commits they were rewritten from, recursively.
+    pub fn evolog(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Evolog(self.clone()))
+    }
+
+    pub fn op_affected(op_id: Option<String>) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::OpAffected(op_id))
+    }
+
     pub fn filter(predicate: RevsetFilterPredicate) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Filter(predicate))
     }
@@ -319,7 +472,63 @@ impl RevsetExpression {
         })
     }
 
-    /// Children of `self`.
+    /// Ancestors of `self` at the generations in the range, `self` being at
+    /// generation 0.
+    pub fn ancestors_range(
+        self: &Rc<RevsetExpression>,
+        generation: Range<u32>,
+    ) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Ancestors {
+            heads: self.clone(),
+            generation,
+        })
+    }
+
+    /// Commits reachable from any of the given roots, walking at most `limit`
+    /// generations from each root (so a limit of 1 selects just the root).
+    ///
+    /// Each root is evaluated as an independent bounded ancestor walk, and the
+    /// results are unioned. A commit reachable within the limit of one root is
+    /// included regardless of the limits of the other roots.
+    pub fn reachable_from_any(
+        roots_with_limits: &[(Rc<RevsetExpression>, u32)],
+    ) -> Rc<RevsetExpression> {
+        roots_with_limits
+            .iter()
+            .map(|(root, limit)| {
+                Rc::new(RevsetExpression::Ancestors {
+                    heads: root.clone(),
+                    generation: 0..*limit,
+                })
+            })
+            .reduce(|acc, expression| acc.union(&expression))
+            .unwrap_or_else(RevsetExpression::none)
+    }
+
+    /// Ancestors of `self`, including `self`, that are also in `predicate`.
+    /// `predicate` is typically a filter such as `description(needle)`, which
+    /// is then only evaluated against the ancestors.
+    pub fn ancestors_matching(
+        self: &Rc<RevsetExpression>,
+        predicate: &Rc<RevsetExpression>,
+    ) -> Rc<RevsetExpression> {
+        self.ancestors().intersection(predicate)
+    }
+
+    /// Ancestors of `self` along first-parent edges, including `self`.
+    pub fn first_ancestors(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::FirstAncestors(self.clone()))
+    }
+
+    /// Descendants of `self` whose first-parent chain reaches `self`, including
+    /// `self`. Merges are included only if `self` is on their first-parent
+    /// side.
+    pub fn first_descendants(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::FirstDescendants(self.clone()))
+    }
+
+    /// Children of `self`. Hidden children (which aren't ancestors of the
+    /// visible heads) are excluded.
     pub fn children(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Children(self.clone()))
     }
@@ -329,6 +538,37 @@ impl RevsetExpression {
         self.dag_range_to(&RevsetExpression::visible_heads())
     }
 
+    /// Descendants of `self` at the generations in the range, `self` being at
+    /// generation 0.
+    pub fn descendants_range(
+        self: &Rc<RevsetExpression>,
+        generation: Range<u32>,
+    ) -> Rc<RevsetExpression> {
+        if generation == GENERATION_RANGE_FULL {
+            self.descendants()
+        } else {
+            Rc::new(RevsetExpression::Descendants {
+                roots: self.clone(),
+                generation,
+            })
+        }
+    }
+
+    /// Descendants of `roots`, including `roots`. If `include_hidden` is true,
+    /// descendants that are only reachable from hidden heads are included as
+    /// well.
+    pub fn descendants_of_roots(
+        roots: &Rc<RevsetExpression>,
+        include_hidden: bool,
+    ) -> Rc<RevsetExpression> {
+        let heads = if include_hidden {
+            RevsetExpression::all_heads()
+        } else {
+            RevsetExpression::visible_heads()
+        };
+        roots.dag_range_to(&heads)
+    }
+
     /// Commits that are descendants of `self` and ancestors of `heads`, both
     /// inclusive.
     pub fn dag_range_to(
@@ -347,6 +587,18 @@ impl RevsetExpression {
         self.dag_range_to(self)
     }
 
+    /// Commits in `domain` that can be reached from `self` by following parent
+    /// or child edges without leaving `domain`.
+    pub fn reachable(
+        self: &Rc<RevsetExpression>,
+        domain: &Rc<RevsetExpression>,
+    ) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Reachable {
+            sources: self.clone(),
+            domain: domain.clone(),
+        })
+    }
+
     /// Commits reachable from `heads` but not from `self`.
     pub fn range(
         self: &Rc<RevsetExpression>,
@@ -395,12 +647,384 @@ impl RevsetExpression {
     ) -> Result<Box<dyn Revset<'index> + 'index>, RevsetError> {
         repo.index().evaluate_revset(repo, self, workspace_ctx)
     }
+
+    /// Evaluates the expression, and also reports the commit ids each symbol
+    /// in the expression resolved to.
+    ///
+    /// Symbols that don't exist (which is allowed inside `present()`) are
+    /// omitted from the map.
+    pub fn evaluate_explained<'index>(
+        self: &Rc<RevsetExpression>,
+        repo: &'index dyn Repo,
+        workspace_ctx: Option<&RevsetWorkspaceContext>,
+    ) -> Result<(Box<dyn Revset<'index> + 'index>, ResolvedSymbols), RevsetError> {
+        let revset = self.evaluate(repo, workspace_ctx)?;
+        let resolved = self.resolve_symbols(repo, workspace_ctx)?;
+        Ok((revset, resolved))
+    }
+
+    /// Returns the names of all symbols in the expression, without duplicates.
+    pub fn collect_symbols(self: &Rc<RevsetExpression>) -> Vec<String> {
+        let mut symbols = vec![];
+        transform_expression_bottom_up(self, |expression| {
+            if let RevsetExpression::Symbol(symbol) = expression.as_ref() {
+                if !symbols.contains(symbol) {
+                    symbols.push(symbol.clone());
+                }
+            }
+            None
+        });
+        symbols
+    }
+
+    /// Resolves the symbols in the expression to commit ids without evaluating
+    /// the expression.
+    ///
+    /// Symbols that don't exist are omitted from the map, since they are
+    /// allowed inside `present()`. Other resolution errors are returned.
+    pub fn resolve_symbols(
+        self: &Rc<RevsetExpression>,
+        repo: &dyn Repo,
+        workspace_ctx: Option<&RevsetWorkspaceContext>,
+    ) -> Result<ResolvedSymbols, RevsetError> {
+        let workspace_id = workspace_ctx.map(|ctx| ctx.workspace_id);
+        let mut resolved = HashMap::new();
+        for symbol in self.collect_symbols() {
+            match resolve_symbol(repo, &symbol, workspace_id) {
+                Ok(commit_ids) => {
+                    resolved.insert(symbol, commit_ids);
+                }
+                Err(RevsetError::NoSuchRevision(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Formats the expression in the revset language, e.g. to show what an alias
+/// expanded to.
+///
+/// Parsing the output and optimizing it gives back the optimized input, as
+/// long as no aliases are defined. File paths are printed relative to the
+/// workspace root, and string patterns are always quoted. `AllHeads` can't be
+/// written in the language and is printed as `all_heads()`, which doesn't
+/// parse.
+impl fmt::Display for RevsetExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (text, _) = format_expression(self);
+        f.write_str(&text)
+    }
+}
+
+/// How tightly an operator binds, from the loosest to the tightest.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Precedence {
+    Union,
+    Intersection,
+    Negate,
+    Range,
+    Neighbors,
+    Primary,
+}
+
+/// Formats the expression, and returns it along with the precedence of its
+/// outermost operator.
+fn format_expression(expression: &RevsetExpression) -> (String, Precedence) {
+    let argument = |expression: &RevsetExpression| format_expression(expression).0;
+    match expression {
+        RevsetExpression::None => format_function("none", &[]),
+        RevsetExpression::All => format_function("all", &[]),
+        RevsetExpression::Root => format_function("root", &[]),
+        RevsetExpression::Commits(commit_ids) => match commit_ids.as_slice() {
+            [] => format_function("none", &[]),
+            [commit_id] => (commit_id.hex(), Precedence::Primary),
+            _ => (
+                commit_ids.iter().map(|id| id.hex()).join(" | "),
+                Precedence::Union,
+            ),
+        },
+        RevsetExpression::Symbol(symbol) => (format_symbol(symbol), Precedence::Primary),
+        RevsetExpression::Children(roots) => (
+            format!("{}+", format_operand(roots, Precedence::Neighbors)),
+            Precedence::Neighbors,
+        ),
+        RevsetExpression::Ancestors { heads, generation } => format_ancestors(heads, generation),
+        RevsetExpression::Descendants { roots, generation } => format_function(
+            "descendants",
+            &[argument(roots), format_generation(generation)],
+        ),
+        RevsetExpression::FirstAncestors(heads) => {
+            format_function("first_ancestors", &[argument(heads)])
+        }
+        RevsetExpression::FirstDescendants(roots) => {
+            format_function("first_descendants", &[argument(roots)])
+        }
+        RevsetExpression::Range {
+            roots,
+            heads,
+            generation,
+        } => {
+            if generation.end != u32::MAX {
+                // There's no syntax for a bounded range, so spell out the
+                // difference it's optimized from.
+                let heads_ancestors = Rc::new(RevsetExpression::Ancestors {
+                    heads: heads.clone(),
+                    generation: generation.clone(),
+                });
+                return format_expression(&heads_ancestors.minus(&roots.ancestors()));
+            }
+            let roots_text = format_operand(roots, Precedence::Neighbors);
+            if generation.start == 0 && matches!(heads.as_ref(), RevsetExpression::VisibleHeads) {
+                (format!("{roots_text}.."), Precedence::Range)
+            } else {
+                let heads_text = format_operand(heads, Precedence::Neighbors);
+                let parents_ops = "-".repeat(generation.start as usize);
+                (
+                    format!("{roots_text}..{heads_text}{parents_ops}"),
+                    Precedence::Range,
+                )
+            }
+        }
+        RevsetExpression::DagRange { roots, heads } => {
+            let roots_text = format_operand(roots, Precedence::Neighbors);
+            if matches!(heads.as_ref(), RevsetExpression::VisibleHeads) {
+                (format!("{roots_text}:"), Precedence::Range)
+            } else {
+                let heads_text = format_operand(heads, Precedence::Neighbors);
+                (format!("{roots_text}:{heads_text}"), Precedence::Range)
+            }
+        }
+        RevsetExpression::Reachable { sources, domain } => {
+            format_function("reachable", &[argument(sources), argument(domain)])
+        }
+        RevsetExpression::Heads(candidates) => format_function("heads", &[argument(candidates)]),
+        RevsetExpression::Roots(candidates) => format_function("roots", &[argument(candidates)]),
+        RevsetExpression::VisibleHeads => format_function("heads", &[]),
+        RevsetExpression::AllHeads => format_function("all_heads", &[]),
+        RevsetExpression::PublicHeads => format_function("public_heads", &[]),
+        RevsetExpression::Branches(needle) => {
+            if needle.is_empty() {
+                format_function("branches", &[])
+            } else {
+                format_function("branches", &[format_string(needle)])
+            }
+        }
+        RevsetExpression::RemoteBranches {
+            branch_pattern,
+            remote_needle,
+        } => {
+            let mut args = vec![];
+            if branch_pattern != &StringPattern::Substring("".to_owned()) {
+                args.push(format_string_pattern(branch_pattern));
+            }
+            if !remote_needle.is_empty() {
+                args.push(format!("remote={}", format_string(remote_needle)));
+            }
+            format_function("remote_branches", &args)
+        }
+        RevsetExpression::BranchesAhead => format_function("branches_ahead", &[]),
+        RevsetExpression::BranchesBehind => format_function("branches_behind", &[]),
+        RevsetExpression::Tags => format_function("tags", &[]),
+        RevsetExpression::GitRefs => format_function("git_refs", &[]),
+        RevsetExpression::GitHead => format_function("git_head", &[]),
+        RevsetExpression::Evolog(candidates) => format_function("evolog", &[argument(candidates)]),
+        RevsetExpression::Obsolete => format_function("obsolete", &[]),
+        RevsetExpression::OpAffected(op_str) => match op_str {
+            Some(op_str) => format_function("op_affected", &[format_string(op_str)]),
+            None => format_function("op_affected", &[]),
+        },
+        RevsetExpression::Filter(predicate) => format_filter(predicate),
+        RevsetExpression::AsFilter(candidates) => format_expression(candidates),
+        RevsetExpression::Present(candidates) => {
+            format_function("present", &[argument(candidates)])
+        }
+        RevsetExpression::NotIn(complement) => match complement.as_ref() {
+            RevsetExpression::Filter(RevsetFilterPredicate::File(None)) => {
+                format_function("empty", &[])
+            }
+            _ => (
+                format!("~{}", format_operand(complement, Precedence::Negate)),
+                Precedence::Negate,
+            ),
+        },
+        RevsetExpression::Union(expression1, expression2) => (
+            format!(
+                "{} | {}",
+                format_operand(expression1, Precedence::Union),
+                format_operand(expression2, Precedence::Intersection)
+            ),
+            Precedence::Union,
+        ),
+        RevsetExpression::Intersection(expression1, expression2) => (
+            format!(
+                "{} & {}",
+                format_operand(expression1, Precedence::Intersection),
+                format_operand(expression2, Precedence::Negate)
+            ),
+            Precedence::Intersection,
+        ),
+        RevsetExpression::Difference(expression1, expression2) => (
+            format!(
+                "{} ~ {}",
+                format_operand(expression1, Precedence::Intersection),
+                format_operand(expression2, Precedence::Negate)
+            ),
+            Precedence::Intersection,
+        ),
+    }
+}
+
+fn format_function(name: &str, args: &[String]) -> (String, Precedence) {
+    (format!("{name}({})", args.join(", ")), Precedence::Primary)
+}
+
+/// Formats the expression, parenthesized if its operator binds looser than
+/// `min_precedence`.
+fn format_operand(expression: &RevsetExpression, min_precedence: Precedence) -> String {
+    let (text, precedence) = format_expression(expression);
+    if precedence < min_precedence {
+        format!("({text})")
+    } else {
+        text
+    }
+}
+
+fn format_ancestors(heads: &RevsetExpression, generation: &Range<u32>) -> (String, Precedence) {
+    if generation.end == u32::MAX {
+        // `:x--` is parsed as `:(x--)`, which is folded into a single range.
+        let heads_text = format_operand(heads, Precedence::Neighbors);
+        let parents_ops = "-".repeat(generation.start as usize);
+        (format!(":{heads_text}{parents_ops}"), Precedence::Range)
+    } else if generation.start == 0 && generation.end == 1 {
+        format_expression(heads)
+    } else if generation.len() == 1 {
+        let heads_text = format_operand(heads, Precedence::Neighbors);
+        let parents_ops = "-".repeat(generation.start as usize);
+        (format!("{heads_text}{parents_ops}"), Precedence::Neighbors)
+    } else {
+        format_function(
+            "ancestors",
+            &[format_expression(heads).0, format_generation(generation)],
+        )
+    }
+}
+
+fn format_generation(generation: &Range<u32>) -> String {
+    let Range { start, end } = generation.clone();
+    if end == u32::MAX {
+        format!("{start}..")
+    } else if generation.len() == 1 {
+        start.to_string()
+    } else {
+        format!("{start}..{end}")
+    }
+}
+
+fn format_filter(predicate: &RevsetFilterPredicate) -> (String, Precedence) {
+    match predicate {
+        RevsetFilterPredicate::ParentCount(parent_count_range) => {
+            let Range { start, end } = parent_count_range.clone();
+            if start == 2 && end == u32::MAX {
+                format_function("merges", &[])
+            } else if end == u32::MAX {
+                format_function("merges", &[start.to_string()])
+            } else if end - start == 1 {
+                format_function("merges", &[format!("exactly={start}")])
+            } else {
+                (
+                    format!("merges({start}) ~ merges({end})"),
+                    Precedence::Intersection,
+                )
+            }
+        }
+        RevsetFilterPredicate::Description(pattern) => {
+            format_function("description", &[format_string_pattern(pattern)])
+        }
+        RevsetFilterPredicate::Author(pattern) => {
+            format_function("author", &[format_string_pattern(pattern)])
+        }
+        RevsetFilterPredicate::Committer(pattern) => {
+            format_function("committer", &[format_string_pattern(pattern)])
+        }
+        RevsetFilterPredicate::File(None) => ("~empty()".to_owned(), Precedence::Negate),
+        RevsetFilterPredicate::File(Some(paths)) => {
+            let args = paths
+                .iter()
+                .map(|path| format_string(&path.to_internal_file_string()))
+                .collect_vec();
+            format_function("file", &args)
+        }
+        RevsetFilterPredicate::UnresolvedFile(patterns) => {
+            let args = patterns
+                .iter()
+                .map(|pattern| format_string(pattern))
+                .collect_vec();
+            format_function("file", &args)
+        }
+        RevsetFilterPredicate::ExactFile(paths) => {
+            let args = paths
+                .iter()
+                .map(|path| format!("exact:{}", format_string(&path.to_internal_file_string())))
+                .collect_vec();
+            format_function("file", &args)
+        }
+        RevsetFilterPredicate::UnresolvedExactFile(patterns) => {
+            let args = patterns
+                .iter()
+                .map(|pattern| format!("exact:{}", format_string(pattern)))
+                .collect_vec();
+            format_function("file", &args)
+        }
+        RevsetFilterPredicate::FileContent(file_id) => {
+            format_function("file_content", &[format_string(&file_id.hex())])
+        }
+        RevsetFilterPredicate::Churn(line_count_range) => {
+            let Range { start, end } = line_count_range.clone();
+            if end == u32::MAX {
+                format_function("churn", &[start.to_string()])
+            } else {
+                format_function("churn", &[start.to_string(), (end - 1).to_string()])
+            }
+        }
+        RevsetFilterPredicate::HasConflict => format_function("conflict", &[]),
+        RevsetFilterPredicate::Divergent => format_function("divergent", &[]),
+    }
+}
+
+fn format_string_pattern(pattern: &StringPattern) -> String {
+    match pattern {
+        StringPattern::Substring(needle) => format_string(needle),
+        StringPattern::SubstringCaseInsensitive(needle) => format!("i:{}", format_string(needle)),
+        StringPattern::Exact(needle) => format!("exact:{}", format_string(needle)),
+        StringPattern::Regex(regex) => format!("regex:{}", format_string(regex.as_str())),
+    }
+}
+
+/// Formats the symbol bare if it's an identifier, and quoted otherwise.
+fn format_symbol(symbol: &str) -> String {
+    let is_identifier_part = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '@' | '/'))
+    };
+    if symbol.split(['.', '-', '+']).all(is_identifier_part) {
+        symbol.to_owned()
+    } else {
+        format_string(symbol)
+    }
+}
+
+/// Quotes the text as a string literal, in which quotes are doubled.
+fn format_string(text: &str) -> String {
+    format!(r#""{}""#, text.replace('"', r#""""#))
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RevsetAliasesMap {
     symbol_aliases: HashMap<String, String>,
-    function_aliases: HashMap<String, (Vec<String>, String)>,
+    function_aliases: HashMap<String, (Vec<String>, Vec<String>, String)>,
 }
 
 impl RevsetAliasesMap {
@@ -421,8 +1045,9 @@ impl RevsetAliasesMap {
             RevsetAliasDeclaration::Symbol(name) => {
                 self.symbol_aliases.insert(name, defn.into());
             }
-            RevsetAliasDeclaration::Function(name, params) => {
-                self.function_aliases.insert(name, (params, defn.into()));
+            RevsetAliasDeclaration::Function(name, params, defaults) => {
+                self.function_aliases
+                    .insert(name, (params, defaults, defn.into()));
             }
         }
         Ok(())
@@ -437,13 +1062,14 @@ impl RevsetAliasesMap {
     fn get_function<'a>(
         &'a self,
         name: &str,
-    ) -> Option<(RevsetAliasId<'a>, &'a [String], &'a str)> {
+    ) -> Option<(RevsetAliasId<'a>, &'a [String], &'a [String], &'a str)> {
         self.function_aliases
             .get_key_value(name)
-            .map(|(name, (params, defn))| {
+            .map(|(name, (params, defaults, defn))| {
                 (
                     RevsetAliasId::Function(name),
                     params.as_ref(),
+                    defaults.as_ref(),
                     defn.as_ref(),
                 )
             })
@@ -454,7 +1080,9 @@ impl RevsetAliasesMap {
 #[derive(Clone, Debug)]
 enum RevsetAliasDeclaration {
     Symbol(String),
-    Function(String, Vec<String>),
+    /// Function name, parameter names, and default expressions of the trailing
+    /// parameters.
+    Function(String, Vec<String>, Vec<String>),
 }
 
 impl RevsetAliasDeclaration {
@@ -467,15 +1095,27 @@ impl RevsetAliasDeclaration {
                 let name = first.as_str().to_owned();
                 let params_pair = pairs.next().unwrap();
                 let params_span = params_pair.as_span();
-                let params = params_pair
-                    .into_inner()
-                    .map(|pair| match pair.as_rule() {
-                        Rule::identifier => pair.as_str().to_owned(),
-                        r => panic!("unexpected formal parameter rule {r:?}"),
-                    })
-                    .collect_vec();
+                let mut params = vec![];
+                let mut defaults = vec![];
+                for pair in params_pair.into_inner() {
+                    assert_eq!(pair.as_rule(), Rule::formal_parameter);
+                    let span = pair.as_span();
+                    let mut pairs = pair.into_inner();
+                    let ident = pairs.next().unwrap();
+                    assert_eq!(ident.as_rule(), Rule::identifier);
+                    params.push(ident.as_str().to_owned());
+                    if let Some(default) = pairs.next() {
+                        assert_eq!(default.as_rule(), Rule::expression);
+                        defaults.push(default.as_str().to_owned());
+                    } else if !defaults.is_empty() {
+                        return Err(RevsetParseError::with_span(
+                            RevsetParseErrorKind::NonDefaultFunctionParameter,
+                            span,
+                        ));
+                    }
+                }
                 if params.iter().all_unique() {
-                    Ok(RevsetAliasDeclaration::Function(name, params))
+                    Ok(RevsetAliasDeclaration::Function(name, params, defaults))
                 } else {
                     Err(RevsetParseError::with_span(
                         RevsetParseErrorKind::RedefinedFunctionParameter,
@@ -509,7 +1149,7 @@ struct ParseState<'a> {
     aliases_map: &'a RevsetAliasesMap,
     aliases_expanding: &'a [RevsetAliasId<'a>],
     locals: &'a HashMap<&'a str, Rc<RevsetExpression>>,
-    workspace_ctx: Option<&'a RevsetWorkspaceContext<'a>>,
+    options: &'a RevsetParseOptions<'a>,
 }
 
 impl ParseState<'_> {
@@ -533,7 +1173,7 @@ impl ParseState<'_> {
             aliases_map: self.aliases_map,
             aliases_expanding: &aliases_expanding,
             locals,
-            workspace_ctx: self.workspace_ctx,
+            options: self.options,
         };
         f(expanding_state).map_err(|e| {
             RevsetParseError::with_span_and_origin(
@@ -588,6 +1228,34 @@ fn parse_expression_rule(
         )
     }
 
+    if let Some(pair) = pairs.peek() {
+        if pair.as_rule() == Rule::string_pattern {
+            // Unless it's the argument of a function taking a string pattern,
+            // `kind:needle` is the DAG range it's spelled as.
+            let mut pattern_pairs = pair.into_inner();
+            let roots = parse_symbol_rule(pattern_pairs.next().unwrap(), state)?;
+            let heads = parse_symbol_rule(
+                pattern_pairs.next().unwrap().into_inner().next().unwrap(),
+                state,
+            )?;
+            return Ok(roots.dag_range_to(&heads));
+        }
+        if pair.as_rule() == Rule::generation_range {
+            // Unless it's a generation argument, `start..end` is the range of
+            // commits it's spelled as, and `n` is a symbol.
+            let mut range_pairs = pair.into_inner();
+            let start = parse_symbol_rule(range_pairs.next().unwrap(), state)?;
+            if range_pairs.next().is_none() {
+                return Ok(start);
+            }
+            let end = match range_pairs.next() {
+                Some(end) => parse_symbol_rule(end, state)?,
+                None => RevsetExpression::visible_heads(),
+            };
+            return Ok(start.range(&end));
+        }
+    }
+
     static PRATT: Lazy<PrattParser<Rule>> = Lazy::new(|| {
         PrattParser::new()
             .op(Op::infix(Rule::union_op, Assoc::Left)
@@ -603,7 +1271,8 @@ fn parse_expression_rule(
             // Neighbors
             .op(Op::postfix(Rule::parents_op)
                 | Op::postfix(Rule::children_op)
-                | Op::postfix(Rule::compat_parents_op))
+                | Op::postfix(Rule::compat_parents_op)
+                | Op::postfix(Rule::compat_ancestors_op))
     });
     PRATT
         .map_primary(|primary| parse_primary_rule(primary, state))
@@ -618,6 +1287,22 @@ fn parse_expression_rule(
             Rule::parents_op => Ok(lhs?.parents()),
             Rule::children_op => Ok(lhs?.children()),
             Rule::compat_parents_op => Err(not_postfix_op(&op, "-", "parents")),
+            Rule::compat_ancestors_op => {
+                let generation = &op.as_str()[1..];
+                match generation {
+                    "" | "1" => Err(not_postfix_op(&op, "-", "parents")),
+                    _ if generation.len() == 1 => {
+                        let similar_op = "-".repeat(generation.parse().unwrap());
+                        Err(not_postfix_op(&op, similar_op, "ancestors"))
+                    }
+                    // Spelling out many generations as "-" would be unreadable
+                    _ => Err(not_postfix_op(
+                        &op,
+                        format!("ancestors(x, {generation})"),
+                        "ancestors",
+                    )),
+                }
+            }
             r => panic!("unexpected postfix operator rule {r:?}"),
         })
         .map_infix(|lhs, op, rhs| match op.as_rule() {
@@ -646,7 +1331,7 @@ fn parse_primary_rule(
             let arguments_pair = pairs.next().unwrap();
             parse_function_expression(first, arguments_pair, state, span)
         }
-        Rule::symbol => parse_symbol_rule(first.into_inner(), state),
+        Rule::symbol => parse_symbol_rule(first.into_inner().next().unwrap(), state),
         _ => {
             panic!("unexpected revset parse rule: {:?}", first.as_str());
         }
@@ -654,12 +1339,11 @@ fn parse_primary_rule(
 }
 
 fn parse_symbol_rule(
-    mut pairs: Pairs<Rule>,
+    first: Pair<Rule>,
     state: ParseState,
 ) -> Result<Rc<RevsetExpression>, RevsetParseError> {
-    let first = pairs.next().unwrap();
     match first.as_rule() {
-        Rule::identifier => {
+        Rule::identifier | Rule::integer_literal => {
             let name = first.as_str();
             if let Some(expr) = state.locals.get(name) {
                 Ok(expr.clone())
@@ -672,23 +1356,31 @@ fn parse_symbol_rule(
                 Ok(RevsetExpression::symbol(name.to_owned()))
             }
         }
-        Rule::literal_string => {
-            return Ok(RevsetExpression::symbol(
-                first
-                    .as_str()
-                    .strip_prefix('"')
-                    .unwrap()
-                    .strip_suffix('"')
-                    .unwrap()
-                    .to_owned(),
-            ));
-        }
+        Rule::literal_string => Ok(RevsetExpression::symbol(parse_symbol_text(first))),
         _ => {
             panic!("unexpected symbol parse rule: {:?}", first.as_str());
         }
     }
 }
 
+/// Returns the text of an identifier, or the unescaped contents of a string
+/// literal, without substituting aliases.
+fn parse_symbol_text(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::identifier => pair.as_str().to_owned(),
+        Rule::literal_string => pair
+            .as_str()
+            .strip_prefix('"')
+            .unwrap()
+            .strip_suffix('"')
+            .unwrap()
+            .replace(r#""""#, r#"""#),
+        _ => {
+            panic!("unexpected symbol parse rule: {:?}", pair.as_str());
+        }
+    }
+}
+
 fn parse_function_expression(
     name_pair: Pair<Rule>,
     arguments_pair: Pair<Rule>,
@@ -696,16 +1388,28 @@ fn parse_function_expression(
     primary_span: pest::Span<'_>,
 ) -> Result<Rc<RevsetExpression>, RevsetParseError> {
     let name = name_pair.as_str();
-    if let Some((id, params, defn)) = state.aliases_map.get_function(name) {
+    if let Some((id, params, defaults, defn)) = state.aliases_map.get_function(name) {
         // Resolve arguments in the current scope, and pass them in to the alias
         // expansion scope.
+        let min_arg_count = params.len() - defaults.len();
         let (required, optional) =
-            expect_named_arguments_vec(name, &[], arguments_pair, params.len(), params.len())?;
-        assert!(optional.is_empty());
-        let args: Vec<_> = required
+            expect_named_arguments_vec(name, &[], arguments_pair, min_arg_count, params.len())?;
+        let mut args: Vec<_> = required
             .into_iter()
             .map(|arg| parse_expression_rule(arg.into_inner(), state))
             .try_collect()?;
+        for (arg, default) in optional.into_iter().zip(defaults) {
+            let expression = if let Some(arg) = arg {
+                parse_expression_rule(arg.into_inner(), state)?
+            } else {
+                // Omitted argument is resolved in the alias definition scope,
+                // where no other parameters are visible.
+                state.with_alias_expanding(id, &HashMap::new(), primary_span, |state| {
+                    parse_program(default, state)
+                })?
+            };
+            args.push(expression);
+        }
         let locals = params.iter().map(|s| s.as_str()).zip(args).collect();
         state.with_alias_expanding(id, &locals, primary_span, |state| {
             parse_program(defn, state)
@@ -733,20 +1437,44 @@ fn parse_builtin_function(
             Ok(expression.children())
         }
         "ancestors" => {
-            let arg = expect_one_argument(name, arguments_pair)?;
+            let ([arg], [generation_opt_arg]) = expect_arguments(name, arguments_pair)?;
             let expression = parse_expression_rule(arg.into_inner(), state)?;
-            Ok(expression.ancestors())
+            let generation = generation_opt_arg
+                .map(|arg| parse_function_argument_to_generation_range(name, arg))
+                .transpose()?
+                .unwrap_or(GENERATION_RANGE_FULL);
+            Ok(expression.ancestors_range(generation))
         }
         "descendants" => {
+            let ([arg], [generation_opt_arg]) = expect_arguments(name, arguments_pair)?;
+            let expression = parse_expression_rule(arg.into_inner(), state)?;
+            let generation = generation_opt_arg
+                .map(|arg| parse_function_argument_to_generation_range(name, arg))
+                .transpose()?
+                .unwrap_or(GENERATION_RANGE_FULL);
+            Ok(expression.descendants_range(generation))
+        }
+        "first_ancestors" => {
+            let arg = expect_one_argument(name, arguments_pair)?;
+            let expression = parse_expression_rule(arg.into_inner(), state)?;
+            Ok(expression.first_ancestors())
+        }
+        "first_descendants" => {
             let arg = expect_one_argument(name, arguments_pair)?;
             let expression = parse_expression_rule(arg.into_inner(), state)?;
-            Ok(expression.descendants())
+            Ok(expression.first_descendants())
         }
         "connected" => {
             let arg = expect_one_argument(name, arguments_pair)?;
             let candidates = parse_expression_rule(arg.into_inner(), state)?;
             Ok(candidates.connected())
         }
+        "reachable" => {
+            let ([source_arg, domain_arg], []) = expect_arguments(name, arguments_pair)?;
+            let sources = parse_expression_rule(source_arg.into_inner(), state)?;
+            let domain = parse_expression_rule(domain_arg.into_inner(), state)?;
+            Ok(sources.reachable(&domain))
+        }
         "none" => {
             expect_no_arguments(name, arguments_pair)?;
             Ok(RevsetExpression::none())
@@ -755,11 +1483,17 @@ fn parse_builtin_function(
             expect_no_arguments(name, arguments_pair)?;
             Ok(RevsetExpression::all())
         }
+        "root" => {
+            expect_no_arguments(name, arguments_pair)?;
+            Ok(RevsetExpression::root())
+        }
         "heads" => {
             let ([], [opt_arg]) = expect_arguments(name, arguments_pair)?;
             if let Some(arg) = opt_arg {
                 let candidates = parse_expression_rule(arg.into_inner(), state)?;
                 Ok(candidates.heads())
+            } else if let Some(default_heads) = &state.options.default_heads {
+                Ok(default_heads.clone())
             } else {
                 Ok(RevsetExpression::visible_heads())
             }
@@ -785,10 +1519,10 @@ fn parse_builtin_function(
         "remote_branches" => {
             let ([], [branch_opt_arg, remote_opt_arg]) =
                 expect_named_arguments(name, &["", "remote"], arguments_pair)?;
-            let branch_needle = if let Some(branch_arg) = branch_opt_arg {
-                parse_function_argument_to_string(name, branch_arg, state)?
+            let branch_pattern = if let Some(branch_arg) = branch_opt_arg {
+                parse_function_argument_to_string_pattern(name, branch_arg, state)?
             } else {
-                "".to_owned()
+                StringPattern::Substring("".to_owned())
             };
             let remote_needle = if let Some(remote_arg) = remote_opt_arg {
                 parse_function_argument_to_string(name, remote_arg, state)?
@@ -796,10 +1530,18 @@ fn parse_builtin_function(
                 "".to_owned()
             };
             Ok(RevsetExpression::remote_branches(
-                branch_needle,
+                branch_pattern,
                 remote_needle,
             ))
         }
+        "branches_ahead" => {
+            expect_no_arguments(name, arguments_pair)?;
+            Ok(RevsetExpression::branches_ahead())
+        }
+        "branches_behind" => {
+            expect_no_arguments(name, arguments_pair)?;
+            Ok(RevsetExpression::branches_behind())
+        }
         "tags" => {
             expect_no_arguments(name, arguments_pair)?;
             Ok(RevsetExpression::tags())
@@ -812,31 +1554,96 @@ fn parse_builtin_function(
             expect_no_arguments(name, arguments_pair)?;
             Ok(RevsetExpression::git_head())
         }
-        "merges" => {
+        "obsolete" => {
             expect_no_arguments(name, arguments_pair)?;
-            Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::ParentCount(2..u32::MAX),
-            ))
+            Ok(RevsetExpression::obsolete())
         }
-        "description" => {
+        "evolog" => {
             let arg = expect_one_argument(name, arguments_pair)?;
-            let needle = parse_function_argument_to_string(name, arg, state)?;
+            let expression = parse_expression_rule(arg.into_inner(), state)?;
+            Ok(expression.evolog())
+        }
+        "op_affected" => {
+            let ([], [opt_arg]) = expect_arguments(name, arguments_pair)?;
+            let op_id = opt_arg
+                .map(|arg| parse_function_argument_to_string(name, arg, state))
+                .transpose()?;
+            Ok(RevsetExpression::op_affected(op_id))
+        }
+        "merges" => {
+            let arguments_span = arguments_pair.as_span();
+            let ([], [min_opt_arg, exact_opt_arg]) =
+                expect_named_arguments(name, &["", "exactly"], arguments_pair)?;
+            let parent_count_range = match (min_opt_arg, exact_opt_arg) {
+                (None, None) => 2..u32::MAX,
+                (Some(min_arg), None) => {
+                    let min = parse_merge_parent_count(name, min_arg, state)?;
+                    min..u32::MAX
+                }
+                (None, Some(exact_arg)) => {
+                    let exact = parse_merge_parent_count(name, exact_arg, state)?;
+                    exact..exact + 1
+                }
+                (Some(_), Some(_)) => {
+                    return Err(RevsetParseError::with_span(
+                        RevsetParseErrorKind::InvalidFunctionArguments {
+                            name: name.to_owned(),
+                            message: "Expected either a minimum or an exact parent count"
+                                .to_owned(),
+                        },
+                        arguments_span,
+                    ));
+                }
+            };
             Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::Description(needle),
+                RevsetFilterPredicate::ParentCount(parent_count_range),
             ))
         }
-        "author" => {
-            let arg = expect_one_argument(name, arguments_pair)?;
-            let needle = parse_function_argument_to_string(name, arg, state)?;
-            Ok(RevsetExpression::filter(RevsetFilterPredicate::Author(
-                needle,
-            )))
-        }
-        "committer" => {
+        "churn" => {
+            let arguments_span = arguments_pair.as_span();
+            let ([min_arg], [max_opt_arg]) = expect_arguments(name, arguments_pair)?;
+            let min = parse_function_argument_to_u32(name, min_arg, state)?;
+            let max = max_opt_arg
+                .map(|arg| parse_function_argument_to_u32(name, arg, state))
+                .transpose()?;
+            let line_count_range = match max {
+                None => min..u32::MAX,
+                Some(max) if min <= max => min..max.saturating_add(1),
+                Some(_) => {
+                    return Err(RevsetParseError::with_span(
+                        RevsetParseErrorKind::InvalidFunctionArguments {
+                            name: name.to_owned(),
+                            message: "Expected the minimum to not exceed the maximum".to_owned(),
+                        },
+                        arguments_span,
+                    ));
+                }
+            };
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Churn(
+                line_count_range,
+            )))
+        }
+        "description" => {
             let arg = expect_one_argument(name, arguments_pair)?;
-            let needle = parse_function_argument_to_string(name, arg, state)?;
+            let pattern = parse_function_argument_to_string_pattern(name, arg, state)?;
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::Description(pattern),
+            ))
+        }
+        "author" => {
+            let arg = expect_one_argument(name, arguments_pair)?;
+            let pattern =
+                trim_string_pattern(parse_function_argument_to_string_pattern(name, arg, state)?);
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Author(
+                pattern,
+            )))
+        }
+        "committer" => {
+            let arg = expect_one_argument(name, arguments_pair)?;
+            let pattern =
+                trim_string_pattern(parse_function_argument_to_string_pattern(name, arg, state)?);
             Ok(RevsetExpression::filter(RevsetFilterPredicate::Committer(
-                needle,
+                pattern,
             )))
         }
         "empty" => {
@@ -844,41 +1651,100 @@ fn parse_builtin_function(
             Ok(RevsetExpression::filter(RevsetFilterPredicate::File(None)).negated())
         }
         "file" => {
-            if let Some(ctx) = state.workspace_ctx {
-                let arguments_span = arguments_pair.as_span();
-                let paths: Vec<_> = arguments_pair
-                    .into_inner()
-                    .map(|arg| -> Result<_, RevsetParseError> {
-                        let span = arg.as_span();
-                        let needle = parse_function_argument_to_string(name, arg, state)?;
-                        let path = RepoPath::parse_fs_path(ctx.cwd, ctx.workspace_root, &needle)
-                            .map_err(|e| {
-                                RevsetParseError::with_span(
-                                    RevsetParseErrorKind::FsPathParseError(e),
-                                    span,
-                                )
-                            })?;
-                        Ok(path)
-                    })
-                    .try_collect()?;
-                if paths.is_empty() {
-                    Err(RevsetParseError::with_span(
-                        RevsetParseErrorKind::InvalidFunctionArguments {
-                            name: name.to_owned(),
-                            message: "Expected at least 1 argument".to_string(),
-                        },
-                        arguments_span,
-                    ))
-                } else {
-                    Ok(RevsetExpression::filter(RevsetFilterPredicate::File(Some(
-                        paths,
-                    ))))
-                }
-            } else {
-                Err(RevsetParseError::new(
+            if state.options.workspace_ctx.is_none() && !state.options.defer_file_patterns {
+                return Err(RevsetParseError::new(
                     RevsetParseErrorKind::FsPathWithoutWorkspace,
-                ))
+                ));
             }
+            let arguments_span = arguments_pair.as_span();
+            let args: Vec<_> = arguments_pair
+                .into_inner()
+                .map(|arg| {
+                    let span = arg.as_span();
+                    let (is_exact, needle) =
+                        parse_function_argument_to_file_pattern(name, arg, state)?;
+                    Ok::<_, RevsetParseError>((is_exact, needle, span))
+                })
+                .try_collect()?;
+            if args.is_empty() {
+                return Err(RevsetParseError::with_span(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name: name.to_owned(),
+                        message: "Expected at least 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ));
+            }
+            let (exact_args, prefix_args): (Vec<_>, Vec<_>) =
+                args.into_iter().partition(|(is_exact, _, _)| *is_exact);
+            let predicates = if let Some(ctx) = state.options.workspace_ctx {
+                let resolve = |args: Vec<(bool, String, pest::Span)>| -> Result<Vec<_>, _> {
+                    args.into_iter()
+                        .map(|(_, needle, span)| {
+                            RepoPath::parse_fs_path(ctx.cwd, ctx.workspace_root, &needle).map_err(
+                                |e| {
+                                    RevsetParseError::with_span(
+                                        RevsetParseErrorKind::FsPathParseError(e),
+                                        span,
+                                    )
+                                },
+                            )
+                        })
+                        .collect()
+                };
+                let paths = resolve(prefix_args)?;
+                let exact_paths = resolve(exact_args)?;
+                [
+                    (!paths.is_empty()).then(|| RevsetFilterPredicate::File(Some(paths))),
+                    (!exact_paths.is_empty())
+                        .then(|| RevsetFilterPredicate::ExactFile(exact_paths)),
+                ]
+            } else {
+                let patterns = prefix_args
+                    .into_iter()
+                    .map(|(_, needle, _)| needle)
+                    .collect_vec();
+                let exact_patterns = exact_args
+                    .into_iter()
+                    .map(|(_, needle, _)| needle)
+                    .collect_vec();
+                [
+                    (!patterns.is_empty()).then(|| RevsetFilterPredicate::UnresolvedFile(patterns)),
+                    (!exact_patterns.is_empty())
+                        .then(|| RevsetFilterPredicate::UnresolvedExactFile(exact_patterns)),
+                ]
+            };
+            Ok(predicates
+                .into_iter()
+                .flatten()
+                .map(RevsetExpression::filter)
+                .reduce(|expression1, expression2| expression1.union(&expression2))
+                .unwrap())
+        }
+        "file_content" => {
+            let arg = expect_one_argument(name, arguments_pair)?;
+            let span = arg.as_span();
+            let needle = parse_function_argument_to_string(name, arg, state)?;
+            let file_id = hex::decode(&needle).map(FileId::new).map_err(|_| {
+                RevsetParseError::with_span(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name: name.to_owned(),
+                        message: "Expected a hexadecimal file id".to_string(),
+                    },
+                    span,
+                )
+            })?;
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::FileContent(file_id),
+            ))
+        }
+        "conflict" => {
+            expect_no_arguments(name, arguments_pair)?;
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::HasConflict))
+        }
+        "divergent" => {
+            expect_no_arguments(name, arguments_pair)?;
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Divergent))
         }
         "present" => {
             let arg = expect_one_argument(name, arguments_pair)?;
@@ -1014,6 +1880,80 @@ fn expect_named_arguments_vec<'i>(
     Ok((required, optional))
 }
 
+/// Returns the kind and the needle of an argument written as `kind:needle`.
+/// Neither of them is substituted with aliases.
+fn parse_string_pattern_rule<'i>(pair: &Pair<'i, Rule>) -> Option<(&'i str, String)> {
+    let first = pair.clone().into_inner().next()?;
+    if first.as_rule() != Rule::string_pattern {
+        return None;
+    }
+    let mut pairs = first.into_inner();
+    let kind = pairs.next().unwrap();
+    let needle = pairs.next().unwrap().into_inner().next().unwrap();
+    Some((kind.as_str(), parse_symbol_text(needle)))
+}
+
+/// Parses a string argument, which is matched as a regular expression if it's
+/// written as `regex:"pattern"`, as a case-insensitive substring if it's
+/// written as `i:"needle"`, as a whole string if it's written as
+/// `exact:"needle"`, and as a substring otherwise.
+fn parse_function_argument_to_string_pattern(
+    name: &str,
+    pair: Pair<Rule>,
+    state: ParseState,
+) -> Result<StringPattern, RevsetParseError> {
+    let span = pair.as_span();
+    match parse_string_pattern_rule(&pair) {
+        Some(("regex", pattern)) => {
+            let regex = Regex::new(&pattern).map_err(|err| {
+                RevsetParseError::with_span(
+                    RevsetParseErrorKind::InvalidRegex(err.to_string()),
+                    span,
+                )
+            })?;
+            Ok(StringPattern::Regex(regex))
+        }
+        Some(("i", needle)) => Ok(StringPattern::SubstringCaseInsensitive(
+            needle.to_lowercase(),
+        )),
+        Some(("exact", needle)) => Ok(StringPattern::Exact(needle)),
+        _ => {
+            let needle = parse_function_argument_to_string(name, pair, state)?;
+            Ok(StringPattern::Substring(needle))
+        }
+    }
+}
+
+/// Parses a `file()` argument, which is matched as an exact file path if it's
+/// written as `exact:"path"`, and as a path prefix otherwise. Returns whether
+/// the argument is exact along with the path.
+fn parse_function_argument_to_file_pattern(
+    name: &str,
+    pair: Pair<Rule>,
+    state: ParseState,
+) -> Result<(bool, String), RevsetParseError> {
+    match parse_string_pattern_rule(&pair) {
+        Some(("exact", path)) => Ok((true, path)),
+        _ => {
+            let needle = parse_function_argument_to_string(name, pair, state)?;
+            Ok((false, needle))
+        }
+    }
+}
+
+/// Strips leading and trailing whitespace from the needle of non-regex
+/// patterns. Regular expressions are left as is.
+fn trim_string_pattern(pattern: StringPattern) -> StringPattern {
+    match pattern {
+        StringPattern::Substring(needle) => StringPattern::Substring(needle.trim().to_owned()),
+        StringPattern::SubstringCaseInsensitive(needle) => {
+            StringPattern::SubstringCaseInsensitive(needle.trim().to_owned())
+        }
+        StringPattern::Exact(needle) => StringPattern::Exact(needle.trim().to_owned()),
+        pattern => pattern,
+    }
+}
+
 fn parse_function_argument_to_string(
     name: &str,
     pair: Pair<Rule>,
@@ -1033,16 +1973,117 @@ fn parse_function_argument_to_string(
     }
 }
 
+fn parse_function_argument_to_u32(
+    name: &str,
+    pair: Pair<Rule>,
+    state: ParseState,
+) -> Result<u32, RevsetParseError> {
+    let span = pair.as_span();
+    let value = parse_function_argument_to_string(name, pair, state)?;
+    value.parse().map_err(|_| {
+        RevsetParseError::with_span(
+            RevsetParseErrorKind::InvalidFunctionArguments {
+                name: name.to_string(),
+                message: "Expected function argument of type integer".to_owned(),
+            },
+            span,
+        )
+    })
+}
+
+/// Parses a generation `n`, or a range of generations `start..end` (exclusive)
+/// or `start..`. These are integer literals, which aren't substituted with
+/// aliases.
+fn parse_function_argument_to_generation_range(
+    name: &str,
+    pair: Pair<Rule>,
+) -> Result<Range<u32>, RevsetParseError> {
+    let span = pair.as_span();
+    let make_error = || {
+        RevsetParseError::with_span(
+            RevsetParseErrorKind::InvalidFunctionArguments {
+                name: name.to_string(),
+                message: "Expected function argument of type integer or range".to_owned(),
+            },
+            span,
+        )
+    };
+    let first = pair.into_inner().next().unwrap();
+    if first.as_rule() != Rule::generation_range {
+        return Err(make_error());
+    }
+    let parse_u32 = |pair: Pair<Rule>| pair.as_str().parse::<u32>().map_err(|_| make_error());
+    let mut pairs = first.into_inner();
+    let start = parse_u32(pairs.next().unwrap())?;
+    let generation = match (pairs.next(), pairs.next()) {
+        (None, _) => start..start.saturating_add(1),
+        (Some(_), Some(end)) => start..parse_u32(end)?,
+        (Some(_), None) => start..u32::MAX,
+    };
+    if generation.is_empty() {
+        Ok(GENERATION_RANGE_EMPTY)
+    } else {
+        Ok(generation)
+    }
+}
+
+fn parse_merge_parent_count(
+    name: &str,
+    pair: Pair<Rule>,
+    state: ParseState,
+) -> Result<u32, RevsetParseError> {
+    let span = pair.as_span();
+    let count = parse_function_argument_to_u32(name, pair, state)?;
+    if (2..u32::MAX).contains(&count) {
+        Ok(count)
+    } else {
+        Err(RevsetParseError::with_span(
+            RevsetParseErrorKind::InvalidFunctionArguments {
+                name: name.to_string(),
+                message: "Expected a parent count of at least 2".to_owned(),
+            },
+            span,
+        ))
+    }
+}
+
+/// Options that change how `parse_with_options()` interprets a revset.
+#[derive(Clone, Debug, Default)]
+pub struct RevsetParseOptions<'a> {
+    /// Workspace used to resolve `@` and `file()` patterns.
+    pub workspace_ctx: Option<&'a RevsetWorkspaceContext<'a>>,
+    /// What `heads()` without arguments evaluates to, instead of
+    /// `visible_heads()`. An explicit argument as in `heads(x)` still takes
+    /// precedence.
+    pub default_heads: Option<Rc<RevsetExpression>>,
+    /// Keep `file()` patterns unresolved until the expression is evaluated in
+    /// a workspace, instead of failing to parse them when there's no
+    /// `workspace_ctx`.
+    pub defer_file_patterns: bool,
+}
+
 pub fn parse(
     revset_str: &str,
     aliases_map: &RevsetAliasesMap,
     workspace_ctx: Option<&RevsetWorkspaceContext>,
+) -> Result<Rc<RevsetExpression>, RevsetParseError> {
+    let options = RevsetParseOptions {
+        workspace_ctx,
+        ..Default::default()
+    };
+    parse_with_options(revset_str, aliases_map, &options)
+}
+
+pub fn parse_with_options(
+    revset_str: &str,
+    aliases_map: &RevsetAliasesMap,
+    options: &RevsetParseOptions,
 ) -> Result<Rc<RevsetExpression>, RevsetParseError> {
     let state = ParseState {
         aliases_map,
         aliases_expanding: &[],
         locals: &HashMap::new(),
-        workspace_ctx,
+        options,
     };
     parse_program(revset_str, state)
 }
@@ -1063,6 +2104,7 @@ fn transform_expression_bottom_up(
         match expression.as_ref() {
             RevsetExpression::None => None,
             RevsetExpression::All => None,
+            RevsetExpression::Root => None,
             RevsetExpression::Commits(_) => None,
             RevsetExpression::Symbol(_) => None,
             RevsetExpression::Children(roots) => {
@@ -1074,6 +2116,18 @@ fn transform_expression_bottom_up(
                     generation: generation.clone(),
                 })
             }
+            RevsetExpression::Descendants { roots, generation } => {
+                transform_rec(roots, f).map(|roots| RevsetExpression::Descendants {
+                    roots,
+                    generation: generation.clone(),
+                })
+            }
+            RevsetExpression::FirstAncestors(heads) => {
+                transform_rec(heads, f).map(RevsetExpression::FirstAncestors)
+            }
+            RevsetExpression::FirstDescendants(roots) => {
+                transform_rec(roots, f).map(RevsetExpression::FirstDescendants)
+            }
             RevsetExpression::Range {
                 roots,
                 heads,
@@ -1087,7 +2141,12 @@ fn transform_expression_bottom_up(
             }),
             RevsetExpression::DagRange { roots, heads } => transform_rec_pair((roots, heads), f)
                 .map(|(roots, heads)| RevsetExpression::DagRange { roots, heads }),
+            RevsetExpression::Reachable { sources, domain } => {
+                transform_rec_pair((sources, domain), f)
+                    .map(|(sources, domain)| RevsetExpression::Reachable { sources, domain })
+            }
             RevsetExpression::VisibleHeads => None,
+            RevsetExpression::AllHeads => None,
             RevsetExpression::Heads(candidates) => {
                 transform_rec(candidates, f).map(RevsetExpression::Heads)
             }
@@ -1097,9 +2156,16 @@ fn transform_expression_bottom_up(
             RevsetExpression::PublicHeads => None,
             RevsetExpression::Branches(_) => None,
             RevsetExpression::RemoteBranches { .. } => None,
+            RevsetExpression::BranchesAhead => None,
+            RevsetExpression::BranchesBehind => None,
             RevsetExpression::Tags => None,
             RevsetExpression::GitRefs => None,
             RevsetExpression::GitHead => None,
+            RevsetExpression::Evolog(candidates) => {
+                transform_rec(candidates, f).map(RevsetExpression::Evolog)
+            }
+            RevsetExpression::Obsolete => None,
+            RevsetExpression::OpAffected(_) => None,
             RevsetExpression::Filter(_) => None,
             RevsetExpression::AsFilter(candidates) => {
                 transform_rec(candidates, f).map(RevsetExpression::AsFilter)
@@ -1255,6 +2321,18 @@ fn fold_redundant_expression(expression: &Rc<RevsetExpression>) -> Option<Rc<Rev
             match (expression1.as_ref(), expression2.as_ref()) {
                 (_, RevsetExpression::All) => Some(expression1.clone()),
                 (RevsetExpression::All, _) => Some(expression2.clone()),
+                // :x & x -> x
+                (RevsetExpression::Ancestors { heads, generation }, _)
+                    if generation.start == 0 && !generation.is_empty() && heads == expression2 =>
+                {
+                    Some(expression2.clone())
+                }
+                // x & :x -> x
+                (_, RevsetExpression::Ancestors { heads, generation })
+                    if generation.start == 0 && !generation.is_empty() && heads == expression1 =>
+                {
+                    Some(expression1.clone())
+                }
                 _ => None,
             }
         }
@@ -1355,6 +2433,8 @@ fn fold_ancestors(expression: &Rc<RevsetExpression>) -> Option<Rc<RevsetExpressi
                         generation,
                     }))
                 }
+                // ancestors(h, 0) -> h
+                _ if generation1 == &(0..1) => Some(heads.clone()),
                 _ => None,
             }
         }
@@ -1433,6 +2513,8 @@ impl<'index, I: Iterator<Item = IndexEntry<'index>>> RevsetIteratorExt<'index, I
         RevsetCommitIterator {
             iter: self,
             store: store.clone(),
+            batch_size: 1,
+            prefetched: VecDeque::new(),
         }
     }
 
@@ -1456,15 +2538,51 @@ impl<'index, I: Iterator<Item = IndexEntry<'index>>> Iterator for RevsetCommitId
 pub struct RevsetCommitIterator<I> {
     store: Arc<Store>,
     iter: I,
+    batch_size: usize,
+    prefetched: VecDeque<BackendResult<Commit>>,
+}
+
+impl<I> RevsetCommitIterator<I> {
+    /// Makes the iterator read up to `batch_size` commits at a time with
+    /// `Store::get_commits()`, which is faster than reading them one by one
+    /// with some backends.
+    pub fn prefetch(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0);
+        self.batch_size = batch_size;
+        self
+    }
 }
 
 impl<'index, I: Iterator<Item = IndexEntry<'index>>> Iterator for RevsetCommitIterator<I> {
     type Item = BackendResult<Commit>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter
-            .next()
-            .map(|index_entry| self.store.get_commit(&index_entry.commit_id()))
+        if let Some(result) = self.prefetched.pop_front() {
+            return Some(result);
+        }
+        if self.batch_size == 1 {
+            return self
+                .iter
+                .next()
+                .map(|index_entry| self.store.get_commit(&index_entry.commit_id()));
+        }
+        let ids = self
+            .iter
+            .by_ref()
+            .take(self.batch_size)
+            .map(|index_entry| index_entry.commit_id())
+            .collect_vec();
+        match self.store.get_commits(&ids) {
+            Ok(commits) => self.prefetched.extend(commits.into_iter().map(Ok)),
+            Err(_) => {
+                // Read the commits one by one so the error is reported for the right
+                // commit and the other commits are still returned
+                let store = &self.store;
+                self.prefetched
+                    .extend(ids.iter().map(|id| store.get_commit(id)));
+            }
+        }
+        self.prefetched.pop_front()
     }
 }
 
@@ -1612,6 +2730,13 @@ mod tests {
                 heads: foo_symbol.clone(),
             })
         );
+        assert_eq!(
+            foo_symbol.reachable(&wc_symbol),
+            Rc::new(RevsetExpression::Reachable {
+                sources: foo_symbol.clone(),
+                domain: wc_symbol.clone(),
+            })
+        );
         assert_eq!(
             foo_symbol.range(&wc_symbol),
             Rc::new(RevsetExpression::Range {
@@ -1680,6 +2805,13 @@ mod tests {
         assert_eq!(parse("(foo)"), Ok(foo_symbol.clone()));
         // Parse a quoted symbol
         assert_eq!(parse("\"foo\""), Ok(foo_symbol.clone()));
+        // A doubled quote in a quoted symbol is a quote
+        assert_eq!(
+            parse(r#""foo""bar""""#),
+            Ok(RevsetExpression::symbol(r#"foo"bar""#.to_string()))
+        );
+        assert_eq!(parse(r#""""#), Ok(RevsetExpression::symbol("".to_string())));
+        assert_eq!(parse(r#""foo"""#), Err(RevsetParseErrorKind::SyntaxError));
         // Parse the "parents" operator
         assert_eq!(parse("@-"), Ok(wc_symbol.parents()));
         // Parse the "children" operator
@@ -1721,16 +2853,16 @@ mod tests {
         // Space is allowed around infix operators and function arguments
         assert_eq!(
             parse("   description(  arg1 ) ~    file(  arg1 ,   arg2 )  ~ heads(  )  "),
-            Ok(
-                RevsetExpression::filter(RevsetFilterPredicate::Description("arg1".to_string()))
-                    .minus(&RevsetExpression::filter(RevsetFilterPredicate::File(
-                        Some(vec![
-                            RepoPath::from_internal_string("arg1"),
-                            RepoPath::from_internal_string("arg2"),
-                        ])
-                    )))
-                    .minus(&RevsetExpression::visible_heads())
-            )
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Description(
+                StringPattern::Substring("arg1".to_string())
+            ))
+            .minus(&RevsetExpression::filter(RevsetFilterPredicate::File(
+                Some(vec![
+                    RepoPath::from_internal_string("arg1"),
+                    RepoPath::from_internal_string("arg2"),
+                ])
+            )))
+            .minus(&RevsetExpression::visible_heads()))
         );
         // Space is allowed around keyword arguments
         assert_eq!(
@@ -1776,6 +2908,17 @@ mod tests {
         assert!(aliases_map.insert("k(a  , , )", "none()").is_err());
         assert!(aliases_map.insert("l(a,b,)", "none()").is_ok());
         assert!(aliases_map.insert("m(a,,b)", "none()").is_err());
+        // Default value may be given to trailing parameters
+        assert!(aliases_map.insert("n(a, b = x|y)", "none()").is_ok());
+        assert!(aliases_map.insert("o(a=x, b=y,)", "none()").is_ok());
+        assert!(aliases_map.insert("p(a=)", "none()").is_err());
+        assert_eq!(
+            aliases_map
+                .insert("q(a = x, b)", "none()")
+                .unwrap_err()
+                .kind,
+            RevsetParseErrorKind::NonDefaultFunctionParameter
+        );
     }
 
     #[test]
@@ -1788,6 +2931,54 @@ mod tests {
                 description: "parents".to_owned(),
             })
         );
+        assert_eq!(
+            parse("foo~"),
+            Err(RevsetParseErrorKind::NotPostfixOperator {
+                op: "~".to_owned(),
+                similar_op: "-".to_owned(),
+                description: "parents".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("@~2"),
+            Err(RevsetParseErrorKind::NotPostfixOperator {
+                op: "~2".to_owned(),
+                similar_op: "--".to_owned(),
+                description: "ancestors".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("(foo~ )"),
+            Err(RevsetParseErrorKind::NotPostfixOperator {
+                op: "~".to_owned(),
+                similar_op: "-".to_owned(),
+                description: "parents".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("@~10"),
+            Err(RevsetParseErrorKind::NotPostfixOperator {
+                op: "~10".to_owned(),
+                similar_op: "ancestors(x, 10)".to_owned(),
+                description: "ancestors".to_owned(),
+            })
+        );
+        // "~" followed by an operand is still a difference
+        assert_eq!(
+            parse("foo~ 12"),
+            Ok(RevsetExpression::symbol("foo".to_owned())
+                .minus(&RevsetExpression::symbol("12".to_owned())))
+        );
+        assert_eq!(
+            parse("foo~2a"),
+            Ok(RevsetExpression::symbol("foo".to_owned())
+                .minus(&RevsetExpression::symbol("2a".to_owned())))
+        );
+        assert_eq!(
+            parse("foo~ bar"),
+            Ok(RevsetExpression::symbol("foo".to_owned())
+                .minus(&RevsetExpression::symbol("bar".to_owned())))
+        );
         assert_eq!(
             parse("foo + bar"),
             Err(RevsetParseErrorKind::NotInfixOperator {
@@ -1859,6 +3050,81 @@ mod tests {
             Ok(wc_symbol.parents().ancestors())
         );
         assert_eq!(parse("parents(@"), Err(RevsetParseErrorKind::SyntaxError));
+        assert_eq!(
+            parse("ancestors(@, 2)"),
+            Ok(wc_symbol.ancestors_range(2..3))
+        );
+        assert_eq!(
+            parse("ancestors(@, 2..5)"),
+            Ok(wc_symbol.ancestors_range(2..5))
+        );
+        assert_eq!(
+            parse("ancestors(@, 2..)"),
+            Ok(wc_symbol.ancestors_range(2..u32::MAX))
+        );
+        assert_eq!(
+            parse("ancestors(@, 5..2)"),
+            Ok(wc_symbol.ancestors_range(GENERATION_RANGE_EMPTY))
+        );
+        assert_eq!(
+            parse("descendants(@, 2)"),
+            Ok(wc_symbol.descendants_range(2..3))
+        );
+        assert_eq!(parse("descendants(@, 0..)"), Ok(wc_symbol.descendants()));
+        assert_eq!(
+            parse("ancestors(@, foo)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "ancestors".to_string(),
+                message: "Expected function argument of type integer or range".to_string()
+            })
+        );
+        assert_eq!(
+            parse("ancestors(@, (2..5))"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "ancestors".to_string(),
+                message: "Expected function argument of type integer or range".to_string()
+            })
+        );
+        assert_eq!(
+            parse("ancestors(@, 2..5-)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "ancestors".to_string(),
+                message: "Expected function argument of type integer or range".to_string()
+            })
+        );
+        assert_eq!(
+            parse("descendants(@, ..2)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "descendants".to_string(),
+                message: "Expected function argument of type integer or range".to_string()
+            })
+        );
+        assert_eq!(
+            parse("ancestors(@, 1, 2)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "ancestors".to_string(),
+                message: "Expected 1 to 2 arguments".to_string()
+            })
+        );
+        assert_eq!(parse("root()"), Ok(RevsetExpression::root()));
+        assert_eq!(
+            parse("root(@)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "root".to_string(),
+                message: "Expected 0 arguments".to_string()
+            })
+        );
+        assert_eq!(
+            parse("reachable(@, all())"),
+            Ok(wc_symbol.reachable(&RevsetExpression::all()))
+        );
+        assert_eq!(
+            parse("reachable(@)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "reachable".to_string(),
+                message: "Expected 2 arguments".to_string()
+            })
+        );
         assert_eq!(
             parse("parents(@,@)"),
             Err(RevsetParseErrorKind::InvalidFunctionArguments {
@@ -1869,13 +3135,13 @@ mod tests {
         assert_eq!(
             parse(r#"description("")"#),
             Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::Description("".to_string())
+                RevsetFilterPredicate::Description(StringPattern::Substring("".to_string()))
             ))
         );
         assert_eq!(
             parse("description(foo)"),
             Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::Description("foo".to_string())
+                RevsetFilterPredicate::Description(StringPattern::Substring("foo".to_string()))
             ))
         );
         assert_eq!(
@@ -1888,19 +3154,111 @@ mod tests {
         assert_eq!(
             parse("description((foo))"),
             Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::Description("foo".to_string())
+                RevsetFilterPredicate::Description(StringPattern::Substring("foo".to_string()))
+            ))
+        );
+        assert_eq!(
+            parse(r#"description(regex:"^fo+$")"#),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::Description(StringPattern::Regex(
+                    Regex::new("^fo+$").unwrap()
+                ))
             ))
         );
+        assert_eq!(
+            parse(r#"author(regex:" foo ")"#),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Author(
+                StringPattern::Regex(Regex::new(" foo ").unwrap())
+            )))
+        );
+        assert!(matches!(
+            parse(r#"committer(regex:"(foo")"#),
+            Err(RevsetParseErrorKind::InvalidRegex(_))
+        ));
+        assert_eq!(
+            parse(r#"description(i:"Foo")"#),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::Description(StringPattern::SubstringCaseInsensitive(
+                    "foo".to_string()
+                ))
+            ))
+        );
+        assert_eq!(
+            parse(r#"author(exact:" Foo ")"#),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Author(
+                StringPattern::Exact("Foo".to_string())
+            )))
+        );
+        assert_eq!(
+            parse(r#"committer(i:" foo ")"#),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Committer(
+                StringPattern::SubstringCaseInsensitive("foo".to_string())
+            )))
+        );
+        // Other ranges aren't string patterns
+        assert_eq!(
+            parse(r#"description(foo:"bar")"#),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "description".to_string(),
+                message: "Expected function argument of type string".to_string()
+            })
+        );
+        assert_eq!(
+            parse(r#"description(regex : "^fo+$")"#),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_eq!(
+            parse(r#"description(regex:"^fo+$" | foo)"#),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "description".to_string(),
+                message: "Expected function argument of type string".to_string()
+            })
+        );
         assert_eq!(
             parse("description(\"(foo)\")"),
             Ok(RevsetExpression::filter(
-                RevsetFilterPredicate::Description("(foo)".to_string())
+                RevsetFilterPredicate::Description(StringPattern::Substring("(foo)".to_string()))
             ))
         );
+        assert_eq!(
+            parse("author(\" foo bar \")"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Author(
+                StringPattern::Substring("foo bar".to_string())
+            )))
+        );
+        assert_eq!(
+            parse("committer(\"\tfoo \")"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Committer(
+                StringPattern::Substring("foo".to_string())
+            )))
+        );
         assert_eq!(
             parse("empty()"),
             Ok(RevsetExpression::filter(RevsetFilterPredicate::File(None)).negated())
         );
+        assert_eq!(
+            parse("file_content(abc123)"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::FileContent(FileId::from_hex("abc123"))
+            ))
+        );
+        assert_eq!(
+            parse("file_content(xyz)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "file_content".to_string(),
+                message: "Expected a hexadecimal file id".to_string()
+            })
+        );
+        assert_eq!(
+            parse("conflict()"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::HasConflict))
+        );
+        assert!(parse("conflict(foo)").is_err());
+        assert_eq!(
+            parse("divergent()"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Divergent))
+        );
+        assert!(parse("divergent(foo)").is_err());
         assert!(parse("empty(foo)").is_err());
         assert!(parse("file()").is_err());
         assert_eq!(
@@ -1919,10 +3277,169 @@ mod tests {
                 ]
             ))))
         );
+        assert_eq!(
+            parse(r#"file(exact:"foo", exact:bar)"#),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::ExactFile(
+                vec![
+                    RepoPath::from_internal_string("foo"),
+                    RepoPath::from_internal_string("bar"),
+                ]
+            )))
+        );
+        assert_eq!(
+            parse("file(foo, exact:bar, baz)"),
+            Ok(
+                RevsetExpression::filter(RevsetFilterPredicate::File(Some(vec![
+                    RepoPath::from_internal_string("foo"),
+                    RepoPath::from_internal_string("baz"),
+                ])))
+                .union(&RevsetExpression::filter(
+                    RevsetFilterPredicate::ExactFile(vec![RepoPath::from_internal_string("bar")])
+                ))
+            )
+        );
+        assert!(parse("file(regex:foo)").is_err());
+    }
+
+    #[test]
+    fn test_parse_op_affected() {
+        assert_eq!(
+            parse("op_affected()"),
+            Ok(RevsetExpression::op_affected(None))
+        );
+        assert_eq!(
+            parse("op_affected(abc123)"),
+            Ok(RevsetExpression::op_affected(Some("abc123".to_string())))
+        );
+        assert_eq!(
+            parse("op_affected(a, b)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "op_affected".to_string(),
+                message: "Expected 0 to 1 arguments".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_churn() {
+        assert_eq!(
+            parse("churn(100)"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Churn(
+                100..u32::MAX
+            )))
+        );
+        assert_eq!(
+            parse("churn(0, 10)"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Churn(
+                0..11
+            )))
+        );
+        assert_eq!(
+            parse("churn(10, 10)"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::Churn(
+                10..11
+            )))
+        );
+        assert_eq!(
+            parse("churn(10, 9)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "churn".to_string(),
+                message: "Expected the minimum to not exceed the maximum".to_string()
+            })
+        );
+        assert_eq!(
+            parse("churn(large)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "churn".to_string(),
+                message: "Expected function argument of type integer".to_string()
+            })
+        );
+        assert_eq!(
+            parse("churn()"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "churn".to_string(),
+                message: "Expected 1 to 2 arguments".to_string()
+            })
+        );
+        // Like other filters, it's applied after the candidate set
+        assert_eq!(
+            optimize(parse("churn(100) & foo").unwrap()),
+            RevsetExpression::symbol("foo".to_string()).intersection(&RevsetExpression::filter(
+                RevsetFilterPredicate::Churn(100..u32::MAX)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_merges() {
+        assert_eq!(
+            parse("merges()"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::ParentCount(2..u32::MAX)
+            ))
+        );
+        assert_eq!(
+            parse("merges(3)"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::ParentCount(3..u32::MAX)
+            ))
+        );
+        assert_eq!(
+            parse("merges(exactly=3)"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::ParentCount(3..4)
+            ))
+        );
+        assert_eq!(
+            parse("merges(foo)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "merges".to_string(),
+                message: "Expected function argument of type integer".to_string()
+            })
+        );
+        assert_eq!(
+            parse("merges(1)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "merges".to_string(),
+                message: "Expected a parent count of at least 2".to_string()
+            })
+        );
+        assert_eq!(
+            parse("merges(3, exactly=3)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "merges".to_string(),
+                message: "Expected either a minimum or an exact parent count".to_string()
+            })
+        );
+        // The count doesn't change how the filter is optimized
+        let filter_foo = |range| {
+            RevsetExpression::symbol("foo".to_string()).intersection(&RevsetExpression::filter(
+                RevsetFilterPredicate::ParentCount(range),
+            ))
+        };
+        assert_eq!(
+            optimize(parse("merges() & foo").unwrap()),
+            filter_foo(2..u32::MAX)
+        );
+        assert_eq!(
+            optimize(parse("merges(3) & foo").unwrap()),
+            filter_foo(3..u32::MAX)
+        );
+        assert_eq!(
+            optimize(parse("merges(exactly=3) & foo").unwrap()),
+            filter_foo(3..4)
+        );
     }
 
     #[test]
     fn test_parse_revset_keyword_arguments() {
+        assert_eq!(
+            parse(r#"remote_branches(exact:"main", remote=origin)"#),
+            Ok(RevsetExpression::remote_branches(
+                StringPattern::Exact("main".to_owned()),
+                "origin".to_owned()
+            ))
+        );
         assert_eq!(
             parse("remote_branches(remote=foo)").unwrap(),
             parse(r#"remote_branches("", foo)"#).unwrap(),
@@ -1965,6 +3482,119 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_parse_default_heads() {
+        let parse_with_default_heads = |revset_str: &str, default_heads| {
+            let options = RevsetParseOptions {
+                default_heads: Some(default_heads),
+                ..Default::default()
+            };
+            parse_with_options(revset_str, &RevsetAliasesMap::new(), &options).map_err(|e| e.kind)
+        };
+
+        // visible_heads() is the default without context
+        assert_eq!(parse("heads()"), Ok(RevsetExpression::visible_heads()));
+        assert_eq!(
+            parse_with_default_heads("heads()", RevsetExpression::public_heads()),
+            Ok(RevsetExpression::public_heads())
+        );
+        assert_eq!(
+            parse_with_default_heads(
+                "heads() & foo",
+                RevsetExpression::symbol("main".to_string())
+            ),
+            Ok(RevsetExpression::symbol("main".to_string())
+                .intersection(&RevsetExpression::symbol("foo".to_string())))
+        );
+        // An explicit argument takes precedence
+        assert_eq!(
+            parse_with_default_heads("heads(foo)", RevsetExpression::public_heads()),
+            Ok(RevsetExpression::symbol("foo".to_string()).heads())
+        );
+
+        // The default also applies inside aliases
+        let mut aliases_map = RevsetAliasesMap::new();
+        aliases_map.insert("tips", "heads()").unwrap();
+        let options = RevsetParseOptions {
+            default_heads: Some(RevsetExpression::public_heads()),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with_options("tips", &aliases_map, &options).map_err(|e| e.kind),
+            Ok(RevsetExpression::public_heads())
+        );
+    }
+
+    #[test]
+    fn test_parse_without_workspace() {
+        let options = RevsetParseOptions {
+            defer_file_patterns: true,
+            ..Default::default()
+        };
+        let parse_without_workspace = |revset_str: &str| {
+            parse_with_options(revset_str, &RevsetAliasesMap::new(), &options).map_err(|e| e.kind)
+        };
+
+        // File patterns are kept unresolved
+        assert_eq!(
+            parse_without_workspace("file(foo)"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::UnresolvedFile(vec!["foo".to_string()])
+            ))
+        );
+        assert_eq!(
+            parse_without_workspace(r#"file("foo", "../bar") & baz"#),
+            Ok(
+                RevsetExpression::filter(RevsetFilterPredicate::UnresolvedFile(vec![
+                    "foo".to_string(),
+                    "../bar".to_string()
+                ]))
+                .intersection(&RevsetExpression::symbol("baz".to_string()))
+            )
+        );
+        assert_eq!(
+            parse_without_workspace("file(exact:foo)"),
+            Ok(RevsetExpression::filter(
+                RevsetFilterPredicate::UnresolvedExactFile(vec!["foo".to_string()])
+            ))
+        );
+        assert_eq!(
+            parse_without_workspace("file()"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "file".to_string(),
+                message: "Expected at least 1 argument".to_string()
+            })
+        );
+        // Other functions parse as usual
+        assert_eq!(
+            parse_without_workspace("empty()"),
+            Ok(RevsetExpression::filter(RevsetFilterPredicate::File(None)).negated())
+        );
+        // The default parser still requires a workspace
+        assert_eq!(
+            super::parse("file(foo)", &RevsetAliasesMap::new(), None).map_err(|e| e.kind),
+            Err(RevsetParseErrorKind::FsPathWithoutWorkspace)
+        );
+    }
+
+    #[test]
+    fn test_parse_with_combined_options() {
+        let options = RevsetParseOptions {
+            workspace_ctx: None,
+            default_heads: Some(RevsetExpression::public_heads()),
+            defer_file_patterns: true,
+        };
+        assert_eq!(
+            parse_with_options("heads() & file(foo)", &RevsetAliasesMap::new(), &options)
+                .map_err(|e| e.kind),
+            Ok(
+                RevsetExpression::public_heads().intersection(&RevsetExpression::filter(
+                    RevsetFilterPredicate::UnresolvedFile(vec!["foo".to_string()])
+                ))
+            )
+        );
+    }
+
     #[test]
     fn test_expand_symbol_alias() {
         assert_eq!(
@@ -1994,6 +3624,40 @@ mod tests {
             parse("author(a)").unwrap()
         );
 
+        // Integer literal of generation is not substituted with alias, but the
+        // range of commits spelled the same way is.
+        assert_eq!(
+            parse_with_aliases("ancestors(x, 2..5)", [("2", "a"), ("5", "b")]).unwrap(),
+            parse("ancestors(x, 2..5)").unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("heads(2..5)", [("2", "a"), ("5", "b")]).unwrap(),
+            parse("heads(a..b)").unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("2", [("2", "a")]).unwrap(),
+            parse("a").unwrap()
+        );
+
+        // Neither the kind nor the needle of string pattern is substituted with
+        // alias, but the DAG range spelled the same way is.
+        assert_eq!(
+            parse_with_aliases(r#"author(regex:"^a")"#, [("regex", "r")]).unwrap(),
+            parse(r#"author(regex:"^a")"#).unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("author(exact:A)", [("A", "a")]).unwrap(),
+            parse(r#"author(exact:"A")"#).unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("file(exact:A)", [("A", "a")]).unwrap(),
+            parse(r#"file(exact:"A")"#).unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("heads(exact:A)", [("exact", "e"), ("A", "a")]).unwrap(),
+            parse("heads(e:a)").unwrap()
+        );
+
         // Multi-level substitution.
         assert_eq!(
             parse_with_aliases("A", [("A", "BC"), ("BC", "b|C"), ("C", "c")]).unwrap(),
@@ -2072,51 +3736,129 @@ mod tests {
             parse("author(a)|committer(a)").unwrap()
         );
 
-        // Function and symbol aliases reside in separate namespaces.
+        // Function and symbol aliases reside in separate namespaces.
+        assert_eq!(
+            parse_with_aliases("A()", [("A()", "A"), ("A", "a")]).unwrap(),
+            parse("a").unwrap()
+        );
+
+        // Invalid number of arguments.
+        assert_eq!(
+            parse_with_aliases("F(a)", [("F()", "x")]),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "F".to_owned(),
+                message: "Expected 0 arguments".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_with_aliases("F()", [("F(x)", "x")]),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "F".to_owned(),
+                message: "Expected 1 arguments".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_with_aliases("F(a,b,c)", [("F(x,y)", "x|y")]),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "F".to_owned(),
+                message: "Expected 2 arguments".to_owned()
+            })
+        );
+
+        // Keyword argument isn't supported for now.
+        assert_eq!(
+            parse_with_aliases("F(x=y)", [("F(x)", "x")]),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "F".to_owned(),
+                message: r#"Unexpected keyword argument "x""#.to_owned()
+            })
+        );
+
+        // Infinite recursion, where the top-level error isn't of RecursiveAlias kind.
+        assert_eq!(
+            parse_with_aliases(
+                "F(a)",
+                [("F(x)", "G(x)"), ("G(x)", "H(x)"), ("H(x)", "F(x)")]
+            ),
+            Err(RevsetParseErrorKind::BadAliasExpansion("F()".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_expand_function_alias_default_parameter() {
+        // Omitted trailing argument is filled with the default.
+        assert_eq!(
+            parse_with_aliases("F(a)", [("F(x, y = b)", "x|y")]).unwrap(),
+            parse("a|b").unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("F(a, c)", [("F(x, y = b)", "x|y")]).unwrap(),
+            parse("a|c").unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("F()", [("F(x=a, y=b:c)", "x|y")]).unwrap(),
+            parse("a|(b:c)").unwrap()
+        );
+
+        // Default is resolved in the definition scope, where the other
+        // parameters aren't visible but aliases are.
         assert_eq!(
-            parse_with_aliases("A()", [("A()", "A"), ("A", "a")]).unwrap(),
+            parse_with_aliases("F(a)", [("F(x, y = x)", "y")]).unwrap(),
+            parse("x").unwrap()
+        );
+        assert_eq!(
+            parse_with_aliases("F()", [("F(x = A)", "x"), ("A", "a")]).unwrap(),
             parse("a").unwrap()
         );
 
-        // Invalid number of arguments.
+        // Parameters without defaults are still required.
         assert_eq!(
-            parse_with_aliases("F(a)", [("F()", "x")]),
+            parse_with_aliases("F()", [("F(x, y = b)", "x|y")]),
             Err(RevsetParseErrorKind::InvalidFunctionArguments {
                 name: "F".to_owned(),
-                message: "Expected 0 arguments".to_owned()
+                message: "Expected 1 to 2 arguments".to_owned()
             })
         );
         assert_eq!(
-            parse_with_aliases("F()", [("F(x)", "x")]),
+            parse_with_aliases("F(a, b, c)", [("F(x, y = b)", "x|y")]),
             Err(RevsetParseErrorKind::InvalidFunctionArguments {
                 name: "F".to_owned(),
-                message: "Expected 1 arguments".to_owned()
+                message: "Expected 1 to 2 arguments".to_owned()
             })
         );
+
+        // Default referring to the alias itself is recursive.
         assert_eq!(
-            parse_with_aliases("F(a,b,c)", [("F(x,y)", "x|y")]),
-            Err(RevsetParseErrorKind::InvalidFunctionArguments {
-                name: "F".to_owned(),
-                message: "Expected 2 arguments".to_owned()
-            })
+            parse_with_aliases("F()", [("F(x = F())", "x")]),
+            Err(RevsetParseErrorKind::BadAliasExpansion("F()".to_owned()))
         );
+    }
 
-        // Keyword argument isn't supported for now.
+    #[test]
+    fn test_collect_symbols() {
         assert_eq!(
-            parse_with_aliases("F(x=y)", [("F(x)", "x")]),
-            Err(RevsetParseErrorKind::InvalidFunctionArguments {
-                name: "F".to_owned(),
-                message: r#"Unexpected keyword argument "x""#.to_owned()
-            })
+            parse("all()").unwrap().collect_symbols(),
+            Vec::<String>::new()
         );
-
-        // Infinite recursion, where the top-level error isn't of RecursiveAlias kind.
         assert_eq!(
-            parse_with_aliases(
-                "F(a)",
-                [("F(x)", "G(x)"), ("G(x)", "H(x)"), ("H(x)", "F(x)")]
-            ),
-            Err(RevsetParseErrorKind::BadAliasExpansion("F()".to_owned()))
+            parse("(foo | bar-) & ~(baz:foo | present(qux))")
+                .unwrap()
+                .collect_symbols(),
+            vec!["foo", "bar", "baz", "qux"]
+        );
+        // Symbols in function arguments that aren't revsets aren't collected
+        assert_eq!(
+            parse("author(foo) | branches(bar) | baz")
+                .unwrap()
+                .collect_symbols(),
+            vec!["baz"]
+        );
+        // Aliases are expanded before symbols are collected
+        assert_eq!(
+            parse_with_aliases("A | F(baz)", [("A", "foo | bar"), ("F(x)", "x- | foo")])
+                .unwrap()
+                .collect_symbols(),
+            vec!["foo", "bar", "baz"]
         );
     }
 
@@ -2164,8 +3906,10 @@ mod tests {
             optimize(parse("present(author(foo) ~ bar)").unwrap()),
             Rc::new(RevsetExpression::AsFilter(Rc::new(
                 RevsetExpression::Present(
-                    RevsetExpression::filter(RevsetFilterPredicate::Author("foo".to_owned()))
-                        .minus(&RevsetExpression::symbol("bar".to_owned()))
+                    RevsetExpression::filter(RevsetFilterPredicate::Author(
+                        StringPattern::Substring("foo".to_owned())
+                    ))
+                    .minus(&RevsetExpression::symbol("bar".to_owned()))
                 )
             )))
         );
@@ -2433,7 +4177,9 @@ mod tests {
             ),
             Filter(
                 Author(
-                    "foo",
+                    Substring(
+                        "foo",
+                    ),
                 ),
             ),
         )
@@ -2445,7 +4191,9 @@ mod tests {
         insta::assert_debug_snapshot!(optimize(parse("author(foo)").unwrap()), @r###"
         Filter(
             Author(
-                "foo",
+                Substring(
+                    "foo",
+                ),
             ),
         )
         "###);
@@ -2457,7 +4205,9 @@ mod tests {
             ),
             Filter(
                 Description(
-                    "bar",
+                    Substring(
+                        "bar",
+                    ),
                 ),
             ),
         )
@@ -2469,22 +4219,38 @@ mod tests {
             ),
             Filter(
                 Author(
-                    "foo",
+                    Substring(
+                        "foo",
+                    ),
                 ),
             ),
         )
         "###);
+        insta::assert_debug_snapshot!(optimize(parse("conflict() & bar").unwrap()), @r###"
+        Intersection(
+            Symbol(
+                "bar",
+            ),
+            Filter(
+                HasConflict,
+            ),
+        )
+        "###);
         insta::assert_debug_snapshot!(
             optimize(parse("author(foo) & committer(bar)").unwrap()), @r###"
         Intersection(
             Filter(
                 Author(
-                    "foo",
+                    Substring(
+                        "foo",
+                    ),
                 ),
             ),
             Filter(
                 Committer(
-                    "bar",
+                    Substring(
+                        "bar",
+                    ),
                 ),
             ),
         )
@@ -2499,13 +4265,17 @@ mod tests {
                 ),
                 Filter(
                     Description(
-                        "bar",
+                        Substring(
+                            "bar",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2519,13 +4289,17 @@ mod tests {
                 ),
                 Filter(
                     Committer(
-                        "foo",
+                        Substring(
+                            "foo",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2539,7 +4313,9 @@ mod tests {
                 ),
                 Filter(
                     Committer(
-                        "foo",
+                        Substring(
+                            "foo",
+                        ),
                     ),
                 ),
             ),
@@ -2560,7 +4336,9 @@ mod tests {
             Intersection(
                 Filter(
                     Committer(
-                        "foo",
+                        Substring(
+                            "foo",
+                        ),
                     ),
                 ),
                 Filter(
@@ -2575,7 +4353,9 @@ mod tests {
             ),
             Filter(
                 Author(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2616,13 +4396,17 @@ mod tests {
                 ),
                 Filter(
                     Description(
-                        "bar",
+                        Substring(
+                            "bar",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2638,7 +4422,9 @@ mod tests {
                     Ancestors {
                         heads: Filter(
                             Author(
-                                "baz",
+                                Substring(
+                                    "baz",
+                                ),
                             ),
                         ),
                         generation: 1..2,
@@ -2650,7 +4436,9 @@ mod tests {
             ),
             Filter(
                 Description(
-                    "bar",
+                    Substring(
+                        "bar",
+                    ),
                 ),
             ),
         )
@@ -2669,7 +4457,9 @@ mod tests {
                         ),
                         Filter(
                             Author(
-                                "baz",
+                                Substring(
+                                    "baz",
+                                ),
                             ),
                         ),
                     ),
@@ -2678,7 +4468,9 @@ mod tests {
             ),
             Filter(
                 Description(
-                    "bar",
+                    Substring(
+                        "bar",
+                    ),
                 ),
             ),
         )
@@ -2705,19 +4497,25 @@ mod tests {
                     ),
                     Filter(
                         Author(
-                            "A",
+                            Substring(
+                                "A",
+                            ),
                         ),
                     ),
                 ),
                 Filter(
                     Author(
-                        "B",
+                        Substring(
+                            "B",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "C",
+                    Substring(
+                        "C",
+                    ),
                 ),
             ),
         )
@@ -2748,19 +4546,25 @@ mod tests {
                     ),
                     Filter(
                         Author(
-                            "A",
+                            Substring(
+                                "A",
+                            ),
                         ),
                     ),
                 ),
                 Filter(
                     Author(
-                        "B",
+                        Substring(
+                            "B",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "C",
+                    Substring(
+                        "C",
+                    ),
                 ),
             ),
         )
@@ -2777,13 +4581,17 @@ mod tests {
                 ),
                 Filter(
                     Description(
-                        "bar",
+                        Substring(
+                            "bar",
+                        ),
                     ),
                 ),
             ),
             Filter(
                 Author(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2802,7 +4610,9 @@ mod tests {
                 Union(
                     Filter(
                         Author(
-                            "foo",
+                            Substring(
+                                "foo",
+                            ),
                         ),
                     ),
                     Symbol(
@@ -2827,7 +4637,9 @@ mod tests {
                         ),
                         Filter(
                             Committer(
-                                "bar",
+                                Substring(
+                                    "bar",
+                                ),
                             ),
                         ),
                     ),
@@ -2835,7 +4647,9 @@ mod tests {
             ),
             Filter(
                 Description(
-                    "baz",
+                    Substring(
+                        "baz",
+                    ),
                 ),
             ),
         )
@@ -2859,7 +4673,9 @@ mod tests {
                                         ),
                                         Filter(
                                             Author(
-                                                "foo",
+                                                Substring(
+                                                    "foo",
+                                                ),
                                             ),
                                         ),
                                     ),
@@ -2900,7 +4716,9 @@ mod tests {
                         Union(
                             Filter(
                                 Author(
-                                    "A",
+                                    Substring(
+                                        "A",
+                                    ),
                                 ),
                             ),
                             Symbol(
@@ -2913,7 +4731,9 @@ mod tests {
                     Union(
                         Filter(
                             Author(
-                                "B",
+                                Substring(
+                                    "B",
+                                ),
                             ),
                         ),
                         Symbol(
@@ -2926,7 +4746,9 @@ mod tests {
                 Union(
                     Filter(
                         Author(
-                            "C",
+                            Substring(
+                                "C",
+                            ),
                         ),
                     ),
                     Symbol(
@@ -3043,40 +4865,337 @@ mod tests {
         }
         "###);
 
+        // Generation ranges are folded the same way as the operators.
+        insta::assert_debug_snapshot!(optimize(parse("ancestors(foo, 2)").unwrap()), @r###"
+        Ancestors {
+            heads: Symbol(
+                "foo",
+            ),
+            generation: 2..3,
+        }
+        "###);
+        insta::assert_debug_snapshot!(
+            optimize(parse("ancestors(ancestors(foo, 1..3), 2..)").unwrap()), @r###"
+        Ancestors {
+            heads: Symbol(
+                "foo",
+            ),
+            generation: 3..4294967295,
+        }
+        "###);
+        insta::assert_debug_snapshot!(
+            optimize(parse("ancestors(ancestors(foo, 1..3), 2..4)").unwrap()), @r###"
+        Ancestors {
+            heads: Symbol(
+                "foo",
+            ),
+            generation: 3..6,
+        }
+        "###);
+
+        // 'ancestors(foo, 0)' is 'foo'.
+        insta::assert_debug_snapshot!(optimize(parse("ancestors(foo, 0)").unwrap()), @r###"
+        Symbol(
+            "foo",
+        )
+        "###);
+        insta::assert_debug_snapshot!(
+            optimize(parse("ancestors(ancestors(foo, 0), 0)").unwrap()), @r###"
+        Symbol(
+            "foo",
+        )
+        "###);
+
         // Ancestors of empty generation range should be empty.
-        // TODO: rewrite these tests if we added syntax for arbitrary generation
-        // ancestors
-        let empty_generation_ancestors = |heads| {
-            Rc::new(RevsetExpression::Ancestors {
-                heads,
-                generation: GENERATION_RANGE_EMPTY,
-            })
-        };
         insta::assert_debug_snapshot!(
-            optimize(empty_generation_ancestors(
-                RevsetExpression::symbol("foo".to_owned()).ancestors()
-            )),
-            @r###"
+            optimize(parse("ancestors(ancestors(foo), 0..0)").unwrap()), @r###"
         Ancestors {
             heads: Symbol(
                 "foo",
             ),
             generation: 0..0,
         }
-        "###
-        );
+        "###);
         insta::assert_debug_snapshot!(
-            optimize(
-                empty_generation_ancestors(RevsetExpression::symbol("foo".to_owned())).ancestors()
-            ),
-            @r###"
+            optimize(parse("ancestors(ancestors(foo, 3..1))").unwrap()), @r###"
         Ancestors {
             heads: Symbol(
                 "foo",
             ),
             generation: 0..0,
         }
-        "###
+        "###);
+
+        // Descendants aren't folded.
+        insta::assert_debug_snapshot!(
+            optimize(parse("descendants(descendants(foo, 1), 2..)").unwrap()), @r###"
+        Descendants {
+            roots: Descendants {
+                roots: Symbol(
+                    "foo",
+                ),
+                generation: 1..2,
+            },
+            generation: 2..4294967295,
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_optimize_ancestors_intersection() {
+        // 'ancestors(x) & x' is 'x'
+        insta::assert_debug_snapshot!(optimize(parse("ancestors(foo) & foo").unwrap()), @r###"
+        Symbol(
+            "foo",
+        )
+        "###);
+        insta::assert_debug_snapshot!(optimize(parse("(foo | bar) & :(foo | bar)").unwrap()), @r###"
+        Union(
+            Symbol(
+                "foo",
+            ),
+            Symbol(
+                "bar",
+            ),
+        )
+        "###);
+        insta::assert_debug_snapshot!(
+            optimize(parse("author(foo) & ancestors(author(foo))").unwrap()), @r###"
+        Filter(
+            Author(
+                Substring(
+                    "foo",
+                ),
+            ),
+        )
+        "###);
+
+        // 'ancestors(x) & y' is not folded.
+        insta::assert_debug_snapshot!(optimize(parse("ancestors(foo) & bar").unwrap()), @r###"
+        Intersection(
+            Ancestors {
+                heads: Symbol(
+                    "foo",
+                ),
+                generation: 0..4294967295,
+            },
+            Symbol(
+                "bar",
+            ),
+        )
+        "###);
+
+        // An empty generation range doesn't include 'x' either.
+        insta::assert_debug_snapshot!(
+            optimize(parse("ancestors(foo, 0..0) & foo").unwrap()), @r###"
+        Intersection(
+            Ancestors {
+                heads: Symbol(
+                    "foo",
+                ),
+                generation: 0..0,
+            },
+            Symbol(
+                "foo",
+            ),
+        )
+        "###);
+        insta::assert_debug_snapshot!(
+            optimize(parse("foo & ancestors(foo, 3..1)").unwrap()), @r###"
+        Intersection(
+            Symbol(
+                "foo",
+            ),
+            Ancestors {
+                heads: Symbol(
+                    "foo",
+                ),
+                generation: 0..0,
+            },
+        )
+        "###);
+
+        // 'x- & x' doesn't include 'x'.
+        insta::assert_debug_snapshot!(optimize(parse("foo- & foo").unwrap()), @r###"
+        Intersection(
+            Ancestors {
+                heads: Symbol(
+                    "foo",
+                ),
+                generation: 1..2,
+            },
+            Symbol(
+                "foo",
+            ),
+        )
+        "###);
+    }
+
+    #[test]
+    fn test_format_revset_expression() {
+        let format = |revset_str: &str| optimize(parse(revset_str).unwrap()).to_string();
+        assert_eq!(format("foo"), "foo");
+        assert_eq!(format(r#""foo bar""#), r#""foo bar""#);
+        assert_eq!(format("@-+"), "@-+");
+        assert_eq!(format("foo---"), "foo---");
+        assert_eq!(format(":foo--"), ":foo--");
+        assert_eq!(format("(:foo)-"), ":foo-");
+        assert_eq!(format("foo:"), "foo:");
+        assert_eq!(format("foo.."), "foo..");
+        assert_eq!(format("foo..bar--"), "foo..bar--");
+        assert_eq!(format("foo-- ~ :bar"), "foo-- ~ :bar");
+        assert_eq!(format("(foo | bar):baz"), "(foo | bar):baz");
+        assert_eq!(format("(foo | bar)-"), "(foo | bar)-");
+        assert_eq!(format("(:foo)+"), "(:foo)+");
+        assert_eq!(format("~(foo & bar)"), "~(foo & bar)");
+        assert_eq!(format("foo ~ (bar ~ baz)"), "foo ~ (bar ~ baz)");
+        assert_eq!(format("(foo ~ bar) ~ baz"), "foo ~ bar ~ baz");
+        assert_eq!(format("foo & (bar | baz)"), "foo & (bar | baz)");
+        assert_eq!(format("foo | bar & baz"), "foo | bar & baz");
+        assert_eq!(format("heads()"), "heads()");
+        assert_eq!(format("empty()"), "empty()");
+        assert_eq!(format("~empty()"), "~empty()");
+        assert_eq!(format("ancestors(foo, 2)"), "foo--");
+        assert_eq!(format("ancestors(foo, 1..3)"), "ancestors(foo, 1..3)");
+        assert_eq!(format("ancestors(foo, 3..1)"), "ancestors(foo, 0..0)");
+        assert_eq!(format("descendants(foo, 2)"), "descendants(foo, 2)");
+        assert_eq!(format("descendants(foo, 1..)"), "descendants(foo, 1..)");
+        assert_eq!(
+            format("author( foo ) & description(regex:\"^fix\")"),
+            r#"author("foo") & description(regex:"^fix")"#
+        );
+        assert_eq!(
+            format("remote_branches(remote=origin)"),
+            r#"remote_branches(remote="origin")"#
+        );
+        assert_eq!(
+            format("remote_branches(exact:main, remote=origin)"),
+            r#"remote_branches(exact:"main", remote="origin")"#
+        );
+        assert_eq!(format("merges(exactly=3)"), "merges(exactly=3)");
+        assert_eq!(format("churn(1, 10)"), "churn(1, 10)");
+
+        // Expressions that can only be built by API are still formatted
+        assert_eq!(RevsetExpression::commits(vec![]).to_string(), "none()");
+        assert_eq!(
+            RevsetExpression::reachable_from_any(&[(
+                RevsetExpression::symbol("foo".to_owned()),
+                3
+            )])
+            .to_string(),
+            "ancestors(foo, 0..3)"
         );
     }
+
+    #[test]
+    fn test_format_revset_expression_round_trip() {
+        let corpus = [
+            "@",
+            "foo",
+            r#""foo bar""#,
+            r#""foo""bar""#,
+            r#""""""#,
+            "foo.bar-baz+qux",
+            "foo-",
+            "foo--",
+            "foo---+",
+            "foo+",
+            ":foo",
+            "..foo",
+            ":(foo---)",
+            "(:foo)---",
+            "foo:",
+            "foo:bar",
+            "foo..",
+            "foo..bar",
+            "(foo--)..(bar---)",
+            "(foo..bar)--",
+            "foo..(bar--)",
+            "foo..(bar..baz)",
+            "(branches() & all())..(all() & tags())",
+            "(branches() & all()):(all() & tags())",
+            "~foo",
+            "~~foo",
+            "~:foo & :bar",
+            "foo & ~bar",
+            "~foo & bar & ~baz",
+            "foo ~ bar",
+            "all() ~ foo",
+            "(all() & ~foo) & bar",
+            "~(all() & ~foo) & bar",
+            "foo | bar & baz",
+            "(foo | bar) & baz",
+            "(foo | bar) & :(foo | bar)",
+            "foo ~ (bar | baz)",
+            "parents(foo)",
+            "children(foo)",
+            "ancestors(foo) & foo",
+            "descendants(foo)",
+            "ancestors(foo, 2..5)",
+            "ancestors(foo, 0..0)",
+            "ancestors(foo, 0..3) ~ :bar",
+            "(foo..bar) & ancestors(bar, 2..3)",
+            "descendants(foo, 3)",
+            "descendants(foo | bar, 1..3)",
+            "descendants(foo, 2..)",
+            "first_ancestors(foo)",
+            "first_descendants(foo)",
+            "connected(foo | bar)",
+            "reachable(foo, bar | baz)",
+            "heads()",
+            "heads(foo)",
+            "roots(foo:)",
+            "none()",
+            "all()",
+            "root()",
+            "root()..foo",
+            "public_heads()",
+            "branches()",
+            "branches(foo)",
+            "remote_branches()",
+            "remote_branches(foo, remote=bar)",
+            "remote_branches(remote=bar)",
+            r#"remote_branches(exact:"foo")"#,
+            r#"remote_branches(regex:"^foo", remote=bar)"#,
+            r#"committer(exact:"foo")"#,
+            r#"description(regex:"^""foo""$")"#,
+            "branches_ahead() | branches_behind()",
+            "tags() | git_refs() | git_head()",
+            "obsolete()",
+            "evolog(@)",
+            "op_affected()",
+            "op_affected(abc)",
+            "merges()",
+            "merges(3)",
+            "merges(exactly=3)",
+            "churn(10)",
+            "churn(1, 10)",
+            "description(foo)",
+            r#"description(regex:"^fix:")"#,
+            r#"author(i:"Foo") | description(i:"BAR")"#,
+            "author(foo) & committer(bar)",
+            "committer(foo) & file(bar) & author(baz)",
+            "file(foo, bar)",
+            r#"file(exact:"foo", bar) & baz"#,
+            "file_content(abcd)",
+            "conflict() & foo",
+            "divergent() & foo",
+            "empty()",
+            "~empty()",
+            "present(foo)",
+            "present(author(foo) ~ bar)",
+            "(~present(author(foo) & bar) | baz) & qux",
+            "foo & description(bar) & parents(author(baz) & qux)",
+            "(a & author(A)) & ((b & author(B)) & (c & author(C))) & d",
+        ];
+        for revset_str in corpus {
+            let expression = optimize(parse(revset_str).unwrap());
+            let formatted = expression.to_string();
+            assert_eq!(
+                optimize(parse(&formatted).unwrap()),
+                expression,
+                "{revset_str:?} was formatted as {formatted:?}"
+            );
+        }
+    }
 }
@@ -16,7 +16,7 @@ use std::collections::{HashMap, HashSet};
 
 use itertools::{process_results, Itertools};
 
-use crate::backend::{BackendError, BackendResult, CommitId, ObjectId};
+use crate::backend::{BackendError, BackendResult, CommitId, ObjectId, TreeId};
 use crate::commit::Commit;
 use crate::dag_walk;
 use crate::op_store::RefTarget;
@@ -54,6 +54,18 @@ pub fn merge_commit_trees(repo: &dyn Repo, commits: &[Commit]) -> Tree {
     }
 }
 
+/// Returns the id of the tree `merge_commit_trees()` would return. Since trees
+/// are content-addressed, this can be compared with a commit's tree id to check
+/// for changes without loading any trees unless there are multiple commits to
+/// merge.
+pub fn merge_commit_tree_id(repo: &dyn Repo, commits: &[Commit]) -> TreeId {
+    match commits {
+        [] => repo.store().empty_tree_id().clone(),
+        [commit] => commit.tree_id().clone(),
+        _ => merge_commit_trees(repo, commits).id().clone(),
+    }
+}
+
 pub fn rebase_commit(
     settings: &UserSettings,
     mut_repo: &mut MutableRepo,
@@ -110,6 +122,73 @@ pub fn back_out_commit(
         .write()
 }
 
+/// Moves all the changes in `commit` into `destination`, abandons `commit`,
+/// and rebases its children onto the rewritten destination. Other descendants
+/// of the rewritten commits are rebased too. The destination keeps its
+/// description unless it's empty, in which case `commit`'s is used. Returns
+/// the rewritten destination.
+///
+/// The destination must not be a descendant of `commit`.
+pub fn squash_commit(
+    settings: &UserSettings,
+    mut_repo: &mut MutableRepo,
+    commit: &Commit,
+    destination: &Commit,
+) -> BackendResult<Commit> {
+    assert!(
+        !mut_repo.index().is_ancestor(commit.id(), destination.id()),
+        "cannot squash a commit into itself or its descendants"
+    );
+    let new_tree_id = if commit.parent_ids() == [destination.id().clone()] {
+        // Squashing into the parent, so the parent gets the commit's tree
+        commit.tree_id().clone()
+    } else {
+        let base_tree = merge_commit_trees(mut_repo, &commit.parents());
+        // TODO: pass in labels for the merge parts
+        merge_trees(&destination.tree(), &base_tree, &commit.tree()).unwrap()
+    };
+    let description = if destination.description().is_empty() {
+        commit.description()
+    } else {
+        destination.description()
+    };
+    let new_destination = mut_repo
+        .rewrite_commit(settings, destination)
+        .set_tree(new_tree_id)
+        .set_predecessors(vec![destination.id().clone(), commit.id().clone()])
+        .set_description(description)
+        .write()?;
+    mut_repo.record_abandoned_commit(commit.id().clone());
+
+    let children_expression = RevsetExpression::commit(commit.id().clone()).children();
+    let child_ids = children_expression
+        .evaluate(mut_repo, None)
+        .unwrap()
+        .iter()
+        .commit_ids()
+        .collect_vec();
+    for child_id in child_ids {
+        let child = mut_repo.store().get_commit(&child_id)?;
+        let new_parents: Vec<_> = child
+            .parents()
+            .into_iter()
+            .map(|parent| {
+                if parent.id() == commit.id() || parent.id() == destination.id() {
+                    new_destination.clone()
+                } else {
+                    parent
+                }
+            })
+            .unique()
+            .collect();
+        rebase_commit(settings, mut_repo, &child, &new_parents)?;
+    }
+    mut_repo.rebase_descendants(settings)?;
+    Ok(new_destination)
+}
+
+type RebaseProgressCallback<'a> = Box<dyn FnMut(&CommitId, usize, usize) + 'a>;
+
 /// Rebases descendants of a commit onto a new commit (or several).
 // TODO: Should there be an option to drop empty commits (and/or an option to
 // drop empty commits only if they weren't already empty)? Or maybe that
@@ -137,6 +216,10 @@ pub struct DescendantRebaser<'settings, 'repo> {
     // have been rebased.
     heads_to_add: HashSet<CommitId>,
     heads_to_remove: Vec<CommitId>,
+    // The number of commits in `to_visit` that may need to be rebased. Commits that turn out to
+    // already be in place are skipped, so fewer commits may end up being rebased.
+    num_to_rebase: usize,
+    progress_callback: Option<RebaseProgressCallback<'settings>>,
 }
 
 impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
@@ -212,6 +295,15 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
             }
         }
 
+        let num_to_rebase = to_visit
+            .iter()
+            .filter(|id| {
+                !new_parents.contains_key(id)
+                    && !divergent.contains_key(id)
+                    && !abandoned.contains(id)
+            })
+            .count();
+
         // Build a map from commit to branches pointing to it, so we don't need to scan
         // all branches each time we rebase a commit.
         let mut branches: HashMap<_, HashSet<_>> = HashMap::new();
@@ -244,9 +336,21 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
             branches,
             heads_to_add,
             heads_to_remove: Default::default(),
+            num_to_rebase,
+            progress_callback: None,
         }
     }
 
+    /// Registers a callback to be called after each commit is rebased. It's
+    /// called with the id of the old commit, the number of commits rebased so
+    /// far, and the total number of commits that may need to be rebased.
+    pub fn set_progress_callback(
+        &mut self,
+        callback: impl FnMut(&CommitId, usize, usize) + 'settings,
+    ) {
+        self.progress_callback = Some(Box::new(callback));
+    }
+
     /// Returns a map from `CommitId` of old commit to new commit. Includes the
     /// commits rebase so far. Does not include the inputs passed to
     /// `rebase_descendants`.
@@ -396,7 +500,8 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
                 continue;
             }
 
-            // Don't create commit where one parent is an ancestor of another.
+            // Don't create commit where one parent is an ancestor of another, or where
+            // a parent is repeated (e.g. when two parents were squashed together).
             let head_set: HashSet<_> = self
                 .mut_repo
                 .index()
@@ -408,6 +513,7 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
                 new_parent_ids
                     .iter()
                     .filter(|new_parent| head_set.contains(new_parent))
+                    .unique()
                     .map(|new_parent_id| self.mut_repo.store().get_commit(new_parent_id)),
                 |iter| iter.collect_vec(),
             )?;
@@ -416,6 +522,9 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
             self.rebased
                 .insert(old_commit_id.clone(), new_commit.id().clone());
             self.update_references(old_commit_id, vec![new_commit.id().clone()], true)?;
+            if let Some(callback) = &mut self.progress_callback {
+                callback(old_commit.id(), self.rebased.len(), self.num_to_rebase);
+            }
             return Ok(Some(RebasedDescendant {
                 old_commit,
                 new_commit,
@@ -443,6 +552,48 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
         while self.rebase_next()?.is_some() {}
         Ok(())
     }
+
+    /// Like `rebase_all()`, but stops after the first commit that got new
+    /// conflicts, i.e. conflicts that neither the original commit nor its new
+    /// parents have. The conflicted commit has been written by then, and
+    /// calling this method (or `rebase_all()`) again continues with the
+    /// remaining descendants.
+    pub fn rebase_until_conflict(&mut self) -> Result<RebaseUntilConflict, BackendError> {
+        while let Some(RebasedDescendant {
+            old_commit,
+            new_commit,
+        }) = self.rebase_next()?
+        {
+            let new_tree = new_commit.tree();
+            if !new_tree.has_conflict() {
+                continue;
+            }
+            // Conflicts inherited from the new parents were reported when the
+            // parents were rebased (or existed before)
+            let new_parent_tree = merge_commit_trees(self.mut_repo, &new_commit.parents());
+            let existing_conflicts: HashSet<_> = old_commit
+                .tree()
+                .conflicts()
+                .into_iter()
+                .chain(new_parent_tree.conflicts())
+                .map(|(path, _conflict_id)| path)
+                .collect();
+            let conflicted_paths = new_tree
+                .conflicts()
+                .into_iter()
+                .map(|(path, _conflict_id)| path)
+                .filter(|path| !existing_conflicts.contains(path))
+                .collect_vec();
+            if !conflicted_paths.is_empty() {
+                return Ok(RebaseUntilConflict::Conflict(RebaseConflict {
+                    old_commit,
+                    new_commit,
+                    conflicted_paths,
+                }));
+            }
+        }
+        Ok(RebaseUntilConflict::Done(self.rebased.len()))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -450,3 +601,22 @@ pub struct RebasedDescendant {
     pub old_commit: Commit,
     pub new_commit: Commit,
 }
+
+/// A commit that got new conflicts when it was rebased.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RebaseConflict {
+    pub old_commit: Commit,
+    pub new_commit: Commit,
+    /// The paths that are conflicted in the new commit but not in the old
+    /// commit or the new parents.
+    pub conflicted_paths: Vec<RepoPath>,
+}
+
+/// The result of `DescendantRebaser::rebase_until_conflict()`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RebaseUntilConflict {
+    /// All descendants were rebased without new conflicts. Contains the total
+    /// number of rebased commits.
+    Done(usize),
+    Conflict(RebaseConflict),
+}
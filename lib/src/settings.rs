@@ -20,6 +20,7 @@ use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 
 use crate::backend::{ChangeId, ObjectId, Signature, Timestamp};
+use crate::store::DEFAULT_COMMIT_CACHE_CAPACITY;
 
 #[derive(Debug, Clone)]
 pub struct UserSettings {
@@ -30,7 +31,28 @@ pub struct UserSettings {
 
 #[derive(Debug, Clone)]
 pub struct RepoSettings {
-    _config: config::Config,
+    config: config::Config,
+}
+
+impl RepoSettings {
+    /// Whether branch symbols in revsets may match branch names differing in
+    /// case, if the match is unambiguous.
+    pub fn case_insensitive_branches(&self) -> bool {
+        self.config
+            .get_bool("revsets.case-insensitive-branches")
+            .unwrap_or(false)
+    }
+
+    /// The number of commits the store keeps in its cache. Falls back to
+    /// `DEFAULT_COMMIT_CACHE_CAPACITY` if unset or not a positive integer.
+    pub fn commit_cache_capacity(&self) -> usize {
+        self.config
+            .get_int("store.commit-cache-capacity")
+            .ok()
+            .and_then(|capacity| usize::try_from(capacity).ok())
+            .filter(|&capacity| capacity > 0)
+            .unwrap_or(DEFAULT_COMMIT_CACHE_CAPACITY)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -86,7 +108,7 @@ impl UserSettings {
     // https://github.com/martinvonz/jj/issues/616#issuecomment-1345170699
     pub fn with_repo(&self, _repo_path: &Path) -> Result<RepoSettings, config::ConfigError> {
         let config = self.config.clone();
-        Ok(RepoSettings { _config: config })
+        Ok(RepoSettings { config })
     }
 
     pub fn get_rng(&self) -> Arc<JJRng> {
@@ -14,6 +14,7 @@
 
 use std::fmt::Debug;
 use std::path::Path;
+use std::time::SystemTime;
 
 use tempfile::PersistError;
 
@@ -152,6 +153,22 @@ impl OpStore for SimpleOpStore {
     fn write_operation(&self, operation: &Operation) -> OpStoreResult<OperationId> {
         self.delegate.write_operation(operation)
     }
+
+    fn view_ids(&self, written_before: SystemTime) -> OpStoreResult<Vec<ViewId>> {
+        self.delegate.view_ids(written_before)
+    }
+
+    fn operation_ids(&self, written_before: SystemTime) -> OpStoreResult<Vec<OperationId>> {
+        self.delegate.operation_ids(written_before)
+    }
+
+    fn remove_view(&self, id: &ViewId) -> OpStoreResult<()> {
+        self.delegate.remove_view(id)
+    }
+
+    fn remove_operation(&self, id: &OperationId) -> OpStoreResult<()> {
+        self.delegate.remove_operation(id)
+    }
 }
 
 #[cfg(test)]
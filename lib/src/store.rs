@@ -13,8 +13,12 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
 use std::io::Read;
-use std::sync::{Arc, RwLock};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, RwLock};
+
+use clru::CLruCache;
 
 use crate::backend;
 use crate::backend::{
@@ -25,24 +29,86 @@ use crate::repo_path::RepoPath;
 use crate::tree::Tree;
 use crate::tree_builder::TreeBuilder;
 
+/// The number of commits `Store::new()` keeps in its cache. Repos use it unless
+/// `store.commit-cache-capacity` is configured.
+pub const DEFAULT_COMMIT_CACHE_CAPACITY: usize = 1000;
+
 /// Wraps the low-level backend and makes it return more convenient types. Also
 /// adds caching.
-#[derive(Debug)]
 pub struct Store {
     backend: Box<dyn Backend>,
-    commit_cache: RwLock<HashMap<CommitId, Arc<backend::Commit>>>,
+    commit_cache: Mutex<CommitCache>,
     tree_cache: RwLock<HashMap<(RepoPath, TreeId), Arc<backend::Tree>>>,
 }
 
+/// Statistics about the commit cache in `Store`, for debugging.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitCacheStats {
+    /// The number of lookups that found the commit in the cache.
+    pub hits: u64,
+    /// The number of lookups that had to read the commit from the backend.
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+// Commits are immutable, so cached entries never need to be invalidated, only
+// evicted when the cache is full.
+struct CommitCache {
+    cache: CLruCache<CommitId, Arc<backend::Commit>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl CommitCache {
+    fn get(&mut self, id: &CommitId) -> Option<Arc<backend::Commit>> {
+        let data = self.cache.get(id).cloned();
+        if data.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        data
+    }
+}
+
+impl Debug for Store {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("Store")
+            .field("backend", &self.backend)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Store {
     pub fn new(backend: Box<dyn Backend>) -> Arc<Self> {
+        Self::with_commit_cache_capacity(backend, DEFAULT_COMMIT_CACHE_CAPACITY)
+    }
+
+    /// Creates a store that caches up to `capacity` commits.
+    pub fn with_commit_cache_capacity(backend: Box<dyn Backend>, capacity: usize) -> Arc<Self> {
+        let capacity = NonZeroUsize::new(capacity).expect("commit cache capacity must be nonzero");
         Arc::new(Store {
             backend,
-            commit_cache: Default::default(),
+            commit_cache: Mutex::new(CommitCache {
+                cache: CLruCache::new(capacity),
+                hits: 0,
+                misses: 0,
+            }),
             tree_cache: Default::default(),
         })
     }
 
+    pub fn commit_cache_stats(&self) -> CommitCacheStats {
+        let locked_cache = self.commit_cache.lock().unwrap();
+        CommitCacheStats {
+            hits: locked_cache.hits,
+            misses: locked_cache.misses,
+            len: locked_cache.cache.len(),
+            capacity: locked_cache.cache.capacity(),
+        }
+    }
+
     pub fn commit_id_length(&self) -> usize {
         self.backend.commit_id_length()
     }
@@ -76,17 +142,51 @@ impl Store {
         Ok(Commit::new(self.clone(), id.clone(), data))
     }
 
+    /// Returns the commits in the order of `ids`. Commits that aren't cached
+    /// are read from the backend in a single batch.
+    pub fn get_commits(self: &Arc<Self>, ids: &[CommitId]) -> BackendResult<Vec<Commit>> {
+        let mut datas = Vec::with_capacity(ids.len());
+        let mut missing_ids = vec![];
+        {
+            let mut locked_cache = self.commit_cache.lock().unwrap();
+            for id in ids {
+                let data = locked_cache.get(id);
+                if data.is_none() {
+                    missing_ids.push(id.clone());
+                }
+                datas.push(data);
+            }
+        }
+        if !missing_ids.is_empty() {
+            let commits = self.backend.read_commits(&missing_ids)?;
+            let mut locked_cache = self.commit_cache.lock().unwrap();
+            let mut new_datas = missing_ids.into_iter().zip(commits).map(|(id, commit)| {
+                let data = Arc::new(commit);
+                locked_cache.cache.put(id, data.clone());
+                data
+            });
+            for data in datas.iter_mut().filter(|data| data.is_none()) {
+                *data = new_datas.next();
+            }
+        }
+        Ok(ids
+            .iter()
+            .zip(datas)
+            .map(|(id, data)| Commit::new(self.clone(), id.clone(), data.unwrap()))
+            .collect())
+    }
+
     fn get_backend_commit(&self, id: &CommitId) -> BackendResult<Arc<backend::Commit>> {
         {
-            let read_locked_cached = self.commit_cache.read().unwrap();
-            if let Some(data) = read_locked_cached.get(id).cloned() {
+            let mut locked_cache = self.commit_cache.lock().unwrap();
+            if let Some(data) = locked_cache.get(id) {
                 return Ok(data);
             }
         }
         let commit = self.backend.read_commit(id)?;
         let data = Arc::new(commit);
-        let mut write_locked_cache = self.commit_cache.write().unwrap();
-        write_locked_cache.insert(id.clone(), data.clone());
+        let mut locked_cache = self.commit_cache.lock().unwrap();
+        locked_cache.cache.put(id.clone(), data.clone());
         Ok(data)
     }
 
@@ -95,8 +195,8 @@ impl Store {
         let commit_id = self.backend.write_commit(&commit)?;
         let data = Arc::new(commit);
         {
-            let mut write_locked_cache = self.commit_cache.write().unwrap();
-            write_locked_cache.insert(commit_id.clone(), data.clone());
+            let mut locked_cache = self.commit_cache.lock().unwrap();
+            locked_cache.cache.put(commit_id.clone(), data.clone());
         }
 
         Ok(Commit::new(self.clone(), commit_id, data))
@@ -18,9 +18,9 @@ use crate::backend::Timestamp;
 use crate::dag_walk::closest_common_node;
 use crate::index::ReadonlyIndex;
 use crate::op_store;
-use crate::op_store::OperationMetadata;
-use crate::operation::Operation;
-use crate::repo::{MutableRepo, ReadonlyRepo, Repo, RepoLoader};
+use crate::op_store::{OperationMetadata, RefTarget};
+use crate::operation::{diff_maps, Operation};
+use crate::repo::{MutableRepo, ReadonlyRepo, Repo, RepoLoader, Savepoint};
 use crate::settings::UserSettings;
 use crate::view::View;
 
@@ -64,6 +64,17 @@ impl Transaction {
         &mut self.mut_repo
     }
 
+    /// Creates a savepoint that the transaction can later be rolled back to
+    /// with `restore()`.
+    pub fn savepoint(&self) -> Savepoint {
+        self.mut_repo.savepoint()
+    }
+
+    /// Rolls the transaction back to the state captured by `savepoint`.
+    pub fn restore(&mut self, savepoint: Savepoint) {
+        self.mut_repo.restore_savepoint(savepoint);
+    }
+
     pub fn merge_operation(&mut self, other_op: Operation) {
         let ancestor_op = closest_common_node(
             self.parent_ops.clone(),
@@ -97,6 +108,7 @@ impl Transaction {
         );
         let base_repo = mut_repo.base_repo().clone();
         let (mut_index, view) = mut_repo.consume();
+        let branch_changes = local_branch_changes(base_repo.view(), &view);
 
         let view_id = base_repo.op_store().write_view(view.store_view()).unwrap();
         self.op_metadata.end_time = self.end_time.unwrap_or_else(Timestamp::now);
@@ -116,10 +128,59 @@ impl Transaction {
             .index_store()
             .write_index(mut_index, operation.id())
             .unwrap();
-        UnpublishedOperation::new(base_repo.loader(), operation, view, index)
+        UnpublishedOperation::new(base_repo.loader(), operation, view, index, branch_changes)
+    }
+
+    /// Returns the outcome of the transaction without writing an operation,
+    /// so it can be previewed. Note that commits written by the mutations are
+    /// still in the backend, but they won't be reachable from any operation.
+    pub fn dry_run(self) -> DryRunResult {
+        let mut_repo = self.mut_repo;
+        assert!(
+            !mut_repo.has_rewrites(),
+            "BUG: Descendants have not been rebased after the last rewrites."
+        );
+        let base_repo = mut_repo.base_repo().clone();
+        let (_, view) = mut_repo.consume();
+        let branch_changes = local_branch_changes(base_repo.view(), &view);
+        DryRunResult {
+            view,
+            branch_changes,
+        }
     }
 }
 
+/// The would-be result of a transaction, as returned by
+/// `Transaction::dry_run()`.
+pub struct DryRunResult {
+    pub view: View,
+    /// Local branches that would be created, deleted, or moved, sorted by name.
+    pub branch_changes: Vec<BranchChange>,
+}
+
+/// A local branch that was created, deleted, or moved by a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchChange {
+    pub name: String,
+    pub old_target: Option<RefTarget>,
+    pub new_target: Option<RefTarget>,
+}
+
+fn local_branch_changes(old_view: &View, new_view: &View) -> Vec<BranchChange> {
+    diff_maps(old_view.branches(), new_view.branches())
+        .into_iter()
+        .filter_map(|(name, (old_branch, new_branch))| {
+            let old_target = old_branch.and_then(|branch| branch.local_target);
+            let new_target = new_branch.and_then(|branch| branch.local_target);
+            (old_target != new_target).then(|| BranchChange {
+                name,
+                old_target,
+                new_target,
+            })
+        })
+        .collect()
+}
+
 pub fn create_op_metadata(user_settings: &UserSettings, description: String) -> OperationMetadata {
     let start_time = user_settings
         .operation_timestamp()
@@ -146,6 +207,7 @@ struct NewRepoData {
 pub struct UnpublishedOperation {
     repo_loader: RepoLoader,
     data: Option<NewRepoData>,
+    branch_changes: Vec<BranchChange>,
     closed: bool,
 }
 
@@ -155,6 +217,7 @@ impl UnpublishedOperation {
         operation: Operation,
         view: View,
         index: Box<dyn ReadonlyIndex>,
+        branch_changes: Vec<BranchChange>,
     ) -> Self {
         let data = Some(NewRepoData {
             operation,
@@ -164,6 +227,7 @@ impl UnpublishedOperation {
         UnpublishedOperation {
             repo_loader,
             data,
+            branch_changes,
             closed: false,
         }
     }
@@ -172,6 +236,12 @@ impl UnpublishedOperation {
         &self.data.as_ref().unwrap().operation
     }
 
+    /// Local branches that were created, deleted, or moved compared to the
+    /// repo the transaction started from, sorted by name.
+    pub fn branch_changes(&self) -> &[BranchChange] {
+        &self.branch_changes
+    }
+
     pub fn publish(mut self) -> Arc<ReadonlyRepo> {
         let data = self.data.take().unwrap();
         self.repo_loader
@@ -225,7 +225,9 @@ impl Tree {
     }
 
     pub fn has_conflict(&self) -> bool {
-        !self.conflicts().is_empty()
+        // Stop at the first conflict instead of collecting all of them
+        self.entries()
+            .any(|(_, value)| matches!(value, TreeValue::Conflict(_)))
     }
 }
 
@@ -462,9 +464,11 @@ impl Iterator for TreeDiffIterator<'_> {
             let (name, before, after) = self.entry_iterator.next()?;
             let tree_before = matches!(before, Some(TreeValue::Tree(_)));
             let tree_after = matches!(after, Some(TreeValue::Tree(_)));
-            if tree_before || tree_after {
+            let subdir_path = self.dir.join(&name);
+            // Don't descend into directories in which the matcher can't match
+            // anything, so we don't even have to read their trees.
+            if (tree_before || tree_after) && !self.matcher.visit(&subdir_path).is_nothing() {
                 let subdir = &name;
-                let subdir_path = self.dir.join(subdir);
                 let before_tree = match before {
                     Some(TreeValue::Tree(id_before)) => {
                         self.tree1.known_sub_tree(subdir, id_before)
@@ -627,13 +631,8 @@ fn merge_tree_value(
             }
             let filename = dir.join(basename);
             let conflict = simplify_conflict(store, &filename, conflict)?;
-            if conflict.adds.is_empty() {
-                // If there are no values to add, then the path doesn't exist
-                return Ok(None);
-            }
-            if conflict.removes.is_empty() && conflict.adds.len() == 1 {
-                // A single add means that the current state is that state.
-                return Ok(Some(conflict.adds[0].value.clone()));
+            if let Some(resolved) = conflict.try_resolve_trivially() {
+                return Ok(resolved);
             }
             if let Some((merged_content, executable)) =
                 try_resolve_file_conflict(store, &filename, &conflict)?
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::sync::Arc;
 
 use crate::backend;
@@ -32,6 +32,9 @@ pub struct TreeBuilder {
     store: Arc<Store>,
     base_tree_id: TreeId,
     overrides: BTreeMap<RepoPath, Override>,
+    // Directories whose base contents are discarded. Overrides under them are
+    // applied on top of an empty directory.
+    removed_subtrees: BTreeSet<RepoPath>,
 }
 
 impl TreeBuilder {
@@ -41,6 +44,7 @@ impl TreeBuilder {
             store,
             base_tree_id,
             overrides,
+            removed_subtrees: BTreeSet::new(),
         }
     }
 
@@ -49,17 +53,53 @@ impl TreeBuilder {
     }
 
     pub fn has_overrides(&self) -> bool {
-        !self.overrides.is_empty()
+        !self.overrides.is_empty() || !self.removed_subtrees.is_empty()
     }
 
     pub fn set(&mut self, path: RepoPath, value: TreeValue) {
+        self.discard_overrides_under(&path);
         self.overrides.insert(path, Override::Replace(value));
     }
 
     pub fn remove(&mut self, path: RepoPath) {
+        self.discard_overrides_under(&path);
         self.overrides.insert(path, Override::Tombstone);
     }
 
+    /// Discards earlier overrides and removed subtrees at or under `path`,
+    /// which are replaced by a new override of `path`.
+    fn discard_overrides_under(&mut self, path: &RepoPath) {
+        // Paths under `path` sort right after it
+        let stale_paths: Vec<_> = self
+            .overrides
+            .range::<RepoPath, _>(path..)
+            .map(|(stale_path, _)| stale_path)
+            .take_while(|stale_path| path.contains(stale_path))
+            .cloned()
+            .collect();
+        for stale_path in &stale_paths {
+            self.overrides.remove(stale_path);
+        }
+        let stale_dirs: Vec<_> = self
+            .removed_subtrees
+            .range::<RepoPath, _>(path..)
+            .take_while(|stale_dir| path.contains(stale_dir))
+            .cloned()
+            .collect();
+        for stale_dir in &stale_dirs {
+            self.removed_subtrees.remove(stale_dir);
+        }
+    }
+
+    /// Removes all entries under `dir`. Earlier calls to `set()` and `remove()`
+    /// for paths under `dir` are discarded, while later calls are applied to
+    /// the then empty directory.
+    pub fn remove_subtree(&mut self, dir: &RepoPath) {
+        self.overrides.retain(|path, _| !dir.contains(path));
+        self.removed_subtrees.retain(|path| !dir.contains(path));
+        self.removed_subtrees.insert(dir.clone());
+    }
+
     pub fn write_tree(mut self) -> TreeId {
         let mut trees_to_write = self.get_base_trees();
         if trees_to_write.is_empty() {
@@ -115,12 +155,17 @@ impl TreeBuilder {
         let mut tree_cache = BTreeMap::new();
         let mut base_trees = BTreeMap::new();
         let store = self.store.clone();
+        let removed_subtrees = &self.removed_subtrees;
 
         let mut populate_trees = |dir: &RepoPath| {
             let mut current_dir = RepoPath::root();
 
             if !tree_cache.contains_key(&current_dir) {
-                let tree = store.get_tree(&current_dir, &self.base_tree_id).unwrap();
+                let tree = if removed_subtrees.contains(&current_dir) {
+                    Tree::null(self.store.clone(), current_dir.clone())
+                } else {
+                    store.get_tree(&current_dir, &self.base_tree_id).unwrap()
+                };
                 let store_tree = tree.data().clone();
                 tree_cache.insert(current_dir.clone(), tree);
                 base_trees.insert(current_dir.clone(), store_tree);
@@ -130,9 +175,12 @@ impl TreeBuilder {
                 let next_dir = current_dir.join(component);
                 let current_tree = tree_cache.get(&current_dir).unwrap();
                 if !tree_cache.contains_key(&next_dir) {
-                    let tree = current_tree
-                        .sub_tree(component)
-                        .unwrap_or_else(|| Tree::null(self.store.clone(), next_dir.clone()));
+                    let tree = if removed_subtrees.contains(&next_dir) {
+                        None
+                    } else {
+                        current_tree.sub_tree(component)
+                    }
+                    .unwrap_or_else(|| Tree::null(self.store.clone(), next_dir.clone()));
                     let store_tree = tree.data().clone();
                     tree_cache.insert(next_dir.clone(), tree);
                     base_trees.insert(next_dir.clone(), store_tree);
@@ -145,6 +193,9 @@ impl TreeBuilder {
                 populate_trees(&parent);
             }
         }
+        for dir in &self.removed_subtrees {
+            populate_trees(dir);
+        }
 
         base_trees
     }
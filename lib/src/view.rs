@@ -212,6 +212,21 @@ impl View {
         }
     }
 
+    /// Moves the local and remote targets of the branch `old` to `new`.
+    /// Returns false without changing anything if `old` doesn't exist or
+    /// `new` already exists.
+    pub fn rename_branch(&mut self, old: &str, new: &str) -> bool {
+        if self.data.branches.contains_key(new) {
+            return false;
+        }
+        if let Some(target) = self.data.branches.remove(old) {
+            self.data.branches.insert(new.to_owned(), target);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn rename_remote(&mut self, old: &str, new: &str) {
         for branch in self.data.branches.values_mut() {
             if let Some(target) = branch.remote_targets.remove(old) {
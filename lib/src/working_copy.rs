@@ -12,10 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashSet};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
-use std::fs::{DirEntry, File, Metadata, OpenOptions};
+use std::fs::{File, Metadata, OpenOptions};
 use std::io::{Read, Write};
 use std::ops::Bound;
 #[cfg(unix)]
@@ -34,7 +35,7 @@ use thiserror::Error;
 use crate::backend::{
     BackendError, ConflictId, FileId, MillisSinceEpoch, ObjectId, SymlinkId, TreeId, TreeValue,
 };
-use crate::conflicts::{materialize_conflict, update_conflict_from_content};
+use crate::conflicts::{materialize_conflict, update_conflict_from_content, MaterializeStyle};
 use crate::gitignore::GitIgnoreFile;
 use crate::lock::FileLock;
 use crate::matchers::{DifferenceMatcher, Matcher, PrefixMatcher};
@@ -275,6 +276,42 @@ pub struct CheckoutStats {
     pub removed_files: u32,
 }
 
+/// A tracked file that was found to be missing from the working copy when
+/// taking a snapshot.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RemovedFile {
+    pub path: RepoPath,
+    /// Whether the path is now ignored by the `.gitignore` files, which
+    /// suggests that the file was removed intentionally.
+    pub ignored: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SnapshotStats {
+    /// The removed files, sorted by path.
+    pub removed_files: Vec<RemovedFile>,
+}
+
+/// Tells a working-copy snapshot which paths may have changed since the
+/// previous snapshot, so it doesn't have to scan the whole working copy. This
+/// can be implemented on top of a file system monitor such as Watchman.
+pub trait FileStateProvider {
+    /// Returns the paths that may have changed, or `None` if the whole working
+    /// copy should be scanned. Everything in a returned directory is scanned,
+    /// and tracked paths that are missing on disk are considered deleted.
+    fn changed_paths(&self) -> Option<Vec<RepoPath>>;
+}
+
+/// A `FileStateProvider` that has the whole working copy scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FullScan;
+
+impl FileStateProvider for FullScan {
+    fn changed_paths(&self) -> Option<Vec<RepoPath>> {
+        None
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum SnapshotError {
     #[error("{message}: {err}")]
@@ -481,29 +518,94 @@ impl TreeState {
     }
 
     /// Look for changes to the working copy. If there are any changes, create
-    /// a new tree from it.
-    pub fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<bool, SnapshotError> {
+    /// a new tree from it. Returns whether there were any changes, along with
+    /// details about them.
+    ///
+    /// Only the paths reported by the `file_state_provider` are scanned.
+    pub fn snapshot(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        file_state_provider: &dyn FileStateProvider,
+    ) -> Result<(bool, SnapshotStats), SnapshotError> {
         let sparse_matcher = self.sparse_matcher();
-        let mut work = vec![(
-            RepoPath::root(),
-            self.working_copy_path.clone(),
-            base_ignores,
-        )];
-
+        let mut work = vec![];
         let mut tree_builder = self.store.tree_builder(self.tree_id.clone());
-        let mut deleted_files: HashSet<_> = self
-            .file_states
-            .iter()
-            .filter_map(|(path, state)| {
-                (state.file_type != FileType::GitSubmodule).then(|| path.clone())
-            })
-            .collect();
+        // Tracked files in the scanned paths that we haven't found on disk yet
+        let mut deleted_files = HashSet::new();
+        // The ignores in effect in each visited directory, for telling whether
+        // deleted files are now ignored
+        let mut dir_ignores = HashMap::new();
+        let changed_paths = file_state_provider
+            .changed_paths()
+            .unwrap_or_else(|| vec![RepoPath::root()]);
+        for path in changed_paths {
+            if path
+                .components()
+                .iter()
+                .any(|component| component.as_str() == ".jj" || component.as_str() == ".git")
+            {
+                continue;
+            }
+            deleted_files.extend(self.tracked_files_under(&path).cloned());
+            let parent = match path.parent() {
+                Some(parent) => parent,
+                None => {
+                    work.push((path, self.working_copy_path.clone(), base_ignores.clone()));
+                    continue;
+                }
+            };
+            if sparse_matcher.visit(&parent).is_nothing() {
+                continue;
+            }
+            if let Some(file_state) = self.file_states.get(&path) {
+                if file_state.file_type == FileType::GitSubmodule {
+                    continue;
+                }
+            }
+            let git_ignore = self.git_ignore_for_dir(&base_ignores, &parent);
+            dir_ignores.insert(parent, git_ignore.clone());
+            let disk_path = path.to_fs_path(&self.working_copy_path);
+            let metadata = match fs::symlink_metadata(&disk_path) {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    // The path was deleted
+                    continue;
+                }
+                Err(err) => {
+                    return Err(SnapshotError::IoError {
+                        message: format!("Failed to stat file {}", disk_path.display()),
+                        err,
+                    });
+                }
+            };
+            if metadata.is_dir() {
+                if git_ignore.matches_all_files_in(&path.to_internal_dir_string())
+                    && !self.has_files_under(&path)
+                {
+                    continue;
+                }
+                work.push((path, disk_path, git_ignore));
+            } else {
+                deleted_files.remove(&path);
+                if sparse_matcher.matches(&path) {
+                    self.update_file_state(
+                        path,
+                        &disk_path,
+                        move || Ok(metadata),
+                        git_ignore.as_ref(),
+                        &mut tree_builder,
+                    )?;
+                }
+            }
+        }
+
         while let Some((dir, disk_dir, git_ignore)) = work.pop() {
             if sparse_matcher.visit(&dir).is_nothing() {
                 continue;
             }
             let git_ignore = git_ignore
                 .chain_with_file(&dir.to_internal_dir_string(), disk_dir.join(".gitignore"));
+            dir_ignores.insert(dir.clone(), git_ignore.clone());
             for maybe_entry in disk_dir.read_dir().unwrap() {
                 let entry = maybe_entry.unwrap();
                 let file_type = entry.file_type().unwrap();
@@ -537,7 +639,8 @@ impl TreeState {
                     if sparse_matcher.matches(&sub_path) {
                         self.update_file_state(
                             sub_path,
-                            &entry,
+                            &entry.path(),
+                            || entry.metadata(),
                             git_ignore.as_ref(),
                             &mut tree_builder,
                         )?;
@@ -546,13 +649,67 @@ impl TreeState {
             }
         }
 
-        for file in &deleted_files {
-            self.file_states.remove(file);
+        let mut removed_files = vec![];
+        for file in deleted_files {
+            self.file_states.remove(&file);
             tree_builder.remove(file.clone());
+            // If the file's directory is gone too, use the ignores of the closest
+            // ancestor that's still there.
+            let mut ignored = false;
+            let mut maybe_dir = file.parent();
+            while let Some(dir) = maybe_dir {
+                if let Some(git_ignore) = dir_ignores.get(&dir) {
+                    ignored = git_ignore.matches_file(&file.to_internal_file_string());
+                    break;
+                }
+                maybe_dir = dir.parent();
+            }
+            removed_files.push(RemovedFile {
+                path: file,
+                ignored,
+            });
         }
+        removed_files.sort_by(|a, b| a.path.cmp(&b.path));
         let changed = tree_builder.has_overrides();
         self.tree_id = tree_builder.write_tree();
-        Ok(changed)
+        Ok((changed, SnapshotStats { removed_files }))
+    }
+
+    /// Returns the tracked files in `dir` and its subdirectories, or `dir`
+    /// itself if it's a tracked file. Git submodules are skipped.
+    fn tracked_files_under<'a>(
+        &'a self,
+        dir: &'a RepoPath,
+    ) -> impl Iterator<Item = &'a RepoPath> + 'a {
+        self.file_states
+            .range(dir.clone()..)
+            .take_while(move |(path, _)| dir.contains(path))
+            .filter(|(_, state)| state.file_type != FileType::GitSubmodule)
+            .map(|(path, _)| path)
+    }
+
+    /// Returns the ignores in effect in `dir`, including the ones from its own
+    /// `.gitignore` file.
+    fn git_ignore_for_dir(
+        &self,
+        base_ignores: &Arc<GitIgnoreFile>,
+        dir: &RepoPath,
+    ) -> Arc<GitIgnoreFile> {
+        let mut current_dir = RepoPath::root();
+        let mut git_ignore = base_ignores.chain_with_file(
+            &current_dir.to_internal_dir_string(),
+            self.working_copy_path.join(".gitignore"),
+        );
+        for component in dir.components() {
+            current_dir = current_dir.join(component);
+            git_ignore = git_ignore.chain_with_file(
+                &current_dir.to_internal_dir_string(),
+                current_dir
+                    .to_fs_path(&self.working_copy_path)
+                    .join(".gitignore"),
+            );
+        }
+        git_ignore
     }
 
     fn has_files_under(&self, dir: &RepoPath) -> bool {
@@ -577,7 +734,8 @@ impl TreeState {
     fn update_file_state(
         &mut self,
         repo_path: RepoPath,
-        dir_entry: &DirEntry,
+        disk_path: &Path,
+        metadata: impl FnOnce() -> std::io::Result<Metadata>,
         git_ignore: &GitIgnoreFile,
         tree_builder: &mut TreeBuilder,
     ) -> Result<(), SnapshotError> {
@@ -590,8 +748,7 @@ impl TreeState {
             return Ok(());
         }
 
-        let disk_path = dir_entry.path();
-        let metadata = dir_entry.metadata().map_err(|err| SnapshotError::IoError {
+        let metadata = metadata().map_err(|err| SnapshotError::IoError {
             message: format!("Failed to stat file {}", disk_path.display()),
             err,
         })?;
@@ -609,7 +766,7 @@ impl TreeState {
                 // untracked
                 let file_type = new_file_state.file_type.clone();
                 self.file_states.insert(repo_path.clone(), new_file_state);
-                let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
+                let file_value = self.write_path_to_store(&repo_path, disk_path, file_type)?;
                 tree_builder.set(repo_path, file_value);
             }
             (Some(current_file_state), Some(mut new_file_state)) => {
@@ -626,6 +783,19 @@ impl TreeState {
                     current_file_state.mtime = MillisSinceEpoch(0);
                 }
                 let mut clean = current_file_state == &new_file_state;
+                // Symlinks may have been checked out as regular files containing the target
+                // (see `write_symlink()`). They're unchanged as long as the file is.
+                #[cfg(windows)]
+                {
+                    if !clean
+                        && current_file_state.file_type == FileType::Symlink
+                        && matches!(new_file_state.file_type, FileType::Normal { .. })
+                        && new_file_state.mtime == current_file_state.mtime
+                        && new_file_state.size == current_file_state.size
+                    {
+                        clean = true;
+                    }
+                }
                 // Because the file system doesn't have a built-in way of indicating a conflict,
                 // we look at the current state instead. If that indicates that the path has a
                 // conflict and the contents are now a file, then we take interpret that as if
@@ -648,7 +818,7 @@ impl TreeState {
                         if let (FileType::Conflict { id }, FileType::Normal { executable: _ }) =
                             (&current_file_state.file_type, &new_file_state.file_type)
                         {
-                            let mut file = File::open(&disk_path).unwrap();
+                            let mut file = File::open(disk_path).unwrap();
                             let mut content = vec![];
                             file.read_to_end(&mut content).unwrap();
                             if let Some(new_conflict_id) = update_conflict_from_content(
@@ -672,7 +842,7 @@ impl TreeState {
                 if !clean {
                     let file_type = new_file_state.file_type.clone();
                     *current_file_state = new_file_state;
-                    let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
+                    let file_value = self.write_path_to_store(&repo_path, disk_path, file_type)?;
                     tree_builder.set(repo_path, file_value);
                 }
             }
@@ -733,7 +903,6 @@ impl TreeState {
         Ok(FileState::for_file(executable, size, &metadata))
     }
 
-    #[cfg_attr(windows, allow(unused_variables))]
     fn write_symlink(
         &self,
         disk_path: &Path,
@@ -744,7 +913,24 @@ impl TreeState {
         let target = self.store.read_symlink(path, id)?;
         #[cfg(windows)]
         {
-            println!("ignoring symlink at {:?}", path);
+            // Creating symlinks requires special privileges on Windows, so fall back to
+            // writing a regular file containing the target, like Git does when
+            // `core.symlinks` is false.
+            let target_path = PathBuf::from(&target);
+            let is_dir_target = disk_path
+                .parent()
+                .map_or(false, |dir| dir.join(&target_path).is_dir());
+            let result = if is_dir_target {
+                std::os::windows::fs::symlink_dir(&target_path, disk_path)
+            } else {
+                std::os::windows::fs::symlink_file(&target_path, disk_path)
+            };
+            if result.is_err() {
+                fs::write(disk_path, target.as_bytes()).map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to write file {}", disk_path.display()),
+                    err,
+                })?;
+            }
         }
         #[cfg(unix)]
         {
@@ -781,8 +967,14 @@ impl TreeState {
                 err,
             })?;
         let mut conflict_data = vec![];
-        materialize_conflict(self.store.as_ref(), path, &conflict, &mut conflict_data)
-            .expect("Failed to materialize conflict to in-memory buffer");
+        materialize_conflict(
+            self.store.as_ref(),
+            path,
+            &conflict,
+            MaterializeStyle::Diff,
+            &mut conflict_data,
+        )
+        .expect("Failed to materialize conflict to in-memory buffer");
         file.write_all(&conflict_data)
             .map_err(|err| CheckoutError::IoError {
                 message: format!("Failed to write conflict to file {}", disk_path.display()),
@@ -1005,7 +1197,97 @@ struct CheckoutState {
     workspace_id: WorkspaceId,
 }
 
-pub struct WorkingCopy {
+/// The working copy of a workspace, which keeps track of the files checked out
+/// into the workspace and the operation they were checked out at.
+pub trait WorkingCopy {
+    fn as_any(&self) -> &dyn Any;
+
+    /// The name of this working-copy implementation. It's recorded in the
+    /// workspace so the matching factory is used when the workspace is loaded.
+    fn name(&self) -> &str;
+
+    fn working_copy_path(&self) -> &Path;
+
+    fn state_path(&self) -> &Path;
+
+    fn workspace_id(&self) -> &WorkspaceId;
+
+    fn operation_id(&self) -> &OperationId;
+
+    fn current_tree_id(&self) -> &TreeId;
+
+    fn sparse_patterns(&self) -> &[RepoPath];
+
+    fn start_mutation(&mut self) -> Box<dyn LockedWorkingCopy + '_>;
+
+    fn check_out(
+        &mut self,
+        operation_id: OperationId,
+        old_tree_id: Option<&TreeId>,
+        new_tree: &Tree,
+    ) -> Result<CheckoutStats, CheckoutError> {
+        let mut locked_wc = self.start_mutation();
+        // Check if the current working-copy commit has changed on disk compared to what
+        // the caller expected. It's safe to check out another commit
+        // regardless, but it's probably not what  the caller wanted, so we let
+        // them know.
+        if let Some(old_tree_id) = old_tree_id {
+            if old_tree_id != locked_wc.old_tree_id() {
+                locked_wc.discard();
+                return Err(CheckoutError::ConcurrentCheckout);
+            }
+        }
+        let stats = locked_wc.check_out(new_tree)?;
+        locked_wc.finish(operation_id);
+        Ok(stats)
+    }
+}
+
+/// A working copy that's locked on disk. The lock is held until you call
+/// `finish()` or `discard()`.
+pub trait LockedWorkingCopy {
+    /// The operation at the time the lock was taken
+    fn old_operation_id(&self) -> &OperationId;
+
+    /// The tree at the time the lock was taken
+    fn old_tree_id(&self) -> &TreeId;
+
+    // The base_ignores are passed in here rather than being set on the TreeState
+    // because the TreeState may be long-lived if the library is used in a
+    // long-lived process.
+    fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<TreeId, SnapshotError> {
+        let (tree_id, _stats) = self.snapshot_with_stats(base_ignores, &FullScan)?;
+        Ok(tree_id)
+    }
+
+    /// Like `snapshot()`, but only scans the paths reported by the
+    /// `file_state_provider`, and also returns details about the changes found
+    /// in the working copy.
+    fn snapshot_with_stats(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        file_state_provider: &dyn FileStateProvider,
+    ) -> Result<(TreeId, SnapshotStats), SnapshotError>;
+
+    fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError>;
+
+    fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError>;
+
+    fn sparse_patterns(&self) -> &[RepoPath];
+
+    fn set_sparse_patterns(
+        &mut self,
+        new_sparse_patterns: Vec<RepoPath>,
+    ) -> Result<CheckoutStats, CheckoutError>;
+
+    fn finish(self: Box<Self>, operation_id: OperationId);
+
+    fn discard(self: Box<Self>);
+}
+
+/// Working copy whose files are checked out in the workspace root, and whose
+/// state is stored in files in the state directory.
+pub struct LocalWorkingCopy {
     store: Arc<Store>,
     working_copy_path: PathBuf,
     state_path: PathBuf,
@@ -1013,7 +1295,67 @@ pub struct WorkingCopy {
     tree_state: OnceCell<TreeState>,
 }
 
-impl WorkingCopy {
+impl WorkingCopy for LocalWorkingCopy {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        LocalWorkingCopy::name()
+    }
+
+    fn working_copy_path(&self) -> &Path {
+        &self.working_copy_path
+    }
+
+    fn state_path(&self) -> &Path {
+        &self.state_path
+    }
+
+    fn workspace_id(&self) -> &WorkspaceId {
+        &self.checkout_state().workspace_id
+    }
+
+    fn operation_id(&self) -> &OperationId {
+        &self.checkout_state().operation_id
+    }
+
+    fn current_tree_id(&self) -> &TreeId {
+        self.tree_state().current_tree_id()
+    }
+
+    fn sparse_patterns(&self) -> &[RepoPath] {
+        self.tree_state().sparse_patterns()
+    }
+
+    fn start_mutation(&mut self) -> Box<dyn LockedWorkingCopy + '_> {
+        let lock_path = self.state_path.join("working_copy.lock");
+        let lock = FileLock::lock(lock_path);
+
+        // Re-read from disk after taking the lock
+        self.checkout_state.take();
+        // TODO: It's expensive to reload the whole tree. We should first check if it
+        // has changed.
+        self.tree_state.take();
+        let old_operation_id = self.operation_id().clone();
+        let old_tree_id = self.current_tree_id().clone();
+
+        Box::new(LockedLocalWorkingCopy {
+            wc: self,
+            lock,
+            old_operation_id,
+            old_tree_id,
+            tree_state_dirty: false,
+            closed: false,
+        })
+    }
+}
+
+impl LocalWorkingCopy {
+    pub fn name() -> &'static str {
+        "local"
+    }
+
     /// Initializes a new working copy at `working_copy_path`. The working
     /// copy's state will be stored in the `state_path` directory. The working
     /// copy will have the empty tree checked out.
@@ -1023,7 +1365,7 @@ impl WorkingCopy {
         state_path: PathBuf,
         operation_id: OperationId,
         workspace_id: WorkspaceId,
-    ) -> WorkingCopy {
+    ) -> LocalWorkingCopy {
         let proto = crate::protos::working_copy::Checkout {
             operation_id: operation_id.to_bytes(),
             workspace_id: workspace_id.as_str().to_string(),
@@ -1037,7 +1379,7 @@ impl WorkingCopy {
         file.write_all(&proto.encode_to_vec()).unwrap();
         let tree_state =
             TreeState::init(store.clone(), working_copy_path.clone(), state_path.clone());
-        WorkingCopy {
+        LocalWorkingCopy {
             store,
             working_copy_path,
             state_path,
@@ -1046,8 +1388,12 @@ impl WorkingCopy {
         }
     }
 
-    pub fn load(store: Arc<Store>, working_copy_path: PathBuf, state_path: PathBuf) -> WorkingCopy {
-        WorkingCopy {
+    pub fn load(
+        store: Arc<Store>,
+        working_copy_path: PathBuf,
+        state_path: PathBuf,
+    ) -> LocalWorkingCopy {
+        LocalWorkingCopy {
             store,
             working_copy_path,
             state_path,
@@ -1056,14 +1402,6 @@ impl WorkingCopy {
         }
     }
 
-    pub fn working_copy_path(&self) -> &Path {
-        &self.working_copy_path
-    }
-
-    pub fn state_path(&self) -> &Path {
-        &self.state_path
-    }
-
     fn write_proto(&self, proto: crate::protos::working_copy::Checkout) {
         let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
         temp_file
@@ -1097,14 +1435,6 @@ impl WorkingCopy {
         self.checkout_state.get_mut().unwrap()
     }
 
-    pub fn operation_id(&self) -> &OperationId {
-        &self.checkout_state().operation_id
-    }
-
-    pub fn workspace_id(&self) -> &WorkspaceId {
-        &self.checkout_state().workspace_id
-    }
-
     fn tree_state(&self) -> &TreeState {
         self.tree_state.get_or_init(|| {
             TreeState::load(
@@ -1120,18 +1450,10 @@ impl WorkingCopy {
         self.tree_state.get_mut().unwrap()
     }
 
-    pub fn current_tree_id(&self) -> &TreeId {
-        self.tree_state().current_tree_id()
-    }
-
     pub fn file_states(&self) -> &BTreeMap<RepoPath, FileState> {
         self.tree_state().file_states()
     }
 
-    pub fn sparse_patterns(&self) -> &[RepoPath] {
-        self.tree_state().sparse_patterns()
-    }
-
     fn save(&mut self) {
         self.write_proto(crate::protos::working_copy::Checkout {
             operation_id: self.operation_id().to_bytes(),
@@ -1139,56 +1461,11 @@ impl WorkingCopy {
             ..Default::default()
         });
     }
-
-    pub fn start_mutation(&mut self) -> LockedWorkingCopy {
-        let lock_path = self.state_path.join("working_copy.lock");
-        let lock = FileLock::lock(lock_path);
-
-        // Re-read from disk after taking the lock
-        self.checkout_state.take();
-        // TODO: It's expensive to reload the whole tree. We should first check if it
-        // has changed.
-        self.tree_state.take();
-        let old_operation_id = self.operation_id().clone();
-        let old_tree_id = self.current_tree_id().clone();
-
-        LockedWorkingCopy {
-            wc: self,
-            lock,
-            old_operation_id,
-            old_tree_id,
-            tree_state_dirty: false,
-            closed: false,
-        }
-    }
-
-    pub fn check_out(
-        &mut self,
-        operation_id: OperationId,
-        old_tree_id: Option<&TreeId>,
-        new_tree: &Tree,
-    ) -> Result<CheckoutStats, CheckoutError> {
-        let mut locked_wc = self.start_mutation();
-        // Check if the current working-copy commit has changed on disk compared to what
-        // the caller expected. It's safe to check out another commit
-        // regardless, but it's probably not what  the caller wanted, so we let
-        // them know.
-        if let Some(old_tree_id) = old_tree_id {
-            if *old_tree_id != locked_wc.old_tree_id {
-                locked_wc.discard();
-                return Err(CheckoutError::ConcurrentCheckout);
-            }
-        }
-        let stats = locked_wc.check_out(new_tree)?;
-        locked_wc.finish(operation_id);
-        Ok(stats)
-    }
 }
 
-/// A working copy that's locked on disk. The lock is held until you call
-/// `finish()` or `discard()`.
-pub struct LockedWorkingCopy<'a> {
-    wc: &'a mut WorkingCopy,
+/// A `LocalWorkingCopy` that's locked on disk.
+pub struct LockedLocalWorkingCopy<'a> {
+    wc: &'a mut LocalWorkingCopy,
     #[allow(dead_code)]
     lock: FileLock,
     old_operation_id: OperationId,
@@ -1197,27 +1474,27 @@ pub struct LockedWorkingCopy<'a> {
     closed: bool,
 }
 
-impl LockedWorkingCopy<'_> {
-    /// The operation at the time the lock was taken
-    pub fn old_operation_id(&self) -> &OperationId {
+impl LockedWorkingCopy for LockedLocalWorkingCopy<'_> {
+    fn old_operation_id(&self) -> &OperationId {
         &self.old_operation_id
     }
 
-    /// The tree at the time the lock was taken
-    pub fn old_tree_id(&self) -> &TreeId {
+    fn old_tree_id(&self) -> &TreeId {
         &self.old_tree_id
     }
 
-    // The base_ignores are passed in here rather than being set on the TreeState
-    // because the TreeState may be long-lived if the library is used in a
-    // long-lived process.
-    pub fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<TreeId, SnapshotError> {
+    fn snapshot_with_stats(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        file_state_provider: &dyn FileStateProvider,
+    ) -> Result<(TreeId, SnapshotStats), SnapshotError> {
         let tree_state = self.wc.tree_state_mut();
-        self.tree_state_dirty |= tree_state.snapshot(base_ignores)?;
-        Ok(tree_state.current_tree_id().clone())
+        let (changed, stats) = tree_state.snapshot(base_ignores, file_state_provider)?;
+        self.tree_state_dirty |= changed;
+        Ok((tree_state.current_tree_id().clone(), stats))
     }
 
-    pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
+    fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
         // TODO: Write a "pending_checkout" file with the new TreeId so we can
         // continue an interrupted update if we find such a file.
         let stats = self.wc.tree_state_mut().check_out(new_tree)?;
@@ -1225,17 +1502,17 @@ impl LockedWorkingCopy<'_> {
         Ok(stats)
     }
 
-    pub fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError> {
+    fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError> {
         self.wc.tree_state_mut().reset(new_tree)?;
         self.tree_state_dirty = true;
         Ok(())
     }
 
-    pub fn sparse_patterns(&self) -> &[RepoPath] {
+    fn sparse_patterns(&self) -> &[RepoPath] {
         self.wc.sparse_patterns()
     }
 
-    pub fn set_sparse_patterns(
+    fn set_sparse_patterns(
         &mut self,
         new_sparse_patterns: Vec<RepoPath>,
     ) -> Result<CheckoutStats, CheckoutError> {
@@ -1249,7 +1526,7 @@ impl LockedWorkingCopy<'_> {
         Ok(stats)
     }
 
-    pub fn finish(mut self, operation_id: OperationId) {
+    fn finish(mut self: Box<Self>, operation_id: OperationId) {
         assert!(self.tree_state_dirty || &self.old_tree_id == self.wc.current_tree_id());
         if self.tree_state_dirty {
             self.wc.tree_state_mut().save();
@@ -1263,7 +1540,7 @@ impl LockedWorkingCopy<'_> {
         self.closed = true;
     }
 
-    pub fn discard(mut self) {
+    fn discard(mut self: Box<Self>) {
         // Undo the changes in memory
         self.wc.tree_state.take();
         self.tree_state_dirty = false;
@@ -1271,7 +1548,7 @@ impl LockedWorkingCopy<'_> {
     }
 }
 
-impl Drop for LockedWorkingCopy<'_> {
+impl Drop for LockedLocalWorkingCopy<'_> {
     fn drop(&mut self) {
         if !self.closed && !std::thread::panicking() {
             eprintln!("BUG: Working copy lock was dropped without being closed.");
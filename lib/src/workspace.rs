@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -24,13 +25,14 @@ use crate::git_backend::GitBackend;
 use crate::index::IndexStore;
 use crate::local_backend::LocalBackend;
 use crate::op_heads_store::OpHeadsStore;
-use crate::op_store::{OpStore, WorkspaceId};
+use crate::op_store::{OpStore, OperationId, WorkspaceId};
 use crate::repo::{
     CheckOutCommitError, IoResultExt, PathError, ReadonlyRepo, Repo, RepoLoader, StoreFactories,
     StoreLoadError,
 };
 use crate::settings::UserSettings;
-use crate::working_copy::WorkingCopy;
+use crate::store::Store;
+use crate::working_copy::{LocalWorkingCopy, WorkingCopy};
 
 #[derive(Error, Debug)]
 pub enum WorkspaceInitError {
@@ -52,12 +54,22 @@ pub enum WorkspaceLoadError {
     NoWorkspaceHere(PathBuf),
     #[error("Cannot read the repo: {0}")]
     StoreLoadError(#[from] StoreLoadError),
+    #[error("Cannot read the working copy: {0}")]
+    WorkingCopyLoadError(#[from] WorkingCopyLoadError),
     #[error("Repo path could not be interpreted as Unicode text")]
     NonUnicodePath,
     #[error(transparent)]
     Path(#[from] PathError),
 }
 
+#[derive(Error, Debug)]
+pub enum WorkingCopyLoadError {
+    #[error("Unsupported working copy type '{0}'")]
+    UnsupportedType(String),
+    #[error(transparent)]
+    Path(#[from] PathError),
+}
+
 /// Represents a workspace, i.e. what's typically the .jj/ directory and its
 /// parent.
 pub struct Workspace {
@@ -65,7 +77,7 @@ pub struct Workspace {
     // working copy files live.
     workspace_root: PathBuf,
     repo_loader: RepoLoader,
-    working_copy: WorkingCopy,
+    working_copy: Box<dyn WorkingCopy>,
 }
 
 fn create_jj_dir(workspace_root: &Path) -> Result<PathBuf, WorkspaceInitError> {
@@ -85,9 +97,17 @@ fn init_working_copy(
     workspace_root: &Path,
     jj_dir: &Path,
     workspace_id: WorkspaceId,
-) -> Result<(WorkingCopy, Arc<ReadonlyRepo>), WorkspaceInitError> {
+    working_copy_initializer: impl FnOnce(
+        Arc<Store>,
+        PathBuf,
+        PathBuf,
+        OperationId,
+        WorkspaceId,
+    ) -> Box<dyn WorkingCopy>,
+) -> Result<(Box<dyn WorkingCopy>, Arc<ReadonlyRepo>), WorkspaceInitError> {
     let working_copy_state_path = jj_dir.join("working_copy");
     std::fs::create_dir(&working_copy_state_path).context(&working_copy_state_path)?;
+    let working_copy_type_path = working_copy_state_path.join("type");
 
     let mut tx = repo.start_transaction(
         user_settings,
@@ -100,20 +120,21 @@ fn init_working_copy(
     )?;
     let repo = tx.commit();
 
-    let working_copy = WorkingCopy::init(
+    let working_copy = working_copy_initializer(
         repo.store().clone(),
         workspace_root.to_path_buf(),
         working_copy_state_path,
         repo.op_id().clone(),
         workspace_id,
     );
+    fs::write(&working_copy_type_path, working_copy.name()).context(&working_copy_type_path)?;
     Ok((working_copy, repo))
 }
 
 impl Workspace {
     fn new(
         workspace_root: &Path,
-        working_copy: WorkingCopy,
+        working_copy: Box<dyn WorkingCopy>,
         repo_loader: RepoLoader,
     ) -> Result<Workspace, PathError> {
         let workspace_root = workspace_root.canonicalize().context(workspace_root)?;
@@ -156,6 +177,22 @@ impl Workspace {
         })
     }
 
+    /// Creates a working copy of the local type, which `WorkingCopyFactories`
+    /// can load by default.
+    pub fn default_working_copy_initializer(
+    ) -> impl FnOnce(Arc<Store>, PathBuf, PathBuf, OperationId, WorkspaceId) -> Box<dyn WorkingCopy>
+    {
+        |store, working_copy_path, state_path, operation_id, workspace_id| {
+            Box::new(LocalWorkingCopy::init(
+                store,
+                working_copy_path,
+                state_path,
+                operation_id,
+                workspace_id,
+            ))
+        }
+    }
+
     pub fn init_with_factories(
         user_settings: &UserSettings,
         workspace_root: &Path,
@@ -163,6 +200,13 @@ impl Workspace {
         op_store_factory: impl FnOnce(&Path) -> Box<dyn OpStore>,
         op_heads_store_factory: impl FnOnce(&Path) -> Box<dyn OpHeadsStore>,
         index_store_factory: impl FnOnce(&Path) -> Box<dyn IndexStore>,
+        working_copy_initializer: impl FnOnce(
+            Arc<Store>,
+            PathBuf,
+            PathBuf,
+            OperationId,
+            WorkspaceId,
+        ) -> Box<dyn WorkingCopy>,
     ) -> Result<(Self, Arc<ReadonlyRepo>), WorkspaceInitError> {
         let jj_dir = create_jj_dir(workspace_root)?;
         let repo_dir = jj_dir.join("repo");
@@ -181,6 +225,7 @@ impl Workspace {
             workspace_root,
             &jj_dir,
             WorkspaceId::default(),
+            working_copy_initializer,
         )?;
         let repo_loader = repo.loader();
         let workspace = Workspace::new(workspace_root, working_copy, repo_loader)?;
@@ -199,6 +244,7 @@ impl Workspace {
             ReadonlyRepo::default_op_store_factory(),
             ReadonlyRepo::default_op_heads_store_factory(),
             ReadonlyRepo::default_index_store_factory(),
+            Self::default_working_copy_initializer(),
         )
     }
 
@@ -207,6 +253,13 @@ impl Workspace {
         workspace_root: &Path,
         repo: &Arc<ReadonlyRepo>,
         workspace_id: WorkspaceId,
+        working_copy_initializer: impl FnOnce(
+            Arc<Store>,
+            PathBuf,
+            PathBuf,
+            OperationId,
+            WorkspaceId,
+        ) -> Box<dyn WorkingCopy>,
     ) -> Result<(Self, Arc<ReadonlyRepo>), WorkspaceInitError> {
         let jj_dir = create_jj_dir(workspace_root)?;
 
@@ -222,8 +275,14 @@ impl Workspace {
             )
             .context(&repo_file_path)?;
 
-        let (working_copy, repo) =
-            init_working_copy(user_settings, repo, workspace_root, &jj_dir, workspace_id)?;
+        let (working_copy, repo) = init_working_copy(
+            user_settings,
+            repo,
+            workspace_root,
+            &jj_dir,
+            workspace_id,
+            working_copy_initializer,
+        )?;
         let workspace = Workspace::new(workspace_root, working_copy, repo.loader())?;
         Ok((workspace, repo))
     }
@@ -232,9 +291,10 @@ impl Workspace {
         user_settings: &UserSettings,
         workspace_path: &Path,
         store_factories: &StoreFactories,
+        working_copy_factories: &WorkingCopyFactories,
     ) -> Result<Self, WorkspaceLoadError> {
         let loader = WorkspaceLoader::init(workspace_path)?;
-        let workspace = loader.load(user_settings, store_factories)?;
+        let workspace = loader.load(user_settings, store_factories, working_copy_factories)?;
         Ok(workspace)
     }
 
@@ -254,12 +314,12 @@ impl Workspace {
         &self.repo_loader
     }
 
-    pub fn working_copy(&self) -> &WorkingCopy {
-        &self.working_copy
+    pub fn working_copy(&self) -> &dyn WorkingCopy {
+        self.working_copy.as_ref()
     }
 
-    pub fn working_copy_mut(&mut self) -> &mut WorkingCopy {
-        &mut self.working_copy
+    pub fn working_copy_mut(&mut self) -> &mut dyn WorkingCopy {
+        self.working_copy.as_mut()
     }
 }
 
@@ -315,14 +375,80 @@ impl WorkspaceLoader {
         &self,
         user_settings: &UserSettings,
         store_factories: &StoreFactories,
+        working_copy_factories: &WorkingCopyFactories,
     ) -> Result<Workspace, WorkspaceLoadError> {
         let repo_loader = RepoLoader::init(user_settings, &self.repo_dir, store_factories)?;
-        let working_copy = WorkingCopy::load(
+        let working_copy = working_copy_factories.load_working_copy(
             repo_loader.store().clone(),
             self.workspace_root.clone(),
             self.working_copy_state_path.clone(),
-        );
+        )?;
         let workspace = Workspace::new(&self.workspace_root, working_copy, repo_loader)?;
         Ok(workspace)
     }
 }
+
+type WorkingCopyFactory = Box<dyn Fn(Arc<Store>, PathBuf, PathBuf) -> Box<dyn WorkingCopy>>;
+
+/// Factories for creating the working copy when a workspace is loaded, keyed
+/// by the working-copy type recorded in `.jj/working_copy/type`. Each factory
+/// is called with the store, the workspace root, and the working copy's state
+/// directory.
+pub struct WorkingCopyFactories {
+    factories: HashMap<String, WorkingCopyFactory>,
+}
+
+impl Default for WorkingCopyFactories {
+    fn default() -> Self {
+        let mut factories = WorkingCopyFactories::empty();
+        factories.add_working_copy(
+            LocalWorkingCopy::name(),
+            Box::new(|store, workspace_root, state_path| {
+                Box::new(LocalWorkingCopy::load(store, workspace_root, state_path))
+            }),
+        );
+        factories
+    }
+}
+
+impl WorkingCopyFactories {
+    pub fn empty() -> Self {
+        WorkingCopyFactories {
+            factories: HashMap::new(),
+        }
+    }
+
+    pub fn add_working_copy(&mut self, name: &str, factory: WorkingCopyFactory) {
+        self.factories.insert(name.to_string(), factory);
+    }
+
+    pub fn load_working_copy(
+        &self,
+        store: Arc<Store>,
+        workspace_root: PathBuf,
+        state_path: PathBuf,
+    ) -> Result<Box<dyn WorkingCopy>, WorkingCopyLoadError> {
+        let type_path = state_path.join("type");
+        let working_copy_type = match fs::read_to_string(&type_path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                // For compatibility with existing workspaces. TODO: Delete in 0.9+
+                let default_type = LocalWorkingCopy::name().to_owned();
+                fs::write(&type_path, &default_type).context(&type_path)?;
+                default_type
+            }
+            Err(err) => {
+                return Err(PathError {
+                    path: type_path,
+                    error: err,
+                }
+                .into());
+            }
+        };
+        let factory = self
+            .factories
+            .get(&working_copy_type)
+            .ok_or(WorkingCopyLoadError::UnsupportedType(working_copy_type))?;
+        Ok(factory(store, workspace_root, state_path))
+    }
+}
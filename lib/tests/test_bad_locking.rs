@@ -15,7 +15,7 @@
 use std::path::Path;
 
 use jujutsu_lib::repo::{Repo, StoreFactories};
-use jujutsu_lib::workspace::Workspace;
+use jujutsu_lib::workspace::{WorkingCopyFactories, Workspace};
 use test_case::test_case;
 use testutils::{create_random_commit, load_repo_at_head, TestWorkspace};
 
@@ -108,8 +108,13 @@ fn test_bad_locking_children(use_git: bool) {
     // Simulate a write of a commit that happens on one machine
     let machine1_root = testutils::new_temp_dir();
     copy_directory(workspace_root, machine1_root.path());
-    let machine1_workspace =
-        Workspace::load(&settings, machine1_root.path(), &StoreFactories::default()).unwrap();
+    let machine1_workspace = Workspace::load(
+        &settings,
+        machine1_root.path(),
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
     let machine1_repo = machine1_workspace
         .repo_loader()
         .load_at_head(&settings)
@@ -124,8 +129,13 @@ fn test_bad_locking_children(use_git: bool) {
     // Simulate a write of a commit that happens on another machine
     let machine2_root = testutils::new_temp_dir();
     copy_directory(workspace_root, machine2_root.path());
-    let machine2_workspace =
-        Workspace::load(&settings, machine2_root.path(), &StoreFactories::default()).unwrap();
+    let machine2_workspace = Workspace::load(
+        &settings,
+        machine2_root.path(),
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
     let machine2_repo = machine2_workspace
         .repo_loader()
         .load_at_head(&settings)
@@ -146,8 +156,13 @@ fn test_bad_locking_children(use_git: bool) {
         machine2_root.path(),
         merged_path.path(),
     );
-    let merged_workspace =
-        Workspace::load(&settings, merged_path.path(), &StoreFactories::default()).unwrap();
+    let merged_workspace = Workspace::load(
+        &settings,
+        merged_path.path(),
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
     let merged_repo = merged_workspace
         .repo_loader()
         .load_at_head(&settings)
@@ -0,0 +1,117 @@
+// Copyright 2023 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::backend::CommitId;
+use jujutsu_lib::blame::blame;
+use jujutsu_lib::commit::Commit;
+use jujutsu_lib::repo::{MutableRepo, Repo};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::settings::UserSettings;
+use test_case::test_case;
+use testutils::TestRepo;
+
+fn write_commit_with_file(
+    settings: &UserSettings,
+    mut_repo: &mut MutableRepo,
+    parents: &[&Commit],
+    path: &RepoPath,
+    content: &str,
+) -> Commit {
+    let tree = testutils::create_tree(mut_repo.base_repo(), &[(path, content)]);
+    let parent_ids = parents.iter().map(|commit| commit.id().clone()).collect();
+    mut_repo
+        .new_commit(settings, parent_ids, tree.id().clone())
+        .write()
+        .unwrap()
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_blame_linear(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let root_commit = repo.store().root_commit();
+    let path = RepoPath::from_internal_string("file");
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = write_commit_with_file(&settings, mut_repo, &[&root_commit], &path, "a\nb\nc\n");
+    let commit2 = write_commit_with_file(&settings, mut_repo, &[&commit1], &path, "a\nB\nc\n");
+    let commit3 = write_commit_with_file(&settings, mut_repo, &[&commit2], &path, "a\nB\nc\nd\n");
+    let commit4 =
+        write_commit_with_file(&settings, mut_repo, &[&commit3], &path, "x\na\nB\nc\nd\n");
+
+    let id = |commit: &Commit| commit.id().clone();
+    assert_eq!(
+        blame(mut_repo, &commit1, &path).unwrap(),
+        vec![(id(&commit1), 0..3)]
+    );
+    assert_eq!(
+        blame(mut_repo, &commit4, &path).unwrap(),
+        vec![
+            (id(&commit4), 0..1),
+            (id(&commit1), 1..2),
+            (id(&commit2), 2..3),
+            (id(&commit1), 3..4),
+            (id(&commit3), 4..5),
+        ]
+    );
+
+    // A path that doesn't exist has nothing to attribute
+    let other_path = RepoPath::from_internal_string("other");
+    assert_eq!(
+        blame(mut_repo, &commit4, &other_path).unwrap(),
+        Vec::<(CommitId, _)>::new()
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_blame_merge(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let root_commit = repo.store().root_commit();
+    let path = RepoPath::from_internal_string("file");
+
+    // 4 (merge)
+    // |\
+    // 2 3
+    // |/
+    // 1
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = write_commit_with_file(&settings, mut_repo, &[&root_commit], &path, "a\nb\nc\n");
+    let commit2 = write_commit_with_file(&settings, mut_repo, &[&commit1], &path, "A\nb\nc\n");
+    let commit3 = write_commit_with_file(&settings, mut_repo, &[&commit1], &path, "a\nb\nC\n");
+    let commit4 = write_commit_with_file(
+        &settings,
+        mut_repo,
+        &[&commit2, &commit3],
+        &path,
+        "A\nb\nC\nd\n",
+    );
+
+    let id = |commit: &Commit| commit.id().clone();
+    assert_eq!(
+        blame(mut_repo, &commit4, &path).unwrap(),
+        vec![
+            (id(&commit2), 0..1),
+            (id(&commit1), 1..2),
+            (id(&commit3), 2..3),
+            (id(&commit4), 3..4),
+        ]
+    );
+}
@@ -12,8 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::backend::{MillisSinceEpoch, Signature, Timestamp};
+use jujutsu_lib::commit::Commit;
 use jujutsu_lib::matchers::EverythingMatcher;
-use jujutsu_lib::repo::Repo;
+use jujutsu_lib::repo::{Repo, RepoLoader, StoreFactories};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::tree::DiffSummary;
@@ -163,6 +165,69 @@ fn test_rewrite(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rewrite_records_predecessors(use_git: bool) {
+    // The predecessor chain survives being written to and read back from the
+    // backend
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.initial_commit();
+    let repo = tx.commit();
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let rewritten1 = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("rewritten once")
+        .write()
+        .unwrap();
+    let rewritten2 = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &rewritten1)
+        .set_description("rewritten twice")
+        .write()
+        .unwrap();
+    // e.g. squashing commit2 into the rewritten commit
+    let squashed = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &rewritten2)
+        .set_predecessors(vec![rewritten2.id().clone(), commit2.id().clone()])
+        .write()
+        .unwrap();
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    let repo = tx.commit();
+
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    let store = loader.store();
+    let get_predecessor_ids = |commit: &Commit| {
+        store
+            .get_commit(commit.id())
+            .unwrap()
+            .predecessor_ids()
+            .to_vec()
+    };
+    assert_eq!(get_predecessor_ids(&commit1), vec![]);
+    assert_eq!(get_predecessor_ids(&rewritten1), vec![commit1.id().clone()]);
+    assert_eq!(
+        get_predecessor_ids(&rewritten2),
+        vec![rewritten1.id().clone()]
+    );
+    assert_eq!(
+        get_predecessor_ids(&squashed),
+        vec![rewritten2.id().clone(), commit2.id().clone()]
+    );
+    assert_eq!(
+        store.get_commit(squashed.id()).unwrap().predecessors(),
+        vec![rewritten2, commit2]
+    );
+}
+
 // An author field with the placeholder name/email should get filled in on
 // rewrite
 #[test_case(false ; "local backend")]
@@ -214,6 +279,151 @@ fn test_rewrite_update_missing_user(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rewrite_author_and_committer(use_git: bool) {
+    // Rewriting keeps the original author (including its timestamp) and updates
+    // the committer, unless they're explicitly overridden
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let signature = |name: &str, timestamp_millis: i64| Signature {
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        timestamp: Timestamp {
+            timestamp: MillisSinceEpoch(timestamp_millis),
+            tz_offset: 60,
+        },
+    };
+    let mut tx = repo.start_transaction(&settings, "test");
+    let initial_commit = tx
+        .mut_repo()
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            repo.store().empty_tree_id().clone(),
+        )
+        .set_author(signature("Original", 1_000_000))
+        .set_committer(signature("Original", 2_000_000))
+        .write()
+        .unwrap();
+    assert_eq!(*initial_commit.author(), signature("Original", 1_000_000));
+    assert_eq!(
+        *initial_commit.committer(),
+        signature("Original", 2_000_000)
+    );
+
+    let rewritten_commit = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &initial_commit)
+        .set_description("rewritten")
+        .write()
+        .unwrap();
+    assert_eq!(*rewritten_commit.author(), signature("Original", 1_000_000));
+    assert_eq!(rewritten_commit.committer().name, settings.user_name());
+    assert_eq!(rewritten_commit.committer().email, settings.user_email());
+    assert_ne!(
+        rewritten_commit.committer().timestamp,
+        initial_commit.committer().timestamp
+    );
+
+    let reauthored_commit = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &rewritten_commit)
+        .set_author(signature("New", 3_000_000))
+        .set_committer(signature("Committer", 4_000_000))
+        .write()
+        .unwrap();
+    assert_eq!(*reauthored_commit.author(), signature("New", 3_000_000));
+    assert_eq!(
+        *reauthored_commit.committer(),
+        signature("Committer", 4_000_000)
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_set_parents(use_git: bool) {
+    // Reparenting through the builder changes only the parents; the tree is kept
+    // unless it's set too
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.initial_commit();
+
+    let rebased_commit = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &commit2)
+        .set_parents(vec![commit3.id().clone()])
+        .write()
+        .unwrap();
+    assert_eq!(rebased_commit.parent_ids(), &[commit3.id().clone()]);
+    assert_eq!(rebased_commit.tree_id(), commit2.tree_id());
+    assert_eq!(rebased_commit.change_id(), commit2.change_id());
+
+    let merge_commit = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &rebased_commit)
+        .set_parents(vec![commit1.id().clone(), commit3.id().clone()])
+        .set_tree(commit3.tree_id().clone())
+        .write()
+        .unwrap();
+    assert_eq!(
+        merge_commit.parent_ids(),
+        &[commit1.id().clone(), commit3.id().clone()]
+    );
+    assert_eq!(merge_commit.tree_id(), commit3.tree_id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+#[should_panic(expected = "duplicate parents")]
+fn test_set_parents_duplicate(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = CommitGraphBuilder::new(&settings, tx.mut_repo()).initial_commit();
+    let _ = tx
+        .mut_repo()
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            repo.store().empty_tree_id().clone(),
+        )
+        .set_parents(vec![commit1.id().clone(), commit1.id().clone()]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+#[should_panic(expected = "the root commit cannot be merged with other parents")]
+fn test_set_parents_root_merge(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = CommitGraphBuilder::new(&settings, tx.mut_repo()).initial_commit();
+    let _ = tx
+        .mut_repo()
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            repo.store().empty_tree_id().clone(),
+        )
+        .set_parents(vec![
+            repo.store().root_commit_id().clone(),
+            commit1.id().clone(),
+        ]);
+}
+
 #[test_case(false ; "local backend")]
 // #[test_case(true ; "git backend")]
 fn test_commit_builder_descendants(use_git: bool) {
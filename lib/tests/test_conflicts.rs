@@ -13,10 +13,14 @@
 // limitations under the License.
 
 use jujutsu_lib::backend::{Conflict, ConflictTerm, FileId, TreeValue};
-use jujutsu_lib::conflicts::{materialize_conflict, parse_conflict, update_conflict_from_content};
+use jujutsu_lib::conflicts::{
+    materialize_conflict, parse_conflict, update_conflict_from_content, MaterializeStyle,
+};
+use jujutsu_lib::files::{ConflictHunk, MergeHunk};
 use jujutsu_lib::repo::Repo;
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::store::Store;
+use jujutsu_lib::tree::merge_trees;
 use testutils::TestRepo;
 
 fn file_conflict_term(file_id: &FileId) -> ConflictTerm {
@@ -172,15 +176,15 @@ line 3
         @r###"
     line 1
     <<<<<<<
-    +++++++
+    +++++++ Contents of side #1
     line 2 a.1
     line 2 a.2
     line 2 a.3
-    %%%%%%%
+    %%%%%%% Changes from base #1 to side #2
     -line 2 base
     +line 2 b.1
     +line 2 b.2
-    %%%%%%%
+    %%%%%%% Changes from base #2 to side #3
     -line 2 base
     +line 2 c.1
     >>>>>>>
@@ -200,14 +204,14 @@ line 3
         @r###"
     line 1
     <<<<<<<
-    %%%%%%%
+    %%%%%%% Changes from base #1 to side #1
     -line 2 base
     +line 2 c.1
-    %%%%%%%
+    %%%%%%% Changes from base #2 to side #2
     -line 2 base
     +line 2 b.1
     +line 2 b.2
-    +++++++
+    +++++++ Contents of side #3
     line 2 a.1
     line 2 a.2
     line 2 a.3
@@ -228,14 +232,14 @@ line 3
         @r###"
     line 1
     <<<<<<<
-    %%%%%%%
+    %%%%%%% Changes from base #1 to side #1
     -line 2 base
     +line 2 c.1
-    +++++++
+    +++++++ Contents of side #2
     line 2 a.1
     line 2 a.2
     line 2 a.3
-    %%%%%%%
+    %%%%%%% Changes from base #2 to side #3
     -line 2 base
     +line 2 b.1
     +line 2 b.2
@@ -287,7 +291,7 @@ line 5 right
         adds: vec![file_conflict_term(&left_id), file_conflict_term(&right_id)],
     };
     let mut result: Vec<u8> = vec![];
-    materialize_conflict(store, &path, &conflict, &mut result).unwrap();
+    materialize_conflict(store, &path, &conflict, MaterializeStyle::Diff, &mut result).unwrap();
     insta::assert_snapshot!(
         String::from_utf8(result.clone()).unwrap(),
         @r###"
@@ -345,6 +349,239 @@ line 5 right
     "###);
 }
 
+#[test]
+fn test_materialize_parse_roundtrip_diff3() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base_id = testutils::write_file(
+        store,
+        &path,
+        "line 1
+line 2
+line 3
+line 4
+line 5
+",
+    );
+    let left_id = testutils::write_file(
+        store,
+        &path,
+        "line 1 left
+line 2 left
+line 3
+line 4
+line 5 left
+",
+    );
+    let right_id = testutils::write_file(
+        store,
+        &path,
+        "line 1 right
+line 2
+line 3
+line 4 right
+line 5 right
+",
+    );
+
+    let conflict = Conflict {
+        removes: vec![file_conflict_term(&base_id)],
+        adds: vec![file_conflict_term(&left_id), file_conflict_term(&right_id)],
+    };
+    let mut result: Vec<u8> = vec![];
+    materialize_conflict(
+        store,
+        &path,
+        &conflict,
+        MaterializeStyle::Diff3,
+        &mut result,
+    )
+    .unwrap();
+    insta::assert_snapshot!(
+        String::from_utf8(result.clone()).unwrap(),
+        @r###"
+    <<<<<<<
+    line 1 left
+    line 2 left
+    |||||||
+    line 1
+    line 2
+    =======
+    line 1 right
+    line 2
+    >>>>>>>
+    line 3
+    <<<<<<<
+    line 4
+    line 5 left
+    |||||||
+    line 4
+    line 5
+    =======
+    line 4 right
+    line 5 right
+    >>>>>>>
+    "###
+    );
+
+    // Parsing the diff3-style markers gives the same hunks as parsing the
+    // default style
+    let mut diff_result: Vec<u8> = vec![];
+    materialize_conflict(
+        store,
+        &path,
+        &conflict,
+        MaterializeStyle::Diff,
+        &mut diff_result,
+    )
+    .unwrap();
+    let hunks = parse_conflict(&result, conflict.removes.len(), conflict.adds.len());
+    assert!(hunks.is_some());
+    assert_eq!(
+        hunks,
+        parse_conflict(&diff_result, conflict.removes.len(), conflict.adds.len())
+    );
+}
+
+#[test]
+fn test_materialize_parse_roundtrip_multi_way() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base1 = "line 1\nline 2 base 1\nline 3\n";
+    let base2 = "line 1\nline 2 base 2\nline 3\n";
+    let side1 = "line 1\nline 2 side 1\nline 3\n";
+    let side2 = "line 1\nline 2 base 1\nline 2 side 2\nline 3\n";
+    let side3 = "line 1\nline 2 side 3.1\nline 2 side 3.2\nline 3\n";
+    let conflict = Conflict {
+        removes: vec![
+            file_conflict_term(&testutils::write_file(store, &path, base1)),
+            file_conflict_term(&testutils::write_file(store, &path, base2)),
+        ],
+        adds: vec![
+            file_conflict_term(&testutils::write_file(store, &path, side1)),
+            file_conflict_term(&testutils::write_file(store, &path, side2)),
+            file_conflict_term(&testutils::write_file(store, &path, side3)),
+        ],
+    };
+    let materialized = materialize_conflict_string(store, &path, &conflict);
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    line 1
+    <<<<<<<
+    +++++++ Contents of side #1
+    line 2 side 1
+    %%%%%%% Changes from base #1 to side #2
+     line 2 base 1
+    +line 2 side 2
+    %%%%%%% Changes from base #2 to side #3
+    -line 2 base 2
+    +line 2 side 3.1
+    +line 2 side 3.2
+    >>>>>>>
+    line 3
+    "###
+    );
+
+    // Parsing the materialized conflict gives back the original terms
+    let hunks = parse_conflict(materialized.as_bytes(), 2, 3).unwrap();
+    let mut removes = vec![String::new(); 2];
+    let mut adds = vec![String::new(); 3];
+    for hunk in hunks {
+        match hunk {
+            MergeHunk::Resolved(content) => {
+                let content = String::from_utf8(content).unwrap();
+                for term in removes.iter_mut().chain(adds.iter_mut()) {
+                    term.push_str(&content);
+                }
+            }
+            MergeHunk::Conflict(ConflictHunk {
+                removes: hunk_removes,
+                adds: hunk_adds,
+            }) => {
+                for (term, content) in removes.iter_mut().zip(hunk_removes) {
+                    term.push_str(&String::from_utf8(content).unwrap());
+                }
+                for (term, content) in adds.iter_mut().zip(hunk_adds) {
+                    term.push_str(&String::from_utf8(content).unwrap());
+                }
+            }
+        }
+    }
+    assert_eq!(removes, vec![base1, base2]);
+    assert_eq!(adds, vec![side1, side2, side3]);
+}
+
+#[test]
+fn test_materialize_parse_roundtrip_marker_like_lines() {
+    // Lines starting with `------` or `++++++` in the diff section look like
+    // conflict markers followed by a label
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base_id = testutils::write_file(
+        store,
+        &path,
+        "------ foo
+line 2
+",
+    );
+    let left_id = testutils::write_file(
+        store,
+        &path,
+        "++++++ bar
+line 2
+",
+    );
+    let right_id = testutils::write_file(
+        store, &path, "right
+",
+    );
+
+    let conflict = Conflict {
+        removes: vec![file_conflict_term(&base_id)],
+        adds: vec![file_conflict_term(&left_id), file_conflict_term(&right_id)],
+    };
+    let mut result: Vec<u8> = vec![];
+    materialize_conflict(store, &path, &conflict, MaterializeStyle::Diff, &mut result).unwrap();
+    insta::assert_snapshot!(
+        String::from_utf8(result.clone()).unwrap(),
+        @r###"
+    <<<<<<<
+    %%%%%%%
+    ------- foo
+    +++++++ bar
+     line 2
+    +++++++
+    right
+    >>>>>>>
+    "###
+    );
+
+    insta::assert_debug_snapshot!(
+        parse_conflict(&result, conflict.removes.len(), conflict.adds.len()),
+        @r###"
+    Some(
+        [
+            Conflict {
+                removes: [
+                    "------ foo\nline 2\n",
+                ],
+                adds: [
+                    "++++++ bar\nline 2\n",
+                    "right\n",
+                ],
+            },
+        ],
+    )
+    "###);
+}
+
 #[test]
 fn test_materialize_conflict_modify_delete() {
     let test_repo = TestRepo::init(false);
@@ -505,6 +742,62 @@ line 5
     )
 }
 
+#[test]
+fn test_parse_conflict_diff3() {
+    insta::assert_debug_snapshot!(
+        parse_conflict(
+            b"line 1
+<<<<<<<
+left
+|||||||
+base
+=======
+>>>>>>>
+line 5
+",
+            1,
+            2
+        ),
+        @r###"
+    Some(
+        [
+            Resolved(
+                "line 1\n",
+            ),
+            Conflict {
+                removes: [
+                    "base\n",
+                ],
+                adds: [
+                    "left\n",
+                    "",
+                ],
+            },
+            Resolved(
+                "line 5\n",
+            ),
+        ],
+    )
+    "###
+    );
+    // The base marker is missing
+    assert_eq!(
+        parse_conflict(
+            b"line 1
+<<<<<<<
+left
+=======
+right
+>>>>>>>
+line 5
+",
+            1,
+            2
+        ),
+        None
+    );
+}
+
 #[test]
 fn test_parse_conflict_multi_way() {
     insta::assert_debug_snapshot!(
@@ -646,7 +939,14 @@ fn test_update_conflict_from_content() {
     // If the content is unchanged compared to the materialized value, we get the
     // old conflict id back.
     let mut materialized = vec![];
-    materialize_conflict(store, &path, &conflict, &mut materialized).unwrap();
+    materialize_conflict(
+        store,
+        &path,
+        &conflict,
+        MaterializeStyle::Diff,
+        &mut materialized,
+    )
+    .unwrap();
     let result = update_conflict_from_content(store, &path, &conflict_id, &materialized).unwrap();
     assert_eq!(result, Some(conflict_id.clone()));
 
@@ -687,8 +987,142 @@ fn test_update_conflict_from_content() {
     )
 }
 
+#[test]
+fn test_try_resolve_trivially() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base = file_conflict_term(&testutils::write_file(store, &path, "base\n"));
+    let side1 = file_conflict_term(&testutils::write_file(store, &path, "side 1\n"));
+    let side2 = file_conflict_term(&testutils::write_file(store, &path, "side 2\n"));
+    let resolve = |removes: &[&ConflictTerm], adds: &[&ConflictTerm]| {
+        Conflict {
+            removes: removes.iter().copied().cloned().collect(),
+            adds: adds.iter().copied().cloned().collect(),
+        }
+        .try_resolve_trivially()
+    };
+
+    // One side unchanged
+    assert_eq!(
+        resolve(&[&base], &[&base, &side2]),
+        Some(Some(side2.value.clone()))
+    );
+    assert_eq!(
+        resolve(&[&base], &[&side1, &base]),
+        Some(Some(side1.value.clone()))
+    );
+    // Both sides made the same change
+    assert_eq!(
+        resolve(&[&base], &[&side1, &side1]),
+        Some(Some(side1.value.clone()))
+    );
+    // Both sides added identical content
+    assert_eq!(
+        resolve(&[], &[&side1, &side1]),
+        Some(Some(side1.value.clone()))
+    );
+    // One side removed the path and the other left it unchanged
+    assert_eq!(resolve(&[&base], &[&base]), Some(None));
+    // Both sides removed the path
+    assert_eq!(resolve(&[&base], &[]), Some(None));
+    // Both sides made different changes
+    assert_eq!(resolve(&[&base], &[&side1, &side2]), None);
+    // Both sides added different content
+    assert_eq!(resolve(&[], &[&side1, &side2]), None);
+    // One side removed the path and the other modified it
+    assert_eq!(resolve(&[&base], &[&side1]), None);
+}
+
+#[test]
+fn test_tree_conflicts_nested() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let clean_path = RepoPath::from_internal_string("dir/clean");
+    let path = RepoPath::from_internal_string("dir/sub/file");
+    let base_tree = testutils::create_tree(repo, &[(&clean_path, "base"), (&path, "base")]);
+    let side1_tree = testutils::create_tree(repo, &[(&clean_path, "base"), (&path, "side 1")]);
+    let side2_tree = testutils::create_tree(repo, &[(&clean_path, "side 2"), (&path, "side 2")]);
+    assert_eq!(base_tree.conflicts(), vec![]);
+    assert!(!base_tree.has_conflict());
+
+    let merged_tree_id = merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let merged_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &merged_tree_id)
+        .unwrap();
+    let conflicts = merged_tree.conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].0, path);
+    assert_eq!(conflicts[0].0.to_internal_file_string(), "dir/sub/file");
+    assert_eq!(
+        merged_tree.path_value(&path),
+        Some(TreeValue::Conflict(conflicts[0].1.clone()))
+    );
+    assert!(merged_tree.has_conflict());
+}
+
+#[test]
+fn test_commit_conflicted_paths() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let clean_path = RepoPath::from_internal_string("clean");
+    let path1 = RepoPath::from_internal_string("file");
+    let path2 = RepoPath::from_internal_string("dir/file");
+    let base_tree = testutils::create_tree(
+        repo,
+        &[(&clean_path, "base"), (&path1, "base"), (&path2, "base")],
+    );
+    let side1_tree = testutils::create_tree(
+        repo,
+        &[
+            (&clean_path, "side 1"),
+            (&path1, "side 1"),
+            (&path2, "side 1"),
+        ],
+    );
+    let side2_tree = testutils::create_tree(
+        repo,
+        &[
+            (&clean_path, "base"),
+            (&path1, "side 2"),
+            (&path2, "side 2"),
+        ],
+    );
+    let merged_tree_id = merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let clean_commit = tx
+        .mut_repo()
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            side1_tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let conflicted_commit = tx
+        .mut_repo()
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            merged_tree_id,
+        )
+        .write()
+        .unwrap();
+    assert_eq!(clean_commit.conflicted_paths().unwrap(), vec![]);
+    assert_eq!(
+        conflicted_commit.conflicted_paths().unwrap(),
+        vec![path2, path1]
+    );
+}
+
 fn materialize_conflict_string(store: &Store, path: &RepoPath, conflict: &Conflict) -> String {
     let mut result: Vec<u8> = vec![];
-    materialize_conflict(store, path, conflict, &mut result).unwrap();
+    materialize_conflict(store, path, conflict, MaterializeStyle::Diff, &mut result).unwrap();
     String::from_utf8(result).unwrap()
 }
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use jujutsu_lib::matchers::{EverythingMatcher, FilesMatcher};
+use itertools::Itertools;
+use jujutsu_lib::matchers::{
+    DifferenceMatcher, EverythingMatcher, FilesMatcher, Matcher, PrefixMatcher, Visit,
+};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::tree::DiffSummary;
 use test_case::test_case;
@@ -281,3 +284,80 @@ fn test_matcher_normal_cases(use_git: bool) {
         }
     );
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_matcher_visit_pruning(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let a_path = RepoPath::from_internal_string("a");
+    let dir1_path = RepoPath::from_internal_string("dir1/file");
+    let dir1_sub_path = RepoPath::from_internal_string("dir1/sub/file");
+    let dir2_path = RepoPath::from_internal_string("dir2/file");
+    let tree1 = testutils::create_tree(
+        repo,
+        &[
+            (&a_path, "before"),
+            (&dir1_path, "before"),
+            (&dir1_sub_path, "before"),
+            (&dir2_path, "before"),
+        ],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&a_path, "after"),
+            (&dir1_path, "after"),
+            (&dir1_sub_path, "after"),
+            (&dir2_path, "after"),
+        ],
+    );
+
+    // The diff restricted by a matcher is the same as the full diff filtered by
+    // the matcher
+    let dir1 = RepoPath::from_internal_string("dir1");
+    let dir1_sub = RepoPath::from_internal_string("dir1/sub");
+    let files_matcher = FilesMatcher::new(&[a_path.clone(), dir1_sub_path.clone()]);
+    let prefix_matcher = PrefixMatcher::new(&[dir1.clone()]);
+    let sub_prefix_matcher = PrefixMatcher::new(&[dir1_sub]);
+    let difference_matcher = DifferenceMatcher::new(&prefix_matcher, &sub_prefix_matcher);
+    let matchers: [&dyn Matcher; 4] = [
+        &files_matcher,
+        &prefix_matcher,
+        &sub_prefix_matcher,
+        &difference_matcher,
+    ];
+    for matcher in matchers {
+        let expected = tree1
+            .diff(&tree2, &EverythingMatcher)
+            .filter(|(path, _)| matcher.matches(path))
+            .collect_vec();
+        assert_eq!(tree1.diff(&tree2, matcher).collect_vec(), expected);
+    }
+
+    // Directories that the matcher doesn't want to visit are skipped, even if
+    // the matcher would match files in them
+    struct SkipDir1Matcher;
+    impl Matcher for SkipDir1Matcher {
+        fn matches(&self, _file: &RepoPath) -> bool {
+            true
+        }
+
+        fn visit(&self, dir: &RepoPath) -> Visit {
+            if dir == &RepoPath::from_internal_string("dir1") {
+                Visit::Nothing
+            } else {
+                Visit::AllRecursively
+            }
+        }
+    }
+    assert_eq!(
+        tree1.diff_summary(&tree2, &SkipDir1Matcher),
+        DiffSummary {
+            modified: vec![a_path, dir2_path],
+            added: vec![],
+            removed: vec![]
+        }
+    );
+}
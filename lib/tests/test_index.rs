@@ -14,13 +14,16 @@
 
 use std::sync::Arc;
 
-use jujutsu_lib::backend::CommitId;
+use itertools::Itertools;
+use jujutsu_lib::backend::{CommitId, ObjectId};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
 use jujutsu_lib::default_index_store::{MutableIndexImpl, ReadonlyIndexImpl};
 use jujutsu_lib::index::Index;
+use jujutsu_lib::op_store::RefTarget;
 use jujutsu_lib::repo::{MutableRepo, ReadonlyRepo, Repo};
 use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::view::RefName;
 use test_case::test_case;
 use testutils::{
     create_random_commit, load_repo_at_head, write_random_commit, CommitGraphBuilder, TestRepo,
@@ -121,6 +124,60 @@ fn test_index_commits_standard_cases(use_git: bool) {
     assert!(index.is_ancestor(commit_a.id(), commit_h.id()));
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_parent_positions(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // o D
+    // |\
+    // o | C
+    // | o B
+    // |/
+    // o A
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_c, &commit_b]);
+
+    let parent_ids = |index: &dyn Index, commit_id: &CommitId| {
+        let pos = index.commit_id_to_pos(commit_id).unwrap();
+        index
+            .parent_positions(pos)
+            .into_iter()
+            .map(|pos| index.entry_by_pos(pos).commit_id())
+            .collect_vec()
+    };
+
+    // Positions in the mutable index map back to the parents, in order
+    let index = tx.repo().index();
+    assert_eq!(
+        parent_ids(index, commit_a.id()),
+        vec![repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        parent_ids(index, commit_b.id()),
+        vec![commit_a.id().clone()]
+    );
+    assert_eq!(
+        parent_ids(index, commit_d.id()),
+        vec![commit_c.id().clone(), commit_b.id().clone()]
+    );
+    assert!(parent_ids(index, repo.store().root_commit_id()).is_empty());
+
+    // Same for the readonly index
+    let repo = tx.commit();
+    let index = repo.index();
+    assert_eq!(
+        parent_ids(index, commit_d.id()),
+        vec![commit_c.id().clone(), commit_b.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_index_commits_criss_cross(use_git: bool) {
@@ -559,3 +616,305 @@ fn test_index_store_type(use_git: bool) {
     );
     assert_eq!(as_readonly_impl(&repo).num_commits(), 1);
 }
+
+/// Test that an index file in the old unversioned format is read as is, and
+/// that an index file in a newer format is rebuilt.
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_format_version(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+    assert_eq!(as_readonly_impl(&repo).num_commits(), 2);
+
+    let index_dir = repo.repo_path().join("index");
+    let op_link_path = index_dir.join("operations").join(repo.op_id().hex());
+    let index_file_name = std::fs::read_to_string(&op_link_path).unwrap();
+    let index_file_path = index_dir.join(&index_file_name);
+    let data = std::fs::read(&index_file_path).unwrap();
+    assert_eq!(&data[0..4], b"JJIX");
+    assert_eq!(&data[4..8], &1_u32.to_le_bytes());
+
+    // An unversioned file has the same content without the magic and version
+    std::fs::write(&index_file_path, &data[8..]).unwrap();
+    let repo = load_repo_at_head(&settings, repo.repo_path());
+    assert_eq!(as_readonly_impl(&repo).num_commits(), 2);
+    assert_eq!(
+        std::fs::read_to_string(&op_link_path).unwrap(),
+        index_file_name
+    );
+
+    // A file in a newer format can't be read, so the index is rebuilt. The
+    // rebuilt file happens to have the same name since only the version was
+    // changed here.
+    let mut newer_data = data.clone();
+    newer_data[4..8].copy_from_slice(&2_u32.to_le_bytes());
+    std::fs::write(&index_file_path, newer_data).unwrap();
+    let repo = load_repo_at_head(&settings, repo.repo_path());
+    assert_eq!(as_readonly_impl(&repo).num_commits(), 2);
+    assert_eq!(
+        std::fs::read_to_string(&op_link_path).unwrap(),
+        index_file_name
+    );
+    assert_eq!(std::fs::read(&index_file_path).unwrap(), data);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_nearest_ref_ancestor(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // D
+    // C   <- branch "main"
+    // B   <- tag "v1.0"
+    // A   <- tag "v0.1"
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_c]);
+    let mut_repo = tx.mut_repo();
+    assert_eq!(mut_repo.nearest_ref_ancestor(commit_d.id()), None);
+
+    mut_repo.set_tag("v0.1".to_string(), RefTarget::Normal(commit_a.id().clone()));
+    mut_repo.set_tag("v1.0".to_string(), RefTarget::Normal(commit_b.id().clone()));
+    assert_eq!(
+        mut_repo.nearest_ref_ancestor(commit_d.id()),
+        Some((RefName::Tag("v1.0".to_string()), 2))
+    );
+    assert_eq!(
+        mut_repo.nearest_ref_ancestor(commit_b.id()),
+        Some((RefName::Tag("v1.0".to_string()), 0))
+    );
+    assert_eq!(
+        mut_repo.nearest_ref_ancestor(commit_a.id()),
+        Some((RefName::Tag("v0.1".to_string()), 0))
+    );
+
+    mut_repo.set_local_branch("main".to_string(), RefTarget::Normal(commit_c.id().clone()));
+    let repo = tx.commit();
+    assert_eq!(
+        repo.nearest_ref_ancestor(commit_d.id()),
+        Some((RefName::LocalBranch("main".to_string()), 1))
+    );
+
+    // A tag is preferred over a branch pointing to the same commit
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo()
+        .set_tag("v2.0".to_string(), RefTarget::Normal(commit_c.id().clone()));
+    assert_eq!(
+        tx.mut_repo().nearest_ref_ancestor(commit_d.id()),
+        Some((RefName::Tag("v2.0".to_string()), 1))
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_branches_containing(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // D   <- branch "feature"
+    // | C <- branch "main"
+    // |/
+    // B   <- branch "base"
+    // A
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_b]);
+    let mut_repo = tx.mut_repo();
+    assert!(mut_repo.branches_containing(commit_a.id()).is_empty());
+
+    mut_repo.set_local_branch("base".to_string(), RefTarget::Normal(commit_b.id().clone()));
+    mut_repo.set_local_branch("main".to_string(), RefTarget::Normal(commit_c.id().clone()));
+    mut_repo.set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit_d.id().clone()),
+    );
+    let repo = tx.commit();
+    assert_eq!(
+        repo.branches_containing(commit_a.id()),
+        vec!["base", "feature", "main"]
+    );
+    assert_eq!(
+        repo.branches_containing(commit_b.id()),
+        vec!["base", "feature", "main"]
+    );
+    assert_eq!(repo.branches_containing(commit_c.id()), vec!["main"]);
+    assert_eq!(repo.branches_containing(commit_d.id()), vec!["feature"]);
+
+    // A conflicted branch contains the commit if any of its sides does
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo().set_local_branch(
+        "base".to_string(),
+        RefTarget::Conflict {
+            removes: vec![commit_b.id().clone()],
+            adds: vec![commit_a.id().clone(), commit_c.id().clone()],
+        },
+    );
+    assert_eq!(
+        tx.repo().branches_containing(commit_c.id()),
+        vec!["base", "main"]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_guess_trunk(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Only the root commit is public in a new repo
+    assert_eq!(
+        repo.guess_trunk(),
+        Some(repo.store().root_commit_id().clone())
+    );
+
+    // C
+    // B D
+    // |/
+    // A
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_a]);
+    let mut_repo = tx.mut_repo();
+
+    // Without a conventionally-named branch, the highest public head is picked
+    mut_repo.add_public_head(&commit_c);
+    mut_repo.add_public_head(&commit_d);
+    mut_repo.set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit_d.id().clone()),
+    );
+    assert_eq!(mut_repo.guess_trunk(), Some(commit_c.id().clone()));
+
+    // A branch named "master" takes precedence over public heads
+    mut_repo.set_local_branch(
+        "master".to_string(),
+        RefTarget::Normal(commit_b.id().clone()),
+    );
+    assert_eq!(mut_repo.guess_trunk(), Some(commit_b.id().clone()));
+
+    // "main" takes precedence over "master"
+    mut_repo.set_local_branch("main".to_string(), RefTarget::Normal(commit_d.id().clone()));
+    let repo = tx.commit();
+    assert_eq!(repo.guess_trunk(), Some(commit_d.id().clone()));
+
+    // A conflicted branch is skipped
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo().set_local_branch(
+        "main".to_string(),
+        RefTarget::Conflict {
+            removes: vec![commit_b.id().clone()],
+            adds: vec![commit_c.id().clone(), commit_d.id().clone()],
+        },
+    );
+    assert_eq!(tx.repo().guess_trunk(), Some(commit_b.id().clone()));
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_unreachable_commits(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // E
+    // | D <- branch "main"
+    // |/
+    // | C
+    // |/
+    // B
+    // A
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_e = graph_builder.commit_with_parents(&[&commit_b]);
+    let mut_repo = tx.mut_repo();
+    assert!(mut_repo.unreachable_commits().is_empty());
+
+    // Hidden commits are unreachable unless a ref points to them
+    mut_repo.set_local_branch("main".to_string(), RefTarget::Normal(commit_d.id().clone()));
+    mut_repo.remove_head(commit_c.id());
+    mut_repo.remove_head(commit_d.id());
+    let repo = tx.commit();
+    assert_eq!(repo.unreachable_commits(), vec![commit_c.id().clone()]);
+
+    // Once the remaining head and the branch are gone, all commits but the
+    // root are unreachable
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo().remove_head(commit_e.id());
+    tx.mut_repo().remove_local_branch("main");
+    let repo = tx.commit();
+    assert_eq!(
+        repo.unreachable_commits(),
+        vec![
+            commit_e.id().clone(),
+            commit_d.id().clone(),
+            commit_c.id().clone(),
+            commit_b.id().clone(),
+            commit_a.id().clone(),
+        ]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_ordered_parents(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // D E (D has parents C, B; E has parents B, C)
+    // |X|
+    // B C
+    // |/
+    // A
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_c, &commit_b]);
+    let commit_e = graph_builder.commit_with_parents(&[&commit_b, &commit_c]);
+    let repo = tx.commit();
+
+    // The recorded order is kept, not the index order
+    assert_eq!(
+        repo.ordered_parents(commit_d.id()),
+        vec![commit_c.id().clone(), commit_b.id().clone()]
+    );
+    assert_eq!(
+        repo.ordered_parents(commit_e.id()),
+        vec![commit_b.id().clone(), commit_c.id().clone()]
+    );
+    assert_eq!(
+        repo.ordered_parents(commit_a.id()),
+        vec![repo.store().root_commit_id().clone()]
+    );
+    assert!(repo
+        .ordered_parents(repo.store().root_commit_id())
+        .is_empty());
+    // Unknown commits have no parents
+    assert!(repo
+        .ordered_parents(&CommitId::from_hex("000000000000"))
+        .is_empty());
+}
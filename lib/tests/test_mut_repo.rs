@@ -12,9 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
+use assert_matches::assert_matches;
+use itertools::Itertools;
+use jujutsu_lib::backend::{self, ChangeId, MillisSinceEpoch, ObjectId, Signature, Timestamp};
+use jujutsu_lib::index::{HexPrefix, PrefixResolution};
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
-use jujutsu_lib::repo::Repo;
-use maplit::hashset;
+use jujutsu_lib::repo::{ReparentError, ReparentTree, Repo, RepoLoader, StoreFactories};
+use jujutsu_lib::repo_path::RepoPath;
+use maplit::{hashmap, hashset};
 use test_case::test_case;
 use testutils::{
     assert_rebased, create_random_commit, write_random_commit, CommitGraphBuilder, TestRepo,
@@ -308,6 +315,52 @@ fn test_add_head_not_immediate_child(use_git: bool) {
     assert!(mut_repo.index().has_id(child.id()));
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_add_head_on_non_head(use_git: bool) {
+    // Test that MutableRepo::add_head() indexes a commit whose parent is indexed
+    // but isn't a head.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let repo = tx.commit();
+
+    // Create a commit on top of the interior commit outside of the repo by using a
+    // temporary transaction. Then add it as a head.
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit3 = create_random_commit(tx.mut_repo(), &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    drop(tx);
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    assert!(!mut_repo.index().has_id(commit3.id()));
+    mut_repo.add_head(&commit3);
+    assert_eq!(
+        mut_repo.view().heads(),
+        &hashset! {commit2.id().clone(), commit3.id().clone()}
+    );
+    assert_eq!(
+        mut_repo
+            .index()
+            .resolve_prefix(&HexPrefix::new(&commit3.id().hex()).unwrap()),
+        PrefixResolution::SingleMatch(commit3.id().clone())
+    );
+    let repo = tx.commit();
+    assert_eq!(
+        repo.view().heads(),
+        &hashset! {commit2.id().clone(), commit3.id().clone()}
+    );
+    assert!(repo.index().has_id(commit3.id()));
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_remove_head(use_git: bool) {
@@ -417,6 +470,87 @@ fn test_remove_public_head(use_git: bool) {
     assert!(!repo.view().public_heads().contains(commit1.id()));
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_add_public_head_updates_change_ids(use_git: bool) {
+    // Test that MutableRepo::add_public_head() makes a hidden commit visible to
+    // the change id lookups in the same transaction.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit2 = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("rewritten")
+        .write()
+        .unwrap();
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    let repo = tx.commit();
+
+    // The hidden commit1 doesn't make its change divergent until it's made
+    // visible again as a public head
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    assert_eq!(mut_repo.divergent_change_ids(), hashmap! {});
+    mut_repo.add_public_head(&commit1);
+    let divergent_change_ids = mut_repo.divergent_change_ids();
+    assert_eq!(
+        divergent_change_ids.keys().collect_vec(),
+        vec![commit1.change_id()]
+    );
+    assert_eq!(
+        divergent_change_ids[commit1.change_id()]
+            .iter()
+            .sorted()
+            .collect_vec(),
+        [commit1.id(), commit2.id()]
+            .into_iter()
+            .sorted()
+            .collect_vec()
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_visible_and_public_heads(use_git: bool) {
+    // Test that Repo::visible_heads() and Repo::public_heads() iterate over the
+    // same heads as the view has.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.initial_commit();
+    let mut_repo = tx.mut_repo();
+    mut_repo.add_public_head(&commit1);
+    assert_eq!(mut_repo.visible_heads().len(), 2);
+    assert_eq!(
+        mut_repo.visible_heads().cloned().collect::<HashSet<_>>(),
+        *mut_repo.view().heads()
+    );
+    assert_eq!(
+        mut_repo.public_heads().cloned().collect::<HashSet<_>>(),
+        hashset! {commit1.id().clone()}
+    );
+    let repo = tx.commit();
+    assert_eq!(
+        repo.visible_heads().cloned().collect::<HashSet<_>>(),
+        hashset! {commit2.id().clone(), commit3.id().clone()}
+    );
+    assert_eq!(
+        repo.public_heads().cloned().collect::<HashSet<_>>(),
+        *repo.view().public_heads()
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_has_changed(use_git: bool) {
@@ -585,6 +719,204 @@ fn test_rebase_descendants_conflicting_rewrite(use_git: bool) {
         .is_none());
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_abandon_commit(use_git: bool) {
+    // Tests that MutableRepo::abandon_commit() rebases descendants and moves
+    // working copies off the abandoned commit.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let ws1_id = WorkspaceId::new("ws1".to_string());
+    let ws2_id = WorkspaceId::new("ws2".to_string());
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    mut_repo.edit(ws1_id.clone(), &commit2).unwrap();
+    mut_repo.edit(ws2_id.clone(), &commit3).unwrap();
+    let repo = tx.commit();
+
+    // Abandon a commit which is a working copy and has a descendant
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    assert_eq!(
+        mut_repo
+            .abandon_commit(&settings, commit2.id().clone())
+            .unwrap(),
+        1
+    );
+    let ws1_commit_id = mut_repo.view().get_wc_commit_id(&ws1_id).unwrap().clone();
+    let ws1_commit = mut_repo.store().get_commit(&ws1_commit_id).unwrap();
+    assert_ne!(ws1_commit.id(), commit2.id());
+    assert_eq!(ws1_commit.parent_ids(), vec![commit1.id().clone()]);
+    assert_eq!(ws1_commit.tree_id(), commit1.tree_id());
+    // The working copy on the descendant follows the rebased commit
+    let ws2_commit_id = mut_repo.view().get_wc_commit_id(&ws2_id).unwrap().clone();
+    let ws2_commit = mut_repo.store().get_commit(&ws2_commit_id).unwrap();
+    assert_eq!(ws2_commit.change_id(), commit3.change_id());
+    assert_eq!(ws2_commit.parent_ids(), vec![commit1.id().clone()]);
+    assert_eq!(
+        *mut_repo.view().heads(),
+        hashset! {ws1_commit.id().clone(), ws2_commit.id().clone()}
+    );
+    assert!(!mut_repo.has_rewrites());
+
+    // Abandon a head commit which is a working copy
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    assert_eq!(
+        mut_repo
+            .abandon_commit(&settings, commit3.id().clone())
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        mut_repo.view().get_wc_commit_id(&ws1_id),
+        Some(commit2.id())
+    );
+    let ws2_commit_id = mut_repo.view().get_wc_commit_id(&ws2_id).unwrap().clone();
+    let ws2_commit = mut_repo.store().get_commit(&ws2_commit_id).unwrap();
+    assert_ne!(ws2_commit.id(), commit3.id());
+    assert_eq!(ws2_commit.parent_ids(), vec![commit2.id().clone()]);
+    assert_eq!(ws2_commit.tree_id(), commit2.tree_id());
+    assert_eq!(*mut_repo.view().heads(), hashset! {ws2_commit.id().clone()});
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_reparent(use_git: bool) {
+    // Tests that MutableRepo::reparent() keeps the tree and records the rewrite
+    // so descendants can be rebased.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.initial_commit();
+    let repo = tx.commit();
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let new_commit2 = mut_repo
+        .reparent(
+            &settings,
+            &commit2,
+            vec![commit4.id().clone()],
+            ReparentTree::Keep,
+        )
+        .unwrap();
+    assert_eq!(new_commit2.parent_ids(), &[commit4.id().clone()]);
+    assert_eq!(new_commit2.tree_id(), commit2.tree_id());
+    assert_eq!(new_commit2.change_id(), commit2.change_id());
+    assert!(mut_repo.has_rewrites());
+
+    let mut rebaser = mut_repo.create_descendant_rebaser(&settings);
+    assert_rebased(rebaser.rebase_next().unwrap(), &commit3, &[&new_commit2]);
+    assert!(rebaser.rebase_next().unwrap().is_none());
+
+    // The root commit can't be reparented
+    let root_commit = repo.store().root_commit();
+    assert_matches!(
+        mut_repo.reparent(
+            &settings,
+            &root_commit,
+            vec![commit1.id().clone()],
+            ReparentTree::Keep
+        ),
+        Err(ReparentError::RewriteRootCommit)
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_reparent_merge_tree(use_git: bool) {
+    // Tests that MutableRepo::reparent() can merge the commit's changes onto the
+    // new parents
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("file2");
+    let tree1 = testutils::create_tree(repo, &[(&file1_path, "1")]);
+    let tree2 = testutils::create_tree(repo, &[(&file1_path, "1"), (&file2_path, "2")]);
+    let tree3 = testutils::create_tree(repo, &[(&file1_path, "3")]);
+    let expected_tree = testutils::create_tree(repo, &[(&file1_path, "3"), (&file2_path, "2")]);
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let root_commit_id = repo.store().root_commit_id().clone();
+    let commit1 = mut_repo
+        .new_commit(&settings, vec![root_commit_id], tree1.id().clone())
+        .write()
+        .unwrap();
+    let commit2 = mut_repo
+        .new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+        .write()
+        .unwrap();
+    let commit3 = mut_repo
+        .new_commit(&settings, vec![commit1.id().clone()], tree3.id().clone())
+        .write()
+        .unwrap();
+
+    let new_commit2 = mut_repo
+        .reparent(
+            &settings,
+            &commit2,
+            vec![commit3.id().clone()],
+            ReparentTree::Merge,
+        )
+        .unwrap();
+    assert_eq!(new_commit2.parent_ids(), &[commit3.id().clone()]);
+    assert_eq!(new_commit2.tree_id(), expected_tree.id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rename_branch(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    let target1 = RefTarget::Normal(commit1.id().clone());
+    let target2 = RefTarget::Normal(commit2.id().clone());
+    mut_repo.set_local_branch("main".to_string(), target1.clone());
+    mut_repo.set_remote_branch("main".to_string(), "origin".to_string(), target2.clone());
+    mut_repo.set_local_branch("other".to_string(), target2.clone());
+    let main_branch = mut_repo.get_branch("main").unwrap();
+
+    // Local and remote targets move to the new name
+    assert!(mut_repo.rename_branch("main", "trunk"));
+    assert_eq!(mut_repo.get_branch("trunk"), Some(main_branch.clone()));
+    assert_eq!(mut_repo.get_local_branch("trunk"), Some(target1));
+    assert_eq!(
+        mut_repo.get_remote_branch("trunk", "origin"),
+        Some(target2.clone())
+    );
+    assert_eq!(mut_repo.get_branch("main"), None);
+    assert_eq!(mut_repo.get_remote_branch("main", "origin"), None);
+
+    // Nonexistent source is a no-op
+    assert!(!mut_repo.rename_branch("main", "new"));
+    assert_eq!(mut_repo.get_branch("new"), None);
+
+    // Existing destination isn't overwritten
+    assert!(!mut_repo.rename_branch("trunk", "other"));
+    assert_eq!(mut_repo.get_branch("trunk"), Some(main_branch));
+    assert_eq!(mut_repo.get_local_branch("other"), Some(target2));
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_rename_remote(use_git: bool) {
@@ -600,3 +932,341 @@ fn test_rename_remote(use_git: bool) {
     assert_eq!(mut_repo.get_remote_branch("main", "upstream"), Some(target));
     assert_eq!(mut_repo.get_remote_branch("main", "origin"), None);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_change_id_siblings(use_git: bool) {
+    // Test that Repo::change_id_siblings() reports the position of each commit
+    // within a divergent change
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit_a1 = write_random_commit(tx.mut_repo(), &settings);
+    let commit_b = write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit_a2 = create_random_commit(mut_repo, &settings)
+        .set_change_id(commit_a1.change_id().clone())
+        .write()
+        .unwrap();
+    let commit_a3 = create_random_commit(mut_repo, &settings)
+        .set_change_id(commit_a1.change_id().clone())
+        .write()
+        .unwrap();
+    assert_eq!(mut_repo.change_id_siblings(commit_a1.id()), Some((0, 3)));
+    assert_eq!(mut_repo.change_id_siblings(commit_a2.id()), Some((1, 3)));
+    assert_eq!(mut_repo.change_id_siblings(commit_a3.id()), Some((2, 3)));
+    assert_eq!(mut_repo.change_id_siblings(commit_b.id()), Some((0, 1)));
+
+    let repo = tx.commit();
+    assert_eq!(repo.change_id_siblings(commit_a1.id()), Some((0, 3)));
+    assert_eq!(repo.change_id_siblings(commit_a2.id()), Some((1, 3)));
+    assert_eq!(repo.change_id_siblings(commit_a3.id()), Some((2, 3)));
+    assert_eq!(repo.change_id_siblings(commit_b.id()), Some((0, 1)));
+
+    // Hidden commits have no position
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo().remove_head(commit_a3.id());
+    let repo = tx.commit();
+    assert_eq!(repo.change_id_siblings(commit_a2.id()), Some((1, 2)));
+    assert_eq!(repo.change_id_siblings(commit_a3.id()), None);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_change_ids_matching_prefix(use_git: bool) {
+    // Test that Repo::change_ids_matching_prefix() lists the visible change ids
+    // that make a prefix ambiguous
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let change_id_a = ChangeId::from_hex("abc0aaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+    let change_id_b = ChangeId::from_hex("abc1bbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+    let change_id_c = ChangeId::from_hex("abd0cccccccccccccccccccccccccccc");
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let mut write_commit = |change_id: &ChangeId| {
+        create_random_commit(mut_repo, &settings)
+            .set_change_id(change_id.clone())
+            .write()
+            .unwrap()
+    };
+    write_commit(&change_id_a);
+    write_commit(&change_id_a);
+    let commit_b = write_commit(&change_id_b);
+    write_commit(&change_id_c);
+
+    let check = |repo: &dyn Repo| {
+        let matching =
+            |prefix: &str| repo.change_ids_matching_prefix(&HexPrefix::new(prefix).unwrap());
+        assert_matches!(
+            repo.resolve_change_id_prefix(&HexPrefix::new("abc").unwrap()),
+            PrefixResolution::AmbiguousMatch
+        );
+        // Divergent commits are reported once
+        assert_eq!(
+            matching("abc"),
+            vec![change_id_a.clone(), change_id_b.clone()]
+        );
+        assert_eq!(
+            matching("ab"),
+            vec![
+                change_id_a.clone(),
+                change_id_b.clone(),
+                change_id_c.clone()
+            ]
+        );
+        assert_eq!(matching("abd"), vec![change_id_c.clone()]);
+        assert_eq!(matching("abe"), vec![]);
+    };
+    check(tx.mut_repo());
+    let repo = tx.commit();
+    check(&repo);
+
+    // Hidden commits aren't included
+    let mut tx = repo.start_transaction(&settings, "test");
+    tx.mut_repo().remove_head(commit_b.id());
+    let repo = tx.commit();
+    assert_eq!(
+        repo.change_ids_matching_prefix(&HexPrefix::new("abc").unwrap()),
+        vec![change_id_a]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_resolve_commit_id_prefix(use_git: bool) {
+    // Test that Repo::resolve_commit_id_prefix() takes hidden commits into
+    // account
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // With the root commit, 17 commits are enough for two of the ids to share
+    // the first hex digit. The root commit comes last, so it's never hidden.
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commits = (0..16)
+        .map(|_| write_random_commit(tx.mut_repo(), &settings))
+        .collect_vec();
+    let mut commit_ids = commits
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
+    commit_ids.push(repo.store().root_commit_id().clone());
+    let (hidden_id, visible_id) = commit_ids
+        .iter()
+        .tuple_combinations()
+        .find(|(id1, id2)| id1.hex()[..1] == id2.hex()[..1])
+        .unwrap();
+    let prefix = HexPrefix::new(&hidden_id.hex()[..1]).unwrap();
+    tx.mut_repo().remove_head(hidden_id);
+
+    let check = |repo: &dyn Repo| {
+        assert_eq!(
+            repo.resolve_commit_id_prefix(&prefix),
+            PrefixResolution::AmbiguousMatch
+        );
+        assert_eq!(
+            repo.resolve_commit_id_prefix(&HexPrefix::new(&hidden_id.hex()).unwrap()),
+            PrefixResolution::SingleMatch(vec![hidden_id.clone()])
+        );
+        assert_eq!(
+            repo.resolve_commit_id_prefix(&HexPrefix::new(&visible_id.hex()).unwrap()),
+            PrefixResolution::SingleMatch(vec![visible_id.clone()])
+        );
+    };
+    check(tx.mut_repo());
+    let repo = tx.commit();
+    check(&repo);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_shortest_unique_change_id_prefix_len(use_git: bool) {
+    // Test that MutableRepo::shortest_unique_change_id_prefix_len() reflects the
+    // commits added and removed within the transaction
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let change_id_a = ChangeId::from_hex("abc0aaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+    let change_id_b = ChangeId::from_hex("abd0bbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+    let mut tx = repo.start_transaction(&settings, "test");
+    create_random_commit(tx.mut_repo(), &settings)
+        .set_change_id(change_id_a.clone())
+        .write()
+        .unwrap();
+    assert_eq!(
+        tx.mut_repo()
+            .shortest_unique_change_id_prefix_len(&change_id_a),
+        1
+    );
+
+    let commit_b = create_random_commit(tx.mut_repo(), &settings)
+        .set_change_id(change_id_b.clone())
+        .write()
+        .unwrap();
+    assert_eq!(
+        tx.mut_repo()
+            .shortest_unique_change_id_prefix_len(&change_id_a),
+        3
+    );
+    assert_eq!(
+        tx.mut_repo()
+            .shortest_unique_change_id_prefix_len(&change_id_b),
+        3
+    );
+
+    // Hidden commits aren't taken into account
+    tx.mut_repo().remove_head(commit_b.id());
+    assert_eq!(
+        tx.mut_repo()
+            .shortest_unique_change_id_prefix_len(&change_id_a),
+        1
+    );
+    let repo = tx.commit();
+    assert_eq!(repo.shortest_unique_change_id_prefix_len(&change_id_a), 1);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_savepoint_restore(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    let savepoint = tx.savepoint();
+
+    // Make more changes, including a recorded rewrite
+    let commit2 = write_random_commit(tx.mut_repo(), &settings);
+    let commit3 = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("rewritten")
+        .write()
+        .unwrap();
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit3.id().clone()));
+    assert!(tx.repo().has_rewrites());
+    assert!(tx.repo().view().heads().contains(commit2.id()));
+
+    tx.restore(savepoint);
+    assert!(!tx.repo().has_rewrites());
+    assert_eq!(*tx.repo().view().heads(), hashset! {commit1.id().clone()});
+    assert_eq!(
+        tx.repo().get_local_branch("main"),
+        Some(RefTarget::Normal(commit1.id().clone()))
+    );
+    // The restored transaction can still be committed
+    let repo = tx.commit();
+    assert_eq!(*repo.view().heads(), hashset! {commit1.id().clone()});
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_write_commit_preserves_fields(use_git: bool) {
+    // Importers can write fully-formed commit data without the defaults applied
+    // by `CommitBuilder`
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let signature = |name: &str, millis, tz_offset| Signature {
+        name: name.to_string(),
+        email: format!("{name}@example.com"),
+        timestamp: Timestamp {
+            timestamp: MillisSinceEpoch(millis),
+            tz_offset,
+        },
+    };
+    let commit_data = backend::Commit {
+        parents: vec![repo.store().root_commit_id().clone()],
+        predecessors: vec![],
+        root_tree: repo.store().empty_tree_id().clone(),
+        change_id: ChangeId::from_hex("0123456789abcdef0123456789abcdef"),
+        description: "imported commit\n".to_string(),
+        author: signature("author", 1_000_000_000_000, -420),
+        committer: signature("committer", 1_234_567_890_000, 330),
+    };
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit = tx.mut_repo().write_commit(commit_data.clone()).unwrap();
+    assert_eq!(*commit.store_commit(), commit_data);
+    let repo = tx.commit();
+    assert!(repo.view().heads().contains(commit.id()));
+
+    // Read back through a fresh store to bypass its cache
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    let reloaded_commit = loader.store().get_commit(commit.id()).unwrap();
+    assert_eq!(*reloaded_commit.store_commit(), commit_data);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_write_commits_bulk_import(use_git: bool) {
+    // Importing a long chain writes every commit and leaves only the tip as a
+    // head
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let initial_head = write_random_commit(mut_repo, &settings);
+    let signature = Signature {
+        name: "importer".to_string(),
+        email: "importer@example.com".to_string(),
+        timestamp: Timestamp {
+            timestamp: MillisSinceEpoch(0),
+            tz_offset: 0,
+        },
+    };
+    let mut parent_id = repo.store().root_commit_id().clone();
+    let mut imported_commits = vec![];
+    for i in 0..1000 {
+        let commit_data = backend::Commit {
+            parents: vec![parent_id.clone()],
+            predecessors: vec![],
+            root_tree: repo.store().empty_tree_id().clone(),
+            change_id: ChangeId::from_hex(&format!("{i:032x}")),
+            description: format!("imported commit {i}\n"),
+            author: signature.clone(),
+            committer: signature.clone(),
+        };
+        // The parent ids must be known up front, so write the objects to the
+        // store first. The repo (index and view) doesn't know about them yet.
+        parent_id = repo
+            .store()
+            .write_commit(commit_data.clone())
+            .unwrap()
+            .id()
+            .clone();
+        imported_commits.push(commit_data);
+    }
+    let written = mut_repo.write_commits(imported_commits.clone()).unwrap();
+    assert_eq!(written.len(), 1000);
+    for (commit, commit_data) in written.iter().zip(&imported_commits) {
+        assert_eq!(*commit.store_commit(), *commit_data);
+        assert!(mut_repo.index().has_id(commit.id()));
+    }
+    let tip = written.last().unwrap();
+    assert_eq!(tip.id(), &parent_id);
+    assert_eq!(
+        *mut_repo.view().heads(),
+        hashset! {initial_head.id().clone(), tip.id().clone()}
+    );
+    let repo = tx.commit();
+    assert_eq!(
+        *repo.view().heads(),
+        hashset! {initial_head.id().clone(), tip.id().clone()}
+    );
+    assert!(repo.index().has_id(written[0].id()));
+}
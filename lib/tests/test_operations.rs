@@ -13,9 +13,21 @@
 // limitations under the License.
 
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use assert_matches::assert_matches;
+use itertools::Itertools;
 use jujutsu_lib::backend::CommitId;
+use jujutsu_lib::op_heads_store::{self, OpHeadsStore, OpHeadsStoreLock};
+use jujutsu_lib::op_store::{BranchTarget, OpStoreError, OperationId, RefTarget, WorkspaceId};
+use jujutsu_lib::operation::{
+    resolve_op_id_prefix, OpIdResolutionError, Operation, OperationSummary, OperationViewDiff,
+};
 use jujutsu_lib::repo::Repo;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::transaction::BranchChange;
+use maplit::btreemap;
 use test_case::test_case;
 use testutils::{create_random_commit, write_random_commit, TestRepo};
 
@@ -122,6 +134,83 @@ fn test_concurrent_operations(use_git: bool) {
     assert_eq!(list_dir(&op_heads_dir), vec![merged_op_id.hex()]);
 }
 
+/// Returns the op heads of the wrapped store in reverse order.
+#[derive(Debug)]
+struct ReversedOpHeadsStore(Arc<dyn OpHeadsStore>);
+
+impl OpHeadsStore for ReversedOpHeadsStore {
+    fn name(&self) -> &str {
+        "reversed_op_heads_store"
+    }
+
+    fn add_op_head(&self, id: &OperationId) {
+        self.0.add_op_head(id);
+    }
+
+    fn remove_op_head(&self, id: &OperationId) {
+        self.0.remove_op_head(id);
+    }
+
+    fn get_op_heads(&self) -> Vec<OperationId> {
+        let mut op_heads = self.0.get_op_heads();
+        op_heads.reverse();
+        op_heads
+    }
+
+    fn lock<'a>(&'a self) -> Box<dyn OpHeadsStoreLock<'a> + 'a> {
+        self.0.lock()
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_resolve_op_heads_deterministic(use_git: bool) {
+    // Test that concurrent operations are merged in the same order regardless of
+    // the order the op heads are stored in, even if their timestamps are equal.
+    let config = config::Config::builder()
+        .add_source(testutils::user_settings().config().clone())
+        .set_override("debug.operation-timestamp", "2001-02-03T04:05:06+07:00")
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Move the same branch in three concurrent operations so the merged view
+    // depends on the merge order
+    let mut op_head_ids = vec![];
+    for i in 0..3 {
+        let mut tx = repo.start_transaction(&settings, &format!("transaction {i}"));
+        let commit = write_random_commit(tx.mut_repo(), &settings);
+        tx.mut_repo()
+            .set_local_branch("main".to_string(), RefTarget::Normal(commit.id().clone()));
+        op_head_ids.push(tx.commit().op_id().clone());
+    }
+
+    let resolve = |op_heads_store: &dyn OpHeadsStore| {
+        op_heads_store::resolve_op_heads(op_heads_store, repo.op_store(), |op_heads| {
+            let base_repo = repo.loader().load_at(&op_heads[0]);
+            let mut tx = base_repo.start_transaction(&settings, "resolve concurrent operations");
+            for other_op_head in op_heads.into_iter().skip(1) {
+                tx.merge_operation(other_op_head);
+            }
+            Ok::<_, ()>(tx.write().leave_unpublished().operation().clone())
+        })
+        .unwrap()
+    };
+    let op_heads_store = repo.op_heads_store().clone();
+    let resolved_op1 = resolve(op_heads_store.as_ref());
+    // Restore the concurrent op heads and resolve them in the opposite order
+    op_heads_store.remove_op_head(resolved_op1.id());
+    for op_head_id in &op_head_ids {
+        op_heads_store.add_op_head(op_head_id);
+    }
+    let resolved_op2 = resolve(&ReversedOpHeadsStore(op_heads_store));
+    assert_eq!(resolved_op1.view().id(), resolved_op2.view().id());
+    assert_eq!(resolved_op1.parent_ids(), resolved_op2.parent_ids());
+}
+
 fn assert_heads(repo: &dyn Repo, expected: Vec<&CommitId>) {
     let expected = expected.iter().cloned().cloned().collect();
     assert_eq!(*repo.view().heads(), expected);
@@ -182,3 +271,401 @@ fn test_isolation(use_git: bool) {
     let repo = repo.reload_at_head(&settings).unwrap();
     assert_heads(&repo, vec![rewrite1.id(), rewrite2.id()]);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_operation_affected_summary(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Adding a commit and a branch. The root commit is no longer a head.
+    let root_commit_id = repo.store().root_commit_id().clone();
+    let mut tx = repo.start_transaction(&settings, "add commit");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    let repo = tx.commit();
+    assert_eq!(
+        repo.operation().affected_summary(),
+        OperationSummary {
+            added_heads: vec![commit1.id().clone()],
+            removed_heads: vec![root_commit_id],
+            changed_branches: vec!["main".to_string()],
+            changed_tags: vec![],
+        }
+    );
+
+    // Rewriting the commit replaces the head and moves the branch
+    let mut tx = repo.start_transaction(&settings, "rewrite commit");
+    let commit2 = tx
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("rewritten")
+        .write()
+        .unwrap();
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    tx.mut_repo()
+        .set_tag("v1".to_string(), RefTarget::Normal(commit2.id().clone()));
+    let repo = tx.commit();
+    assert_eq!(
+        repo.operation().affected_summary(),
+        OperationSummary {
+            added_heads: vec![commit2.id().clone()],
+            removed_heads: vec![commit1.id().clone()],
+            changed_branches: vec!["main".to_string()],
+            changed_tags: vec!["v1".to_string()],
+        }
+    );
+
+    // Moving and deleting refs
+    let mut tx = repo.start_transaction(&settings, "move refs");
+    tx.mut_repo().set_remote_branch(
+        "main".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    tx.mut_repo().remove_tag("v1");
+    let repo = tx.commit();
+    assert_eq!(
+        repo.operation().affected_summary(),
+        OperationSummary {
+            added_heads: vec![],
+            removed_heads: vec![],
+            changed_branches: vec!["main".to_string()],
+            changed_tags: vec!["v1".to_string()],
+        }
+    );
+
+    // An operation without changes has an empty summary
+    let tx = repo.start_transaction(&settings, "no-op");
+    let repo = tx.commit();
+    assert!(repo.operation().affected_summary().is_empty());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_operation_view_diff(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "add commits");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    let commit2 = write_random_commit(tx.mut_repo(), &settings);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    tx.mut_repo().set_local_branch(
+        "stable".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    let repo1 = tx.commit();
+
+    // Only one branch moves
+    let mut tx = repo1.start_transaction(&settings, "move branch");
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit2.id().clone()));
+    let repo2 = tx.commit();
+    let diff = repo2.operation().view_diff(repo1.operation());
+    assert_eq!(
+        diff,
+        OperationViewDiff {
+            changed_branches: btreemap! {
+                "main".to_string() => (
+                    Some(BranchTarget {
+                        local_target: Some(RefTarget::Normal(commit1.id().clone())),
+                        remote_targets: btreemap! {},
+                    }),
+                    Some(BranchTarget {
+                        local_target: Some(RefTarget::Normal(commit2.id().clone())),
+                        remote_targets: btreemap! {},
+                    }),
+                ),
+            },
+            ..Default::default()
+        }
+    );
+
+    // The diff in the other direction is reversed
+    let reverse_diff = repo1.operation().view_diff(repo2.operation());
+    assert_eq!(
+        reverse_diff.changed_branches["main"],
+        (
+            diff.changed_branches["main"].1.clone(),
+            diff.changed_branches["main"].0.clone()
+        )
+    );
+
+    // Working-copy moves and head changes are included
+    let mut tx = repo2.start_transaction(&settings, "edit commit");
+    tx.mut_repo()
+        .set_wc_commit(WorkspaceId::default(), commit1.id().clone())
+        .unwrap();
+    tx.mut_repo().remove_head(commit2.id());
+    let repo3 = tx.commit();
+    let diff = repo3.operation().view_diff(repo2.operation());
+    let old_wc_commit_id = repo2
+        .view()
+        .get_wc_commit_id(&WorkspaceId::default())
+        .cloned();
+    assert_eq!(diff.added_heads, vec![]);
+    assert_eq!(diff.removed_heads, vec![commit2.id().clone()]);
+    assert_eq!(diff.changed_branches, btreemap! {});
+    assert_eq!(
+        diff.changed_wc_commits,
+        btreemap! {
+            WorkspaceId::default() => (old_wc_commit_id, Some(commit1.id().clone())),
+        }
+    );
+
+    // An operation compared to itself has no changes
+    assert!(repo3.operation().view_diff(repo3.operation()).is_empty());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_reachable_operations(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let initial_op_ids = repo.operation().reachable_operations();
+    assert!(initial_op_ids.contains(repo.op_id()));
+
+    let mut tx1 = repo.start_transaction(&settings, "transaction 1");
+    write_random_commit(tx1.mut_repo(), &settings);
+    let repo1 = tx1.commit();
+    let mut tx2 = repo1.start_transaction(&settings, "transaction 2");
+    write_random_commit(tx2.mut_repo(), &settings);
+    let repo2 = tx2.commit();
+
+    let mut expected_op_ids = initial_op_ids.clone();
+    expected_op_ids.insert(repo1.op_id().clone());
+    expected_op_ids.insert(repo2.op_id().clone());
+    assert_eq!(repo2.operation().reachable_operations(), expected_op_ids);
+    // Operations are not reachable from their ancestors
+    assert_eq!(repo.operation().reachable_operations(), initial_op_ids);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_gc_unreachable_operations(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let op_store = repo.op_store();
+
+    // Create two concurrent operations, then drop one of them as an op head
+    let mut tx1 = repo.start_transaction(&settings, "transaction 1");
+    write_random_commit(tx1.mut_repo(), &settings);
+    let repo1 = tx1.commit();
+    let mut tx2 = repo.start_transaction(&settings, "transaction 2");
+    write_random_commit(tx2.mut_repo(), &settings);
+    let repo2 = tx2.commit();
+    let view_id1 = repo1.operation().store_operation().view_id.clone();
+    let view_id2 = repo2.operation().store_operation().view_id.clone();
+    repo.op_heads_store().remove_op_head(repo1.op_id());
+
+    // Everything was written before now, so nothing is kept for being recent
+    let loader = repo.loader();
+    assert_eq!(
+        loader.gc_unreachable_operations(SystemTime::now()).unwrap(),
+        1
+    );
+    assert_matches!(
+        op_store.read_operation(repo1.op_id()),
+        Err(OpStoreError::NotFound)
+    );
+    assert!(op_store.read_view(&view_id1).is_err());
+    assert!(op_store.read_operation(repo2.op_id()).is_ok());
+    assert!(op_store.read_view(&view_id2).is_ok());
+    let mut stored_op_ids = op_store.operation_ids(SystemTime::now()).unwrap();
+    stored_op_ids.sort();
+    assert_eq!(
+        stored_op_ids,
+        repo2
+            .operation()
+            .reachable_operations()
+            .into_iter()
+            .sorted()
+            .collect_vec()
+    );
+
+    // Nothing left to collect, and the repo still loads
+    assert_eq!(
+        loader.gc_unreachable_operations(SystemTime::now()).unwrap(),
+        0
+    );
+    let repo = loader.load_at_head(&settings).unwrap();
+    assert_eq!(repo.op_id(), repo2.op_id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_gc_keeps_unpublished_operations(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let op_store = repo.op_store();
+
+    // Write an operation without publishing it, as a concurrent process in the
+    // middle of a transaction would
+    let mut tx = repo.start_transaction(&settings, "unpublished");
+    write_random_commit(tx.mut_repo(), &settings);
+    let unpublished = tx.write();
+    let unpublished_op_id = unpublished.operation().id().clone();
+    let unpublished_view_id = unpublished.operation().store_operation().view_id.clone();
+
+    // The unpublished operation is recent, so it's kept even though no op head
+    // reaches it
+    let loader = repo.loader();
+    let keep_newer = SystemTime::now() - Duration::from_secs(60 * 60);
+    assert_eq!(loader.gc_unreachable_operations(keep_newer).unwrap(), 0);
+    assert!(op_store.read_operation(&unpublished_op_id).is_ok());
+    assert!(op_store.read_view(&unpublished_view_id).is_ok());
+
+    // Publishing it afterwards leaves the repo loadable
+    let repo = unpublished.publish();
+    let repo = repo.loader().load_at_head(&settings).unwrap();
+    assert_eq!(repo.op_id(), &unpublished_op_id);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_transaction_branch_changes(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "add commit");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    tx.mut_repo()
+        .set_local_branch("stale".to_string(), RefTarget::Normal(commit1.id().clone()));
+    let unpublished_op = tx.write();
+    assert_eq!(
+        unpublished_op.branch_changes(),
+        [
+            BranchChange {
+                name: "main".to_string(),
+                old_target: None,
+                new_target: Some(RefTarget::Normal(commit1.id().clone())),
+            },
+            BranchChange {
+                name: "stale".to_string(),
+                old_target: None,
+                new_target: Some(RefTarget::Normal(commit1.id().clone())),
+            },
+        ]
+    );
+    let repo = unpublished_op.publish();
+
+    // Committing on top of the branch advances it. Remote branches and
+    // unchanged local branches aren't reported.
+    let mut tx = repo.start_transaction(&settings, "commit");
+    let commit2 = create_random_commit(tx.mut_repo(), &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit2.id().clone()));
+    tx.mut_repo().set_remote_branch(
+        "stale".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    let unpublished_op = tx.write();
+    assert_eq!(
+        unpublished_op.branch_changes(),
+        [BranchChange {
+            name: "main".to_string(),
+            old_target: Some(RefTarget::Normal(commit1.id().clone())),
+            new_target: Some(RefTarget::Normal(commit2.id().clone())),
+        }]
+    );
+    let repo = unpublished_op.publish();
+
+    // Deleted branches are reported too
+    let mut tx = repo.start_transaction(&settings, "delete branch");
+    tx.mut_repo().remove_local_branch("main");
+    let unpublished_op = tx.write();
+    assert_eq!(
+        unpublished_op.branch_changes(),
+        [BranchChange {
+            name: "main".to_string(),
+            old_target: Some(RefTarget::Normal(commit2.id().clone())),
+            new_target: None,
+        }]
+    );
+    unpublished_op.publish();
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_transaction_dry_run(use_git: bool) {
+    // Test that a dry run reports the outcome without creating an operation
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let op_heads_dir = repo.repo_path().join("op_heads").join("heads");
+    let operations_dir = repo.repo_path().join("op_store").join("operations");
+    let num_operations = list_dir(&operations_dir).len();
+
+    let mut tx = repo.start_transaction(&settings, "dry run");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    let result = tx.dry_run();
+    assert!(result.view.heads().contains(commit1.id()));
+    assert_eq!(
+        result.branch_changes,
+        [BranchChange {
+            name: "main".to_string(),
+            old_target: None,
+            new_target: Some(RefTarget::Normal(commit1.id().clone())),
+        }]
+    );
+
+    assert_eq!(list_dir(&op_heads_dir), vec![repo.op_id().hex()]);
+    assert_eq!(list_dir(&operations_dir).len(), num_operations);
+    let reloaded_repo = repo.reload_at_head(&settings).unwrap();
+    assert_eq!(reloaded_repo.op_id(), repo.op_id());
+    assert!(!reloaded_repo.view().heads().contains(commit1.id()));
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_resolve_op_id_prefix(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo0 = &test_repo.repo;
+    let op_store = repo0.op_store();
+
+    let mut tx = repo0.start_transaction(&settings, "test");
+    write_random_commit(tx.mut_repo(), &settings);
+    let repo1 = tx.commit();
+    let op0 = repo0.operation();
+    let op1 = repo1.operation();
+    let resolve = |head_op: &Operation, op_str: &str| {
+        resolve_op_id_prefix(op_store, [head_op.id().clone()], op_str)
+    };
+
+    // A full id resolves even if it's not an ancestor of the heads
+    assert_eq!(resolve(op0, &op1.id().hex()).unwrap(), *op1);
+    // A prefix resolves only among the heads and their ancestors
+    assert_eq!(resolve(op1, &op0.id().hex()[..12]).unwrap(), *op0);
+    assert_eq!(resolve(op1, &op1.id().hex()[..12]).unwrap(), *op1);
+    assert_matches!(
+        resolve(op0, &op1.id().hex()[..12]),
+        Err(OpIdResolutionError::NoSuchOperation(_))
+    );
+    assert_matches!(
+        resolve(op1, ""),
+        Err(OpIdResolutionError::InvalidIdPrefix(_))
+    );
+    assert_matches!(
+        resolve(op1, "xyz"),
+        Err(OpIdResolutionError::InvalidIdPrefix(_))
+    );
+}
@@ -13,22 +13,29 @@
 // limitations under the License.
 
 use std::path::Path;
+use std::sync::Arc;
 
 use assert_matches::assert_matches;
 use itertools::Itertools;
-use jujutsu_lib::backend::{CommitId, MillisSinceEpoch, ObjectId, Signature, Timestamp};
+use jujutsu_lib::backend::{CommitId, MillisSinceEpoch, ObjectId, Signature, Timestamp, TreeValue};
+use jujutsu_lib::commit::Commit;
 use jujutsu_lib::default_revset_engine::{resolve_symbol, revset_for_commits};
 use jujutsu_lib::git;
+use jujutsu_lib::local_backend::LocalBackend;
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
-use jujutsu_lib::repo::Repo;
+use jujutsu_lib::repo::{ReadonlyRepo, Repo, RepoLoader, StoreFactories};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::revset::{
-    optimize, parse, ReverseRevsetGraphIterator, RevsetAliasesMap, RevsetError, RevsetExpression,
-    RevsetFilterPredicate, RevsetGraphEdge, RevsetIteratorExt, RevsetWorkspaceContext,
+    optimize, parse, parse_with_options, ReverseRevsetGraphIterator, RevsetAliasesMap, RevsetError,
+    RevsetExpression, RevsetFilterPredicate, RevsetGraphEdge, RevsetIteratorExt,
+    RevsetParseOptions, RevsetWorkspaceContext, StringPattern,
 };
-use jujutsu_lib::settings::GitSettings;
+use jujutsu_lib::settings::{GitSettings, UserSettings};
+use jujutsu_lib::tree::{merge_trees, Tree};
 use jujutsu_lib::workspace::Workspace;
+use maplit::hashmap;
 use test_case::test_case;
+use testutils::counting_backend::{CountingBackend, ReadCounts};
 use testutils::{
     create_random_commit, write_random_commit, CommitGraphBuilder, TestRepo, TestWorkspace,
 };
@@ -476,6 +483,69 @@ fn test_resolve_symbol_git_refs() {
     );
 }
 
+#[test]
+fn test_resolve_symbol_branches_case_insensitive() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    let commit3 = write_random_commit(mut_repo, &settings);
+    mut_repo.set_local_branch("Main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    mut_repo.set_remote_branch(
+        "Main".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    mut_repo.set_local_branch("Dup".to_string(), RefTarget::Normal(commit2.id().clone()));
+    mut_repo.set_local_branch("DUP".to_string(), RefTarget::Normal(commit3.id().clone()));
+    let repo = tx.commit();
+
+    // Branch names are case-sensitive by default
+    assert_matches!(
+        resolve_symbol(&repo, "main", None),
+        Err(RevsetError::NoSuchRevision(_))
+    );
+
+    let config = config::Config::builder()
+        .set_override("revsets.case-insensitive-branches", true)
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let repo = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default())
+        .unwrap()
+        .load_at_head(&settings)
+        .unwrap();
+
+    // A unique match differing in case
+    assert_eq!(
+        resolve_symbol(&repo, "main", None).unwrap(),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_symbol(&repo, "MAIN@origin", None).unwrap(),
+        vec![commit2.id().clone()]
+    );
+    // An exact match takes precedence
+    assert_eq!(
+        resolve_symbol(&repo, "Dup", None).unwrap(),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_symbol(&repo, "DUP", None).unwrap(),
+        vec![commit3.id().clone()]
+    );
+    // Multiple matches differing in case are ambiguous
+    assert_matches!(
+        resolve_symbol(&repo, "dup", None),
+        Err(RevsetError::AmbiguousBranchName(name)) if name == "dup"
+    );
+}
+
 fn resolve_commit_ids(repo: &dyn Repo, revset_str: &str) -> Vec<CommitId> {
     let expression = optimize(parse(revset_str, &RevsetAliasesMap::new(), None).unwrap());
     expression
@@ -525,6 +595,17 @@ fn test_evaluate_expression_root_and_checkout(use_git: bool) {
         resolve_commit_ids(mut_repo, "root"),
         vec![root_commit.id().clone()]
     );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "root()"),
+        vec![root_commit.id().clone()]
+    );
+    // The root commit is an ancestor of every commit, and has no parents
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "root():"),
+        resolve_commit_ids(mut_repo, "all()")
+    );
+    assert_eq!(resolve_commit_ids(mut_repo, "all() ~ root():"), vec![]);
+    assert_eq!(resolve_commit_ids(mut_repo, "root()-"), vec![]);
 
     // Can find the current working-copy commit
     mut_repo
@@ -789,6 +870,43 @@ fn test_evaluate_expression_children(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_children_hidden(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    let commit3 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("{}+", commit1.id().hex())),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+
+    // Hidden children aren't included even though they are still indexed
+    mut_repo.remove_head(commit3.id());
+    assert!(mut_repo.index().has_id(commit3.id()));
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("{}+", commit1.id().hex())),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("children({})", commit1.id().hex())),
+        vec![commit2.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_ancestors(use_git: bool) {
@@ -857,6 +975,49 @@ fn test_evaluate_expression_ancestors(use_git: bool) {
             root_commit.id().clone(),
         ]
     );
+
+    // Can select a range of generations
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("ancestors({}, 0)", commit4.id().hex())),
+        vec![commit4.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("ancestors({}, 1)", commit4.id().hex())),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("ancestors({}, 2..4)", commit4.id().hex())
+        ),
+        vec![
+            commit2.id().clone(),
+            commit1.id().clone(),
+            root_commit.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("ancestors({}, 3..)", commit4.id().hex())),
+        vec![commit1.id().clone(), root_commit.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("ancestors({}, 2..2)", commit4.id().hex())
+        ),
+        vec![]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!(
+                "ancestors({}, 0..0) & {}",
+                commit4.id().hex(),
+                commit4.id().hex()
+            )
+        ),
+        vec![]
+    );
 }
 
 #[test_case(false ; "local backend")]
@@ -1066,6 +1227,127 @@ fn test_evaluate_expression_connected(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_reachable(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let root_commit_id = repo.store().root_commit_id().clone();
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit5 = graph_builder.commit_with_parents(&[&commit3, &commit4]);
+    let commit6 = graph_builder.initial_commit();
+
+    // Nothing is reachable within an empty domain, or from empty sources
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("reachable({}, none())", commit2.id().hex())
+        ),
+        vec![]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "reachable(none(), all())"),
+        vec![]
+    );
+
+    // Sources outside the domain don't reach anything
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!(
+                "reachable({}, {} | {})",
+                commit6.id().hex(),
+                commit1.id().hex(),
+                commit2.id().hex()
+            )
+        ),
+        vec![]
+    );
+
+    // Everything is connected through the root commit
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("reachable({}, all())", commit2.id().hex())
+        ),
+        vec![
+            commit6.id().clone(),
+            commit5.id().clone(),
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+            root_commit_id,
+        ]
+    );
+
+    // A component disconnected from the sources is excluded
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("reachable({}, ~root)", commit2.id().hex())
+        ),
+        vec![
+            commit5.id().clone(),
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+
+    // Edges are followed in both directions, e.g. to reach a sibling
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!(
+                "reachable({}, {} | {} | {})",
+                commit2.id().hex(),
+                commit1.id().hex(),
+                commit2.id().hex(),
+                commit4.id().hex()
+            )
+        ),
+        vec![
+            commit4.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+
+    // Siblings aren't connected without their parent in the domain
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!(
+                "reachable({}, {} | {})",
+                commit2.id().hex(),
+                commit2.id().hex(),
+                commit4.id().hex()
+            )
+        ),
+        vec![commit2.id().clone()]
+    );
+
+    // Within the connected set, everything is reachable
+    let connected = format!("connected({} | {})", commit2.id().hex(), commit5.id().hex());
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("reachable({}, {})", commit2.id().hex(), connected)
+        ),
+        resolve_commit_ids(mut_repo, &connected)
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_descendants(use_git: bool) {
@@ -1117,21 +1399,239 @@ fn test_evaluate_expression_descendants(use_git: bool) {
             commit2.id().clone(),
         ]
     );
-}
 
-#[test_case(false ; "local backend")]
-#[test_case(true ; "git backend")]
-fn test_evaluate_expression_none(use_git: bool) {
-    let test_repo = TestRepo::init(use_git);
-    let repo = &test_repo.repo;
-
-    // none() is empty (doesn't include the checkout, for example)
-    assert_eq!(resolve_commit_ids(repo, "none()"), vec![]);
+    // Can select a range of generations. A commit is included if any of the
+    // paths to it has a length in the range.
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("descendants({}, 0)", commit1.id().hex())),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("descendants({}, 1)", commit1.id().hex())),
+        vec![commit4.id().clone(), commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("descendants({}, 2)", commit1.id().hex())),
+        vec![commit5.id().clone(), commit3.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("descendants({}, 3)", commit1.id().hex())),
+        vec![commit5.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("descendants({}, 0..2)", commit1.id().hex())
+        ),
+        vec![
+            commit4.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("descendants({}, 2..)", commit1.id().hex())
+        ),
+        vec![commit5.id().clone(), commit3.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("descendants({}, 4..)", commit1.id().hex())
+        ),
+        vec![]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("descendants({}, 2..2)", commit1.id().hex())
+        ),
+        vec![]
+    );
 }
 
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
-fn test_evaluate_expression_all(use_git: bool) {
+fn test_evaluate_expression_first_parent(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    // 6
+    // |\
+    // 5 |
+    // | 4
+    // |/|
+    // 3 |
+    // | 2
+    // |/
+    // 1
+    let root_commit_id = repo.store().root_commit_id().clone();
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    let commit3 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    let commit4 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit2.id().clone(), commit3.id().clone()])
+        .write()
+        .unwrap();
+    let commit5 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit3.id().clone()])
+        .write()
+        .unwrap();
+    let commit6 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit5.id().clone(), commit4.id().clone()])
+        .write()
+        .unwrap();
+
+    // Second parents of the merges aren't followed
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("first_ancestors({})", commit6.id().hex())
+        ),
+        vec![
+            commit6.id().clone(),
+            commit5.id().clone(),
+            commit3.id().clone(),
+            commit1.id().clone(),
+            root_commit_id.clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("first_ancestors({})", commit4.id().hex())
+        ),
+        vec![
+            commit4.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+            root_commit_id,
+        ]
+    );
+    // Shared first-parent history is included once
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!(
+                "first_ancestors({} | {}) ~ first_ancestors({})",
+                commit4.id().hex(),
+                commit5.id().hex(),
+                commit1.id().hex()
+            )
+        ),
+        vec![
+            commit5.id().clone(),
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+        ]
+    );
+
+    // A merge is a first descendant only if it's reached through its first
+    // parent
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("first_descendants({})", commit3.id().hex())
+        ),
+        vec![
+            commit6.id().clone(),
+            commit5.id().clone(),
+            commit3.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("first_descendants({})", commit2.id().hex())
+        ),
+        vec![commit4.id().clone(), commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo,
+            &format!("first_descendants({})", commit4.id().hex())
+        ),
+        vec![commit4.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_descendants_of_roots(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    let commit3 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .write()
+        .unwrap();
+    let commit4 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit3.id().clone()])
+        .write()
+        .unwrap();
+    mut_repo.remove_head(commit4.id());
+
+    let resolve = |include_hidden| -> Vec<CommitId> {
+        let mut_repo = &*mut_repo;
+        let roots = RevsetExpression::commit(commit1.id().clone());
+        let expression = RevsetExpression::descendants_of_roots(&roots, include_hidden);
+        let revset = expression.evaluate(mut_repo, None).unwrap();
+        let commit_ids = revset.iter().commit_ids().collect();
+        commit_ids
+    };
+
+    // Only visible descendants by default
+    assert_eq!(
+        resolve(false),
+        vec![commit2.id().clone(), commit1.id().clone(),]
+    );
+    // Descendants reachable from the hidden head are included on request
+    assert_eq!(
+        resolve(true),
+        vec![
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_none(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // none() is empty (doesn't include the checkout, for example)
+    assert_eq!(resolve_commit_ids(repo, "none()"), vec![]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_all(use_git: bool) {
     let settings = testutils::user_settings();
     let test_repo = TestRepo::init(use_git);
     let repo = &test_repo.repo;
@@ -1297,6 +1797,138 @@ fn test_evaluate_expression_git_head(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_evolog_and_obsolete(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    let commit3 = write_random_commit(mut_repo, &settings);
+    let repo = tx.commit();
+
+    // No commits have been rewritten yet
+    assert_eq!(resolve_commit_ids(&repo, "obsolete()"), vec![]);
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("evolog({})", commit1.id().hex())),
+        vec![commit1.id().clone()]
+    );
+
+    // Rewrite commit1 twice, then squash commit2 into it
+    let rewrite = |repo: &Arc<ReadonlyRepo>, commit: &Commit, predecessors: Vec<CommitId>| {
+        let mut tx = repo.start_transaction(&settings, "test");
+        let mut_repo = tx.mut_repo();
+        let new_commit = mut_repo
+            .rewrite_commit(&settings, commit)
+            .set_description(format!("rewritten {}", predecessors.len()))
+            .set_predecessors(predecessors.clone())
+            .write()
+            .unwrap();
+        for predecessor_id in predecessors.iter().skip(1) {
+            mut_repo.record_abandoned_commit(predecessor_id.clone());
+        }
+        mut_repo.rebase_descendants(&settings).unwrap();
+        (tx.commit(), new_commit)
+    };
+    let (repo, commit1b) = rewrite(&repo, &commit1, vec![commit1.id().clone()]);
+    let (repo, commit1c) = rewrite(&repo, &commit1b, vec![commit1b.id().clone()]);
+    let (repo, commit1d) = rewrite(
+        &repo,
+        &commit1c,
+        vec![commit1c.id().clone(), commit2.id().clone()],
+    );
+
+    // The rewritten commits are obsolete even though they're hidden
+    assert_eq!(
+        resolve_commit_ids(&repo, "obsolete()"),
+        vec![
+            commit1c.id().clone(),
+            commit1b.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(resolve_commit_ids(&repo, "obsolete() & :heads()"), vec![]);
+    // The chain is returned newest first
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("evolog({})", commit1d.id().hex())),
+        vec![
+            commit1d.id().clone(),
+            commit1c.id().clone(),
+            commit1b.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("evolog({})", commit1b.id().hex())),
+        vec![commit1b.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("evolog({})", commit3.id().hex())),
+        vec![commit3.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_op_affected(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let repo1 = tx.commit();
+    let op1_id = repo1.op_id().clone();
+
+    let mut tx = repo1.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit1]);
+    let repo2 = tx.commit();
+
+    // The current operation by default
+    assert_eq!(
+        resolve_commit_ids(&repo2, "op_affected()"),
+        vec![commit4.id().clone(), commit3.id().clone()]
+    );
+    // An earlier operation by full id or prefix
+    assert_eq!(
+        resolve_commit_ids(&repo2, &format!("op_affected({})", op1_id.hex())),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo2, &format!("op_affected({})", &op1_id.hex()[..12])),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    // Commits abandoned by an operation aren't included
+    let mut tx = repo2.start_transaction(&settings, "test");
+    tx.mut_repo().remove_head(commit4.id());
+    let repo3 = tx.commit();
+    assert_eq!(resolve_commit_ids(&repo3, "op_affected()"), vec![]);
+
+    let evaluate = |revset_str: &str| {
+        optimize(parse(revset_str, &RevsetAliasesMap::new(), None).unwrap())
+            .evaluate(&repo3, None)
+            .map(|_| ())
+    };
+    assert_matches!(
+        evaluate("op_affected(000000000000)"),
+        Err(RevsetError::NoSuchOperation(_))
+    );
+    assert_matches!(
+        evaluate("op_affected(xyz)"),
+        Err(RevsetError::NoSuchOperation(_))
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_branches(use_git: bool) {
@@ -1493,35 +2125,60 @@ fn test_evaluate_expression_remote_branches(use_git: bool) {
 
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
-fn test_evaluate_expression_merges(use_git: bool) {
+fn test_evaluate_expression_remote_branches_exact(use_git: bool) {
     let settings = testutils::user_settings();
     let test_repo = TestRepo::init(use_git);
     let repo = &test_repo.repo;
 
     let mut tx = repo.start_transaction(&settings, "test");
     let mut_repo = tx.mut_repo();
-    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
-    let commit1 = graph_builder.initial_commit();
-    let commit2 = graph_builder.initial_commit();
-    let commit3 = graph_builder.initial_commit();
-    let commit4 = graph_builder.commit_with_parents(&[&commit1, &commit2]);
-    let commit5 = graph_builder.commit_with_parents(&[&commit1, &commit2, &commit3]);
 
-    // Finds all merges by default
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    let commit3 = write_random_commit(mut_repo, &settings);
+    mut_repo.set_remote_branch(
+        "main".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    mut_repo.set_remote_branch(
+        "maintenance".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    mut_repo.set_remote_branch(
+        "main".to_string(),
+        "upstream".to_string(),
+        RefTarget::Normal(commit3.id().clone()),
+    );
+
+    // Substring matching is the default
     assert_eq!(
-        resolve_commit_ids(mut_repo, "merges()"),
-        vec![commit5.id().clone(), commit4.id().clone(),]
+        resolve_commit_ids(mut_repo, "remote_branches(main)"),
+        vec![
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone()
+        ]
     );
-    // Searches only among candidates if specified
+    // Exact matching excludes longer names
     assert_eq!(
-        resolve_commit_ids(mut_repo, &format!(":{} & merges()", commit5.id().hex())),
-        vec![commit5.id().clone()]
+        resolve_commit_ids(mut_repo, r#"remote_branches(exact:"main")"#),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, r#"remote_branches(exact:"main", remote=origin)"#),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, r#"remote_branches(exact:"mai")"#),
+        vec![]
     );
 }
 
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
-fn test_evaluate_expression_description(use_git: bool) {
+fn test_evaluate_expression_branches_ahead_behind(use_git: bool) {
     let settings = testutils::user_settings();
     let test_repo = TestRepo::init(use_git);
     let repo = &test_repo.repo;
@@ -1529,9 +2186,109 @@ fn test_evaluate_expression_description(use_git: bool) {
     let mut tx = repo.start_transaction(&settings, "test");
     let mut_repo = tx.mut_repo();
 
-    let commit1 = create_random_commit(mut_repo, &settings)
-        .set_description("commit 1")
-        .write()
+    // 4 5
+    // |/
+    // 2 3
+    // |/
+    // 1
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit5 = graph_builder.commit_with_parents(&[&commit2]);
+
+    // Nothing to compare without branches
+    assert_eq!(resolve_commit_ids(mut_repo, "branches_ahead()"), vec![]);
+    assert_eq!(resolve_commit_ids(mut_repo, "branches_behind()"), vec![]);
+
+    let mut set_branch = |name: &str, local: &CommitId, remote: &CommitId| {
+        mut_repo.set_local_branch(name.to_string(), RefTarget::Normal(local.clone()));
+        mut_repo.set_remote_branch(
+            name.to_string(),
+            "origin".to_string(),
+            RefTarget::Normal(remote.clone()),
+        );
+    };
+    set_branch("synced", commit1.id(), commit1.id());
+    set_branch("ahead", commit4.id(), commit2.id());
+    set_branch("behind", commit2.id(), commit5.id());
+    set_branch("diverged", commit3.id(), commit2.id());
+    // A local-only branch is neither ahead nor behind
+    mut_repo.set_local_branch("local".to_string(), RefTarget::Normal(commit5.id().clone()));
+
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "branches_ahead()"),
+        vec![commit4.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "branches_behind()"),
+        vec![commit2.id().clone()]
+    );
+
+    // Conflicted local targets are skipped
+    mut_repo.set_local_branch(
+        "ahead".to_string(),
+        RefTarget::Conflict {
+            removes: vec![commit2.id().clone()],
+            adds: vec![commit4.id().clone(), commit5.id().clone()],
+        },
+    );
+    assert_eq!(resolve_commit_ids(mut_repo, "branches_ahead()"), vec![]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_merges(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.initial_commit();
+    let commit3 = graph_builder.initial_commit();
+    let commit4 = graph_builder.commit_with_parents(&[&commit1, &commit2]);
+    let commit5 = graph_builder.commit_with_parents(&[&commit1, &commit2, &commit3]);
+
+    // Finds all merges by default
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "merges()"),
+        vec![commit5.id().clone(), commit4.id().clone(),]
+    );
+    // Searches only among candidates if specified
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!(":{} & merges()", commit5.id().hex())),
+        vec![commit5.id().clone()]
+    );
+    // Finds merges with at least the given number of parents
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "merges(3)"),
+        vec![commit5.id().clone()]
+    );
+    assert_eq!(resolve_commit_ids(mut_repo, "merges(4)"), vec![]);
+    // Finds merges with exactly the given number of parents
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "merges(exactly=2)"),
+        vec![commit4.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_description(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = create_random_commit(mut_repo, &settings)
+        .set_description("commit 1")
+        .write()
         .unwrap();
     let commit2 = create_random_commit(mut_repo, &settings)
         .set_parents(vec![commit1.id().clone()])
@@ -1563,6 +2320,136 @@ fn test_evaluate_expression_description(use_git: bool) {
         resolve_commit_ids(mut_repo, "heads() & description(\"commit 2\")"),
         vec![]
     );
+    // Can match a regular expression
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "description(regex:\"[12]$\")"),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "description(regex:\"^commit\")"),
+        vec![
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone()
+        ]
+    );
+    // Without the prefix, the needle is matched literally
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "description(\"^commit\")"),
+        vec![]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_reachable_from_any(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    // 3 5
+    // 2 4
+    // |/
+    // 1
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit5 = graph_builder.commit_with_parents(&[&commit4]);
+
+    let resolve = |roots_with_limits: &[(&CommitId, u32)]| -> Vec<CommitId> {
+        let mut_repo = &*mut_repo;
+        let roots_with_limits = roots_with_limits
+            .iter()
+            .map(|&(id, limit)| (RevsetExpression::commit(id.clone()), limit))
+            .collect_vec();
+        let expression = RevsetExpression::reachable_from_any(&roots_with_limits);
+        let revset = expression.evaluate(mut_repo, None).unwrap();
+        let commit_ids = revset.iter().commit_ids().collect();
+        commit_ids
+    };
+
+    assert_eq!(resolve(&[]), vec![]);
+    assert_eq!(resolve(&[(commit3.id(), 0)]), vec![]);
+    assert_eq!(resolve(&[(commit3.id(), 1)]), vec![commit3.id().clone()]);
+    // Each root is capped independently
+    assert_eq!(
+        resolve(&[(commit3.id(), 2), (commit5.id(), 1)]),
+        vec![
+            commit5.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone()
+        ]
+    );
+    // A shared ancestor is included if it's within the limit of any root
+    assert_eq!(
+        resolve(&[(commit3.id(), 1), (commit5.id(), 3)]),
+        vec![
+            commit5.id().clone(),
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit1.id().clone()
+        ]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_ancestors_matching(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = create_random_commit(mut_repo, &settings)
+        .set_description("fix 1")
+        .write()
+        .unwrap();
+    let commit2 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .set_description("feature 2")
+        .write()
+        .unwrap();
+    let commit3 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit2.id().clone()])
+        .set_description("fix 3")
+        .write()
+        .unwrap();
+    let commit4 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit3.id().clone()])
+        .set_description("fix 4")
+        .write()
+        .unwrap();
+
+    let resolve = |heads: &[&CommitId], needle: &str| -> Vec<CommitId> {
+        let mut_repo = &*mut_repo;
+        let expression = RevsetExpression::commits(heads.iter().map(|&id| id.clone()).collect())
+            .ancestors_matching(&RevsetExpression::filter(
+                RevsetFilterPredicate::Description(StringPattern::Substring(needle.to_string())),
+            ));
+        let revset = expression.evaluate(mut_repo, None).unwrap();
+        let commit_ids = revset.iter().commit_ids().collect();
+        commit_ids
+    };
+
+    // Only matching ancestors are included
+    assert_eq!(
+        resolve(&[commit3.id()], "fix"),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    // Descendants aren't included even if they match
+    assert_eq!(resolve(&[commit2.id()], "fix"), vec![commit1.id().clone()]);
+    assert_eq!(
+        resolve(&[commit4.id()], "feature"),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(resolve(&[commit4.id()], "nothing"), vec![]);
 }
 
 #[test_case(false ; "local backend")]
@@ -1624,6 +2511,22 @@ fn test_evaluate_expression_author(use_git: bool) {
         resolve_commit_ids(mut_repo, "author(\"name3\")"),
         vec![commit3.id().clone()]
     );
+    // Leading and trailing whitespace in the needle is ignored, but internal
+    // whitespace isn't
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(\" name2 \")"),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(resolve_commit_ids(mut_repo, "author(\"name 2\")"), vec![]);
+    // Can match a regular expression against either name or email
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(regex:\"^name[13]$\")"),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(regex:\"^email2$\")"),
+        vec![commit2.id().clone()]
+    );
     // Searches only among candidates if specified
     assert_eq!(
         resolve_commit_ids(mut_repo, "heads() & author(\"name2\")"),
@@ -1639,6 +2542,67 @@ fn test_evaluate_expression_author(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_author_case_insensitive(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let timestamp = Timestamp {
+        timestamp: MillisSinceEpoch(0),
+        tz_offset: 0,
+    };
+    let commit1 = create_random_commit(mut_repo, &settings)
+        .set_author(Signature {
+            name: "Zoë Ärger".to_string(),
+            email: "zoe@example.com".to_string(),
+            timestamp: timestamp.clone(),
+        })
+        .write()
+        .unwrap();
+    let commit2 = create_random_commit(mut_repo, &settings)
+        .set_parents(vec![commit1.id().clone()])
+        .set_author(Signature {
+            name: "ZOË SMITH".to_string(),
+            email: "smith@example.com".to_string(),
+            timestamp,
+        })
+        .write()
+        .unwrap();
+
+    // Matching is case-sensitive by default
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(\"Zoë\")"),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(\"smith\")"),
+        vec![commit2.id().clone()]
+    );
+    // Non-ASCII characters are case-folded too
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(i:\"zoë\")"),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(i:\"ÄRGER\")"),
+        vec![commit1.id().clone()]
+    );
+    // Leading and trailing whitespace in the needle is ignored
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "author(i:\" Smith \")"),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "description(i:\"ÄRGER\")"),
+        vec![]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_committer(use_git: bool) {
@@ -1698,6 +2662,16 @@ fn test_evaluate_expression_committer(use_git: bool) {
         resolve_commit_ids(mut_repo, "committer(\"name3\")"),
         vec![commit3.id().clone()]
     );
+    // Leading and trailing whitespace in the needle is ignored, but internal
+    // whitespace isn't
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "committer(\" name2 \")"),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "committer(\"name 2\")"),
+        vec![]
+    );
     // Searches only among candidates if specified
     assert_eq!(
         resolve_commit_ids(mut_repo, "heads() & committer(\"name2\")"),
@@ -1907,41 +2881,24 @@ fn test_evaluate_expression_difference(use_git: bool) {
 
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
-fn test_evaluate_expression_file(use_git: bool) {
+fn test_evaluate_expression_churn(use_git: bool) {
     let settings = testutils::user_settings();
-    let test_workspace = TestWorkspace::init(&settings, use_git);
-    let repo = &test_workspace.repo;
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
 
     let mut tx = repo.start_transaction(&settings, "test");
     let mut_repo = tx.mut_repo();
 
-    let added_clean_clean = RepoPath::from_internal_string("added_clean_clean");
-    let added_modified_clean = RepoPath::from_internal_string("added_modified_clean");
-    let added_modified_removed = RepoPath::from_internal_string("added_modified_removed");
-    let tree1 = testutils::create_tree(
-        repo,
-        &[
-            (&added_clean_clean, "1"),
-            (&added_modified_clean, "1"),
-            (&added_modified_removed, "1"),
-        ],
-    );
-    let tree2 = testutils::create_tree(
-        repo,
-        &[
-            (&added_clean_clean, "1"),
-            (&added_modified_clean, "2"),
-            (&added_modified_removed, "2"),
-        ],
-    );
-    let tree3 = testutils::create_tree(
-        repo,
-        &[
-            (&added_clean_clean, "1"),
-            (&added_modified_clean, "2"),
-            // added_modified_removed,
-        ],
-    );
+    let file1 = RepoPath::from_internal_string("file1");
+    let file2 = RepoPath::from_internal_string("file2");
+    let many_lines = (0..100).map(|i| format!("line {i}\n")).join("");
+    // Adds 100 lines
+    let tree1 = testutils::create_tree(repo, &[(&file1, &many_lines)]);
+    // Modifies 1 line (1 removed, 1 added) and adds a 2-line file
+    let modified_lines = many_lines.replace("line 50\n", "line fifty\n");
+    let tree2 = testutils::create_tree(repo, &[(&file1, &modified_lines), (&file2, "a\nb\n")]);
+    // Removes the 2-line file
+    let tree3 = testutils::create_tree(repo, &[(&file1, &modified_lines)]);
     let commit1 = mut_repo
         .new_commit(
             &settings,
@@ -1963,10 +2920,118 @@ fn test_evaluate_expression_file(use_git: bool) {
         .write()
         .unwrap();
 
-    let resolve = |file_path: &RepoPath| -> Vec<CommitId> {
-        let mut_repo = &*mut_repo;
-        let expression =
-            RevsetExpression::filter(RevsetFilterPredicate::File(Some(vec![file_path.clone()])));
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "churn(100)"),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "churn(4)"),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "churn(2, 4)"),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+    assert_eq!(resolve_commit_ids(mut_repo, "churn(5, 99)"), vec![]);
+    // Empty commits (and the root) have no churn
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "churn(0, 0)"),
+        vec![commit4.id().clone(), repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("{}: & churn(1)", commit2.id().hex())),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+
+    // A merge is compared to its first parent, so the lines merged in from the
+    // other parent count
+    let file3 = RepoPath::from_internal_string("file3");
+    let tree5 = testutils::create_tree(repo, &[(&file3, "x\ny\nz\n")]);
+    let tree6 = testutils::create_tree(repo, &[(&file1, &many_lines), (&file3, "x\ny\nz\n")]);
+    let commit5 = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree5.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit6 = mut_repo
+        .new_commit(
+            &settings,
+            vec![commit1.id().clone(), commit5.id().clone()],
+            tree6.id().clone(),
+        )
+        .write()
+        .unwrap();
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("{} & churn(3, 3)", commit6.id().hex())),
+        vec![commit6.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_file(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let added_clean_clean = RepoPath::from_internal_string("added_clean_clean");
+    let added_modified_clean = RepoPath::from_internal_string("added_modified_clean");
+    let added_modified_removed = RepoPath::from_internal_string("added_modified_removed");
+    let tree1 = testutils::create_tree(
+        repo,
+        &[
+            (&added_clean_clean, "1"),
+            (&added_modified_clean, "1"),
+            (&added_modified_removed, "1"),
+        ],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&added_clean_clean, "1"),
+            (&added_modified_clean, "2"),
+            (&added_modified_removed, "2"),
+        ],
+    );
+    let tree3 = testutils::create_tree(
+        repo,
+        &[
+            (&added_clean_clean, "1"),
+            (&added_modified_clean, "2"),
+            // added_modified_removed,
+        ],
+    );
+    let commit1 = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree1.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit2 = mut_repo
+        .new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+        .write()
+        .unwrap();
+    let commit3 = mut_repo
+        .new_commit(&settings, vec![commit2.id().clone()], tree3.id().clone())
+        .write()
+        .unwrap();
+    let commit4 = mut_repo
+        .new_commit(&settings, vec![commit3.id().clone()], tree3.id().clone())
+        .write()
+        .unwrap();
+
+    let resolve = |file_path: &RepoPath| -> Vec<CommitId> {
+        let mut_repo = &*mut_repo;
+        let expression =
+            RevsetExpression::filter(RevsetFilterPredicate::File(Some(vec![file_path.clone()])));
         let revset = expression.evaluate(mut_repo, None).unwrap();
         let commit_ids = revset.iter().commit_ids().collect();
         commit_ids
@@ -2006,6 +3071,117 @@ fn test_evaluate_expression_file(use_git: bool) {
         vec![commit2.id().clone()]
     );
 
+    // file() parsed without workspace is resolved at evaluation time
+    let deferred_file_options = RevsetParseOptions {
+        defer_file_patterns: true,
+        ..Default::default()
+    };
+    let expression = optimize(
+        parse_with_options(
+            r#"file("repo/added_clean_clean")"#,
+            &RevsetAliasesMap::new(),
+            &deferred_file_options,
+        )
+        .unwrap(),
+    );
+    let workspace = &test_workspace.workspace;
+    let workspace_ctx = RevsetWorkspaceContext {
+        cwd: workspace.workspace_root().parent().unwrap(),
+        workspace_id: workspace.workspace_id(),
+        workspace_root: workspace.workspace_root(),
+    };
+    assert_eq!(
+        expression
+            .evaluate(mut_repo, Some(&workspace_ctx))
+            .unwrap()
+            .iter()
+            .commit_ids()
+            .collect_vec(),
+        vec![commit1.id().clone()]
+    );
+    assert_matches!(
+        expression.evaluate(mut_repo, None).map(|_| ()),
+        Err(RevsetError::FsPathWithoutWorkspace)
+    );
+    let expression = optimize(
+        parse_with_options(
+            r#"file("../outside")"#,
+            &RevsetAliasesMap::new(),
+            &deferred_file_options,
+        )
+        .unwrap(),
+    );
+    assert_matches!(
+        expression
+            .evaluate(mut_repo, Some(&workspace_ctx))
+            .map(|_| ()),
+        Err(RevsetError::FsPathParseError(_))
+    );
+
+    // exact: only matches the file itself, not a directory of the same name or
+    // other files with the same prefix
+    let lib_file = RepoPath::from_internal_string("src/lib.rs");
+    let backup_file = RepoPath::from_internal_string("src/lib.rs.bak");
+    let nested_file = RepoPath::from_internal_string("src/lib.rs/nested");
+    let tree5 = testutils::create_tree(repo, &[(&lib_file, "1"), (&backup_file, "1")]);
+    let tree6 = testutils::create_tree(repo, &[(&lib_file, "1"), (&backup_file, "2")]);
+    let tree7 = testutils::create_tree(repo, &[(&nested_file, "1"), (&backup_file, "2")]);
+    let tree8 = testutils::create_tree(repo, &[(&nested_file, "2"), (&backup_file, "2")]);
+    let commit5 = mut_repo
+        .new_commit(&settings, vec![commit4.id().clone()], tree5.id().clone())
+        .write()
+        .unwrap();
+    let commit6 = mut_repo
+        .new_commit(&settings, vec![commit5.id().clone()], tree6.id().clone())
+        .write()
+        .unwrap();
+    let commit7 = mut_repo
+        .new_commit(&settings, vec![commit6.id().clone()], tree7.id().clone())
+        .write()
+        .unwrap();
+    let commit8 = mut_repo
+        .new_commit(&settings, vec![commit7.id().clone()], tree8.id().clone())
+        .write()
+        .unwrap();
+    let resolve_descendants = |revset_str: &str| {
+        resolve_commit_ids_in_workspace(
+            mut_repo,
+            &format!("{}: & ({})", commit5.id().hex(), revset_str),
+            &test_workspace.workspace,
+            None,
+        )
+    };
+    assert_eq!(
+        resolve_descendants(r#"file(exact:"src/lib.rs")"#),
+        vec![commit7.id().clone(), commit5.id().clone()]
+    );
+    assert_eq!(
+        resolve_descendants(r#"file(exact:"src/lib.rs/nested")"#),
+        vec![commit8.id().clone(), commit7.id().clone()]
+    );
+    assert_eq!(
+        resolve_descendants(r#"file(exact:"src/lib.rs.bak")"#),
+        vec![commit6.id().clone(), commit5.id().clone()]
+    );
+    assert_eq!(resolve_descendants(r#"file(exact:"src")"#), vec![]);
+    assert_eq!(
+        resolve_descendants(r#"file("src/lib.rs")"#),
+        vec![
+            commit8.id().clone(),
+            commit7.id().clone(),
+            commit5.id().clone()
+        ]
+    );
+    assert_eq!(
+        resolve_descendants(r#"file("src/lib.rs.bak", exact:"src/lib.rs/nested")"#),
+        vec![
+            commit8.id().clone(),
+            commit7.id().clone(),
+            commit6.id().clone(),
+            commit5.id().clone()
+        ]
+    );
+
     // empty() revset, which is identical to ~file(".")
     assert_eq!(
         resolve_commit_ids(mut_repo, &format!("{}: & empty()", commit1.id().hex())),
@@ -2013,6 +3189,245 @@ fn test_evaluate_expression_file(use_git: bool) {
     );
 }
 
+#[test]
+fn test_evaluate_expression_empty_compares_tree_ids() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let file = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(repo, &[(&file, "contents")]);
+    let commit1 = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit2 = mut_repo
+        .new_commit(&settings, vec![commit1.id().clone()], tree.id().clone())
+        .write()
+        .unwrap();
+    tx.commit();
+
+    // Load the repo again so no trees are cached in the store
+    let read_counts = Arc::new(ReadCounts::default());
+    let mut store_factories = StoreFactories::default();
+    store_factories.add_backend("local", {
+        let read_counts = read_counts.clone();
+        Box::new(move |store_path| {
+            Box::new(CountingBackend::new(
+                Box::new(LocalBackend::load(store_path)),
+                read_counts.clone(),
+            ))
+        })
+    });
+    let repo = RepoLoader::init(&settings, repo.repo_path(), &store_factories)
+        .unwrap()
+        .load_at_head(&settings)
+        .unwrap();
+
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("{} & empty()", commit2.id().hex())),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(read_counts.trees(), 0);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_file_content(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let file1 = RepoPath::from_internal_string("file1");
+    let file2 = RepoPath::from_internal_string("dir/file2");
+    let tree1 = testutils::create_tree(repo, &[(&file1, "shared")]);
+    let tree2 = testutils::create_tree(repo, &[(&file1, "other"), (&file2, "shared")]);
+    let tree3 = testutils::create_tree(repo, &[(&file1, "other")]);
+    let commit1 = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree1.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit2 = mut_repo
+        .new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+        .write()
+        .unwrap();
+    let commit3 = mut_repo
+        .new_commit(&settings, vec![commit2.id().clone()], tree3.id().clone())
+        .write()
+        .unwrap();
+
+    let file_id = |tree: &Tree, path: &RepoPath| match tree.path_value(path) {
+        Some(TreeValue::File { id, .. }) => id,
+        value => panic!("unexpected tree value: {value:?}"),
+    };
+    let shared_id = file_id(&tree1, &file1);
+    assert_eq!(file_id(&tree2, &file2), shared_id);
+    let other_id = file_id(&tree3, &file1);
+
+    // Both commits containing the blob match, regardless of its path
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("file_content({})", shared_id.hex())),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, &format!("file_content({})", other_id.hex())),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_conflict(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let file1 = RepoPath::from_internal_string("file1");
+    let base_tree = testutils::create_tree(repo, &[(&file1, "base")]);
+    let side1_tree = testutils::create_tree(repo, &[(&file1, "side 1")]);
+    let side2_tree = testutils::create_tree(repo, &[(&file1, "side 2")]);
+    let merged_tree_id = merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let commit1 = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            base_tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit2 = mut_repo
+        .new_commit(
+            &settings,
+            vec![commit1.id().clone()],
+            side1_tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit3 = mut_repo
+        .new_commit(
+            &settings,
+            vec![commit1.id().clone()],
+            side2_tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+    // A merge with conflicts in its tree
+    let commit4 = mut_repo
+        .new_commit(
+            &settings,
+            vec![commit2.id().clone(), commit3.id().clone()],
+            merged_tree_id,
+        )
+        .write()
+        .unwrap();
+    // A merge with the conflicts resolved
+    let commit5 = mut_repo
+        .new_commit(
+            &settings,
+            vec![commit2.id().clone(), commit3.id().clone()],
+            side1_tree.id().clone(),
+        )
+        .write()
+        .unwrap();
+
+    assert!(commit4.tree().has_conflict());
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "conflict()"),
+        vec![commit4.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo, "merges() ~ conflict()"),
+        vec![commit5.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_divergent(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    let commit2 = write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+    assert_eq!(repo.divergent_change_ids(), hashmap! {});
+    assert_eq!(resolve_commit_ids(&repo, "divergent()"), vec![]);
+
+    // Rewrite commit1 in two concurrent operations
+    let mut tx1 = repo.start_transaction(&settings, "test");
+    let commit1_a = tx1
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("a")
+        .write()
+        .unwrap();
+    tx1.mut_repo().rebase_descendants(&settings).unwrap();
+    tx1.commit();
+    let mut tx2 = repo.start_transaction(&settings, "test");
+    let commit1_b = tx2
+        .mut_repo()
+        .rewrite_commit(&settings, &commit1)
+        .set_description("b")
+        .write()
+        .unwrap();
+    tx2.mut_repo().rebase_descendants(&settings).unwrap();
+    tx2.commit();
+    let repo = repo.reload_at_head(&settings).unwrap();
+
+    let divergent_ids = [commit1_a.id().clone(), commit1_b.id().clone()]
+        .into_iter()
+        .sorted()
+        .collect_vec();
+    // The hidden commit1 doesn't count
+    assert_eq!(
+        repo.divergent_change_ids(),
+        hashmap! {commit1.change_id().clone() => divergent_ids.clone()}
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo, "divergent()")
+            .into_iter()
+            .sorted()
+            .collect_vec(),
+        divergent_ids
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("divergent() & {}", commit1_a.id().hex())),
+        vec![commit1_a.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(&repo, &format!("divergent() & {}", commit2.id().hex())),
+        vec![]
+    );
+
+    // Abandoning one side resolves the divergence
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    assert_eq!(mut_repo.divergent_change_ids().len(), 1);
+    mut_repo
+        .abandon_commit(&settings, commit1_b.id().clone())
+        .unwrap();
+    assert_eq!(mut_repo.divergent_change_ids(), hashmap! {});
+    assert_eq!(resolve_commit_ids(mut_repo, "divergent()"), vec![]);
+}
+
 #[test]
 fn test_reverse_graph_iterator() {
     let settings = testutils::user_settings();
@@ -2073,3 +3488,92 @@ fn test_reverse_graph_iterator() {
     assert_eq!(commits[3].1, vec![RevsetGraphEdge::direct(pos_f)]);
     assert_eq!(commits[4].1, vec![]);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_explained(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    let commit3 = write_random_commit(mut_repo, &settings);
+    mut_repo.set_local_branch(
+        "branch1".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    mut_repo.set_local_branch(
+        "branch2".to_string(),
+        RefTarget::Conflict {
+            removes: vec![commit1.id().clone()],
+            adds: vec![commit2.id().clone(), commit3.id().clone()],
+        },
+    );
+
+    let expression = optimize(
+        parse(
+            "branch1 | branch2- | present(branch3)",
+            &RevsetAliasesMap::new(),
+            None,
+        )
+        .unwrap(),
+    );
+    let (revset, resolved) = expression.evaluate_explained(mut_repo, None).unwrap();
+    assert_eq!(
+        revset.iter().commit_ids().collect_vec(),
+        vec![commit1.id().clone(), repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        resolved,
+        hashmap! {
+            "branch1".to_string() => vec![commit1.id().clone()],
+            "branch2".to_string() => vec![commit2.id().clone(), commit3.id().clone()],
+        }
+    );
+
+    // Resolution errors are propagated
+    let expression = parse("branch1 | branch3", &RevsetAliasesMap::new(), None).unwrap();
+    assert_matches!(
+        expression.evaluate_explained(mut_repo, None).err(),
+        Some(RevsetError::NoSuchRevision(_))
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_resolve_symbols(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = write_random_commit(mut_repo, &settings);
+    let commit2 = write_random_commit(mut_repo, &settings);
+    mut_repo.set_local_branch(
+        "branch1".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    mut_repo.set_tag("tag2".to_string(), RefTarget::Normal(commit2.id().clone()));
+
+    let mut aliases_map = RevsetAliasesMap::new();
+    aliases_map.insert("A", "branch1 | missing").unwrap();
+    let expression = parse("(A & tag2-) | heads(tag2 | branch1)", &aliases_map, None).unwrap();
+    assert_eq!(
+        expression.collect_symbols(),
+        vec!["branch1", "missing", "tag2"]
+    );
+    // Symbols that don't exist are omitted
+    assert_eq!(
+        expression.resolve_symbols(mut_repo, None).unwrap(),
+        hashmap! {
+            "branch1".to_string() => vec![commit1.id().clone()],
+            "tag2".to_string() => vec![commit2.id().clone()],
+        }
+    );
+}
@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
+
+use itertools::Itertools;
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
 use jujutsu_lib::repo::Repo;
 use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::rewrite::DescendantRebaser;
+use jujutsu_lib::rewrite::{squash_commit, DescendantRebaser, RebaseUntilConflict};
 use maplit::{hashmap, hashset};
 use test_case::test_case;
 use testutils::{
@@ -487,6 +490,48 @@ fn test_rebase_descendants_abandon_and_replace(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rebase_descendants_merge_parents_squashed(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Commits B and C were both replaced by commit E (e.g. squashed together).
+    // Commit D should get rebased to have only E as parent (not E twice).
+    //
+    //   D
+    //   |\
+    // E B C
+    // |/ /
+    // A-
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_b, &commit_c]);
+    let commit_e = graph_builder.commit_with_parents(&[&commit_a]);
+
+    let mut rebaser = DescendantRebaser::new(
+        &settings,
+        tx.mut_repo(),
+        hashmap! {
+            commit_b.id().clone() => hashset!{commit_e.id().clone()},
+            commit_c.id().clone() => hashset!{commit_e.id().clone()},
+        },
+        hashset! {},
+    );
+    let new_commit_d = assert_rebased(rebaser.rebase_next().unwrap(), &commit_d, &[&commit_e]);
+    assert!(rebaser.rebase_next().unwrap().is_none());
+    assert_eq!(rebaser.rebased().len(), 1);
+
+    assert_eq!(
+        *tx.mut_repo().view().heads(),
+        hashset! {new_commit_d.id().clone()}
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_rebase_descendants_abandon_degenerate_merge(use_git: bool) {
@@ -917,6 +962,279 @@ fn test_rebase_descendants_contents(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rebase_descendants_until_conflict(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Commit A was rewritten to change the file that commit C also changes.
+    // Rebasing commit B should succeed, but rebasing commit C should stop with a
+    // conflict. Commit D should be rebased when continuing.
+    //
+    // D
+    // C
+    // B
+    // A
+    let path1 = RepoPath::from_internal_string("file1");
+    let path2 = RepoPath::from_internal_string("file2");
+    let path3 = RepoPath::from_internal_string("file3");
+    let tree_a = testutils::create_tree(repo, &[(&path1, "a")]);
+    let tree_b = testutils::create_tree(repo, &[(&path1, "a"), (&path2, "b")]);
+    let tree_c = testutils::create_tree(repo, &[(&path1, "c"), (&path2, "b")]);
+    let tree_d = testutils::create_tree(repo, &[(&path1, "c"), (&path2, "b"), (&path3, "d")]);
+    let tree_a2 = testutils::create_tree(repo, &[(&path1, "a2")]);
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit_a = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree_a.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit_b = mut_repo
+        .new_commit(&settings, vec![commit_a.id().clone()], tree_b.id().clone())
+        .write()
+        .unwrap();
+    let commit_c = mut_repo
+        .new_commit(&settings, vec![commit_b.id().clone()], tree_c.id().clone())
+        .write()
+        .unwrap();
+    let commit_d = mut_repo
+        .new_commit(&settings, vec![commit_c.id().clone()], tree_d.id().clone())
+        .write()
+        .unwrap();
+    mut_repo
+        .rewrite_commit(&settings, &commit_a)
+        .set_tree(tree_a2.id().clone())
+        .write()
+        .unwrap();
+
+    let mut rebaser = mut_repo.create_descendant_rebaser(&settings);
+    let conflict = match rebaser.rebase_until_conflict().unwrap() {
+        RebaseUntilConflict::Conflict(conflict) => conflict,
+        result => panic!("unexpected result: {result:?}"),
+    };
+    assert_eq!(conflict.old_commit, commit_c);
+    assert_eq!(conflict.conflicted_paths, vec![path1]);
+    assert!(conflict.new_commit.tree().has_conflict());
+    assert_eq!(rebaser.rebased().len(), 2);
+    assert!(rebaser.rebased().contains_key(commit_b.id()));
+
+    // Commit D already had the conflict in its parent, so continuing doesn't stop
+    // again
+    assert_eq!(
+        rebaser.rebase_until_conflict().unwrap(),
+        RebaseUntilConflict::Done(3)
+    );
+    let new_commit_d = repo
+        .store()
+        .get_commit(rebaser.rebased().get(commit_d.id()).unwrap())
+        .unwrap();
+    assert_eq!(
+        new_commit_d.parent_ids(),
+        &[conflict.new_commit.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rebase_descendants_progress_callback(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Commit A was replaced by commit A2. Commits B, C, and D should be rebased,
+    // with B first since it's the parent of the others.
+    //
+    // C D
+    // |/
+    // B
+    // A A2
+    // |/
+    // root
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_b]);
+    let commit_a2 = graph_builder.initial_commit();
+
+    let mut calls = vec![];
+    {
+        let mut rebaser = DescendantRebaser::new(
+            &settings,
+            tx.mut_repo(),
+            hashmap! {
+                commit_a.id().clone() => hashset!{commit_a2.id().clone()},
+            },
+            hashset! {},
+        );
+        rebaser.set_progress_callback(|id, current, total| {
+            calls.push((id.clone(), current, total));
+        });
+        rebaser.rebase_all().unwrap();
+    }
+
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0], (commit_b.id().clone(), 1, 3));
+    assert_eq!(
+        calls[1..]
+            .iter()
+            .map(|(_id, current, total)| (*current, *total))
+            .collect_vec(),
+        vec![(2, 3), (3, 3)]
+    );
+    assert_eq!(
+        calls[1..]
+            .iter()
+            .map(|(id, _current, _total)| id.clone())
+            .collect::<HashSet<_>>(),
+        hashset! {commit_c.id().clone(), commit_d.id().clone()}
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_squash_commit_into_parent(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Commit B is squashed into commit A. Commit C should be rebased onto the
+    // new commit A, which should have the changes from both A and B.
+    //
+    // C
+    // B
+    // A
+    let path1 = RepoPath::from_internal_string("file1");
+    let path2 = RepoPath::from_internal_string("file2");
+    let path3 = RepoPath::from_internal_string("file3");
+    let tree_a = testutils::create_tree(repo, &[(&path1, "a")]);
+    let tree_b = testutils::create_tree(repo, &[(&path1, "b"), (&path2, "b")]);
+    let tree_c = testutils::create_tree(repo, &[(&path1, "b"), (&path2, "b"), (&path3, "c")]);
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit_a = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree_a.id().clone(),
+        )
+        .set_description("A")
+        .write()
+        .unwrap();
+    let commit_b = mut_repo
+        .new_commit(&settings, vec![commit_a.id().clone()], tree_b.id().clone())
+        .set_description("B")
+        .write()
+        .unwrap();
+    let commit_c = mut_repo
+        .new_commit(&settings, vec![commit_b.id().clone()], tree_c.id().clone())
+        .write()
+        .unwrap();
+
+    let new_commit_a = squash_commit(&settings, mut_repo, &commit_b, &commit_a).unwrap();
+    assert_eq!(new_commit_a.change_id(), commit_a.change_id());
+    assert_eq!(new_commit_a.parent_ids(), commit_a.parent_ids());
+    assert_eq!(new_commit_a.tree_id(), tree_b.id());
+    assert_eq!(new_commit_a.description(), "A");
+    assert_eq!(
+        new_commit_a.predecessor_ids(),
+        &[commit_a.id().clone(), commit_b.id().clone()]
+    );
+
+    let heads = mut_repo.view().heads().clone();
+    assert_eq!(heads.len(), 1);
+    let new_commit_c = repo
+        .store()
+        .get_commit(heads.iter().next().unwrap())
+        .unwrap();
+    assert_eq!(new_commit_c.change_id(), commit_c.change_id());
+    assert_eq!(new_commit_c.parent_ids(), &[new_commit_a.id().clone()]);
+    assert_eq!(new_commit_c.tree_id(), tree_c.id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_squash_commit_into_sibling(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Commit B is squashed into commit D. Commit C should be rebased onto the new
+    // commit D, which should have the changes from both B and D.
+    //
+    // C
+    // B D
+    // |/
+    // A
+    let path1 = RepoPath::from_internal_string("file1");
+    let path2 = RepoPath::from_internal_string("file2");
+    let path3 = RepoPath::from_internal_string("file3");
+    let path4 = RepoPath::from_internal_string("file4");
+    let tree_a = testutils::create_tree(repo, &[(&path1, "a")]);
+    let tree_b = testutils::create_tree(repo, &[(&path1, "a"), (&path2, "b")]);
+    let tree_c = testutils::create_tree(repo, &[(&path1, "a"), (&path2, "b"), (&path3, "c")]);
+    let tree_d = testutils::create_tree(repo, &[(&path1, "a"), (&path4, "d")]);
+    let mut tx = repo.start_transaction(&settings, "test");
+    let mut_repo = tx.mut_repo();
+    let commit_a = mut_repo
+        .new_commit(
+            &settings,
+            vec![repo.store().root_commit_id().clone()],
+            tree_a.id().clone(),
+        )
+        .write()
+        .unwrap();
+    let commit_b = mut_repo
+        .new_commit(&settings, vec![commit_a.id().clone()], tree_b.id().clone())
+        .set_description("B")
+        .write()
+        .unwrap();
+    let commit_c = mut_repo
+        .new_commit(&settings, vec![commit_b.id().clone()], tree_c.id().clone())
+        .write()
+        .unwrap();
+    let commit_d = mut_repo
+        .new_commit(&settings, vec![commit_a.id().clone()], tree_d.id().clone())
+        .write()
+        .unwrap();
+
+    let new_commit_d = squash_commit(&settings, mut_repo, &commit_b, &commit_d).unwrap();
+    assert_eq!(new_commit_d.parent_ids(), &[commit_a.id().clone()]);
+    assert_eq!(
+        *new_commit_d.tree_id(),
+        testutils::create_tree(repo, &[(&path1, "a"), (&path2, "b"), (&path4, "d")])
+            .id()
+            .clone()
+    );
+    // The destination had no description, so it takes the squashed commit's
+    assert_eq!(new_commit_d.description(), "B");
+
+    let heads = mut_repo.view().heads().clone();
+    assert_eq!(heads.len(), 1);
+    let new_commit_c = repo
+        .store()
+        .get_commit(heads.iter().next().unwrap())
+        .unwrap();
+    assert_eq!(new_commit_c.change_id(), commit_c.change_id());
+    assert_eq!(new_commit_c.parent_ids(), &[new_commit_d.id().clone()]);
+    assert_eq!(
+        *new_commit_c.tree_id(),
+        testutils::create_tree(
+            repo,
+            &[(&path1, "a"), (&path2, "b"), (&path3, "c"), (&path4, "d")]
+        )
+        .id()
+        .clone()
+    );
+}
+
 #[test]
 fn test_rebase_descendants_basic_branch_update() {
     let settings = testutils::user_settings();
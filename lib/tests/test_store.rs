@@ -0,0 +1,192 @@
+// Copyright 2023 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use itertools::Itertools;
+use jujutsu_lib::backend::{CommitId, ObjectId};
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::repo::{Repo, RepoLoader, StoreFactories};
+use jujutsu_lib::revset::{RevsetExpression, RevsetIteratorExt};
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::store::{CommitCacheStats, Store, DEFAULT_COMMIT_CACHE_CAPACITY};
+use test_case::test_case;
+use testutils::counting_backend::{CountingBackend, ReadCounts};
+use testutils::{write_random_commit, TestRepo};
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_get_commits(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    let commit2 = write_random_commit(tx.mut_repo(), &settings);
+    let commit3 = write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+
+    // Load the repo again so nothing is cached, then read one of the commits so
+    // the batch includes both cached and uncached commits
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    let store = loader.store();
+    store.get_commit(commit2.id()).unwrap();
+    let ids = vec![
+        commit3.id().clone(),
+        store.root_commit_id().clone(),
+        commit2.id().clone(),
+        commit1.id().clone(),
+        commit3.id().clone(),
+    ];
+    let commits = store.get_commits(&ids).unwrap();
+    assert_eq!(
+        commits.iter().map(|commit| commit.id()).collect_vec(),
+        ids.iter().collect_vec()
+    );
+    for commit in &commits {
+        assert_eq!(
+            commit.store_commit(),
+            store.get_commit(commit.id()).unwrap().store_commit()
+        );
+    }
+    assert_eq!(store.get_commits(&[]).unwrap(), vec![]);
+
+    let missing_id = CommitId::new(vec![1; store.commit_id_length()]);
+    assert!(store
+        .get_commits(&[commit1.id().clone(), missing_id])
+        .is_err());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_revset_commits_prefetch(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    for _ in 0..5 {
+        write_random_commit(tx.mut_repo(), &settings);
+    }
+    let repo = tx.commit();
+
+    let revset = RevsetExpression::all().evaluate(&repo, None).unwrap();
+    let expected = revset
+        .iter()
+        .commits(repo.store())
+        .map(Result::unwrap)
+        .collect_vec();
+    assert_eq!(expected.len(), 6);
+    for batch_size in [1, 2, 4, 10] {
+        let commits = revset
+            .iter()
+            .commits(repo.store())
+            .prefetch(batch_size)
+            .map(Result::unwrap)
+            .collect_vec();
+        assert_eq!(commits, expected, "batch size {batch_size}");
+    }
+}
+
+#[test]
+fn test_commit_cache() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction(&settings, "test");
+    let commit1 = write_random_commit(tx.mut_repo(), &settings);
+    let commit2 = write_random_commit(tx.mut_repo(), &settings);
+    let commit3 = write_random_commit(tx.mut_repo(), &settings);
+    let repo = tx.commit();
+
+    let counts = Arc::new(ReadCounts::default());
+    let backend = CountingBackend::new(
+        Box::new(LocalBackend::load(&repo.repo_path().join("store"))),
+        counts.clone(),
+    );
+    let store = Store::with_commit_cache_capacity(Box::new(backend), 2);
+    assert_eq!(
+        store.commit_cache_stats(),
+        CommitCacheStats {
+            hits: 0,
+            misses: 0,
+            len: 0,
+            capacity: 2
+        }
+    );
+
+    // The second read is served from the cache
+    store.get_commit(commit1.id()).unwrap();
+    assert_eq!(counts.commits(), 1);
+    store.get_commit(commit1.id()).unwrap();
+    assert_eq!(counts.commits(), 1);
+    assert_eq!(
+        store.commit_cache_stats(),
+        CommitCacheStats {
+            hits: 1,
+            misses: 1,
+            len: 1,
+            capacity: 2
+        }
+    );
+
+    // Reading more commits than fit in the cache evicts the least recently used
+    // one
+    store.get_commit(commit2.id()).unwrap();
+    store.get_commit(commit3.id()).unwrap();
+    assert_eq!(counts.commits(), 3);
+    store.get_commit(commit3.id()).unwrap();
+    assert_eq!(counts.commits(), 3);
+    store.get_commit(commit1.id()).unwrap();
+    assert_eq!(counts.commits(), 4);
+    assert_eq!(store.commit_cache_stats().len, 2);
+}
+
+#[test]
+fn test_commit_cache_capacity_config() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    // The default capacity is used if it isn't configured
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    assert_eq!(
+        loader.store().commit_cache_stats().capacity,
+        DEFAULT_COMMIT_CACHE_CAPACITY
+    );
+
+    let config = config::Config::builder()
+        .set_override("store.commit-cache-capacity", 5)
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    assert_eq!(loader.store().commit_cache_stats().capacity, 5);
+
+    // Invalid values fall back to the default
+    let config = config::Config::builder()
+        .set_override("store.commit-cache-capacity", 0)
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &StoreFactories::default()).unwrap();
+    assert_eq!(
+        loader.store().commit_cache_stats().capacity,
+        DEFAULT_COMMIT_CACHE_CAPACITY
+    );
+}
@@ -0,0 +1,131 @@
+// Copyright 2023 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+use jujutsu_lib::repo::Repo;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::tree::Tree;
+use test_case::test_case;
+use testutils::TestRepo;
+
+fn tree_paths(tree: &Tree) -> Vec<String> {
+    tree.entries()
+        .map(|(path, _value)| path.to_internal_file_string())
+        .collect_vec()
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_remove_subtree(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let base_tree = testutils::create_tree(
+        repo,
+        &[
+            (&RepoPath::from_internal_string("dir/file1"), "1"),
+            (&RepoPath::from_internal_string("dir/sub/file2"), "2"),
+            (&RepoPath::from_internal_string("dirfile"), "3"),
+            (&RepoPath::from_internal_string("other/file4"), "4"),
+        ],
+    );
+
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir"));
+    assert!(tree_builder.has_overrides());
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(tree_paths(&tree), vec!["dirfile", "other/file4"]);
+
+    // Removing a nested directory keeps the rest of the parent directory
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir/sub"));
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(
+        tree_paths(&tree),
+        vec!["dir/file1", "dirfile", "other/file4"]
+    );
+
+    // Removing a directory that doesn't exist is a no-op
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("missing"));
+    assert_eq!(tree_builder.write_tree(), *base_tree.id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_remove_subtree_then_set(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let file1_path = RepoPath::from_internal_string("dir/file1");
+    let file2_path = RepoPath::from_internal_string("dir/sub/file2");
+    let new_path = RepoPath::from_internal_string("dir/sub/new");
+    let base_tree = testutils::create_tree(repo, &[(&file1_path, "1"), (&file2_path, "2")]);
+
+    // Files set after removing the directory are kept, while the other files
+    // in the base tree are removed
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir"));
+    testutils::write_normal_file(&mut tree_builder, &new_path, "new");
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(tree_paths(&tree), vec!["dir/sub/new"]);
+
+    // Files set before removing the directory are removed too
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    testutils::write_normal_file(&mut tree_builder, &new_path, "new");
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir"));
+    let tree_id = tree_builder.write_tree();
+    assert_eq!(tree_id, *store.empty_tree_id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_remove_subtree_then_set_parent(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let dir_path = RepoPath::from_internal_string("dir");
+    let file1_path = RepoPath::from_internal_string("dir/file1");
+    let file2_path = RepoPath::from_internal_string("dir/sub/file2");
+    let other_path = RepoPath::from_internal_string("other");
+    let base_tree = testutils::create_tree(
+        repo,
+        &[(&file1_path, "1"), (&file2_path, "2"), (&other_path, "3")],
+    );
+
+    // Replacing the parent directory with a file discards the removal of the
+    // nested directory
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir/sub"));
+    testutils::write_normal_file(&mut tree_builder, &file1_path, "new");
+    testutils::write_normal_file(&mut tree_builder, &dir_path, "dir");
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(tree_paths(&tree), vec!["dir", "other"]);
+
+    // So does removing the parent directory
+    let mut tree_builder = store.tree_builder(base_tree.id().clone());
+    tree_builder.remove_subtree(&RepoPath::from_internal_string("dir/sub"));
+    testutils::write_normal_file(&mut tree_builder, &file1_path, "new");
+    tree_builder.remove(dir_path.clone());
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(tree_paths(&tree), vec!["other"]);
+}
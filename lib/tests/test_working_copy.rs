@@ -30,7 +30,9 @@ use jujutsu_lib::repo::{ReadonlyRepo, Repo};
 use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::tree_builder::TreeBuilder;
-use jujutsu_lib::working_copy::WorkingCopy;
+use jujutsu_lib::working_copy::{
+    FileStateProvider, FullScan, LocalWorkingCopy, RemovedFile, WorkingCopy,
+};
 use test_case::test_case;
 use testutils::{write_random_commit, TestWorkspace};
 
@@ -296,7 +298,9 @@ fn test_reset() {
 
     // Test the setup: the file should exist on disk and in the tree state.
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
-    assert!(wc.file_states().contains_key(&ignored_path));
+    assert!(testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&ignored_path));
 
     // After we reset to the commit without the file, it should still exist on disk,
     // but it should not be in the tree state, and it should not get added when we
@@ -305,7 +309,9 @@ fn test_reset() {
     locked_wc.reset(&tree_without_file).unwrap();
     locked_wc.finish(repo.op_id().clone());
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
-    assert!(!wc.file_states().contains_key(&ignored_path));
+    assert!(!testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
     let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
     assert_eq!(new_tree_id, *tree_without_file.id());
@@ -318,7 +324,9 @@ fn test_reset() {
     locked_wc.reset(&tree_without_file).unwrap();
     locked_wc.finish(repo.op_id().clone());
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
-    assert!(!wc.file_states().contains_key(&ignored_path));
+    assert!(!testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
     let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
     assert_eq!(new_tree_id, *tree_without_file.id());
@@ -330,7 +338,9 @@ fn test_reset() {
     locked_wc.reset(&tree_with_file).unwrap();
     locked_wc.finish(repo.op_id().clone());
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
-    assert!(wc.file_states().contains_key(&ignored_path));
+    assert!(testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
     let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
     assert_eq!(new_tree_id, *tree_with_file.id());
@@ -360,7 +370,9 @@ fn test_checkout_discard() {
 
     // Test the setup: the file should exist on disk and in the tree state.
     assert!(file1_path.to_fs_path(&workspace_root).is_file());
-    assert!(wc.file_states().contains_key(&file1_path));
+    assert!(testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&file1_path));
 
     // Start a checkout
     let mut locked_wc = wc.start_mutation();
@@ -368,17 +380,22 @@ fn test_checkout_discard() {
     // The change should be reflected in the working copy but not saved
     assert!(!file1_path.to_fs_path(&workspace_root).is_file());
     assert!(file2_path.to_fs_path(&workspace_root).is_file());
-    let reloaded_wc = WorkingCopy::load(store.clone(), workspace_root.clone(), state_path.clone());
+    let reloaded_wc =
+        LocalWorkingCopy::load(store.clone(), workspace_root.clone(), state_path.clone());
     assert!(reloaded_wc.file_states().contains_key(&file1_path));
     assert!(!reloaded_wc.file_states().contains_key(&file2_path));
     locked_wc.discard();
 
     // The change should remain in the working copy, but not in memory and not saved
-    assert!(wc.file_states().contains_key(&file1_path));
-    assert!(!wc.file_states().contains_key(&file2_path));
+    assert!(testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&file1_path));
+    assert!(!testutils::as_local_working_copy(wc)
+        .file_states()
+        .contains_key(&file2_path));
     assert!(!file1_path.to_fs_path(&workspace_root).is_file());
     assert!(file2_path.to_fs_path(&workspace_root).is_file());
-    let reloaded_wc = WorkingCopy::load(store.clone(), workspace_root, state_path);
+    let reloaded_wc = LocalWorkingCopy::load(store.clone(), workspace_root, state_path);
     assert!(reloaded_wc.file_states().contains_key(&file1_path));
     assert!(!reloaded_wc.file_states().contains_key(&file2_path));
 }
@@ -415,6 +432,88 @@ fn test_snapshot_racy_timestamps(use_git: bool) {
     }
 }
 
+#[cfg(unix)]
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_executable_bit(use_git: bool) {
+    // Tests that the executable bit is written on checkout and that a change to
+    // only the executable bit is detected by snapshot
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = test_workspace.repo.clone();
+    let store = repo.store();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let path = RepoPath::from_internal_string("file");
+    let disk_path = path.to_fs_path(&workspace_root);
+    let file_mode = || disk_path.metadata().unwrap().permissions().mode() & 0o777;
+    let snapshot = |wc: &mut dyn WorkingCopy| {
+        let mut locked_wc = wc.start_mutation();
+        let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+        locked_wc.finish(repo.op_id().clone());
+        store.get_tree(&RepoPath::root(), &tree_id).unwrap()
+    };
+
+    let id = testutils::write_file(store, &path, "contents");
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    tree_builder.set(
+        path.clone(),
+        TreeValue::File {
+            id: id.clone(),
+            executable: true,
+        },
+    );
+    let executable_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    tree_builder.set(
+        path.clone(),
+        TreeValue::File {
+            id: id.clone(),
+            executable: false,
+        },
+    );
+    let normal_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+
+    // Checking out sets the mode, including when only the executable bit changed
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &executable_tree)
+        .unwrap();
+    assert_eq!(file_mode(), 0o755);
+    wc.check_out(repo.op_id().clone(), None, &normal_tree)
+        .unwrap();
+    assert_eq!(file_mode(), 0o644);
+    wc.check_out(repo.op_id().clone(), None, &executable_tree)
+        .unwrap();
+    assert_eq!(file_mode(), 0o755);
+    assert_eq!(snapshot(wc).id(), executable_tree.id());
+
+    // Changing only the executable bit on disk results in a new tree
+    std::fs::set_permissions(&disk_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let tree = snapshot(wc);
+    assert_eq!(tree.id(), normal_tree.id());
+    assert_eq!(
+        tree.path_value(&path),
+        Some(TreeValue::File {
+            id: id.clone(),
+            executable: false,
+        })
+    );
+    std::fs::set_permissions(&disk_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let tree = snapshot(wc);
+    assert_eq!(tree.id(), executable_tree.id());
+    assert_eq!(
+        tree.path_value(&path),
+        Some(TreeValue::File {
+            id,
+            executable: true,
+        })
+    );
+}
+
 #[cfg(unix)]
 #[test]
 fn test_snapshot_special_file() {
@@ -449,7 +548,11 @@ fn test_snapshot_special_file() {
         vec![file1_path.clone(), file2_path.clone()]
     );
     assert_eq!(
-        wc.file_states().keys().cloned().collect_vec(),
+        testutils::as_local_working_copy(wc)
+            .file_states()
+            .keys()
+            .cloned()
+            .collect_vec(),
         vec![file1_path, file2_path.clone()]
     );
 
@@ -466,7 +569,11 @@ fn test_snapshot_special_file() {
         vec![file2_path.clone()]
     );
     assert_eq!(
-        wc.file_states().keys().cloned().collect_vec(),
+        testutils::as_local_working_copy(wc)
+            .file_states()
+            .keys()
+            .cloned()
+            .collect_vec(),
         vec![file2_path]
     );
 }
@@ -545,6 +652,193 @@ fn test_gitignores(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_gitignores_removed_files(use_git: bool) {
+    // Tests that removed files are reported as ignored if they're now matched by
+    // a .gitignore file
+
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let gitignore_path = RepoPath::from_internal_string(".gitignore");
+    let removed_path = RepoPath::from_internal_string("removed");
+    let removed_ignored_path = RepoPath::from_internal_string("removed-ignored");
+    let subdir_gitignore_path = RepoPath::from_internal_string("dir/.gitignore");
+    let subdir_removed_ignored_path = RepoPath::from_internal_string("dir/removed-ignored");
+    let deleted_dir_ignored_path = RepoPath::from_internal_string("deleted-dir/file");
+
+    testutils::write_working_copy_file(&workspace_root, &gitignore_path, "");
+    testutils::write_working_copy_file(&workspace_root, &removed_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &removed_ignored_path, "1");
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &subdir_gitignore_path, "");
+    testutils::write_working_copy_file(&workspace_root, &subdir_removed_ignored_path, "1");
+    std::fs::create_dir(workspace_root.join("deleted-dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &deleted_dir_ignored_path, "1");
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    let (_tree_id, stats) = locked_wc
+        .snapshot_with_stats(GitIgnoreFile::empty(), &FullScan)
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    assert_eq!(stats.removed_files, vec![]);
+
+    // Remove the files, and make some of them ignored at the same time
+    testutils::write_working_copy_file(
+        &workspace_root,
+        &gitignore_path,
+        "removed-ignored\ndeleted-dir/\n",
+    );
+    testutils::write_working_copy_file(
+        &workspace_root,
+        &subdir_gitignore_path,
+        "removed-ignored\n",
+    );
+    std::fs::remove_file(removed_path.to_fs_path(&workspace_root)).unwrap();
+    std::fs::remove_file(removed_ignored_path.to_fs_path(&workspace_root)).unwrap();
+    std::fs::remove_file(subdir_removed_ignored_path.to_fs_path(&workspace_root)).unwrap();
+    std::fs::remove_dir_all(workspace_root.join("deleted-dir")).unwrap();
+
+    let mut locked_wc = wc.start_mutation();
+    let (_tree_id, stats) = locked_wc
+        .snapshot_with_stats(GitIgnoreFile::empty(), &FullScan)
+        .unwrap();
+    locked_wc.discard();
+    assert_eq!(
+        stats.removed_files,
+        vec![
+            RemovedFile {
+                path: deleted_dir_ignored_path,
+                ignored: true,
+            },
+            RemovedFile {
+                path: subdir_removed_ignored_path,
+                ignored: true,
+            },
+            RemovedFile {
+                path: removed_path,
+                ignored: false,
+            },
+            RemovedFile {
+                path: removed_ignored_path,
+                ignored: true,
+            },
+        ]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_snapshot_file_state_provider(use_git: bool) {
+    // Tests that only the paths reported by the FileStateProvider are scanned
+
+    struct FakeFileStateProvider(Vec<RepoPath>);
+    impl FileStateProvider for FakeFileStateProvider {
+        fn changed_paths(&self) -> Option<Vec<RepoPath>> {
+            Some(self.0.clone())
+        }
+    }
+
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("file2");
+    let subdir_file_path = RepoPath::from_internal_string("dir/file");
+    let subdir_new_path = RepoPath::from_internal_string("dir/new");
+    testutils::write_working_copy_file(&workspace_root, &file1_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "1");
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &subdir_file_path, "1");
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let expected_tree = testutils::create_tree(
+        &repo,
+        &[
+            (&file1_path, "1"),
+            (&file2_path, "1"),
+            (&subdir_file_path, "1"),
+        ],
+    );
+    assert_eq!(tree_id, *expected_tree.id());
+
+    testutils::write_working_copy_file(&workspace_root, &file1_path, "2");
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "2");
+    std::fs::remove_file(subdir_file_path.to_fs_path(&workspace_root)).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &subdir_new_path, "2");
+
+    // Only the change to the reported file is picked up
+    let mut locked_wc = wc.start_mutation();
+    let (tree_id, stats) = locked_wc
+        .snapshot_with_stats(
+            GitIgnoreFile::empty(),
+            &FakeFileStateProvider(vec![file1_path.clone()]),
+        )
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let expected_tree = testutils::create_tree(
+        &repo,
+        &[
+            (&file1_path, "2"),
+            (&file2_path, "1"),
+            (&subdir_file_path, "1"),
+        ],
+    );
+    assert_eq!(tree_id, *expected_tree.id());
+    assert_eq!(stats.removed_files, vec![]);
+
+    // Everything in a reported directory is scanned
+    let mut locked_wc = wc.start_mutation();
+    let (tree_id, stats) = locked_wc
+        .snapshot_with_stats(
+            GitIgnoreFile::empty(),
+            &FakeFileStateProvider(vec![RepoPath::from_internal_string("dir")]),
+        )
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let expected_tree = testutils::create_tree(
+        &repo,
+        &[
+            (&file1_path, "2"),
+            (&file2_path, "1"),
+            (&subdir_new_path, "2"),
+        ],
+    );
+    assert_eq!(tree_id, *expected_tree.id());
+    assert_eq!(
+        stats.removed_files,
+        vec![RemovedFile {
+            path: subdir_file_path,
+            ignored: false,
+        }]
+    );
+
+    // A full scan picks up the remaining change
+    let mut locked_wc = wc.start_mutation();
+    let (tree_id, _stats) = locked_wc
+        .snapshot_with_stats(GitIgnoreFile::empty(), &FullScan)
+        .unwrap();
+    locked_wc.discard();
+    let expected_tree = testutils::create_tree(
+        &repo,
+        &[
+            (&file1_path, "2"),
+            (&file2_path, "2"),
+            (&subdir_new_path, "2"),
+        ],
+    );
+    assert_eq!(tree_id, *expected_tree.id());
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_gitignores_checkout_never_overwrites_ignored(use_git: bool) {
@@ -724,6 +1018,77 @@ fn test_gitsubmodule() {
     );
 }
 
+#[cfg(unix)]
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_symlink_roundtrip(use_git: bool) {
+    // Tests that symlinks are snapshotted as symlinks and checked out as symlinks
+    // again
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = test_workspace.repo.clone();
+    let store = repo.store();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = RepoPath::from_internal_string("dir/file");
+    let file_link_path = RepoPath::from_internal_string("file-link");
+    let dir_link_path = RepoPath::from_internal_string("dir-link");
+    let dangling_link_path = RepoPath::from_internal_string("dir/dangling-link");
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &file_path, "contents");
+    let links = [
+        (&file_link_path, "dir/file"),
+        (&dir_link_path, "dir"),
+        (&dangling_link_path, "../missing"),
+    ];
+    for (path, target) in links {
+        std::os::unix::fs::symlink(target, path.to_fs_path(&workspace_root)).unwrap();
+    }
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    for (path, target) in links {
+        match tree.path_value(path) {
+            Some(TreeValue::Symlink(id)) => {
+                assert_eq!(store.read_symlink(path, &id).unwrap(), target);
+            }
+            value => panic!("unexpected value for {path:?}: {value:?}"),
+        }
+    }
+
+    // Remove everything from the working copy and check out the tree again
+    let empty_tree = store
+        .get_tree(&RepoPath::root(), store.empty_tree_id())
+        .unwrap();
+    wc.check_out(repo.op_id().clone(), None, &empty_tree)
+        .unwrap();
+    for (path, _target) in links {
+        assert!(path.to_fs_path(&workspace_root).symlink_metadata().is_err());
+    }
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    for (path, target) in links {
+        let disk_path = path.to_fs_path(&workspace_root);
+        assert!(disk_path
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            std::fs::read_link(&disk_path).unwrap(),
+            std::path::Path::new(target)
+        );
+    }
+
+    // The working copy is clean after the checkout
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, tree_id);
+}
+
 #[cfg(unix)]
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
@@ -20,7 +20,7 @@ use jujutsu_lib::gitignore::GitIgnoreFile;
 use jujutsu_lib::repo::{Repo, StoreFactories};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::working_copy::CheckoutError;
-use jujutsu_lib::workspace::Workspace;
+use jujutsu_lib::workspace::{WorkingCopyFactories, Workspace};
 use test_case::test_case;
 use testutils::TestWorkspace;
 
@@ -57,8 +57,13 @@ fn test_concurrent_checkout(use_git: bool) {
 
     // Check out tree2 from another process (simulated by another workspace
     // instance)
-    let mut workspace2 =
-        Workspace::load(&settings, &workspace1_root, &StoreFactories::default()).unwrap();
+    let mut workspace2 = Workspace::load(
+        &settings,
+        &workspace1_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
     workspace2
         .working_copy_mut()
         .check_out(repo1.op_id().clone(), Some(&tree_id1), &tree2)
@@ -71,8 +76,13 @@ fn test_concurrent_checkout(use_git: bool) {
     );
 
     // Check that the tree2 is still checked out on disk.
-    let workspace3 =
-        Workspace::load(&settings, &workspace1_root, &StoreFactories::default()).unwrap();
+    let workspace3 = Workspace::load(
+        &settings,
+        &workspace1_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
     assert_eq!(workspace3.working_copy().current_tree_id(), &tree_id2);
 }
 
@@ -114,8 +124,13 @@ fn test_checkout_parallel(use_git: bool) {
         let settings = settings.clone();
         let workspace_root = workspace_root.clone();
         let handle = thread::spawn(move || {
-            let mut workspace =
-                Workspace::load(&settings, &workspace_root, &StoreFactories::default()).unwrap();
+            let mut workspace = Workspace::load(
+                &settings,
+                &workspace_root,
+                &StoreFactories::default(),
+                &WorkingCopyFactories::default(),
+            )
+            .unwrap();
             let tree = workspace
                 .repo_loader()
                 .store()
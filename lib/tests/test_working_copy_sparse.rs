@@ -17,7 +17,9 @@ use jujutsu_lib::gitignore::GitIgnoreFile;
 use jujutsu_lib::matchers::EverythingMatcher;
 use jujutsu_lib::repo::Repo;
 use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::working_copy::{CheckoutStats, WorkingCopy};
+use jujutsu_lib::working_copy::{
+    CheckoutStats, FileStateProvider, FullScan, LocalWorkingCopy, WorkingCopy,
+};
 use testutils::TestWorkspace;
 
 #[test]
@@ -79,13 +81,16 @@ fn test_sparse_checkout() {
     // Write the new state to disk
     locked_wc.finish(repo.op_id().clone());
     assert_eq!(
-        wc.file_states().keys().collect_vec(),
+        testutils::as_local_working_copy(wc)
+            .file_states()
+            .keys()
+            .collect_vec(),
         vec![&dir1_file1_path, &dir1_file2_path, &dir1_subdir1_file1_path]
     );
     assert_eq!(wc.sparse_patterns(), sparse_patterns);
 
     // Reload the state to check that it was persisted
-    let mut wc = WorkingCopy::load(
+    let mut wc = LocalWorkingCopy::load(
         repo.store().clone(),
         wc.working_copy_path().to_path_buf(),
         wc.state_path().to_path_buf(),
@@ -238,3 +243,88 @@ fn test_sparse_commit_gitignore() {
     assert_eq!(entries.len(), 1);
     assert_eq!(entries[0].0, dir1_file2_path);
 }
+
+#[test]
+fn test_sparse_snapshot_excluded_paths() {
+    // Test that paths outside the sparse patterns are kept in the tree but are
+    // neither written on checkout nor reported as deleted on snapshot
+    struct FakeFileStateProvider(Vec<RepoPath>);
+    impl FileStateProvider for FakeFileStateProvider {
+        fn changed_paths(&self) -> Option<Vec<RepoPath>> {
+            Some(self.0.clone())
+        }
+    }
+
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let working_copy_path = test_workspace.workspace.workspace_root().clone();
+
+    let src_path = RepoPath::from_internal_string("src");
+    let src_file_path = RepoPath::from_internal_string("src/main.rs");
+    let docs_path = RepoPath::from_internal_string("docs");
+    let docs_file_path = RepoPath::from_internal_string("docs/index.md");
+    let docs_subdir_file_path = RepoPath::from_internal_string("docs/guide/intro.md");
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    locked_wc
+        .set_sparse_patterns(vec![src_path.clone()])
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+
+    let tree = testutils::create_tree(
+        repo,
+        &[
+            (&src_file_path, "main"),
+            (&docs_file_path, "index"),
+            (&docs_subdir_file_path, "intro"),
+        ],
+    );
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    assert_eq!(
+        stats,
+        CheckoutStats {
+            updated_files: 0,
+            added_files: 1,
+            removed_files: 0
+        }
+    );
+    assert!(src_file_path.to_fs_path(&working_copy_path).is_file());
+    assert!(!docs_path.to_fs_path(&working_copy_path).exists());
+    assert_eq!(
+        testutils::as_local_working_copy(wc)
+            .file_states()
+            .keys()
+            .collect_vec(),
+        vec![&src_file_path]
+    );
+
+    // The excluded files are not considered deleted, whether the whole working
+    // copy is scanned or only the excluded paths are
+    let file_state_providers: [&dyn FileStateProvider; 3] = [
+        &FullScan,
+        &FakeFileStateProvider(vec![docs_path.clone()]),
+        &FakeFileStateProvider(vec![docs_file_path.clone(), docs_subdir_file_path.clone()]),
+    ];
+    for file_state_provider in file_state_providers {
+        let mut locked_wc = wc.start_mutation();
+        let (tree_id, stats) = locked_wc
+            .snapshot_with_stats(GitIgnoreFile::empty(), file_state_provider)
+            .unwrap();
+        locked_wc.discard();
+        assert_eq!(tree_id, *tree.id());
+        assert_eq!(stats.removed_files, vec![]);
+    }
+
+    // Deleting an included file is still detected
+    std::fs::remove_file(src_file_path.to_fs_path(&working_copy_path)).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(
+        new_tree.entries().map(|(path, _value)| path).collect_vec(),
+        vec![docs_subdir_file_path, docs_file_path]
+    );
+}
@@ -12,10 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::any::Any;
+use std::path::Path;
+
 use assert_matches::assert_matches;
-use jujutsu_lib::op_store::WorkspaceId;
-use jujutsu_lib::repo::{Repo, StoreFactories};
-use jujutsu_lib::workspace::{Workspace, WorkspaceLoadError};
+use jujutsu_lib::backend::TreeId;
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::op_store::{OperationId, WorkspaceId};
+use jujutsu_lib::repo::{ReadonlyRepo, Repo, StoreFactories};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::working_copy::{LocalWorkingCopy, LockedWorkingCopy, WorkingCopy};
+use jujutsu_lib::workspace::{
+    WorkingCopyFactories, WorkingCopyLoadError, Workspace, WorkspaceLoadError,
+};
 use test_case::test_case;
 use testutils::TestWorkspace;
 
@@ -25,7 +34,12 @@ fn test_load_bad_path() {
     let temp_dir = testutils::new_temp_dir();
     let workspace_root = temp_dir.path().to_owned();
     // We haven't created a repo in the workspace_root, so it should fail to load.
-    let result = Workspace::load(&settings, &workspace_root, &StoreFactories::default());
+    let result = Workspace::load(
+        &settings,
+        &workspace_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    );
     assert_matches!(
         result.err(),
         Some(WorkspaceLoadError::NoWorkspaceHere(root)) if root == workspace_root
@@ -47,6 +61,7 @@ fn test_init_additional_workspace(use_git: bool) {
         &ws2_root,
         &test_workspace.repo,
         ws2_id.clone(),
+        Workspace::default_working_copy_initializer(),
     )
     .unwrap();
     let wc_commit_id = repo.view().get_wc_commit_id(&ws2_id);
@@ -63,7 +78,12 @@ fn test_init_additional_workspace(use_git: bool) {
         workspace.repo_path().canonicalize().unwrap()
     );
     assert_eq!(*ws2.workspace_root(), ws2_root.canonicalize().unwrap());
-    let same_workspace = Workspace::load(&settings, &ws2_root, &StoreFactories::default());
+    let same_workspace = Workspace::load(
+        &settings,
+        &ws2_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    );
     assert!(same_workspace.is_ok());
     let same_workspace = same_workspace.unwrap();
     assert_eq!(same_workspace.workspace_id(), &ws2_id);
@@ -73,3 +93,133 @@ fn test_init_additional_workspace(use_git: bool) {
     );
     assert_eq!(same_workspace.workspace_root(), ws2.workspace_root());
 }
+
+/// Working copy that stores its state like `LocalWorkingCopy`, but is of
+/// another type.
+struct DummyWorkingCopy(LocalWorkingCopy);
+
+impl WorkingCopy for DummyWorkingCopy {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "dummy"
+    }
+
+    fn working_copy_path(&self) -> &Path {
+        self.0.working_copy_path()
+    }
+
+    fn state_path(&self) -> &Path {
+        self.0.state_path()
+    }
+
+    fn workspace_id(&self) -> &WorkspaceId {
+        self.0.workspace_id()
+    }
+
+    fn operation_id(&self) -> &OperationId {
+        self.0.operation_id()
+    }
+
+    fn current_tree_id(&self) -> &TreeId {
+        self.0.current_tree_id()
+    }
+
+    fn sparse_patterns(&self) -> &[RepoPath] {
+        self.0.sparse_patterns()
+    }
+
+    fn start_mutation(&mut self) -> Box<dyn LockedWorkingCopy + '_> {
+        self.0.start_mutation()
+    }
+}
+
+#[test]
+fn test_init_and_load_custom_working_copy() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let workspace_root = temp_dir.path().to_owned();
+
+    // The working copy is created by the initializer, and its type is recorded
+    let (workspace, _repo) = Workspace::init_with_factories(
+        &settings,
+        &workspace_root,
+        |store_path| Box::new(LocalBackend::init(store_path)),
+        ReadonlyRepo::default_op_store_factory(),
+        ReadonlyRepo::default_op_heads_store_factory(),
+        ReadonlyRepo::default_index_store_factory(),
+        |store, working_copy_path, state_path, operation_id, workspace_id| {
+            Box::new(DummyWorkingCopy(LocalWorkingCopy::init(
+                store,
+                working_copy_path,
+                state_path,
+                operation_id,
+                workspace_id,
+            )))
+        },
+    )
+    .unwrap();
+    assert_eq!(workspace.working_copy().name(), "dummy");
+    let type_path = workspace_root.join(".jj").join("working_copy").join("type");
+    assert_eq!(std::fs::read_to_string(&type_path).unwrap(), "dummy");
+
+    // Loading a workspace with an unknown working-copy type fails
+    let result = Workspace::load(
+        &settings,
+        &workspace_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    );
+    assert_matches!(
+        result.err(),
+        Some(WorkspaceLoadError::WorkingCopyLoadError(
+            WorkingCopyLoadError::UnsupportedType(working_copy_type)
+        )) if working_copy_type == "dummy"
+    );
+
+    // The registered factory is used to load the working copy
+    let mut working_copy_factories = WorkingCopyFactories::empty();
+    working_copy_factories.add_working_copy(
+        "dummy",
+        Box::new(|store, workspace_root, state_path| {
+            Box::new(DummyWorkingCopy(LocalWorkingCopy::load(
+                store,
+                workspace_root,
+                state_path,
+            )))
+        }),
+    );
+    let workspace = Workspace::load(
+        &settings,
+        &workspace_root,
+        &StoreFactories::default(),
+        &working_copy_factories,
+    )
+    .unwrap();
+    assert!(workspace.working_copy().as_any().is::<DummyWorkingCopy>());
+    assert_eq!(workspace.workspace_id(), &WorkspaceId::default());
+}
+
+#[test]
+fn test_load_working_copy_without_type() {
+    // Workspaces created before the working-copy type was recorded are loaded
+    // as local ones, and the type is recorded
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let type_path = workspace_root.join(".jj").join("working_copy").join("type");
+    assert_eq!(std::fs::read_to_string(&type_path).unwrap(), "local");
+    std::fs::remove_file(&type_path).unwrap();
+
+    let workspace = Workspace::load(
+        &settings,
+        &workspace_root,
+        &StoreFactories::default(),
+        &WorkingCopyFactories::default(),
+    )
+    .unwrap();
+    assert_eq!(workspace.working_copy().name(), "local");
+    assert_eq!(std::fs::read_to_string(&type_path).unwrap(), "local");
+}
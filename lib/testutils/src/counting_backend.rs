@@ -0,0 +1,130 @@
+// Copyright 2023 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use jujutsu_lib::backend::{
+    Backend, BackendResult, ChangeId, Commit, CommitId, Conflict, ConflictId, FileId, SymlinkId,
+    Tree, TreeId,
+};
+use jujutsu_lib::repo_path::RepoPath;
+
+/// Number of objects read through a `CountingBackend`.
+#[derive(Debug, Default)]
+pub struct ReadCounts {
+    commits: AtomicUsize,
+    trees: AtomicUsize,
+}
+
+impl ReadCounts {
+    pub fn commits(&self) -> usize {
+        self.commits.load(Ordering::SeqCst)
+    }
+
+    pub fn trees(&self) -> usize {
+        self.trees.load(Ordering::SeqCst)
+    }
+}
+
+/// Backend that delegates to another backend and counts the commits and trees
+/// read from it. The counts can be shared with the test, so they can be
+/// checked after the backend has been moved into a `Store`.
+#[derive(Debug)]
+pub struct CountingBackend {
+    inner: Box<dyn Backend>,
+    counts: Arc<ReadCounts>,
+}
+
+impl CountingBackend {
+    pub fn new(inner: Box<dyn Backend>, counts: Arc<ReadCounts>) -> Self {
+        CountingBackend { inner, counts }
+    }
+}
+
+impl Backend for CountingBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn commit_id_length(&self) -> usize {
+        self.inner.commit_id_length()
+    }
+
+    fn change_id_length(&self) -> usize {
+        self.inner.change_id_length()
+    }
+
+    fn git_repo(&self) -> Option<git2::Repository> {
+        self.inner.git_repo()
+    }
+
+    fn read_file(&self, path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
+        self.inner.read_file(path, id)
+    }
+
+    fn write_file(&self, path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
+        self.inner.write_file(path, contents)
+    }
+
+    fn read_symlink(&self, path: &RepoPath, id: &SymlinkId) -> BackendResult<String> {
+        self.inner.read_symlink(path, id)
+    }
+
+    fn write_symlink(&self, path: &RepoPath, target: &str) -> BackendResult<SymlinkId> {
+        self.inner.write_symlink(path, target)
+    }
+
+    fn root_commit_id(&self) -> &CommitId {
+        self.inner.root_commit_id()
+    }
+
+    fn root_change_id(&self) -> &ChangeId {
+        self.inner.root_change_id()
+    }
+
+    fn empty_tree_id(&self) -> &TreeId {
+        self.inner.empty_tree_id()
+    }
+
+    fn read_tree(&self, path: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
+        self.counts.trees.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_tree(path, id)
+    }
+
+    fn write_tree(&self, path: &RepoPath, contents: &Tree) -> BackendResult<TreeId> {
+        self.inner.write_tree(path, contents)
+    }
+
+    fn read_conflict(&self, path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
+        self.inner.read_conflict(path, id)
+    }
+
+    fn write_conflict(&self, path: &RepoPath, contents: &Conflict) -> BackendResult<ConflictId> {
+        self.inner.write_conflict(path, contents)
+    }
+
+    fn read_commit(&self, id: &CommitId) -> BackendResult<Commit> {
+        self.counts.commits.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_commit(id)
+    }
+
+    // `read_commits()` isn't forwarded so that batch reads are counted per
+    // commit through `read_commit()`.
+
+    fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId> {
+        self.inner.write_commit(contents)
+    }
+}
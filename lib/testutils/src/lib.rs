@@ -31,9 +31,12 @@ use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::store::Store;
 use jujutsu_lib::tree::Tree;
 use jujutsu_lib::tree_builder::TreeBuilder;
+use jujutsu_lib::working_copy::{LocalWorkingCopy, WorkingCopy};
 use jujutsu_lib::workspace::Workspace;
 use tempfile::TempDir;
 
+pub mod counting_backend;
+
 pub fn hermetic_libgit2() {
     // libgit2 respects init.defaultBranch (and possibly other config
     // variables) in the user's config files. Disable access to them to make
@@ -238,6 +241,12 @@ pub fn write_random_commit(mut_repo: &mut MutableRepo, settings: &UserSettings)
     create_random_commit(mut_repo, settings).write().unwrap()
 }
 
+/// Returns the working copy of a workspace created by `TestWorkspace`, which is
+/// always a `LocalWorkingCopy`.
+pub fn as_local_working_copy(wc: &dyn WorkingCopy) -> &LocalWorkingCopy {
+    wc.as_any().downcast_ref().unwrap()
+}
+
 pub fn write_working_copy_file(workspace_root: &Path, path: &RepoPath, contents: &str) {
     let mut file = OpenOptions::new()
         .write(true)
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::HashSet;
 use std::env::{self, ArgsOs, VarError};
 use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
@@ -36,8 +36,8 @@ use jujutsu_lib::gitignore::GitIgnoreFile;
 use jujutsu_lib::hex_util::to_reverse_hex;
 use jujutsu_lib::matchers::{EverythingMatcher, Matcher, PrefixMatcher, Visit};
 use jujutsu_lib::op_heads_store::{self, OpHeadResolutionError, OpHeadsStore};
-use jujutsu_lib::op_store::{OpStore, OpStoreError, OperationId, RefTarget, WorkspaceId};
-use jujutsu_lib::operation::Operation;
+use jujutsu_lib::op_store::{OpStore, OperationId, RefTarget, WorkspaceId};
+use jujutsu_lib::operation::{self, OpIdResolutionError, Operation};
 use jujutsu_lib::repo::{
     CheckOutCommitError, EditCommitError, MutableRepo, ReadonlyRepo, Repo, RepoLoader,
     RewriteRootCommit, StoreFactories, StoreLoadError,
@@ -53,7 +53,10 @@ use jujutsu_lib::tree::{Tree, TreeMergeError};
 use jujutsu_lib::working_copy::{
     CheckoutStats, LockedWorkingCopy, ResetError, SnapshotError, WorkingCopy,
 };
-use jujutsu_lib::workspace::{Workspace, WorkspaceInitError, WorkspaceLoadError, WorkspaceLoader};
+use jujutsu_lib::workspace::{
+    WorkingCopyFactories, WorkingCopyLoadError, Workspace, WorkspaceInitError, WorkspaceLoadError,
+    WorkspaceLoader,
+};
 use jujutsu_lib::{dag_walk, file_util, git, revset};
 use thiserror::Error;
 use toml_edit;
@@ -62,11 +65,13 @@ use tracing_subscriber::prelude::*;
 use crate::config::{
     config_path, AnnotatedValue, CommandNameAndArgs, ConfigSource, LayeredConfigs,
 };
-use crate::formatter::{FormatRecorder, Formatter, PlainTextFormatter};
+use crate::formatter::{
+    validate_color_config, ColorChoice, FormatRecorder, Formatter, PlainTextFormatter,
+};
 use crate::merge_tools::{ConflictResolveError, DiffEditError};
 use crate::template_parser::{TemplateAliasesMap, TemplateParseError};
 use crate::templater::Template;
-use crate::ui::{ColorChoice, Ui};
+use crate::ui::Ui;
 use crate::{commit_templater, text_util};
 
 #[derive(Clone, Debug)]
@@ -312,6 +317,7 @@ pub struct CommandHelper {
     layered_configs: LayeredConfigs,
     maybe_workspace_loader: Result<WorkspaceLoader, CommandError>,
     store_factories: StoreFactories,
+    working_copy_factories: WorkingCopyFactories,
 }
 
 impl CommandHelper {
@@ -325,6 +331,7 @@ impl CommandHelper {
         layered_configs: LayeredConfigs,
         maybe_workspace_loader: Result<WorkspaceLoader, CommandError>,
         store_factories: StoreFactories,
+        working_copy_factories: WorkingCopyFactories,
     ) -> Self {
         Self {
             app,
@@ -335,6 +342,7 @@ impl CommandHelper {
             layered_configs,
             maybe_workspace_loader,
             store_factories,
+            working_copy_factories,
         }
     }
 
@@ -398,7 +406,11 @@ impl CommandHelper {
     pub fn load_workspace(&self) -> Result<Workspace, CommandError> {
         let loader = self.workspace_loader()?;
         loader
-            .load(&self.settings, &self.store_factories)
+            .load(
+                &self.settings,
+                &self.store_factories,
+                &self.working_copy_factories,
+            )
             .map_err(|err| map_workspace_load_error(err, &self.global_args))
     }
 
@@ -656,13 +668,13 @@ impl WorkspaceCommandHelper {
         &self.repo
     }
 
-    pub fn working_copy(&self) -> &WorkingCopy {
+    pub fn working_copy(&self) -> &dyn WorkingCopy {
         self.workspace.working_copy()
     }
 
     pub fn unsafe_start_working_copy_mutation(
         &mut self,
-    ) -> Result<(LockedWorkingCopy, Commit), CommandError> {
+    ) -> Result<(Box<dyn LockedWorkingCopy + '_>, Commit), CommandError> {
         self.check_working_copy_writable()?;
         let wc_commit = if let Some(wc_commit_id) = self.get_wc_commit_id() {
             self.repo.store().get_commit(wc_commit_id)?
@@ -677,7 +689,7 @@ impl WorkspaceCommandHelper {
 
     pub fn start_working_copy_mutation(
         &mut self,
-    ) -> Result<(LockedWorkingCopy, Commit), CommandError> {
+    ) -> Result<(Box<dyn LockedWorkingCopy + '_>, Commit), CommandError> {
         let (locked_working_copy, wc_commit) = self.unsafe_start_working_copy_mutation()?;
         if wc_commit.tree_id() != locked_working_copy.old_tree_id() {
             return Err(user_error("Concurrent working copy operation. Try again."));
@@ -1236,6 +1248,14 @@ jj init --git-repo=.",
                 "The repository appears broken or inaccessible: {err}"
             ))
         }
+        WorkspaceLoadError::WorkingCopyLoadError(
+            err @ WorkingCopyLoadError::UnsupportedType(_),
+        ) => CommandError::InternalError(format!(
+            "This version of the jj binary doesn't support this type of working copy: {err}"
+        )),
+        WorkspaceLoadError::WorkingCopyLoadError(WorkingCopyLoadError::Path(e)) => {
+            user_error(format!("{}: {}", e, e.error))
+        }
     }
 }
 
@@ -1285,7 +1305,7 @@ pub enum StaleWorkingCopyError {
 }
 
 pub fn check_stale_working_copy(
-    locked_wc: &LockedWorkingCopy,
+    locked_wc: &dyn LockedWorkingCopy,
     wc_commit: &Commit,
     repo: Arc<ReadonlyRepo>,
 ) -> Result<Arc<ReadonlyRepo>, StaleWorkingCopyError> {
@@ -1415,65 +1435,19 @@ fn resolve_single_op(
     Ok(operation)
 }
 
-fn find_all_operations(
-    op_store: &Arc<dyn OpStore>,
-    op_heads_store: &Arc<dyn OpHeadsStore>,
-) -> Vec<Operation> {
-    let mut visited = HashSet::new();
-    let mut work: VecDeque<_> = op_heads_store.get_op_heads().into_iter().collect();
-    let mut operations = vec![];
-    while let Some(op_id) = work.pop_front() {
-        if visited.insert(op_id.clone()) {
-            let store_operation = op_store.read_operation(&op_id).unwrap();
-            work.extend(store_operation.parents.iter().cloned());
-            let operation = Operation::new(op_store.clone(), op_id, store_operation);
-            operations.push(operation);
-        }
-    }
-    operations
-}
-
 fn resolve_single_op_from_store(
     op_store: &Arc<dyn OpStore>,
     op_heads_store: &Arc<dyn OpHeadsStore>,
     op_str: &str,
 ) -> Result<Operation, CommandError> {
-    if op_str.is_empty() || !op_str.as_bytes().iter().all(|b| b.is_ascii_hexdigit()) {
-        return Err(user_error(format!(
-            "Operation ID \"{op_str}\" is not a valid hexadecimal prefix"
-        )));
-    }
-    if let Ok(binary_op_id) = hex::decode(op_str) {
-        let op_id = OperationId::new(binary_op_id);
-        match op_store.read_operation(&op_id) {
-            Ok(operation) => {
-                return Ok(Operation::new(op_store.clone(), op_id, operation));
-            }
-            Err(OpStoreError::NotFound) => {
-                // Fall through
-            }
-            Err(err) => {
-                return Err(CommandError::InternalError(format!(
-                    "Failed to read operation: {err}"
-                )));
-            }
-        }
-    }
-    let mut matches = vec![];
-    for op in find_all_operations(op_store, op_heads_store) {
-        if op.id().hex().starts_with(op_str) {
-            matches.push(op);
-        }
-    }
-    if matches.is_empty() {
-        Err(user_error(format!("No operation ID matching \"{op_str}\"")))
-    } else if matches.len() == 1 {
-        Ok(matches.pop().unwrap())
-    } else {
-        Err(user_error(format!(
-            "Operation ID prefix \"{op_str}\" is ambiguous"
-        )))
-    }
+    operation::resolve_op_id_prefix(op_store, op_heads_store.get_op_heads(), op_str).map_err(
+        |err| match err {
+            OpIdResolutionError::OpStoreError(_) => CommandError::InternalError(err.to_string()),
+            OpIdResolutionError::InvalidIdPrefix(_)
+            | OpIdResolutionError::NoSuchOperation(_)
+            | OpIdResolutionError::AmbiguousIdPrefix(_) => user_error(err.to_string()),
+        },
+    )
 }
 
 fn load_revset_aliases(
@@ -1552,7 +1526,7 @@ fn append_large_revsets_hint_if_multiple_revisions(err: CommandError) -> Command
 
 pub fn update_working_copy(
     repo: &Arc<ReadonlyRepo>,
-    wc: &mut WorkingCopy,
+    wc: &mut dyn WorkingCopy,
     old_commit: Option<&Commit>,
     new_commit: &Commit,
 ) -> Result<Option<CheckoutStats>, CommandError> {
@@ -1883,7 +1857,7 @@ pub struct GlobalArgs {
 
 #[derive(clap::Args, Clone, Debug)]
 pub struct EarlyArgs {
-    /// When to colorize output (always, never, auto)
+    /// When to colorize output (always, never, auto, debug)
     #[arg(
         long,
         value_name = "WHEN",
@@ -2174,6 +2148,7 @@ pub struct CliRunner {
     tracing_subscription: TracingSubscription,
     app: Command,
     store_factories: Option<StoreFactories>,
+    working_copy_factories: Option<WorkingCopyFactories>,
     dispatch_fn: CliDispatchFn,
     process_global_args_fns: Vec<ProcessGlobalArgsFn>,
 }
@@ -2193,6 +2168,7 @@ impl CliRunner {
             tracing_subscription,
             app: crate::commands::default_app(),
             store_factories: None,
+            working_copy_factories: None,
             dispatch_fn: Box::new(crate::commands::run_command),
             process_global_args_fns: vec![],
         }
@@ -2204,6 +2180,15 @@ impl CliRunner {
         self
     }
 
+    /// Replaces `WorkingCopyFactories` to be used.
+    pub fn set_working_copy_factories(
+        mut self,
+        working_copy_factories: WorkingCopyFactories,
+    ) -> Self {
+        self.working_copy_factories = Some(working_copy_factories);
+        self
+    }
+
     /// Registers new subcommands in addition to the default ones.
     pub fn add_subcommand<C, F>(mut self, custom_dispatch_fn: F) -> Self
     where
@@ -2267,6 +2252,9 @@ impl CliRunner {
         }
         let config = layered_configs.merge();
         ui.reset(&config)?;
+        for warning in validate_color_config(&config)? {
+            writeln!(ui.warning(), "{warning}")?;
+        }
         let settings = UserSettings::from_config(config);
         let command_helper = CommandHelper::new(
             self.app,
@@ -2277,6 +2265,7 @@ impl CliRunner {
             layered_configs,
             maybe_workspace_loader,
             self.store_factories.unwrap_or_default(),
+            self.working_copy_factories.unwrap_or_default(),
         );
         (self.dispatch_fn)(ui, &command_helper, &matches)
     }
@@ -29,6 +29,7 @@ use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use jujutsu_lib::backend::{CommitId, ObjectId, TreeValue};
 use jujutsu_lib::commit::Commit;
+use jujutsu_lib::conflicts::MaterializeStyle;
 use jujutsu_lib::dag_walk::topo_order_reverse;
 use jujutsu_lib::default_index_store::{DefaultIndexStore, IndexEntry, ReadonlyIndexWrapper};
 use jujutsu_lib::matchers::EverythingMatcher;
@@ -42,6 +43,7 @@ use jujutsu_lib::revset::{
 use jujutsu_lib::rewrite::{back_out_commit, merge_commit_trees, rebase_commit, DescendantRebaser};
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::tree::{merge_trees, Tree};
+use jujutsu_lib::working_copy::LocalWorkingCopy;
 use jujutsu_lib::workspace::Workspace;
 use jujutsu_lib::{conflicts, file_util, revset};
 use maplit::{hashmap, hashset};
@@ -1288,7 +1290,14 @@ fn cmd_cat(ui: &mut Ui, command: &CommandHelper, args: &CatArgs) -> Result<(), C
         Some(TreeValue::Conflict(id)) => {
             let conflict = repo.store().read_conflict(&path, &id)?;
             let mut contents = vec![];
-            conflicts::materialize_conflict(repo.store(), &path, &conflict, &mut contents).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                &path,
+                &conflict,
+                MaterializeStyle::Diff,
+                &mut contents,
+            )
+            .unwrap();
             ui.request_pager();
             ui.stdout_formatter().write_all(&contents)?;
         }
@@ -1487,7 +1496,8 @@ fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), C
         let formatter = formatter.as_mut();
 
         if !args.no_graph {
-            let mut graph = get_graphlog(command.settings(), formatter.raw());
+            let mut raw_output = formatter.raw();
+            let mut graph = get_graphlog(command.settings(), raw_output.as_mut());
             let default_node_symbol = graph.default_node_symbol().to_owned();
             let iter: Box<dyn Iterator<Item = (IndexEntry, Vec<RevsetGraphEdge>)>> =
                 if args.reversed {
@@ -1628,7 +1638,8 @@ fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result
         Box::new(|commit: &Commit| commit.predecessors()),
     );
     if !args.no_graph {
-        let mut graph = get_graphlog(command.settings(), formatter.raw());
+        let mut raw_output = formatter.raw();
+        let mut graph = get_graphlog(command.settings(), raw_output.as_mut());
         let default_node_symbol = graph.default_node_symbol().to_owned();
         for commit in commits {
             let mut edges = vec![];
@@ -3093,7 +3104,11 @@ fn cmd_debug(
             let wc = workspace_command.working_copy();
             writeln!(ui, "Current operation: {:?}", wc.operation_id())?;
             writeln!(ui, "Current tree: {:?}", wc.current_tree_id())?;
-            for (file, state) in wc.file_states() {
+            let local_wc = wc
+                .as_any()
+                .downcast_ref::<LocalWorkingCopy>()
+                .ok_or_else(|| user_error("Not a local working copy"))?;
+            for (file, state) in local_wc.file_states() {
                 writeln!(
                     ui,
                     "{:?} {:13?} {:10?} {:?}",
@@ -3221,6 +3236,7 @@ fn cmd_workspace_add(
         &destination_path,
         repo,
         workspace_id,
+        Workspace::default_working_copy_initializer(),
     )?;
     writeln!(
         ui,
@@ -80,7 +80,8 @@ fn cmd_op_log(
     ui.request_pager();
     let mut formatter = ui.stdout_formatter();
     let formatter = formatter.as_mut();
-    let mut graph = get_graphlog(command.settings(), formatter.raw());
+    let mut raw_output = formatter.raw();
+    let mut graph = get_graphlog(command.settings(), raw_output.as_mut());
     let default_node_symbol = graph.default_node_symbol().to_owned();
     for op in topo_order_reverse(
         vec![head_op],
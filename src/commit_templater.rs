@@ -195,7 +195,7 @@ fn build_commit_keyword<'repo>(
         })),
         "conflict" => language.wrap_boolean(wrap_fn(|commit| commit.tree().has_conflict())),
         "empty" => language.wrap_boolean(wrap_fn(move |commit| {
-            commit.tree().id() == rewrite::merge_commit_trees(repo, &commit.parents()).id()
+            *commit.tree_id() == rewrite::merge_commit_tree_id(repo, &commit.parents())
         })),
         _ => return Err(TemplateParseError::no_such_keyword(name, span)),
     };
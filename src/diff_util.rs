@@ -14,13 +14,12 @@
 
 use std::collections::VecDeque;
 use std::io;
-use std::ops::Range;
 use std::sync::Arc;
 
-use itertools::Itertools;
 use jujutsu_lib::backend::{ObjectId, TreeValue};
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::diff::{Diff, DiffHunk};
+use jujutsu_lib::conflicts::MaterializeStyle;
+use jujutsu_lib::diff::{DiffContent, DiffHunk, DiffLineType};
 use jujutsu_lib::files::DiffLine;
 use jujutsu_lib::matchers::Matcher;
 use jujutsu_lib::repo::{ReadonlyRepo, Repo};
@@ -307,7 +306,14 @@ fn diff_content(
         TreeValue::Conflict(id) => {
             let conflict = repo.store().read_conflict(path, id).unwrap();
             let mut content = vec![];
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                path,
+                &conflict,
+                MaterializeStyle::Diff,
+                &mut content,
+            )
+            .unwrap();
             Ok(content)
         }
     }
@@ -456,7 +462,14 @@ fn git_diff_part(
             mode = "100644".to_string();
             hash = id.hex();
             let conflict = repo.store().read_conflict(path, id).unwrap();
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                path,
+                &conflict,
+                MaterializeStyle::Diff,
+                &mut content,
+            )
+            .unwrap();
         }
     }
     let hash = hash[0..10].to_string();
@@ -467,105 +480,19 @@ fn git_diff_part(
     })
 }
 
-#[derive(PartialEq)]
-enum DiffLineType {
-    Context,
-    Removed,
-    Added,
-}
-
-struct UnifiedDiffHunk<'content> {
-    left_line_range: Range<usize>,
-    right_line_range: Range<usize>,
-    lines: Vec<(DiffLineType, &'content [u8])>,
-}
-
-fn unified_diff_hunks<'content>(
-    left_content: &'content [u8],
-    right_content: &'content [u8],
-    num_context_lines: usize,
-) -> Vec<UnifiedDiffHunk<'content>> {
-    let mut hunks = vec![];
-    let mut current_hunk = UnifiedDiffHunk {
-        left_line_range: 1..1,
-        right_line_range: 1..1,
-        lines: vec![],
-    };
-    let mut show_context_after = false;
-    let diff = Diff::for_tokenizer(&[left_content, right_content], &diff::find_line_ranges);
-    for hunk in diff.hunks() {
-        match hunk {
-            DiffHunk::Matching(content) => {
-                let lines = content.split_inclusive(|b| *b == b'\n').collect_vec();
-                // Number of context lines to print after the previous non-matching hunk.
-                let num_after_lines = lines.len().min(if show_context_after {
-                    num_context_lines
-                } else {
-                    0
-                });
-                current_hunk.left_line_range.end += num_after_lines;
-                current_hunk.right_line_range.end += num_after_lines;
-                for line in lines.iter().take(num_after_lines) {
-                    current_hunk.lines.push((DiffLineType::Context, line));
-                }
-                let num_skip_lines = lines
-                    .len()
-                    .saturating_sub(num_after_lines)
-                    .saturating_sub(num_context_lines);
-                if num_skip_lines > 0 {
-                    let left_start = current_hunk.left_line_range.end + num_skip_lines;
-                    let right_start = current_hunk.right_line_range.end + num_skip_lines;
-                    if !current_hunk.lines.is_empty() {
-                        hunks.push(current_hunk);
-                    }
-                    current_hunk = UnifiedDiffHunk {
-                        left_line_range: left_start..left_start,
-                        right_line_range: right_start..right_start,
-                        lines: vec![],
-                    };
-                }
-                let num_before_lines = lines.len() - num_after_lines - num_skip_lines;
-                current_hunk.left_line_range.end += num_before_lines;
-                current_hunk.right_line_range.end += num_before_lines;
-                for line in lines.iter().skip(num_after_lines + num_skip_lines) {
-                    current_hunk.lines.push((DiffLineType::Context, line));
-                }
-            }
-            DiffHunk::Different(content) => {
-                show_context_after = true;
-                let left_lines = content[0].split_inclusive(|b| *b == b'\n').collect_vec();
-                let right_lines = content[1].split_inclusive(|b| *b == b'\n').collect_vec();
-                if !left_lines.is_empty() {
-                    current_hunk.left_line_range.end += left_lines.len();
-                    for line in left_lines {
-                        current_hunk.lines.push((DiffLineType::Removed, line));
-                    }
-                }
-                if !right_lines.is_empty() {
-                    current_hunk.right_line_range.end += right_lines.len();
-                    for line in right_lines {
-                        current_hunk.lines.push((DiffLineType::Added, line));
-                    }
-                }
-            }
-        }
-    }
-    if !current_hunk
-        .lines
-        .iter()
-        .all(|(diff_type, _line)| *diff_type == DiffLineType::Context)
-    {
-        hunks.push(current_hunk);
-    }
-    hunks
-}
-
 fn show_unified_diff_hunks(
     formatter: &mut dyn Formatter,
     left_content: &[u8],
     right_content: &[u8],
 ) -> Result<(), CommandError> {
-    for hunk in unified_diff_hunks(left_content, right_content, 3) {
+    let hunks = match diff::unified_diff_content(left_content, right_content, 3) {
+        DiffContent::Binary => {
+            writeln!(formatter, "Binary files differ")?;
+            return Ok(());
+        }
+        DiffContent::Text(hunks) => hunks,
+    };
+    for hunk in hunks {
         writeln!(
             formatter.labeled("hunk_header"),
             "@@ -{},{} +{},{} @@",
@@ -14,10 +14,13 @@
 
 use std::borrow::BorrowMut;
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::io::{Error, Write};
 use std::ops::Range;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::{fmt, io, mem};
+use std::time::Duration;
+use std::{env, fmt, io, mem, thread};
 
 use crossterm::queue;
 use crossterm::style::{Attribute, Color, SetAttribute, SetBackgroundColor, SetForegroundColor};
@@ -29,9 +32,10 @@ pub trait Formatter: Write {
         self.write_all(text.as_bytes())
     }
 
-    /// Returns the backing `Write`. This is useful for writing data that is
-    /// already formatted, such as in the graphical log.
-    fn raw(&mut self) -> &mut dyn Write;
+    /// Returns a `Write` for the backing output. This is useful for writing
+    /// data that is already formatted, such as in the graphical log. Wrapping
+    /// formatters return a writer around the wrapped formatter's raw output.
+    fn raw(&mut self) -> Box<dyn Write + '_>;
 
     fn push_label(&mut self, label: &str) -> io::Result<()>;
 
@@ -85,6 +89,60 @@ where
 
 type Rules = Vec<(Vec<String>, Style)>;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    Auto,
+    /// Writes label markers instead of colors.
+    Debug,
+}
+
+impl Default for ColorChoice {
+    fn default() -> Self {
+        ColorChoice::Auto
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            "auto" => Ok(ColorChoice::Auto),
+            "debug" => Ok(ColorChoice::Debug),
+            _ => Err("must be one of always, never, auto, or debug"),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+            ColorChoice::Auto => "auto",
+            ColorChoice::Debug => "debug",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl ColorChoice {
+    /// Decides whether to emit color. In `Auto` mode, color is used only when
+    /// writing to a terminal and `$NO_COLOR` isn't set to a non-empty value.
+    /// See <https://no-color.org/>.
+    fn use_color(self, is_tty: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never | ColorChoice::Debug => false,
+            ColorChoice::Auto => is_tty && no_color.map_or(true, |value| value.is_empty()),
+        }
+    }
+}
+
 /// Creates `Formatter` instances with preconfigured parameters.
 #[derive(Clone, Debug)]
 pub struct FormatterFactory {
@@ -96,18 +154,24 @@ enum FormatterFactoryKind {
     PlainText,
     Sanitized,
     Color { rules: Arc<Rules> },
+    Debug,
 }
 
 impl FormatterFactory {
+    /// Prepares formatters for output which is a terminal if `is_tty`.
     pub fn prepare(
         config: &config::Config,
-        color: bool,
-        sanitized: bool,
+        color: ColorChoice,
+        is_tty: bool,
     ) -> Result<Self, config::ConfigError> {
-        let kind = if color {
+        let kind = if color == ColorChoice::Debug {
+            FormatterFactoryKind::Debug
+        } else if color.use_color(is_tty, env::var_os("NO_COLOR").as_deref()) {
             let rules = Arc::new(rules_from_config(config)?);
             FormatterFactoryKind::Color { rules }
-        } else if sanitized {
+        } else if is_tty {
+            // Sanitize ANSI escape codes if we're printing to a terminal. Doesn't
+            // affect ANSI escape codes that originate from the formatter itself.
             FormatterFactoryKind::Sanitized
         } else {
             FormatterFactoryKind::PlainText
@@ -115,6 +179,11 @@ impl FormatterFactory {
         Ok(FormatterFactory { kind })
     }
 
+    /// Returns true if the created formatters emit color.
+    pub fn is_color(&self) -> bool {
+        matches!(self.kind, FormatterFactoryKind::Color { .. })
+    }
+
     pub fn new_formatter<'output, W: Write + 'output>(
         &self,
         output: W,
@@ -125,6 +194,7 @@ impl FormatterFactory {
             FormatterFactoryKind::Color { rules } => {
                 Box::new(ColorFormatter::new(output, rules.clone()))
             }
+            FormatterFactoryKind::Debug => Box::new(DebugFormatter::new(output)),
         }
     }
 }
@@ -150,8 +220,8 @@ impl<W: Write> Write for PlainTextFormatter<W> {
 }
 
 impl<W: Write> Formatter for PlainTextFormatter<W> {
-    fn raw(&mut self) -> &mut dyn Write {
-        &mut self.output
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(&mut self.output)
     }
 
     fn push_label(&mut self, _label: &str) -> io::Result<()> {
@@ -185,8 +255,8 @@ impl<W: Write> Write for SanitizingFormatter<W> {
 }
 
 impl<W: Write> Formatter for SanitizingFormatter<W> {
-    fn raw(&mut self) -> &mut dyn Write {
-        &mut self.output
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(&mut self.output)
     }
 
     fn push_label(&mut self, _label: &str) -> io::Result<()> {
@@ -198,21 +268,146 @@ impl<W: Write> Formatter for SanitizingFormatter<W> {
     }
 }
 
+/// Writes `<<label>>` and `<</label>>` markers around labeled text instead of
+/// colors. This is useful for testing and debugging templates.
+pub struct DebugFormatter<W> {
+    output: W,
+    labels: Vec<String>,
+}
+
+impl<W> DebugFormatter<W> {
+    pub fn new(output: W) -> DebugFormatter<W> {
+        Self {
+            output,
+            labels: vec![],
+        }
+    }
+}
+
+impl<W: Write> Write for DebugFormatter<W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        write_sanitized(&mut self.output, data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.output.flush()
+    }
+}
+
+impl<W: Write> Formatter for DebugFormatter<W> {
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(&mut self.output)
+    }
+
+    fn push_label(&mut self, label: &str) -> io::Result<()> {
+        write!(self.output, "<<{label}>>")?;
+        self.labels.push(label.to_owned());
+        Ok(())
+    }
+
+    fn pop_label(&mut self) -> io::Result<()> {
+        if let Some(label) = self.labels.pop() {
+            write!(self.output, "<</{label}>>")?;
+        }
+        Ok(())
+    }
+}
+
+/// Style attributes to apply to labeled text. `None` means that the attribute
+/// is inherited from the rules of the outer labels. A color can be explicitly
+/// set back to the terminal default with `Color::Reset`.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Style {
     pub fg_color: Option<Color>,
     pub bg_color: Option<Color>,
     pub bold: Option<bool>,
+    pub dim: Option<bool>,
+    pub italic: Option<bool>,
     pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
 }
 
 impl Style {
+    /// Returns the names of attributes set to different values in `self` and
+    /// `other`.
+    fn conflicting_attributes(&self, other: &Style) -> Vec<&'static str> {
+        fn conflicts<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            matches!((a, b), (Some(a), Some(b)) if a != b)
+        }
+        let mut names = vec![];
+        if conflicts(&self.fg_color, &other.fg_color) {
+            names.push("fg");
+        }
+        if conflicts(&self.bg_color, &other.bg_color) {
+            names.push("bg");
+        }
+        if conflicts(&self.bold, &other.bold) {
+            names.push("bold");
+        }
+        if conflicts(&self.dim, &other.dim) {
+            names.push("dim");
+        }
+        if conflicts(&self.italic, &other.italic) {
+            names.push("italic");
+        }
+        if conflicts(&self.underlined, &other.underlined) {
+            names.push("underline");
+        }
+        if conflicts(&self.strikethrough, &other.strikethrough) {
+            names.push("strikethrough");
+        }
+        names
+    }
+
     fn merge(&mut self, other: &Style) {
         self.fg_color = other.fg_color.or(self.fg_color);
         self.bg_color = other.bg_color.or(self.bg_color);
         self.bold = other.bold.or(self.bold);
+        self.dim = other.dim.or(self.dim);
+        self.italic = other.italic.or(self.italic);
         self.underlined = other.underlined.or(self.underlined);
+        self.strikethrough = other.strikethrough.or(self.strikethrough);
+    }
+}
+
+/// Computes the effective style for the label stack `labels`.
+///
+/// The styles of all matching rules are merged from the lowest priority to the
+/// highest, so each attribute comes from the highest-priority rule that sets
+/// it. Rules with the same priority are applied in order, so the later one
+/// wins.
+fn resolve_style(rules: &Rules, labels: &[impl AsRef<str>]) -> Style {
+    // We use the reverse list of matched indices as a measure of how well the rule
+    // matches the actual labels. For example, for rule "a d" and the actual labels
+    // "a b c d", we'll get [3,0]. We compare them by Rust's default Vec comparison.
+    // That means "a d" will trump both rule "d" (priority [3]) and rule
+    // "a b c" (priority [2,1,0]).
+    let mut matched_styles = vec![];
+    for (rule_labels, style) in rules {
+        let mut labels_iter = labels.iter().enumerate();
+        // The indexes in the current label stack that match the required label.
+        let mut matched_indices = vec![];
+        for required_label in rule_labels {
+            for (label_index, label) in &mut labels_iter {
+                if label.as_ref() == required_label {
+                    matched_indices.push(label_index);
+                    break;
+                }
+            }
+        }
+        if matched_indices.len() == rule_labels.len() {
+            matched_indices.reverse();
+            matched_styles.push((style, matched_indices));
+        }
+    }
+    matched_styles.sort_by_key(|(_, indices)| indices.clone());
+
+    let mut style = Style::default();
+    for (matched_style, _) in matched_styles {
+        style.merge(matched_style);
     }
+    style
 }
 
 pub struct ColorFormatter<W> {
@@ -242,39 +437,17 @@ impl<W: Write> ColorFormatter<W> {
         Ok(Self::new(output, Arc::new(rules)))
     }
 
+    /// Returns the style that text would be rendered with if the given labels
+    /// were pushed in order. Nothing is written to the output.
+    pub fn style_for_labels(&self, labels: &[impl AsRef<str>]) -> Style {
+        resolve_style(&self.rules, labels)
+    }
+
     fn requested_style(&mut self) -> Style {
         if let Some(cached) = self.cached_styles.get(&self.labels) {
             cached.clone()
         } else {
-            // We use the reverse list of matched indices as a measure of how well the rule
-            // matches the actual labels. For example, for rule "a d" and the actual labels
-            // "a b c d", we'll get [3,0]. We compare them by Rust's default Vec comparison.
-            // That means "a d" will trump both rule "d" (priority [3]) and rule
-            // "a b c" (priority [2,1,0]).
-            let mut matched_styles = vec![];
-            for (labels, style) in self.rules.as_ref() {
-                let mut labels_iter = self.labels.iter().enumerate();
-                // The indexes in the current label stack that match the required label.
-                let mut matched_indices = vec![];
-                for required_label in labels {
-                    for (label_index, label) in &mut labels_iter {
-                        if label == required_label {
-                            matched_indices.push(label_index);
-                            break;
-                        }
-                    }
-                }
-                if matched_indices.len() == labels.len() {
-                    matched_indices.reverse();
-                    matched_styles.push((style, matched_indices));
-                }
-            }
-            matched_styles.sort_by_key(|(_, indices)| indices.clone());
-
-            let mut style = Style::default();
-            for (matched_style, _) in matched_styles {
-                style.merge(matched_style);
-            }
+            let style = resolve_style(&self.rules, &self.labels);
             self.cached_styles
                 .insert(self.labels.clone(), style.clone());
             style
@@ -284,37 +457,62 @@ impl<W: Write> ColorFormatter<W> {
     fn write_new_style(&mut self) -> io::Result<()> {
         let new_style = self.requested_style();
         if new_style != self.current_style {
-            if new_style.bold != self.current_style.bold {
-                if new_style.bold.unwrap_or_default() {
-                    queue!(self.output, SetAttribute(Attribute::Bold))?;
+            // Render the escape sequences into a buffer and write them in one go,
+            // so that the terminal is never left in the middle of an escape
+            // sequence. See write_all_retrying().
+            let mut escapes = vec![];
+            let mut current_style = self.current_style.clone();
+            let turned_off = |new: Option<bool>, current: Option<bool>| {
+                new != current && !new.unwrap_or_default()
+            };
+            if turned_off(new_style.bold, current_style.bold)
+                || turned_off(new_style.dim, current_style.dim)
+            {
+                // NoBold results in double underlining on some terminals, and
+                // NormalIntensity clears both bold and dim, so we use reset
+                // instead. However, that resets other attributes as well, so we
+                // reset our record of the current style so we re-apply the
+                // other attributes below.
+                queue!(escapes, SetAttribute(Attribute::Reset))?;
+                current_style = Style::default();
+            }
+            if new_style.bold != current_style.bold && new_style.bold.unwrap_or_default() {
+                queue!(escapes, SetAttribute(Attribute::Bold))?;
+            }
+            if new_style.dim != current_style.dim && new_style.dim.unwrap_or_default() {
+                queue!(escapes, SetAttribute(Attribute::Dim))?;
+            }
+            if new_style.italic != current_style.italic {
+                if new_style.italic.unwrap_or_default() {
+                    queue!(escapes, SetAttribute(Attribute::Italic))?;
                 } else {
-                    // NoBold results in double underlining on some terminals, so we use reset
-                    // instead. However, that resets other attributes as well, so we reset
-                    // our record of the current style so we re-apply the other attributes
-                    // below.
-                    queue!(self.output, SetAttribute(Attribute::Reset))?;
-                    self.current_style = Style::default();
+                    queue!(escapes, SetAttribute(Attribute::NoItalic))?;
                 }
             }
-            if new_style.underlined != self.current_style.underlined {
+            if new_style.underlined != current_style.underlined {
                 if new_style.underlined.unwrap_or_default() {
-                    queue!(self.output, SetAttribute(Attribute::Underlined))?;
+                    queue!(escapes, SetAttribute(Attribute::Underlined))?;
                 } else {
-                    queue!(self.output, SetAttribute(Attribute::NoUnderline))?;
+                    queue!(escapes, SetAttribute(Attribute::NoUnderline))?;
                 }
             }
-            if new_style.fg_color != self.current_style.fg_color {
-                queue!(
-                    self.output,
-                    SetForegroundColor(new_style.fg_color.unwrap_or(Color::Reset))
-                )?;
+            if new_style.strikethrough != current_style.strikethrough {
+                if new_style.strikethrough.unwrap_or_default() {
+                    queue!(escapes, SetAttribute(Attribute::CrossedOut))?;
+                } else {
+                    queue!(escapes, SetAttribute(Attribute::NotCrossedOut))?;
+                }
+            }
+            // An unset color is rendered as the default color.
+            let new_fg_color = new_style.fg_color.unwrap_or(Color::Reset);
+            if new_fg_color != current_style.fg_color.unwrap_or(Color::Reset) {
+                queue!(escapes, SetForegroundColor(new_fg_color))?;
             }
-            if new_style.bg_color != self.current_style.bg_color {
-                queue!(
-                    self.output,
-                    SetBackgroundColor(new_style.bg_color.unwrap_or(Color::Reset))
-                )?;
+            let new_bg_color = new_style.bg_color.unwrap_or(Color::Reset);
+            if new_bg_color != current_style.bg_color.unwrap_or(Color::Reset) {
+                queue!(escapes, SetBackgroundColor(new_bg_color))?;
             }
+            write_all_retrying(&mut self.output, &escapes)?;
             self.current_style = new_style;
         }
         Ok(())
@@ -322,55 +520,120 @@ impl<W: Write> ColorFormatter<W> {
 }
 
 fn rules_from_config(config: &config::Config) -> Result<Rules, config::ConfigError> {
-    let mut result = vec![];
-    let table = config.get_table("colors")?;
-    for (key, value) in table {
+    // Parsing is lenient. Use validate_color_config() to report ignored settings.
+    parse_rules_from_config(config, &mut vec![])
+}
+
+/// Checks the color configuration, and returns warnings about settings that
+/// are ignored or overridden by the formatter, such as unknown style keys or
+/// unrecognized color names.
+pub fn validate_color_config(config: &config::Config) -> Result<Vec<String>, config::ConfigError> {
+    let mut warnings = vec![];
+    parse_rules_from_config(config, &mut warnings)?;
+    Ok(warnings)
+}
+
+fn parse_rules_from_config(
+    config: &config::Config,
+    warnings: &mut Vec<String>,
+) -> Result<Rules, config::ConfigError> {
+    let mut result = rules_from_table("colors", config.get_table("colors")?, warnings);
+    // Rules of the selected theme are appended so they take precedence over the
+    // top-level colors. Labels not covered by the theme fall back to the latter.
+    match config.get_string("ui.color-theme") {
+        Ok(theme_name) => {
+            let table_key = format!("themes.{theme_name}.colors");
+            match config.get_table(&table_key) {
+                Ok(theme_table) => {
+                    result.extend(rules_from_table(&table_key, theme_table, warnings));
+                }
+                Err(config::ConfigError::NotFound(_)) => {
+                    warnings.push(format!(r#"Unknown color theme "{theme_name}""#));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(config::ConfigError::NotFound(_)) => {}
+        Err(err) => return Err(err),
+    }
+    Ok(result)
+}
+
+fn rules_from_table(
+    table_key: &str,
+    table: HashMap<String, config::Value>,
+    warnings: &mut Vec<String>,
+) -> Rules {
+    fn parse_color(value: &config::Value, warn: &mut impl FnMut(String)) -> Option<Color> {
+        if let config::ValueKind::String(color_name) = &value.kind {
+            let color = color_for_name(color_name);
+            if color.is_none() {
+                warn(format!(r#"Unrecognized color "{color_name}""#));
+            }
+            color
+        } else {
+            warn(format!("Invalid color {value}"));
+            None
+        }
+    }
+
+    let mut result: Rules = vec![];
+    for (key, value) in table.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
         let labels = key
             .split_whitespace()
             .map(ToString::to_string)
             .collect_vec();
-        match value.kind {
-            config::ValueKind::String(color_name) => {
-                let style = Style {
-                    fg_color: color_for_name(&color_name),
-                    bg_color: None,
-                    bold: None,
-                    underlined: None,
-                };
-                result.push((labels, style));
-            }
+        let mut warn =
+            |message: String| warnings.push(format!(r#"{message} in "{table_key}.{key}""#));
+        let style = match &value.kind {
+            config::ValueKind::String(_) => Style {
+                fg_color: parse_color(&value, &mut warn),
+                ..Style::default()
+            },
             config::ValueKind::Table(style_table) => {
                 let mut style = Style::default();
-                if let Some(value) = style_table.get("fg") {
-                    if let config::ValueKind::String(color_name) = &value.kind {
-                        style.fg_color = color_for_name(color_name);
-                    }
-                }
-                if let Some(value) = style_table.get("bg") {
-                    if let config::ValueKind::String(color_name) = &value.kind {
-                        style.bg_color = color_for_name(color_name);
-                    }
-                }
-                if let Some(value) = style_table.get("bold") {
-                    if let config::ValueKind::Boolean(value) = &value.kind {
-                        style.bold = Some(*value);
-                    }
-                }
-                if let Some(value) = style_table.get("underline") {
-                    if let config::ValueKind::Boolean(value) = &value.kind {
-                        style.underlined = Some(*value);
+                for (name, value) in style_table.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+                    match (name.as_str(), &value.kind) {
+                        ("fg", _) => style.fg_color = parse_color(value, &mut warn),
+                        ("bg", _) => style.bg_color = parse_color(value, &mut warn),
+                        ("bold", config::ValueKind::Boolean(value)) => style.bold = Some(*value),
+                        ("dim", config::ValueKind::Boolean(value)) => style.dim = Some(*value),
+                        ("italic", config::ValueKind::Boolean(value)) => {
+                            style.italic = Some(*value);
+                        }
+                        ("underline", config::ValueKind::Boolean(value)) => {
+                            style.underlined = Some(*value);
+                        }
+                        ("strikethrough", config::ValueKind::Boolean(value)) => {
+                            style.strikethrough = Some(*value);
+                        }
+                        ("bold" | "dim" | "italic" | "underline" | "strikethrough", _) => {
+                            warn(format!(r#"Invalid value for "{name}""#));
+                        }
+                        _ => warn(format!(r#"Unknown key "{name}""#)),
                     }
                 }
-                result.push((labels, style));
+                style
+            }
+            _ => {
+                warn("Invalid style".to_owned());
+                continue;
+            }
+        };
+        // Keys differing only in whitespace map to the same labels.
+        for (_, other_style) in result.iter().filter(|(other, _)| *other == labels) {
+            for name in style.conflicting_attributes(other_style) {
+                warn(format!(r#"Conflicting values for "{name}""#));
             }
-            _ => {}
         }
+        result.push((labels, style));
     }
-    Ok(result)
+    result
 }
 
 fn color_for_name(color_name: &str) -> Option<Color> {
     match color_name {
+        "default" => Some(Color::Reset),
         "black" => Some(Color::Black),
         "red" => Some(Color::DarkRed),
         "green" => Some(Color::DarkGreen),
@@ -387,8 +650,32 @@ fn color_for_name(color_name: &str) -> Option<Color> {
         "bright magenta" => Some(Color::Magenta),
         "bright cyan" => Some(Color::Cyan),
         "bright white" => Some(Color::White),
-        _ => None,
+        _ => color_for_hex(color_name).or_else(|| color_for_ansi_index(color_name)),
+    }
+}
+
+/// Parses a 24-bit color written as `#rrggbb`.
+fn color_for_hex(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
     }
+    let component = |range: Range<usize>| u8::from_str_radix(&hex[range], 16).ok();
+    Some(Color::Rgb {
+        r: component(0..2)?,
+        g: component(2..4)?,
+        b: component(4..6)?,
+    })
+}
+
+/// Parses an index into the 256-color palette written as `ansi-color-N`.
+fn color_for_ansi_index(color: &str) -> Option<Color> {
+    let index = color.strip_prefix("ansi-color-")?;
+    // u8::from_str() would also accept a leading "+"
+    if !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok().map(Color::AnsiValue)
 }
 
 impl<W: Write> Write for ColorFormatter<W> {
@@ -438,8 +725,8 @@ impl<W: Write> Write for ColorFormatter<W> {
 }
 
 impl<W: Write> Formatter for ColorFormatter<W> {
-    fn raw(&mut self) -> &mut dyn Write {
-        &mut self.output
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(&mut self.output)
     }
 
     fn push_label(&mut self, label: &str) -> io::Result<()> {
@@ -463,13 +750,13 @@ impl<W: Write> Formatter for ColorFormatter<W> {
 /// the destination formatter has already been labeled, the recorded labels
 /// will be stacked on top of the existing labels, and the subsequent data
 /// may be colorized differently.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Eq, Hash, PartialEq)]
 pub struct FormatRecorder {
     data: Vec<u8>,
     label_ops: Vec<(usize, LabelOp)>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 enum LabelOp {
     PushLabel(String),
     PopLabel,
@@ -484,6 +771,24 @@ impl FormatRecorder {
         &self.data
     }
 
+    /// Returns the display width of the recorded text, which is the width of
+    /// the widest line. Labels don't take up any columns.
+    pub fn width(&self) -> usize {
+        self.line_widths().max().unwrap_or(0)
+    }
+
+    /// Returns the display width of each line of the recorded text.
+    ///
+    /// The width is calculated in the same manner as `text_util::wrap_bytes()`,
+    /// so wide characters take two columns, and ANSI escape sequences take none.
+    pub fn line_widths(&self) -> impl Iterator<Item = usize> + '_ {
+        self.data.split_inclusive(|&b| b == b'\n').map(|line| {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            // We don't care about the width of non-UTF-8 bytes, but should not panic.
+            textwrap::core::display_width(&String::from_utf8_lossy(line))
+        })
+    }
+
     fn push_label_op(&mut self, op: LabelOp) {
         self.label_ops.push((self.data.len(), op));
     }
@@ -530,7 +835,7 @@ impl Write for FormatRecorder {
 }
 
 impl Formatter for FormatRecorder {
-    fn raw(&mut self) -> &mut dyn Write {
+    fn raw(&mut self) -> Box<dyn Write + '_> {
         panic!("raw output isn't supported by FormatRecorder")
     }
 
@@ -545,6 +850,217 @@ impl Formatter for FormatRecorder {
     }
 }
 
+/// Wraps a formatter and stops writing to it after a number of lines.
+///
+/// Output past the limit is discarded, but the lines are still counted so the
+/// caller can tell how many were omitted and print a footer. Labels are passed
+/// through as is.
+pub struct LineLimitingFormatter<'a> {
+    formatter: &'a mut dyn Formatter,
+    limit: LineLimit,
+}
+
+impl<'a> LineLimitingFormatter<'a> {
+    pub fn new(formatter: &'a mut dyn Formatter, max_lines: usize) -> Self {
+        LineLimitingFormatter {
+            formatter,
+            limit: LineLimit {
+                max_lines,
+                num_lines: 0,
+                at_line_start: true,
+            },
+        }
+    }
+
+    /// Whether any further output would be discarded.
+    pub fn is_exhausted(&self) -> bool {
+        let limit = &self.limit;
+        limit.num_lines > limit.max_lines
+            || (limit.num_lines == limit.max_lines && limit.at_line_start)
+    }
+
+    /// The number of lines that were discarded, counting a trailing partial
+    /// line as a line.
+    pub fn num_omitted_lines(&self) -> usize {
+        self.limit.num_lines.saturating_sub(self.limit.max_lines)
+    }
+}
+
+/// Line count shared by a `LineLimitingFormatter` and its raw writer.
+struct LineLimit {
+    max_lines: usize,
+    /// The number of lines started so far, including the omitted ones.
+    num_lines: usize,
+    at_line_start: bool,
+}
+
+impl LineLimit {
+    /// Counts the lines in `data` and returns the length of its prefix that's
+    /// within the limit.
+    fn count(&mut self, data: &[u8]) -> usize {
+        let mut end = 0;
+        for (i, &b) in data.iter().enumerate() {
+            if self.at_line_start {
+                self.num_lines += 1;
+                self.at_line_start = false;
+            }
+            if self.num_lines <= self.max_lines {
+                end = i + 1;
+            }
+            if b == b'\n' {
+                self.at_line_start = true;
+            }
+        }
+        end
+    }
+}
+
+impl Write for LineLimitingFormatter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.limit.count(data);
+        self.formatter.write_all(&data[..end])?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.formatter.flush()
+    }
+}
+
+impl Formatter for LineLimitingFormatter<'_> {
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(LineLimitingWriter {
+            output: self.formatter.raw(),
+            limit: &mut self.limit,
+        })
+    }
+
+    fn push_label(&mut self, label: &str) -> io::Result<()> {
+        self.formatter.push_label(label)
+    }
+
+    fn pop_label(&mut self) -> io::Result<()> {
+        self.formatter.pop_label()
+    }
+}
+
+/// Raw output of a `LineLimitingFormatter`. It's written to the wrapped
+/// formatter's raw output, and counts towards the same line limit.
+struct LineLimitingWriter<'a> {
+    output: Box<dyn Write + 'a>,
+    limit: &'a mut LineLimit,
+}
+
+impl Write for LineLimitingWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let end = self.limit.count(data);
+        self.output.write_all(&data[..end])?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Forwards labels and output to two formatters, e.g. a color formatter for
+/// the terminal and a plain-text one for a log file.
+pub struct TeeFormatter<'a> {
+    first: &'a mut dyn Formatter,
+    second: &'a mut dyn Formatter,
+}
+
+impl<'a> TeeFormatter<'a> {
+    pub fn new(first: &'a mut dyn Formatter, second: &'a mut dyn Formatter) -> Self {
+        TeeFormatter { first, second }
+    }
+}
+
+impl Write for TeeFormatter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.first.write_all(data)?;
+        self.second.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
+
+impl Formatter for TeeFormatter<'_> {
+    fn raw(&mut self) -> Box<dyn Write + '_> {
+        Box::new(TeeWriter {
+            first: self.first.raw(),
+            second: self.second.raw(),
+        })
+    }
+
+    fn push_label(&mut self, label: &str) -> io::Result<()> {
+        self.first.push_label(label)?;
+        self.second.push_label(label)
+    }
+
+    fn pop_label(&mut self) -> io::Result<()> {
+        self.first.pop_label()?;
+        self.second.pop_label()
+    }
+}
+
+/// Raw output of a `TeeFormatter`, which is written to the raw output of both
+/// formatters.
+struct TeeWriter<'a> {
+    first: Box<dyn Write + 'a>,
+    second: Box<dyn Write + 'a>,
+}
+
+impl Write for TeeWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.first.write_all(data)?;
+        self.second.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+}
+
+/// How many times in a row `write_all_retrying()` waits for a writer that
+/// would block before giving up.
+const MAX_WOULD_BLOCK_RETRIES: u32 = 12;
+
+/// Like `Write::write_all()`, but also retries if the writer would block, e.g.
+/// on a non-blocking pipe. An error in the middle of an escape sequence would
+/// leave the terminal in a broken state, and the caller can't tell how much of
+/// it was written. Rather than spinning, it sleeps with exponential backoff
+/// (up to 64ms) between retries, and returns the error if the writer is still
+/// blocked after `MAX_WOULD_BLOCK_RETRIES` attempts.
+fn write_all_retrying(output: &mut impl Write, mut buf: &[u8]) -> io::Result<()> {
+    let mut num_retries = 0;
+    while !buf.is_empty() {
+        match output.write(buf) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                num_retries = 0;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    && num_retries < MAX_WOULD_BLOCK_RETRIES =>
+            {
+                thread::sleep(Duration::from_millis(1 << num_retries.min(6)));
+                num_retries += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
 fn write_sanitized(output: &mut impl Write, buf: &[u8]) -> Result<(), Error> {
     if buf.contains(&b'\x1b') {
         let mut sanitized = Vec::with_capacity(buf.len());
@@ -563,6 +1079,9 @@ fn write_sanitized(output: &mut impl Write, buf: &[u8]) -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+    use std::collections::HashSet;
+    use std::rc::Rc;
     use std::str;
 
     use super::*;
@@ -574,6 +1093,61 @@ mod tests {
             .unwrap()
     }
 
+    #[test]
+    fn test_color_choice_use_color() {
+        let unset = None;
+        let empty = Some(OsStr::new(""));
+        let set = Some(OsStr::new("1"));
+        for no_color in [unset, empty, set] {
+            assert!(ColorChoice::Always.use_color(true, no_color));
+            assert!(ColorChoice::Always.use_color(false, no_color));
+            assert!(!ColorChoice::Never.use_color(true, no_color));
+            assert!(!ColorChoice::Never.use_color(false, no_color));
+            assert!(!ColorChoice::Auto.use_color(false, no_color));
+        }
+        assert!(ColorChoice::Auto.use_color(true, unset));
+        // An empty $NO_COLOR is the same as an unset one
+        assert!(ColorChoice::Auto.use_color(true, empty));
+        assert!(!ColorChoice::Auto.use_color(true, set));
+    }
+
+    #[test]
+    fn test_formatter_factory_prepare() {
+        let config = config_from_string(r#" colors.inner = "red" "#);
+        let format = |color: ColorChoice, is_tty: bool| {
+            let factory = FormatterFactory::prepare(&config, color, is_tty).unwrap();
+            let mut output: Vec<u8> = vec![];
+            let mut formatter = factory.new_formatter(&mut output);
+            formatter.push_label("inner").unwrap();
+            formatter.write_str("\x1b[1mtext").unwrap();
+            formatter.pop_label().unwrap();
+            drop(formatter);
+            (factory.is_color(), String::from_utf8(output).unwrap())
+        };
+        assert_eq!(
+            format(ColorChoice::Always, false),
+            (true, "\x1b[38;5;1m␛[1mtext\x1b[39m".to_owned())
+        );
+        // Escape codes in the text are sanitized when writing to a terminal
+        assert_eq!(
+            format(ColorChoice::Never, true),
+            (false, "␛[1mtext".to_owned())
+        );
+        assert_eq!(
+            format(ColorChoice::Never, false),
+            (false, "\x1b[1mtext".to_owned())
+        );
+        // Auto doesn't depend on $NO_COLOR if not writing to a terminal
+        assert_eq!(
+            format(ColorChoice::Auto, false),
+            (false, "\x1b[1mtext".to_owned())
+        );
+        assert_eq!(
+            format(ColorChoice::Debug, true),
+            (false, "<<inner>>␛[1mtext<</inner>>".to_owned())
+        );
+    }
+
     #[test]
     fn test_plaintext_formatter() {
         // Test that PlainTextFormatter ignores labels.
@@ -662,6 +1236,81 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_debug_formatter() {
+        let mut recorder = FormatRecorder::new();
+        recorder.write_str(" outer1 ").unwrap();
+        recorder.push_label("inner").unwrap();
+        recorder.write_str(" inner1 ").unwrap();
+        recorder.push_label("innermost").unwrap();
+        recorder.write_str(" \x1b[1minnermost ").unwrap();
+        recorder.pop_label().unwrap();
+        recorder.write_str(" inner2 ").unwrap();
+        recorder.pop_label().unwrap();
+        recorder.push_label("empty").unwrap();
+        recorder.pop_label().unwrap();
+        recorder.write_str(" outer2 ").unwrap();
+
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = DebugFormatter::new(&mut output);
+        recorder.replay(&mut formatter).unwrap();
+        // Extra pop_label() is ignored
+        formatter.pop_label().unwrap();
+        insta::assert_snapshot!(
+            String::from_utf8(output).unwrap(),
+            @" outer1 <<inner>> inner1 <<innermost>> ␛[1minnermost <</innermost>> inner2 <</inner>><<empty>><</empty>> outer2 ");
+    }
+
+    #[test]
+    fn test_resolve_style_priority() {
+        let rule = |labels: &str, fg_color: Option<Color>, bold: Option<bool>| {
+            let style = Style {
+                fg_color,
+                bold,
+                ..Default::default()
+            };
+            (labels.split(' ').map(str::to_owned).collect_vec(), style)
+        };
+        let labels = ["a", "b", "c", "d"];
+
+        // "a d" matches at [3,0], which trumps "d" ([3]) and "a b c" ([2,1,0])
+        let rules = vec![
+            rule("d", Some(Color::Red), None),
+            rule("a d", Some(Color::Green), None),
+            rule("a b c", Some(Color::Blue), None),
+        ];
+        assert_eq!(resolve_style(&rules, &labels).fg_color, Some(Color::Green));
+
+        // Rules that don't match all their labels in order are ignored
+        let rules = vec![
+            rule("a", Some(Color::Red), None),
+            rule("d a", Some(Color::Green), None),
+            rule("a e", Some(Color::Blue), None),
+        ];
+        assert_eq!(resolve_style(&rules, &labels).fg_color, Some(Color::Red));
+
+        // Among rules with the same priority, the later one wins
+        let rules = vec![
+            rule("c", Some(Color::Red), None),
+            rule("c", Some(Color::Green), None),
+        ];
+        assert_eq!(resolve_style(&rules, &labels).fg_color, Some(Color::Green));
+
+        // Attributes not set by the winning rule fall back to lower priorities
+        let rules = vec![
+            rule("a", Some(Color::Red), Some(true)),
+            rule("b", None, Some(false)),
+            rule("d", Some(Color::Green), None),
+        ];
+        let style = resolve_style(&rules, &labels);
+        assert_eq!(style.fg_color, Some(Color::Green));
+        assert_eq!(style.bold, Some(false));
+
+        // No rule matches an empty label stack
+        let rules = vec![rule("a", Some(Color::Red), None)];
+        assert_eq!(resolve_style(&rules, &[] as &[&str]), Style::default());
+    }
+
     #[test]
     fn test_color_formatter_single_label() {
         // Test that a single label can be colored and that the color is reset
@@ -732,6 +1381,51 @@ mod tests {
         "###);
     }
 
+    #[test]
+    fn test_color_formatter_more_attributes() {
+        // Test that the dim, italic, and strikethrough attributes can be set and
+        // combined with the others.
+        let config = config_from_string(
+            r#"
+        colors.dim_text = { dim = true }
+        colors.italic_text = { italic = true }
+        colors.strikethrough_text = { strikethrough = true }
+        colors.multiple = { bold = true, dim = true, italic = true, strikethrough = true }
+        "#,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        formatter.push_label("dim_text").unwrap();
+        formatter.write_str(" dim only ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str("\n").unwrap();
+        formatter.push_label("italic_text").unwrap();
+        formatter.write_str(" italic only ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str("\n").unwrap();
+        formatter.push_label("strikethrough_text").unwrap();
+        formatter.write_str(" strikethrough only ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str("\n").unwrap();
+        formatter.push_label("multiple").unwrap();
+        formatter.write_str(" single rule ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str("\n").unwrap();
+        formatter.push_label("dim_text").unwrap();
+        formatter.push_label("italic_text").unwrap();
+        formatter.write_str(" two rules ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str("\n").unwrap();
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(), @r###"
+        [2m dim only [0m
+        [3m italic only [23m
+        [9m strikethrough only [29m
+        [1m[2m[3m[9m single rule [0m
+        [2m[3m two rules [0m
+        "###);
+    }
+
     #[test]
     fn test_color_formatter_bold_reset() {
         // Test that we don't lose other attributes when we reset the bold attribute.
@@ -753,6 +1447,122 @@ mod tests {
         insta::assert_snapshot!(String::from_utf8(output).unwrap(), @"[4m[38;5;1m[48;5;4m not bold [1m bold [0m[4m[38;5;1m[48;5;4m not bold again [24m[39m[49m");
     }
 
+    #[test]
+    fn test_color_formatter_dim_reset() {
+        // Test that we don't lose other attributes, including bold, when we reset
+        // the dim attribute.
+        let config = config_from_string(
+            r#"
+        colors.not_dim = { fg = "red", bold = true, italic = true, strikethrough = true }
+        colors.dim_text = { dim = true }
+        "#,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        formatter.push_label("not_dim").unwrap();
+        formatter.write_str(" not dim ").unwrap();
+        formatter.push_label("dim_text").unwrap();
+        formatter.write_str(" dim ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str(" not dim again ").unwrap();
+        formatter.pop_label().unwrap();
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(), @"[1m[3m[9m[38;5;1m not dim [2m dim [0m[1m[3m[9m[38;5;1m not dim again [0m");
+    }
+
+    #[test]
+    fn test_color_formatter_short_and_blocked_writes() {
+        // Test that escape sequences are written intact when the underlying writer
+        // accepts only a byte at a time, or would block in the middle of an escape
+        // sequence as a non-blocking pipe may.
+        struct ByteWriter {
+            output: Vec<u8>,
+            block_after: Rc<Cell<Option<usize>>>,
+        }
+
+        impl Write for ByteWriter {
+            fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+                match self.block_after.get() {
+                    Some(0) => {
+                        self.block_after.set(None);
+                        return Err(io::ErrorKind::WouldBlock.into());
+                    }
+                    Some(n) => self.block_after.set(Some(n - 1)),
+                    None => {}
+                }
+                self.output.push(data[0]);
+                Ok(1)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let config = config_from_string(
+            r#"
+        colors.not_bold = { fg = "red", bg = "blue", underline = true }
+        colors.bold_font = { bold = true }
+        "#,
+        );
+        let write_all = |formatter: &mut dyn Formatter, block_after: &Cell<Option<usize>>| {
+            formatter.push_label("not_bold").unwrap();
+            formatter.write_str(" not bold ").unwrap();
+            formatter.push_label("bold_font").unwrap();
+            // Block after the first byte of the bold escape sequence
+            block_after.set(Some(1));
+            formatter.write_str(" bold ").unwrap();
+            formatter.pop_label().unwrap();
+            // Block in the middle of the escape sequences restoring the style
+            block_after.set(Some(5));
+            formatter.write_str(" not bold again ").unwrap();
+            formatter.pop_label().unwrap();
+        };
+
+        let mut expected: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut expected, &config).unwrap();
+        write_all(&mut formatter, &Cell::new(None));
+        drop(formatter);
+
+        let block_after = Rc::new(Cell::new(None));
+        let mut writer = ByteWriter {
+            output: vec![],
+            block_after: block_after.clone(),
+        };
+        let mut formatter = ColorFormatter::for_config(&mut writer, &config).unwrap();
+        write_all(&mut formatter, &block_after);
+        drop(formatter);
+        assert_eq!(block_after.get(), None);
+        assert_eq!(
+            String::from_utf8(writer.output).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_all_retrying_gives_up() {
+        // A writer that stays blocked makes the write fail instead of spinning
+        // forever
+        struct BlockedWriter {
+            num_writes: u32,
+        }
+
+        impl Write for BlockedWriter {
+            fn write(&mut self, _data: &[u8]) -> io::Result<usize> {
+                self.num_writes += 1;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = BlockedWriter { num_writes: 0 };
+        let err = write_all_retrying(&mut writer, b"\x1b[1m").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(writer.num_writes, MAX_WOULD_BLOCK_RETRIES + 1);
+    }
+
     #[test]
     fn test_color_formatter_no_space() {
         // Test that two different colors can touch.
@@ -841,6 +1651,131 @@ mod tests {
         @" not colored [38;5;2m colored [39m not colored ");
     }
 
+    #[test]
+    fn test_color_formatter_style_for_labels() {
+        let config = config_from_string(
+            r#"
+        colors.outer = { fg = "blue", bold = true }
+        colors.inner = "red"
+        colors."outer inner" = { fg = "green", underline = true }
+        colors."outer other" = { bg = "yellow" }
+        "#,
+        );
+        let formatter = ColorFormatter::for_config(vec![], &config).unwrap();
+        let empty: [&str; 0] = [];
+        assert_eq!(formatter.style_for_labels(&empty), Style::default());
+        assert_eq!(formatter.style_for_labels(&["unknown"]), Style::default());
+        assert_eq!(
+            formatter.style_for_labels(&["outer"]),
+            Style {
+                fg_color: Some(Color::DarkBlue),
+                bold: Some(true),
+                ..Style::default()
+            }
+        );
+        assert_eq!(
+            formatter.style_for_labels(&["outer", "inner"]),
+            Style {
+                fg_color: Some(Color::DarkGreen),
+                bold: Some(true),
+                underlined: Some(true),
+                ..Style::default()
+            }
+        );
+        // Rules don't match labels out of order
+        assert_eq!(
+            formatter.style_for_labels(&["inner", "outer"]),
+            Style {
+                fg_color: Some(Color::DarkBlue),
+                bold: Some(true),
+                ..Style::default()
+            }
+        );
+
+        // The resolved style renders the same as the label stack it came from
+        for labels in [
+            vec!["outer"],
+            vec!["inner"],
+            vec!["outer", "inner"],
+            vec!["outer", "other", "inner"],
+        ] {
+            let mut output: Vec<u8> = vec![];
+            let mut labeled_formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+            for label in &labels {
+                labeled_formatter.push_label(label).unwrap();
+            }
+            labeled_formatter.write_str(" text ").unwrap();
+            for _ in &labels {
+                labeled_formatter.pop_label().unwrap();
+            }
+
+            let style = formatter.style_for_labels(&labels);
+            let rules = vec![(vec!["resolved".to_string()], style)];
+            let mut expected_output: Vec<u8> = vec![];
+            let mut resolved_formatter = ColorFormatter::new(&mut expected_output, Arc::new(rules));
+            resolved_formatter.push_label("resolved").unwrap();
+            resolved_formatter.write_str(" text ").unwrap();
+            resolved_formatter.pop_label().unwrap();
+            assert_eq!(output, expected_output, "labels: {labels:?}");
+        }
+    }
+
+    #[test]
+    fn test_color_formatter_rgb() {
+        // Test that hex colors are emitted as 24-bit color codes, and that
+        // malformed ones are ignored.
+        let config = config_from_string(
+            r##"
+        colors.orange = "#ff8800"
+        colors.both = { fg = "#FFFFFF", bg = "#0a0b0c" }
+        colors.too_short = "#ff88"
+        colors.not_hex = "#gg0000"
+        "##,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        for label in ["orange", "both", "too_short", "not_hex"] {
+            formatter.push_label(label).unwrap();
+            formatter.write_str(label).unwrap();
+            formatter.pop_label().unwrap();
+            formatter.write_str("\n").unwrap();
+        }
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(), @r###"
+        [38;2;255;136;0morange[39m
+        [38;2;255;255;255m[48;2;10;11;12mboth[39m[49m
+        too_short
+        not_hex
+        "###);
+    }
+
+    #[test]
+    fn test_color_formatter_ansi_index() {
+        // Test that palette indices are emitted as 256-color codes, and that
+        // out-of-range ones are ignored.
+        let config = config_from_string(
+            r#"
+        colors.first = "ansi-color-0"
+        colors.last = { fg = "ansi-color-255", bg = "ansi-color-214" }
+        colors.out_of_range = "ansi-color-256"
+        colors.signed = "ansi-color-+1"
+        "#,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        for label in ["first", "last", "out_of_range", "signed"] {
+            formatter.push_label(label).unwrap();
+            formatter.write_str(label).unwrap();
+            formatter.pop_label().unwrap();
+            formatter.write_str("\n").unwrap();
+        }
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(), @r###"
+        [38;5;0mfirst[39m
+        [38;5;255m[48;5;214mlast[39m[49m
+        out_of_range
+        signed
+        "###);
+    }
+
     #[test]
     fn test_color_formatter_unrecognized_color() {
         // An unrecognized color is ignored; it doesn't reset the color.
@@ -863,6 +1798,38 @@ mod tests {
         @"[38;5;1m red before  still red inside  also red afterwards [39m");
     }
 
+    #[test]
+    fn test_color_formatter_default_color() {
+        // An inner rule can reset a color to the terminal default, while the
+        // other color is inherited from the outer rule.
+        let config = config_from_string(
+            r#"
+        colors.outer = { fg = "red", bg = "blue" }
+        colors."outer inner" = { fg = "default" }
+        colors."outer inner innermost" = { bg = "default" }
+        colors.alone = "default"
+        "#,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        formatter.push_label("outer").unwrap();
+        formatter.write_str(" outer ").unwrap();
+        formatter.push_label("inner").unwrap();
+        formatter.write_str(" inner ").unwrap();
+        formatter.push_label("innermost").unwrap();
+        formatter.write_str(" innermost ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str(" outer again ").unwrap();
+        formatter.pop_label().unwrap();
+        // Explicit default is the same as no color
+        formatter.push_label("alone").unwrap();
+        formatter.write_str(" alone ").unwrap();
+        formatter.pop_label().unwrap();
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(),
+        @"[38;5;1m[48;5;4m outer [39m inner [49m innermost [38;5;1m[48;5;4m outer again [39m[49m alone ");
+    }
+
     #[test]
     fn test_color_formatter_sibling() {
         // A partial match on one rule does not eliminate other rules.
@@ -929,6 +1896,94 @@ mod tests {
         @"[38;5;1m a1 [38;5;2m b1 [38;5;3m c [38;5;2m b2 [38;5;1m a2 [39m");
     }
 
+    #[test]
+    fn test_color_formatter_theme() {
+        // Rules of the selected theme override the top-level colors, and the
+        // top-level colors apply to labels not covered by the theme.
+        let config = config_from_string(
+            r#"
+        ui.color-theme = "dark"
+        colors.a = "red"
+        colors.b = { fg = "green", bold = true }
+        themes.dark.colors.b = "blue"
+        themes.light.colors.a = "yellow"
+        "#,
+        );
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        formatter.push_label("a").unwrap();
+        formatter.write_str(" a ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.push_label("b").unwrap();
+        formatter.write_str(" b ").unwrap();
+        formatter.pop_label().unwrap();
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(),
+        @"[38;5;1m a [39m[1m[38;5;4m b [0m");
+    }
+
+    #[test]
+    fn test_color_formatter_unknown_theme() {
+        let config = config_from_string(
+            r#"
+        ui.color-theme = "nonexistent"
+        colors.a = "red"
+        "#,
+        );
+        // Only the top-level colors apply
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        formatter.push_label("a").unwrap();
+        formatter.write_str(" a ").unwrap();
+        formatter.pop_label().unwrap();
+        insta::assert_snapshot!(String::from_utf8(output).unwrap(),
+        @"[38;5;1m a [39m");
+        assert_eq!(
+            validate_color_config(&config).unwrap(),
+            vec![r#"Unknown color theme "nonexistent""#.to_owned()]
+        );
+    }
+
+    #[test]
+    fn test_validate_color_config() {
+        let config = config_from_string(
+            r##"
+        ui.color-theme = "dark"
+        colors.ok = { fg = "red", bold = true }
+        colors.bad_color = "bloo"
+        colors.bad_hex = "#12345"
+        colors.bad_index = "ansi-color-256"
+        colors.bad_fg = { fg = "bloo", bg = 1 }
+        colors.bad_key = { fg = "red", italics = true }
+        colors.bad_bold = { bold = "yes" }
+        colors.bad_style = 1
+        colors."a b" = { fg = "red", bold = true }
+        colors."a  b" = { fg = "green", bold = true }
+        themes.dark.colors.ok = "bloo"
+        "##,
+        );
+        insta::assert_debug_snapshot!(validate_color_config(&config).unwrap(), @r###"
+        [
+            "Conflicting values for \"fg\" in \"colors.a b\"",
+            "Invalid value for \"bold\" in \"colors.bad_bold\"",
+            "Unrecognized color \"bloo\" in \"colors.bad_color\"",
+            "Invalid color 1 in \"colors.bad_fg\"",
+            "Unrecognized color \"bloo\" in \"colors.bad_fg\"",
+            "Unrecognized color \"#12345\" in \"colors.bad_hex\"",
+            "Unrecognized color \"ansi-color-256\" in \"colors.bad_index\"",
+            "Unknown key \"italics\" in \"colors.bad_key\"",
+            "Invalid style in \"colors.bad_style\"",
+            "Unrecognized color \"bloo\" in \"themes.dark.colors.ok\"",
+        ]
+        "###);
+
+        // The default config should be valid
+        let config = crate::config::default_config();
+        assert_eq!(
+            validate_color_config(&config).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
     #[test]
     fn test_format_recorder() {
         let mut recorder = FormatRecorder::new();
@@ -965,4 +2020,190 @@ mod tests {
             String::from_utf8(output).unwrap(),
             @"<< outer1 >>[38;5;1m<< inner1  inner2 >>[39m<< outer2 >>");
     }
+
+    #[test]
+    fn test_format_recorder_width() {
+        let record = |chunks: &[&str]| {
+            let mut recorder = FormatRecorder::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                recorder.push_label(&format!("label{i}")).unwrap();
+                recorder.write_str(chunk).unwrap();
+                recorder.pop_label().unwrap();
+            }
+            recorder
+        };
+        let widths = |recorder: &FormatRecorder| recorder.line_widths().collect_vec();
+
+        let recorder = record(&[]);
+        assert_eq!(recorder.width(), 0);
+        assert_eq!(widths(&recorder), Vec::<usize>::new());
+
+        // Labels have no width
+        let recorder = record(&["foo", "", " bar"]);
+        assert_eq!(recorder.width(), 7);
+        assert_eq!(widths(&recorder), vec![7]);
+
+        // CJK characters take two columns each
+        let recorder = record(&["日本", "語 x"]);
+        assert_eq!(recorder.width(), 8);
+
+        // Combining marks take no column
+        let recorder = record(&["e\u{301}", "a\u{308}\u{304}"]);
+        assert_eq!(recorder.width(), 2);
+
+        // ANSI escape sequences take no column
+        let recorder = record(&["\x1b[1mbold\x1b[0m"]);
+        assert_eq!(recorder.width(), 4);
+
+        // The widest line determines the width
+        let recorder = record(&["a\n", "日本語\nbc", "\n"]);
+        assert_eq!(recorder.width(), 6);
+        assert_eq!(widths(&recorder), vec![1, 6, 2]);
+        let recorder = record(&["a\n\n"]);
+        assert_eq!(widths(&recorder), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_format_recorder_eq() {
+        let record = |label: &str| {
+            let mut recorder = FormatRecorder::new();
+            recorder.write_str(" outer ").unwrap();
+            recorder.push_label(label).unwrap();
+            recorder.write_str(" inner ").unwrap();
+            recorder.pop_label().unwrap();
+            recorder
+        };
+        assert_eq!(record("inner"), record("inner"));
+        // Same data, but labeled differently
+        assert_ne!(record("inner"), record("other"));
+
+        // Same data and labels, but labels pushed at a different position
+        let mut recorder = FormatRecorder::new();
+        recorder.push_label("inner").unwrap();
+        recorder.write_str(" outer ").unwrap();
+        recorder.write_str(" inner ").unwrap();
+        recorder.pop_label().unwrap();
+        assert_ne!(record("inner"), recorder);
+
+        let recorders: HashSet<_> = [record("inner"), record("inner"), record("other")]
+            .into_iter()
+            .collect();
+        assert_eq!(recorders.len(), 2);
+    }
+
+    #[test]
+    fn test_line_limiting_formatter() {
+        // Recorded entries are replayed until the budget runs out
+        let mut recorder = FormatRecorder::new();
+        recorder.push_label("inner").unwrap();
+        recorder.write_str("line1\nline2\n").unwrap();
+        recorder.pop_label().unwrap();
+        recorder.write_str("line3\nline4\nline5").unwrap();
+
+        let config = config_from_string(r#" colors.inner = "red" "#);
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = ColorFormatter::for_config(&mut output, &config).unwrap();
+        let mut limited_formatter = LineLimitingFormatter::new(&mut formatter, 3);
+        recorder.replay(&mut limited_formatter).unwrap();
+        assert!(limited_formatter.is_exhausted());
+        let num_omitted_lines = limited_formatter.num_omitted_lines();
+        assert_eq!(num_omitted_lines, 2);
+        writeln!(formatter, "... ({num_omitted_lines} more)").unwrap();
+        insta::assert_snapshot!(
+            String::from_utf8(output).unwrap(),
+            @r###"
+        [38;5;1mline1[39m
+        [38;5;1mline2[39m
+        line3
+        ... (2 more)
+        "###);
+
+        // Output within the budget is written as is
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = PlainTextFormatter::new(&mut output);
+        let mut limited_formatter = LineLimitingFormatter::new(&mut formatter, 2);
+        limited_formatter.write_str("line1\n").unwrap();
+        assert!(!limited_formatter.is_exhausted());
+        limited_formatter.write_str("line2").unwrap();
+        assert!(!limited_formatter.is_exhausted());
+        limited_formatter.write_str("\n").unwrap();
+        assert!(limited_formatter.is_exhausted());
+        assert_eq!(limited_formatter.num_omitted_lines(), 0);
+        limited_formatter.raw().write_all(b"line3\n").unwrap();
+        assert_eq!(limited_formatter.num_omitted_lines(), 1);
+        assert_eq!(String::from_utf8(output).unwrap(), "line1\nline2\n");
+
+        // Raw output goes to the wrapped formatter's raw output, so escape
+        // sequences aren't sanitized, but it's still limited
+        let mut output: Vec<u8> = vec![];
+        let mut formatter = SanitizingFormatter::new(&mut output);
+        let mut limited_formatter = LineLimitingFormatter::new(&mut formatter, 2);
+        limited_formatter.write_str("\x1b[1mtext\n").unwrap();
+        limited_formatter
+            .raw()
+            .write_all(b"\x1b[1mraw1\x1b[0m\nraw2\n")
+            .unwrap();
+        assert_eq!(limited_formatter.num_omitted_lines(), 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "␛[1mtext\n\x1b[1mraw1\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_tee_formatter() {
+        let config = config_from_string(
+            r#"
+        colors.outer = "green"
+        colors.inner = "red"
+        "#,
+        );
+        let mut color_output: Vec<u8> = vec![];
+        let mut plain_output: Vec<u8> = vec![];
+        let mut color_formatter = ColorFormatter::for_config(&mut color_output, &config).unwrap();
+        let mut plain_formatter = PlainTextFormatter::new(&mut plain_output);
+        let mut formatter = TeeFormatter::new(&mut color_formatter, &mut plain_formatter);
+        formatter.push_label("outer").unwrap();
+        formatter.write_str(" outer1 ").unwrap();
+        formatter.push_label("inner").unwrap();
+        formatter.write_str(" inner ").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.write_str(" outer2 \n").unwrap();
+        formatter.pop_label().unwrap();
+        formatter.raw().write_all(b"raw\n").unwrap();
+        drop(color_formatter);
+        insta::assert_snapshot!(String::from_utf8(color_output).unwrap(), @r###"
+        [38;5;2m outer1 [38;5;1m inner [38;5;2m outer2 [39m
+        raw
+        "###);
+        assert_eq!(
+            String::from_utf8(plain_output).unwrap(),
+            " outer1  inner  outer2 \nraw\n"
+        );
+    }
+
+    #[test]
+    fn test_tee_formatter_raw() {
+        // Raw output is written to the raw output of both formatters, so escape
+        // sequences aren't sanitized and active labels aren't applied to it
+        let config = config_from_string(r#"colors.outer = "green""#);
+        let mut color_output: Vec<u8> = vec![];
+        let mut plain_output: Vec<u8> = vec![];
+        let mut color_formatter = ColorFormatter::for_config(&mut color_output, &config).unwrap();
+        let mut plain_formatter = SanitizingFormatter::new(&mut plain_output);
+        let mut formatter = TeeFormatter::new(&mut color_formatter, &mut plain_formatter);
+        formatter.push_label("outer").unwrap();
+        formatter.raw().write_all(b"\x1b[1mraw\x1b[0m\n").unwrap();
+        formatter.write_str("\x1b[1mtext\n").unwrap();
+        formatter.pop_label().unwrap();
+        drop(color_formatter);
+        assert_eq!(
+            String::from_utf8(color_output).unwrap(),
+            "\x1b[1mraw\x1b[0m\n\x1b[38;5;2m␛[1mtext\x1b[39m\n"
+        );
+        assert_eq!(
+            String::from_utf8(plain_output).unwrap(),
+            "\x1b[1mraw\x1b[0m\n␛[1mtext\n"
+        );
+    }
 }
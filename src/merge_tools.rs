@@ -24,7 +24,7 @@ use itertools::Itertools;
 use jujutsu_lib::backend::{TreeId, TreeValue};
 use jujutsu_lib::conflicts::{
     describe_conflict, extract_file_conflict_as_single_hunk, materialize_merge_result,
-    update_conflict_from_content,
+    update_conflict_from_content, MaterializeStyle,
 };
 use jujutsu_lib::gitignore::GitIgnoreFile;
 use jujutsu_lib::matchers::EverythingMatcher;
@@ -32,7 +32,7 @@ use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::store::Store;
 use jujutsu_lib::tree::Tree;
-use jujutsu_lib::working_copy::{CheckoutError, SnapshotError, TreeState};
+use jujutsu_lib::working_copy::{CheckoutError, FullScan, SnapshotError, TreeState};
 use regex::{Captures, Regex};
 use thiserror::Error;
 
@@ -195,7 +195,7 @@ pub fn run_mergetool(
     let editor = get_merge_tool_from_settings(ui, settings)?;
     let initial_output_content: Vec<u8> = if editor.merge_tool_edits_conflict_markers {
         let mut materialized_conflict = vec![];
-        materialize_merge_result(&content, &mut materialized_conflict)
+        materialize_merge_result(&content, MaterializeStyle::Diff, &mut materialized_conflict)
             .expect("Writing to an in-memory buffer should never fail");
         materialized_conflict
     } else {
@@ -379,7 +379,7 @@ pub fn edit_diff(
         std::fs::remove_file(instructions_path).ok();
     }
 
-    right_tree_state.snapshot(base_ignores)?;
+    right_tree_state.snapshot(base_ignores, &FullScan)?;
     Ok(right_tree_state.current_tree_id().clone())
 }
 
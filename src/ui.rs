@@ -14,17 +14,15 @@
 
 use std::io::{Stderr, Stdout, Write};
 use std::process::{Child, ChildStdin, Stdio};
-use std::str::FromStr;
 use std::{env, fmt, io, mem};
 
 use crossterm::tty::IsTty;
 use maplit::hashmap;
 
 use crate::config::{CommandNameAndArgs, NonEmptyCommandArgsVec};
-use crate::formatter::{Formatter, FormatterFactory, LabeledWriter};
+use crate::formatter::{ColorChoice, Formatter, FormatterFactory, LabeledWriter};
 
 pub struct Ui {
-    color: bool,
     pager_cmd: CommandNameAndArgs,
     paginate: PaginationChoice,
     progress_indicator: bool,
@@ -36,43 +34,6 @@ fn progress_indicator_setting(config: &config::Config) -> bool {
     config.get_bool("ui.progress-indicator").unwrap_or(true)
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum ColorChoice {
-    Always,
-    Never,
-    Auto,
-}
-
-impl Default for ColorChoice {
-    fn default() -> Self {
-        ColorChoice::Auto
-    }
-}
-
-impl FromStr for ColorChoice {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "always" => Ok(ColorChoice::Always),
-            "never" => Ok(ColorChoice::Never),
-            "auto" => Ok(ColorChoice::Auto),
-            _ => Err("must be one of always, never, or auto"),
-        }
-    }
-}
-
-impl fmt::Display for ColorChoice {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            ColorChoice::Always => "always",
-            ColorChoice::Never => "never",
-            ColorChoice::Auto => "auto",
-        };
-        write!(f, "{s}")
-    }
-}
-
 fn color_setting(config: &config::Config) -> ColorChoice {
     config
         .get_string("ui.color")
@@ -81,14 +42,6 @@ fn color_setting(config: &config::Config) -> ColorChoice {
         .unwrap_or_default()
 }
 
-fn use_color(choice: ColorChoice) -> bool {
-    match choice {
-        ColorChoice::Always => true,
-        ColorChoice::Never => false,
-        ColorChoice::Auto => io::stdout().is_tty(),
-    }
-}
-
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum PaginationChoice {
     No,
@@ -113,14 +66,10 @@ fn pager_setting(config: &config::Config) -> CommandNameAndArgs {
 
 impl Ui {
     pub fn with_config(config: &config::Config) -> Result<Ui, config::ConfigError> {
-        let color = use_color(color_setting(config));
-        // Sanitize ANSI escape codes if we're printing to a terminal. Doesn't affect
-        // ANSI escape codes that originate from the formatter itself.
-        let sanitize = io::stdout().is_tty();
-        let formatter_factory = FormatterFactory::prepare(config, color, sanitize)?;
+        let formatter_factory =
+            FormatterFactory::prepare(config, color_setting(config), io::stdout().is_tty())?;
         let progress_indicator = progress_indicator_setting(config);
         Ok(Ui {
-            color,
             formatter_factory,
             pager_cmd: pager_setting(config),
             paginate: PaginationChoice::Auto,
@@ -130,11 +79,10 @@ impl Ui {
     }
 
     pub fn reset(&mut self, config: &config::Config) -> Result<(), config::ConfigError> {
-        self.color = use_color(color_setting(config));
         self.pager_cmd = pager_setting(config);
         self.progress_indicator = progress_indicator_setting(config);
-        let sanitize = io::stdout().is_tty();
-        self.formatter_factory = FormatterFactory::prepare(config, self.color, sanitize)?;
+        self.formatter_factory =
+            FormatterFactory::prepare(config, color_setting(config), io::stdout().is_tty())?;
         Ok(())
     }
 
@@ -170,7 +118,7 @@ impl Ui {
     }
 
     pub fn color(&self) -> bool {
-        self.color
+        self.formatter_factory.is_color()
     }
 
     pub fn new_formatter<'output, W: Write + 'output>(
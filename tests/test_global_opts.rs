@@ -274,7 +274,7 @@ fn test_color_config() {
     "###);
 
     // Test that NO_COLOR does NOT override the request for color in the config file
-    test_env.add_env_var("NO_COLOR", "");
+    test_env.add_env_var("NO_COLOR", "1");
     let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "commit_id"]);
     insta::assert_snapshot!(stdout, @r###"
     @  [38;5;4m230dd059e1b059aefc0da06a2e5a7dbf22362f22[39m
@@ -294,6 +294,28 @@ fn test_color_config() {
     "###);
 }
 
+#[test]
+fn test_color_config_warnings() {
+    let test_env = TestEnvironment::default();
+
+    // Invalid color settings are reported once, and don't prevent the command
+    // from running
+    test_env.add_config(
+        r#"
+    colors.commit_id = "bloo"
+    colors.change_id = { fg = "red", italics = true }
+    "#,
+    );
+    let assert = test_env
+        .jj_cmd(test_env.env_root(), &["init", "repo", "--git"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Unknown key "italics" in "colors.change_id"
+    Unrecognized color "bloo" in "colors.commit_id"
+    "###);
+}
+
 #[test]
 fn test_early_args() {
     // Test that help output parses early args
@@ -380,7 +402,7 @@ fn test_help() {
           --ignore-working-copy          Don't snapshot the working copy, and don't update it
           --at-operation <AT_OPERATION>  Operation to load the repo at [default: @] [aliases: at-op]
       -v, --verbose                      Enable verbose logging
-          --color <WHEN>                 When to colorize output (always, never, auto)
+          --color <WHEN>                 When to colorize output (always, never, auto, debug)
           --no-pager                     Disable the pager
           --config-toml <TOML>           Additional configuration options
     "###);